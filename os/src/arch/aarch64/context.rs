@@ -1,5 +1,13 @@
 use core::arch::asm;
 
+// TrapContext 中 sp_el0 和 x0 的位置
+pub const SP_REG: usize = 31;
+pub const RET_REG: usize = 0;
+// ELR_EL1
+pub const PC_REG: usize = 33;
+// TPIDR_EL0 不在 TrapContext 中，由 __switch 保存
+pub const TLS_REG: Option<usize> = None;
+
 pub fn create_ctx(entry: usize, sp: usize) -> [usize; 34] {
     let mut ctx: [usize; 34] = [0; 34];
     unsafe {
@@ -11,4 +19,10 @@ pub fn create_ctx(entry: usize, sp: usize) -> [usize; 34] {
         ctx[33] = entry;
         ctx
     }
-}
\ No newline at end of file
+}
+
+pub fn read_tls() -> usize {
+    let tpidr: usize;
+    unsafe { asm!("mrs {}, TPIDR_EL0", out(reg) tpidr); }
+    tpidr
+}
@@ -1,38 +1,38 @@
-use core::{arch::asm, ffi::CStr};
+use core::arch::global_asm;
 
-use alloc::string::{String, ToString};
+// 逐字节复制，返回没有复制的字节数
+// 访问用户地址的页错误无法处理时，current_elx_synchronous 把 ELR 改为 __copy_user_fixup，返回剩下的字节数
+global_asm!(
+    ".section .text",
+    ".globl __copy_user",
+    ".globl __copy_user_end",
+    ".globl __copy_user_fixup",
+    "__copy_user:",
+    "    cbz x2, __copy_user_fixup",
+    "1:  ldrb w3, [x1], #1",
+    "    strb w3, [x0], #1",
+    "    subs x2, x2, #1",
+    "    b.ne 1b",
+    "__copy_user_fixup:",
+    "    mov x0, x2",
+    "    ret",
+    "__copy_user_end:",
+);
 
-#[inline]
-pub unsafe fn copy_with_user(to: *mut u8, from: *const u8, n: usize) {
-    let mut remaining = n;
-    let mut src = from;
-    let mut dst = to;
-
-    while remaining > 0 {
-        let _byte: u8;
-        asm!(
-            "ldrb w3, [{src}], #1",
-            "strb w3, [{dst}], #1", 
-            "subs {remaining}, {remaining}, #1",
-            src = inout(reg) src,
-            dst = inout(reg) dst,
-            remaining = inout(reg) remaining,
-            options(nostack, preserves_flags),
-        )
-    }
+extern "C" {
+    fn __copy_user(to: *mut u8, from: *const u8, n: usize) -> usize;
+    fn __copy_user_end();
+    fn __copy_user_fixup();
 }
 
-pub fn copy_usize_with_user(src: usize, dst: *mut usize) {
-    unsafe {
-        *dst = src;
-    }
+// pc 在 __copy_user 中时返回出错后继续执行的地址
+pub fn copy_fixup(pc: usize) -> Option<usize> {
+    (__copy_user as usize <= pc && pc < __copy_user_end as usize).then_some(__copy_user_fixup as usize)
 }
 
-pub fn copy_str_with_user(src: *const i8) -> String {
-    unsafe {
-        let str = CStr::from_ptr(src).to_str().unwrap().to_string().clone();
-        str
-    }
+// 访问无效的用户地址时返回 false
+pub unsafe fn copy_with_user(to: *mut u8, from: *const u8, n: usize) -> bool {
+    __copy_user(to, from, n) == 0
 }
 
 pub fn enable_user_access() {
@@ -41,4 +41,4 @@ pub fn enable_user_access() {
 
 pub fn disable_user_access() {
     // do nothing
-}
\ No newline at end of file
+}
@@ -14,16 +14,32 @@ pub fn root_ppn() -> usize {
     (aarch64_cpu::registers::TTBR0_EL1.get_baddr() as usize) >> 12
 }
 
+// 广播到 inner shareable 域中的所有 cpu
 pub unsafe fn flush_tlb(asid: usize) {
     asm!("dsb ishst");
-    asm!("tlbi aside1, {}", in(reg) asid);
+    asm!("tlbi aside1is, {}", in(reg) asid << 48);
     asm!("dsb ish");
     asm!("isb");
 }
 
+pub unsafe fn flush_tlb_all() {
+    asm!("dsb ishst");
+    asm!("tlbi vmalle1is");
+    asm!("dsb ish");
+    asm!("isb");
+}
+
+// 只刷新当前 cpu
+unsafe fn flush_local_tlb(asid: usize) {
+    asm!("dsb nshst");
+    asm!("tlbi aside1, {}", in(reg) asid << 48);
+    asm!("dsb nsh");
+    asm!("isb");
+}
+
 pub fn enable_va(id: usize, ppn: usize) {
     TTBR0_EL1.write(TTBR0_EL1::ASID.val(id as u64) + TTBR0_EL1::BADDR.val((ppn << 11) as u64));
-    unsafe {flush_tlb(id);}
+    unsafe {flush_local_tlb(id);}
 }
 
 pub fn pte(ppn: usize, flags: PTEFlags) -> usize {
@@ -68,6 +84,15 @@ pub fn pte(ppn: usize, flags: PTEFlags) -> usize {
     pte
 }
 
+// 4K granule 下 level 1/2 的块描述符映射 1G/2M，bit 1 为 0，其余的位和页描述符相同
+pub fn block_pte(ppn: usize, flags: PTEFlags) -> usize {
+    pte(ppn, flags - PTEFlags::T) & !(1usize << 1)
+}
+
+pub fn is_block(pte: usize) -> bool {
+    pte & 0b11 == 0b01
+}
+
 pub fn ppn(pte: usize) -> usize {
     (pte >> 12) & 0x0000_0000_000F_FFFF
 }
@@ -196,4 +221,32 @@ pub fn clear_flag(pte: usize, f: PTEFlags) -> usize {
     }
 
     p
-}
\ No newline at end of file
+}
+
+// AF 为 0 时访问这个页会产生 access flag fault，由页错误处理重新设置
+pub fn is_accessed(pte: usize) -> bool {
+    pte & (1usize << 10) != 0
+}
+
+pub fn set_accessed(pte: usize) -> usize {
+    pte | (1usize << 10)
+}
+
+pub fn clear_accessed(pte: usize) -> usize {
+    pte & !(1usize << 10)
+}
+
+// 无效的描述符中其余的位由软件使用，bit 55 标记换出的页，交换槽位放在 ppn 的位置
+const SWAP_BIT: usize = 1 << 55;
+
+pub fn swap_pte(slot: usize) -> usize {
+    slot << 12 | SWAP_BIT
+}
+
+pub fn swap_slot(pte: usize) -> Option<usize> {
+    if pte & 1 == 0 && pte & SWAP_BIT != 0 {
+        Some((pte & !SWAP_BIT) >> 12)
+    } else {
+        None
+    }
+}
@@ -2,6 +2,7 @@ pub mod trap;
 pub mod context;
 pub mod memory;
 pub mod trampoline;
+pub mod smp;
 
 use core::arch::global_asm;
 
@@ -0,0 +1,53 @@
+use aarch64_cpu::registers::*;
+use core::arch::asm;
+
+// PSCI 0.2 CPU_ON，64 位调用
+const PSCI_CPU_ON: usize = 0xC400_0003;
+
+extern "C" {
+    fn __secondary_trampoline();
+}
+
+// trampoline 链接在物理地址上，通过变量得到它的地址
+static SECONDARY_ENTRY: unsafe extern "C" fn() = __secondary_trampoline;
+
+// qemu virt 中 MPIDR_EL1 的 Aff0 即为 cpu 编号
+pub fn cpu_id() -> usize {
+    (MPIDR_EL1.get() & 0xFF) as usize
+}
+
+pub fn wait_for_interrupt() {
+    aarch64_cpu::asm::wfi();
+}
+
+// 屏蔽当前 cpu 的 IRQ，返回之前是否打开
+pub fn irq_save() -> bool {
+    let masked = DAIF.is_set(DAIF::I);
+    disable_irq();
+    !masked
+}
+
+pub fn enable_irq() {
+    unsafe { asm!("msr daifclr, #2"); }
+}
+
+pub fn disable_irq() {
+    unsafe { asm!("msr daifset, #2"); }
+}
+
+// 通过 PSCI CPU_ON 启动其他 cpu，它会从 __secondary_trampoline 开始执行
+// 没有 EL2/EL3 固件时，qemu 使用 hvc 作为 PSCI 的调用方式
+pub fn start_cpu(cpu: usize) -> isize {
+    let entry = unsafe { core::ptr::read_volatile(&SECONDARY_ENTRY) } as usize;
+    let ret: usize;
+    unsafe {
+        asm!(
+            "hvc #0",
+            inlateout("x0") PSCI_CPU_ON => ret,
+            in("x1") cpu,
+            in("x2") entry,
+            in("x3") 0,
+        );
+    }
+    ret as isize
+}
@@ -110,3 +110,44 @@ pub extern "C" fn __trampoline() {
     );
   }
 }
+
+// 其他 cpu 的入口，内核页表已经由 __trampoline 建立好了，只需要使能 MMU
+#[no_mangle]
+#[naked]
+#[link_section = ".trampoline.secondary"]
+pub extern "C" fn __secondary_trampoline() {
+  unsafe {
+    asm!(
+      "ldr x9, =0xff04",
+      "msr MAIR_EL1, x9",
+      "ldr x9, =0x4B5193519",
+      "msr TCR_EL1, x9",
+      "adrp x9, skpt",
+      "msr TTBR1_EL1, x9",
+      "msr TTBR0_EL1, x9",
+      "isb",
+      "mrs x9, SCTLR_EL1",
+      "orr x9, x9, #(1 << 0)",
+      "orr x9, x9, #(1 << 2)",
+      "orr x9, x9, #(1 << 12)",
+      "msr SCTLR_EL1, x9",
+      "isb",
+      "mrs x9, cpacr_el1",
+      "orr x9, x9, #(0x3 << 20)",
+      "msr cpacr_el1, x9",
+
+      // 每个 cpu 使用自己的启动栈
+      "mrs x10, MPIDR_EL1",
+      "and x10, x10, #0xFF",
+      "adrp x0, sstack",
+      "add x0, x0, :lo12:sstack",
+      "ldr x9, =_stack_size",
+      "mul x9, x9, x10",
+      "sub x0, x0, x9",
+      "mov sp, x0",
+      "ldr x10, =os_secondary_main",
+      "br x10",
+      options(noreturn)
+    );
+  }
+}
@@ -1,9 +1,10 @@
 use aarch64_cpu::{asm::barrier, registers::*};
 use tock_registers::interfaces::ReadWriteable;
 use crate::{
-    arch::context::TrapContext, 
-    board::{inner::GIC, timer::set_trigger}, 
-    process::{app::SignalCode, back_to_idle, cow, exit, save_trap_ctx, signal_handler}, 
+    arch::{context::TrapContext, disable_irq, enable_irq}, 
+    board::{inner::{interrupt::{IPI_SGI, TIMER_IRQ}, GIC}, timer::set_trigger}, 
+    mm::area::Access, 
+    process::{app::SignalCode, exit_by_signal, page_fault, stop, save_trap_ctx, signal_handler, tick, trap_enter, trap_exit}, 
     syscall::syscall
 };
 use core::arch::{asm, global_asm};
@@ -20,23 +21,24 @@ pub fn init() {
     barrier::isb(barrier::SY);
 }
 
+// data abort 的 ISS 中 WnR 位为 1 表示写
+fn data_access(esr: usize) -> Access {
+    if esr & (1 << 6) != 0 {
+        Access::Write
+    } else {
+        Access::Read
+    }
+}
+
 #[no_mangle]
 pub fn current_elx_synchronous(ctx: &mut TrapContext) -> &mut TrapContext {
     let mut esr: usize;
     unsafe { asm!("mrs {0}, ESR_EL1", out(reg) esr); }
     let ec: usize = (esr >> 26) & 0x3F;
     match ec {
-        0x24 => {
-            // access failed
-            let addr = FAR_EL1.get() as usize;
-            match cow(addr) {
-                Ok(_) => {
-                    back_to_idle();
-                }
-                Err(e) => {
-                    panic!("[kernel] copy on write failed: {}, kernel killed it.", e);
-                }
-            }
+        0x24 | 0x25 => {
+            // 内核访问用户地址时的页错误，返回后继续执行系统调用
+            page_fault(ctx, FAR_EL1.get() as usize, data_access(esr), false);
         }
         _ => {
             panic!("current elx sync unsupported ec value: {}", ec);
@@ -45,9 +47,11 @@ pub fn current_elx_synchronous(ctx: &mut TrapContext) -> &mut TrapContext {
     ctx
 }
 
+// 系统调用中被中断打断，持有锁时中断是关闭的，所以这里可以通过 tick 切换任务
 #[no_mangle]
-pub fn current_elx_irq(_ctx: &mut TrapContext) {
-    panic!("current elx irq");
+pub fn current_elx_irq(ctx: &mut TrapContext) -> &mut TrapContext {
+    irq_dispatch(false);
+    ctx
 }
 
 #[no_mangle]
@@ -57,47 +61,57 @@ pub fn current_elx_serror(_ctx: &mut TrapContext) {
 
 #[no_mangle]
 pub fn lower_aarch64_synchronous(ctx: &mut TrapContext) -> &mut TrapContext {
+    trap_enter();
     let mut esr: usize;
     unsafe { asm!("mrs {0}, ESR_EL1", out(reg) esr); }
     let ec: usize = (esr >> 26) & 0x3F;
     match ec {
         0x15 => {
-            ctx.x[0] = syscall(ctx.x[8], [ctx.x[0], ctx.x[1], ctx.x[2], ctx.x[3]]) as usize
+            // 系统调用中打开中断，没有持有锁时可以被时钟中断抢占
+            enable_irq();
+            let ret = syscall(ctx.x[8], [ctx.x[0], ctx.x[1], ctx.x[2], ctx.x[3], ctx.x[4], ctx.x[5]]);
+            disable_irq();
+            ctx.x[0] = ret as usize;
+        }
+        0x20 => {
+            page_fault(ctx, FAR_EL1.get() as usize, Access::Execute, true);
         }
         0x24 => {
-            // access failed
-            let addr = FAR_EL1.get() as usize;
-            match cow(addr) {
-                Ok(_) => {
-                    back_to_idle();
-                }
-                Err(e) => {
-                    panic!("[kernel] copy on write failed: {}, kernel killed it.", e);
-                }
-            }
+            page_fault(ctx, FAR_EL1.get() as usize, data_access(esr), true);
         }
         _ => {
             panic!("unsupported ec value: {}", ec);
         }
     }
     signal_hook(ctx);
+    trap_exit();
     ctx
 }
 
 #[no_mangle]
 pub fn lower_aarch64_irq(ctx: &mut TrapContext) -> &mut TrapContext {
-    let irq_num = GIC.exclusive_access().claim();
+    trap_enter();
+    irq_dispatch(true);
+    signal_hook(ctx);
+    trap_exit();
+    ctx
+}
+
+fn irq_dispatch(from_user: bool) {
+    let (irq_num, iar) = GIC.lock().claim();
     match irq_num {
-        30 => {
+        TIMER_IRQ => {
             set_trigger();
-            GIC.exclusive_access().complete(irq_num);
-            back_to_idle();
+            GIC.lock().complete(iar);
+            tick(from_user);
+        },
+        // 核间中断只是用来唤醒 wfi，任务运行时收到直接忽略
+        IPI_SGI => {
+            GIC.lock().complete(iar);
         },
         1020.. => {},
         _ => {panic!("irq {} not supported now", irq_num);},
     }
-    signal_hook(ctx);
-    return ctx;
 }
 
 #[no_mangle]
@@ -114,8 +128,11 @@ pub fn signal_hook(ctx: &mut TrapContext) {
             ctx.x[33] = handler.handler;
             ctx.x[0] = handler.sig;
         }
-        SignalCode::KILL(e) => {
-            exit(e)
+        SignalCode::KILL(signal) => {
+            exit_by_signal(signal)
+        }
+        SignalCode::STOP(signal) => {
+            stop(signal)
         }
     }
 }
\ No newline at end of file
@@ -10,6 +10,32 @@ impl TrapContext {
     pub fn new(entry: usize, sp: usize) -> Self {
         Self { x: context::create_ctx(entry, sp) }
     }
+
+    // 用户栈指针
+    pub fn set_sp(&mut self, sp: usize) {
+        self.x[context::SP_REG] = sp;
+    }
+
+    // 异常返回后执行的地址
+    pub fn pc(&self) -> usize {
+        self.x[context::PC_REG]
+    }
+
+    pub fn set_pc(&mut self, pc: usize) {
+        self.x[context::PC_REG] = pc;
+    }
+
+    // 系统调用返回值
+    pub fn set_ret(&mut self, ret: usize) {
+        self.x[context::RET_REG] = ret;
+    }
+
+    // 用户线程局部存储寄存器，只有 riscv 的 tp 保存在 TrapContext 中
+    pub fn set_tls(&mut self, tls: usize) {
+        if let Some(reg) = context::TLS_REG {
+            self.x[reg] = tls;
+        }
+    }
 }
 
 extern "C" {
@@ -36,15 +62,22 @@ pub struct SwitchContext {
     ra: usize,
     sp: usize,
     s: [usize; 12],
+    // 用户线程局部存储寄存器 TPIDR_EL0，内核不使用这个寄存器，所以只需要在切换任务时保存
+    // riscv 的 tp 在内核中保存 hartid，用户的 tp 保存在 TrapContext 中，不使用这个字段
+    tls: usize,
 }
 
 impl SwitchContext {
     pub fn new(ra: usize, sp: usize) -> Self {
-        Self { ra, sp, s: [0; 12] }
+        Self { ra, sp, s: [0; 12], tls: 0 }
     }
 
     pub fn bare() -> Self {
-        Self { ra: 0, sp: 0, s: [0; 12] }
+        Self { ra: 0, sp: 0, s: [0; 12], tls: 0 }
+    }
+
+    pub fn set_tls(&mut self, tls: usize) {
+        self.tls = tls;
     }
 
     pub fn new_with_restore_addr(sp: usize) -> Self {
@@ -62,4 +95,10 @@ impl SwitchContext {
 extern "C" {
     // 由于每个 CPU 寄存器存在差异，因此每个 arch 都需要使用汇编实现该函数
     pub fn __switch(current_app_ctx: *mut SwitchContext, next_app_ctx: *mut SwitchContext);
-}
\ No newline at end of file
+}
+
+// 读取当前的线程局部存储寄存器，它的值在切换任务时才会保存到 SwitchContext 中
+// riscv 返回 0，用户的 tp 已经在 TrapContext 中
+pub fn read_tls() -> usize {
+    context::read_tls()
+}
@@ -1,37 +1,44 @@
 use alloc::{string::String, vec::Vec};
 
-pub unsafe fn copy_with_user(to: *mut u8, from: *const u8, n: usize) {
+// 访问用户地址失败时不结束进程，复制返回失败，系统调用返回 -1
+// 内核访问用户地址的页错误无法处理时，trap 处理函数通过 copy_fixup 让复制从出错的地方返回
+pub unsafe fn copy_with_user(to: *mut u8, from: *const u8, n: usize) -> bool {
     crate::arch::inner::memory::copy::copy_with_user(to, from, n)
 }
 
-pub fn copy_usize_with_user(src: usize, dst: *mut usize) {
-    crate::arch::inner::memory::copy::copy_usize_with_user(src, dst)
+pub fn copy_fixup(pc: usize) -> Option<usize> {
+    crate::arch::inner::memory::copy::copy_fixup(pc)
 }
 
-pub fn copy_str_with_user(src: *const i8) -> String {
-    crate::arch::inner::memory::copy::copy_str_with_user(src)
+pub fn copy_usize_with_user(src: usize, dst: *mut usize) -> bool {
+    unsafe { copy_with_user(dst as *mut u8, &src as *const usize as *const u8, core::mem::size_of::<usize>()) }
 }
 
-pub fn copy_from_user_into_vector(from: *const u8, n: usize) -> Vec<u8> {
-    let mut vec = Vec::new();
-    vec.resize(n, 0);
-    unsafe { copy_with_user(vec.as_mut_ptr(), from, n) }
-    vec
-}
-
-pub fn copy_vector_to_user(v: Vec<u8>, dst: *mut u8) -> usize {
-    unsafe { copy_with_user(dst, v.as_ptr(), v.len()) }
-    v.len()
+// 一次复制一个字节直到 '\0'，不是 utf-8 或者访问无效地址时返回 None
+pub fn copy_str_with_user(src: *const i8) -> Option<String> {
+    let mut bytes = Vec::new();
+    loop {
+        let mut c = 0u8;
+        if !unsafe { copy_with_user(&mut c, (src as *const u8).wrapping_add(bytes.len()), 1) } {
+            return None;
+        }
+        if c == 0 {
+            return String::from_utf8(bytes).ok();
+        }
+        bytes.push(c);
+    }
 }
 
-pub fn copy_user_page_to_vector(vpn: super::page::VirtPage) -> Vec<u8> {
-    let src = vpn.bytes_array().as_ptr();
-    copy_from_user_into_vector(src, 4096)
+// 内核堆不够时也返回 None
+pub fn copy_from_user_into_vector(from: *const u8, n: usize) -> Option<Vec<u8>> {
+    let mut vec = Vec::new();
+    vec.try_reserve_exact(n).ok()?;
+    vec.resize(n, 0);
+    unsafe { copy_with_user(vec.as_mut_ptr(), from, n) }.then_some(vec)
 }
 
-pub fn copy_vector_to_user_page(v: Vec<u8>, vpn: super::page::VirtPage) {
-    let dst = vpn.bytes_array().as_mut_ptr();
-    copy_vector_to_user(v, dst);
+pub fn copy_vector_to_user(v: Vec<u8>, dst: *mut u8) -> Option<usize> {
+    unsafe { copy_with_user(dst, v.as_ptr(), v.len()) }.then_some(v.len())
 }
 
 pub fn enable_user_access() {
@@ -6,6 +6,14 @@ const PN_LEVEL_NUM: usize = page::PN_LEVEL_NUM;
 const PN_BITSIZE: usize = page::PN_BITSIZE;
 pub const INPAGE_OFFSET_WIDTH: usize = page::INPAGE_OFFSET_WIDTH;
 pub const PAGE_SIZE: usize = page::PAGE_SIZE;
+// 2M 大页所在的页表级别（根页表是 0 级）和包含的页数，0 级的大页是 1G
+pub const HUGE_LEVEL: usize = PN_LEVEL_NUM - 2;
+pub const HUGE_PAGES: usize = 1 << PN_BITSIZE;
+
+// level 级页表中一个页表项映射的页数
+pub fn level_pages(level: usize) -> usize {
+    1 << (PN_BITSIZE * (PN_LEVEL_NUM - 1 - level))
+}
 
 #[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug)]
 pub struct PhysAddr(pub usize);
@@ -216,6 +224,16 @@ impl PageTableEntry {
         Self(page::pte(ppn.0, flags))
     }
 
+    // 直接映射 level_pages(level) 个页的大页页表项，只能放在非最后一级的页表中
+    pub fn new_block(ppn: PhysPage, flags: PTEFlags) -> Self {
+        Self(page::block_pte(ppn.0, flags))
+    }
+
+    // 非最后一级的页表项映射的是大页，而不是下一级页表
+    pub fn is_block(&self) -> bool {
+        page::is_block(self.0)
+    }
+
     pub fn ppn(&self) -> PhysPage {
         (page::ppn(self.0)).into()
     }
@@ -243,6 +261,28 @@ impl PageTableEntry {
     pub fn set_flag(&mut self, bit: PTEFlags) {
         self.0 = page::set_flag(self.0, bit)
     }
+
+    // 访问位由硬件（aarch64 上由页错误处理）设置，换出时用来近似 LRU
+    pub fn is_accessed(&self) -> bool {
+        page::is_accessed(self.0)
+    }
+
+    pub fn set_accessed(&mut self) {
+        self.0 = page::set_accessed(self.0)
+    }
+
+    pub fn clear_accessed(&mut self) {
+        self.0 = page::clear_accessed(self.0)
+    }
+
+    // 换出的页在无效的页表项中记录交换槽位
+    pub fn new_swap(slot: usize) -> Self {
+        Self(page::swap_pte(slot))
+    }
+
+    pub fn swap_slot(&self) -> Option<usize> {
+        page::swap_slot(self.0)
+    }
 }
 
 pub fn root_ppn() -> usize {
@@ -259,6 +299,23 @@ pub fn flush_tlb(asid: usize) {
     }
 }
 
+pub fn flush_tlb_all() {
+    unsafe {
+        crate::arch::inner::memory::page::flush_tlb_all();
+    }
+}
+
+// 切换到 trampoline 中建立的内核页表，回到 idle 后使用，避免继续使用可能被其他 cpu 释放的进程页表
+pub fn enable_kernel_va() {
+    extern "C" {
+        fn skpt();
+    }
+    // skpt 链接在物理地址上，超出了 pc 相对寻址的范围，通过变量得到它的地址
+    static KERNEL_PT: unsafe extern "C" fn() = skpt;
+    let root = unsafe { core::ptr::read_volatile(&KERNEL_PT) } as usize;
+    enable_va(0, root >> INPAGE_OFFSET_WIDTH)
+}
+
 pub fn kernel_phys_to_virt(phys: PhysAddr) -> VirtAddr {
     (0xFFFF_FFFF_0000_0000usize + phys.0).into()
 }
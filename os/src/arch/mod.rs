@@ -12,3 +12,38 @@ mod inner;
 pub fn init() {
     inner::trap::init();
 }
+
+// 当前 cpu 的编号
+pub fn cpu_id() -> usize {
+    inner::smp::cpu_id()
+}
+
+// 等待中断，中断关闭时有中断到来也会返回
+pub fn wait_for_interrupt() {
+    inner::smp::wait_for_interrupt()
+}
+
+// 关闭当前 cpu 的中断，返回之前中断是否打开，和 irq_restore 配对使用
+pub fn irq_save() -> bool {
+    inner::smp::irq_save()
+}
+
+pub fn irq_restore(enabled: bool) {
+    if enabled {
+        inner::smp::enable_irq();
+    }
+}
+
+// 打开当前 cpu 的中断，只在系统调用中和没有持有锁时使用
+pub fn enable_irq() {
+    inner::smp::enable_irq()
+}
+
+pub fn disable_irq() {
+    inner::smp::disable_irq()
+}
+
+// 启动编号为 cpu 的处理器，失败时返回负数
+pub fn start_cpu(cpu: usize) -> isize {
+    inner::smp::start_cpu(cpu)
+}
@@ -1,6 +1,13 @@
 use crate::utils::bits::clear_bit;
 use core::arch::asm;
 
+// TrapContext 中 sp、a0 和 tp 的位置
+pub const SP_REG: usize = 2;
+pub const RET_REG: usize = 10;
+// sepc
+pub const PC_REG: usize = 33;
+pub const TLS_REG: Option<usize> = Some(4);
+
 pub fn create_ctx(entry: usize, sp: usize) -> [usize; 34] {
     let mut ctx: [usize; 34] = [0; 34];
     unsafe {
@@ -17,4 +24,9 @@ pub fn create_ctx(entry: usize, sp: usize) -> [usize; 34] {
         ctx[33] = entry;
         ctx
     }
-}
\ No newline at end of file
+}
+
+// 内核中 tp 保存的是 hartid，用户的 tp 保存在 TrapContext 中，不需要在 __switch 中保存
+pub fn read_tls() -> usize {
+    0
+}
@@ -1,55 +1,45 @@
-use core::arch::asm;
-use core::ffi::CStr;
-
-use alloc::string::{String, ToString};
-
-#[inline]
-pub unsafe fn copy_with_user(to: *mut u8, from: *const u8, n: usize) {
-    // TODO: access check
+use core::arch::global_asm;
+
+// 逐字节复制，返回没有复制的字节数
+// 访问用户地址的页错误无法处理时，trap_handler 把 sepc 改为 __copy_user_fixup，返回剩下的字节数
+global_asm!(
+    ".section .text",
+    ".globl __copy_user",
+    ".globl __copy_user_end",
+    ".globl __copy_user_fixup",
+    "__copy_user:",
+    "    sfence.vma",
+    "    beqz a2, __copy_user_fixup",
+    "1:  lb t0, 0(a1)",
+    "    sb t0, 0(a0)",
+    "    addi a1, a1, 1",
+    "    addi a0, a0, 1",
+    "    addi a2, a2, -1",
+    "    bnez a2, 1b",
+    "__copy_user_fixup:",
+    "    mv a0, a2",
+    "    ret",
+    "__copy_user_end:",
+);
+
+extern "C" {
+    fn __copy_user(to: *mut u8, from: *const u8, n: usize) -> usize;
+    fn __copy_user_end();
+    fn __copy_user_fixup();
+}
 
-    let mut remaining = n;
-    let mut src = from;
-    let mut dst = to;
+// pc 在 __copy_user 中时返回出错后继续执行的地址
+pub fn copy_fixup(pc: usize) -> Option<usize> {
+    (__copy_user as usize <= pc && pc < __copy_user_end as usize).then_some(__copy_user_fixup as usize)
+}
 
+// 访问无效的用户地址时返回 false
+pub unsafe fn copy_with_user(to: *mut u8, from: *const u8, n: usize) -> bool {
     // enable SUM flag
     riscv::register::sstatus::set_sum();
-
-    while remaining > 0 {
-        let _byte: u8;
-        asm!(
-            "sfence.vma",
-            "1: lb {byte}, 0({src})",
-            "sb {byte}, 0({dst})",
-            "addi {src}, {src}, 1",
-            "addi {dst}, {dst}, 1",
-            "addi {remaining}, {remaining}, -1",
-            "bnez {remaining}, 1b",
-            byte = out(reg) _byte,
-            src = inout(reg) src,
-            dst = inout(reg) dst,
-            remaining = inout(reg) remaining,
-            options(nostack, preserves_flags)
-        );
-    }
-
+    let remaining = __copy_user(to, from, n);
     riscv::register::sstatus::clear_sum();
-}
-
-pub fn copy_usize_with_user(src: usize, dst: *mut usize) {
-    unsafe {
-        riscv::register::sstatus::set_sum();
-        *dst = src;
-        riscv::register::sstatus::clear_sum();
-    }
-}
-
-pub fn copy_str_with_user(src: *const i8) -> String {
-    unsafe {
-        riscv::register::sstatus::set_sum();
-        let str = CStr::from_ptr(src).to_str().unwrap().to_string().clone();
-        riscv::register::sstatus::clear_sum();
-        str
-    }
+    remaining == 0
 }
 
 pub fn enable_user_access() {
@@ -57,6 +57,17 @@ pub fn pte(ppn: usize, flags: PTEFlags) -> usize {
     ppn << 10 | riscv_flags.bits() as usize
 }
 
+// Sv39 的大页和普通页的页表项格式相同，只是放在更高一级的页表中，ppn 需要按大页对齐
+pub fn block_pte(ppn: usize, flags: PTEFlags) -> usize {
+    pte(ppn, flags)
+}
+
+// R、W、X 中有一个不为 0 的页表项是叶子，在非最后一级的页表中就是大页
+pub fn is_block(pte: usize) -> bool {
+    let leaf = (RiscvPteFlags::R | RiscvPteFlags::W | RiscvPteFlags::X).bits() as usize;
+    pte & RiscvPteFlags::V.bits() as usize != 0 && pte & leaf != 0
+}
+
 pub fn ppn(pte: usize) -> usize {
     pte >> 10
 }
@@ -156,6 +167,10 @@ pub fn set_flag(pte: usize, flags: PTEFlags) -> usize {
 pub fn clear_flag(pte: usize, flags: PTEFlags) -> usize {
     let mut p = pte;
     if let Some(mut riscv_flags) = RiscvPteFlags::from_bits(p as u8) {
+        if flags.contains(PTEFlags::V) {
+            riscv_flags.remove(RiscvPteFlags::V);
+        }
+
         if flags.contains(PTEFlags::U) {
             riscv_flags.remove(RiscvPteFlags::U);
         } 
@@ -179,6 +194,41 @@ pub fn clear_flag(pte: usize, flags: PTEFlags) -> usize {
     p
 }
 
-pub unsafe fn flush_tlb(_asid: usize) {
+pub fn is_accessed(pte: usize) -> bool {
+    pte & RiscvPteFlags::A.bits() as usize != 0
+}
+
+pub fn set_accessed(pte: usize) -> usize {
+    pte | RiscvPteFlags::A.bits() as usize
+}
+
+pub fn clear_accessed(pte: usize) -> usize {
+    pte & !(RiscvPteFlags::A.bits() as usize)
+}
+
+// V 为 0 时硬件忽略其余的位，用 RSW 的第一位标记换出的页，交换槽位放在 ppn 的位置
+const SWAP_BIT: usize = 1 << 8;
+
+pub fn swap_pte(slot: usize) -> usize {
+    slot << 10 | SWAP_BIT
+}
+
+pub fn swap_slot(pte: usize) -> Option<usize> {
+    if pte & (RiscvPteFlags::V.bits() as usize | SWAP_BIT) == SWAP_BIT {
+        Some(pte >> 10)
+    } else {
+        None
+    }
+}
+
+pub unsafe fn flush_tlb(asid: usize) {
     asm!("sfence.vma");
-}
\ No newline at end of file
+    // 其他 hart 上可能运行着共享这个地址空间的线程，通过 SBI 让它们也刷新 tlb
+    sbi_rt::remote_sfence_vma_asid(sbi_rt::HartMask::from_mask_base(0, usize::MAX), 0, usize::MAX, asid);
+}
+
+// 刷新所有 hart 上所有地址空间的 tlb，换出其他进程的页之后使用
+pub unsafe fn flush_tlb_all() {
+    asm!("sfence.vma");
+    sbi_rt::remote_sfence_vma(sbi_rt::HartMask::from_mask_base(0, usize::MAX), 0, usize::MAX);
+}
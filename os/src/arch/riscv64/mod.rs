@@ -2,6 +2,7 @@ pub mod context;
 pub mod trap;
 pub mod memory;
 pub mod trampoline;
+pub mod smp;
 
 use core::arch::global_asm;
 
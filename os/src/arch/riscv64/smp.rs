@@ -0,0 +1,45 @@
+use core::arch::asm;
+
+extern "C" {
+    fn __secondary_trampoline();
+}
+
+// trampoline 链接在物理地址上，超出了 pc 相对寻址的范围，通过变量得到它的地址
+static SECONDARY_ENTRY: unsafe extern "C" fn() = __secondary_trampoline;
+
+// 内核中 tp 保存当前 hart 的编号，用户的 tp 在 trap 时保存到 TrapContext 中
+pub fn cpu_id() -> usize {
+    let id: usize;
+    unsafe { asm!("mv {}, tp", out(reg) id); }
+    id
+}
+
+pub fn wait_for_interrupt() {
+    unsafe { riscv::asm::wfi(); }
+}
+
+// 关闭当前 hart 的中断，返回之前是否打开
+pub fn irq_save() -> bool {
+    let enabled = riscv::register::sstatus::read().sie();
+    disable_irq();
+    enabled
+}
+
+pub fn enable_irq() {
+    unsafe { riscv::register::sstatus::set_sie(); }
+}
+
+pub fn disable_irq() {
+    unsafe { riscv::register::sstatus::clear_sie(); }
+}
+
+// 通过 SBI HSM 扩展启动其他 hart，它会从 __secondary_trampoline 开始执行
+pub fn start_cpu(cpu: usize) -> isize {
+    let entry = unsafe { core::ptr::read_volatile(&SECONDARY_ENTRY) } as usize;
+    let ret = sbi_rt::hart_start(cpu, entry, 0);
+    if ret.error == 0 {
+        0
+    } else {
+        -1
+    }
+}
@@ -6,6 +6,9 @@ use core::arch::asm;
 pub extern "C" fn __trampoline() {
     unsafe {
         asm!(
+            // a0 为 sbi 传入的 hartid，内核中 tp 保存 hartid
+            "mv tp, a0",
+
             // Set Identical map for trampoline
             "la a0, skpt",
             "la a1, strampoline",
@@ -62,13 +65,47 @@ pub extern "C" fn __trampoline() {
             "or a0, a0, t0",
             "csrw satp, a0",
             "sfence.vma",
+            // 每个 hart 使用自己的启动栈
             "lui t0, %hi(sstack)",
             "addi t0, t0, %lo(sstack)",
-            "mv sp, t0",
+            "lui t1, %hi(_stack_size)",
+            "addi t1, t1, %lo(_stack_size)",
+            "mul t1, t1, tp",
+            "sub sp, t0, t1",
             "lui t0, %hi(os_main)",
             "addi t0, t0, %lo(os_main)",
             "jalr t1, t0, 0",
             options(noreturn)
         )
     }
-}
\ No newline at end of file
+}
+
+// 其他 hart 的入口，内核页表已经由 __trampoline 建立好了，只需要使能虚地址模式
+#[no_mangle]
+#[naked]
+#[link_section = ".trampoline.secondary"]
+pub extern "C" fn __secondary_trampoline() {
+    unsafe {
+        asm!(
+            "mv tp, a0",
+
+            // enable virtual addr
+            "la a0, skpt",
+            "srli a0, a0, 12",
+            "li t0, 0x8000000000000000",
+            "or a0, a0, t0",
+            "csrw satp, a0",
+            "sfence.vma",
+            "lui t0, %hi(sstack)",
+            "addi t0, t0, %lo(sstack)",
+            "lui t1, %hi(_stack_size)",
+            "addi t1, t1, %lo(_stack_size)",
+            "mul t1, t1, tp",
+            "sub sp, t0, t1",
+            "lui t0, %hi(os_secondary_main)",
+            "addi t0, t0, %lo(os_secondary_main)",
+            "jalr t1, t0, 0",
+            options(noreturn)
+        )
+    }
+}
@@ -1,3 +1,5 @@
+use core::arch::asm;
+
 use riscv::register::{
     scause::{self, Exception, Trap, Interrupt}, 
     stval, sie,
@@ -5,72 +7,75 @@ use riscv::register::{
 };
 
 use crate::{
-    arch::context::TrapContext, board::timer::set_trigger, ipc::signal::{SIGILL, SIGSEGV}, println, process::{
+    arch::{context::TrapContext, disable_irq, enable_irq}, board::timer::set_trigger, ipc::signal::SIGILL, mm::area::Access, println, process::{
         app::SignalCode, 
-        back_to_idle, 
-        cow, exit, 
+        exit_by_signal, page_fault, stop, 
         save_trap_ctx, 
         set_signal, 
-        signal_handler
+        signal_handler,
+        tick, trap_enter, trap_exit
     }, syscall::syscall
 };
 
 pub fn init() {
     extern "C" {
-        fn __alltraps();
+        fn __trap_vector();
     }
 
-    // TODO: set stvec with vector mod
+    // vectored 模式下异常进入 __alltraps，中断进入 __allirqs，内核态也可以处理中断
     // riscv 的中断处罚等级需要设置 clint 外设，并使能 sie 寄存器中 STIE 位
     // 当然也可以通过 machine level 转发，目前似乎使用的是这种，rustsbi 转发
     unsafe {
-        stvec::write(__alltraps as usize, TrapMode::Direct);
+        // sscratch 为 0 表示在内核态，参考 trap.S
+        asm!("csrw sscratch, zero");
+        stvec::write(__trap_vector as usize, TrapMode::Vectored);
         sie::set_stimer();
+        // 核间中断
+        sie::set_ssoft();
     }
 }
 
+// sstatus 的 SPP 位为 0 表示来自用户态
+fn from_user(ctx: &TrapContext) -> bool {
+    ctx.x[32] & (1 << 8) == 0
+}
+
+// 异常，内核访问用户地址时的页错误也会进入这里
 #[no_mangle]
 pub fn trap_handler(ctx: &mut TrapContext) -> &mut TrapContext {
     let scause = scause::read();
     let stval = stval::read();
+    let from_user = from_user(ctx);
+    if from_user {
+        trap_enter();
+    }
     match scause.cause() {
-        // TODO: 如果在调用 syscall 的时候发生任务切换，会形成 trap 嵌套，这种情况控制流是如何走，需要研究下
-        // 理论上切换任务回来时会走到 back_to_idle 的下一句话。然后回会出 trap handler，接着走到 restore，后面需要详细分析下
-        // 经过分析确实无法回到 syscall，所以目前的设计是不允许中断嵌套的，也就是说内核不会被 S 特权级终端打断
-        // 是否被打断是由 sie 位控制的。中断嵌套功能后续实现，想到的方法应该是使用中断向量表，区分 trap 和 interrupt
         Trap::Exception(Exception::UserEnvCall) => {
             ctx.x[33] += 4;
-            ctx.x[10] = syscall(ctx.x[17], [ctx.x[10], ctx.x[11], ctx.x[12], ctx.x[13]]) as usize;
-        }
-        Trap::Interrupt(Interrupt::SupervisorTimer) => {
-            set_trigger();
-            back_to_idle();
+            // 系统调用中打开中断，没有持有锁时可以被时钟中断抢占，切换回来后从被打断的地方继续
+            enable_irq();
+            let ret = syscall(ctx.x[17], [ctx.x[10], ctx.x[11], ctx.x[12], ctx.x[13], ctx.x[14], ctx.x[15]]);
+            disable_irq();
+            ctx.x[10] = ret as usize;
         }
+        // 内核中的页错误返回后继续访问用户地址
         Trap::Exception(Exception::StoreFault)
-        | Trap::Exception(Exception::StorePageFault)
-        | Trap::Exception(Exception::LoadFault)
+        | Trap::Exception(Exception::StorePageFault) => {
+            page_fault(ctx, stval, Access::Write, from_user);
+        }
+        Trap::Exception(Exception::LoadFault)
         | Trap::Exception(Exception::LoadPageFault) => {
-            let r = cow(stval);
-            match r {
-                Ok(_) => {
-                    back_to_idle();
-                }
-                Err(e) => {
-                    println!("[kernel] copy on write failed: {}, kernel killed it.", e);
-                    set_signal(None, SIGSEGV);
-                }
-            }
+            page_fault(ctx, stval, Access::Read, from_user);
+        }
+        Trap::Exception(Exception::InstructionPageFault) => {
+            page_fault(ctx, stval, Access::Execute, from_user);
         }
         Trap::Exception(Exception::IllegalInstruction)
         | Trap::Exception(Exception::InstructionFault)
-        | Trap::Exception(Exception::InstructionPageFault)
         | Trap::Exception(Exception::InstructionMisaligned) => {
             println!("[kernel] IllegalInstruction in application, kernel killed it.");
             set_signal(None, SIGILL);
         }
-        Trap::Interrupt(Interrupt::SupervisorExternal) => {
-            crate::board::external_irq_handler();
-        }
         _ => {
             panic!(
                 "Unsupported trap {:?}, stval = {:#x}",
@@ -80,6 +85,48 @@ pub fn trap_handler(ctx: &mut TrapContext) -> &mut TrapContext {
         }
     }
 
+    if from_user {
+        signal_hook(ctx);
+        trap_exit();
+    }
+    ctx
+}
+
+// 中断，来自内核态时打断的是系统调用，返回后继续执行系统调用
+// 持有锁时中断是关闭的，所以这里可以获取锁，也可以通过 tick 切换任务
+#[no_mangle]
+pub fn irq_handler(ctx: &mut TrapContext) -> &mut TrapContext {
+    let scause = scause::read();
+    let from_user = from_user(ctx);
+    if from_user {
+        trap_enter();
+    }
+    match scause.cause() {
+        Trap::Interrupt(Interrupt::SupervisorTimer) => {
+            set_trigger();
+            tick(from_user);
+        }
+        Trap::Interrupt(Interrupt::SupervisorExternal) => {
+            crate::board::external_irq_handler();
+        }
+        // 核间中断只是用来唤醒 wfi，任务运行时收到直接清除
+        Trap::Interrupt(Interrupt::SupervisorSoft) => {
+            crate::board::inner::interrupt::clear_ipi();
+        }
+        _ => {
+            panic!("Unsupported interrupt {:?}", scause.cause())
+        }
+    }
+
+    // 信号只在返回用户态时处理
+    if from_user {
+        signal_hook(ctx);
+        trap_exit();
+    }
+    ctx
+}
+
+fn signal_hook(ctx: &mut TrapContext) {
     let signal_code = signal_handler();
     match signal_code {
         SignalCode::IGNORE => {
@@ -91,10 +138,11 @@ pub fn trap_handler(ctx: &mut TrapContext) -> &mut TrapContext {
             ctx.x[33] = handler.handler;
             ctx.x[10] = handler.sig;
         }
-        SignalCode::KILL(e) => {
-            exit(e)
+        SignalCode::KILL(signal) => {
+            exit_by_signal(signal)
+        }
+        SignalCode::STOP(signal) => {
+            stop(signal)
         }
     }
-
-    ctx
-}
\ No newline at end of file
+}
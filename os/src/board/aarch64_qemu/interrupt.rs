@@ -1,3 +1,25 @@
 pub const GIC_ADDR: usize = 0x800_0000;
 
-pub fn external_irq_handler() {}
\ No newline at end of file
+// 物理定时器的 PPI
+pub const TIMER_IRQ: usize = 30;
+// 用作核间中断的 SGI
+pub const IPI_SGI: usize = 0;
+
+pub fn external_irq_handler() {}
+
+pub fn send_ipi(cpu: usize) {
+    super::GIC.lock().send_sgi(cpu, IPI_SGI);
+}
+
+// 取出所有等待中的中断，时钟中断先关闭，idle 会重新设置
+pub fn idle_irq_handler() {
+    loop {
+        let (irq_num, iar) = super::GIC.lock().claim();
+        match irq_num {
+            1020.. => break,
+            TIMER_IRQ => super::timer::disable_trigger(),
+            _ => {}
+        }
+        super::GIC.lock().complete(iar);
+    }
+}
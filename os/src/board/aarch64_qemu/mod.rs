@@ -5,26 +5,29 @@ pub mod peripheral;
 
 use alloc::sync::Arc;
 use lazy_static::*;
-use spin::mutex::Mutex;
+use crate::sync::Mutex;
 
 use crate::{
     arch::memory::page::kernel_phys_to_virt, 
     driver::{self, block::{qemu_blk::QemuBlk, BlkDeviceForFs}}, 
-    file::fs::FILESYSTEM, utils::type_extern::RefCellWrap
+    file::fs::FILESYSTEM,
+    mm::swap
 };
 
+// cpu 的数量，需要和 linker.ld 中的 _cpu_num 一致
+pub const CPU_NUM: usize = 4;
+
 lazy_static! {
-    pub static ref CONSOLE: RefCellWrap<arm_pl011::Pl011Uart> = unsafe {
-        RefCellWrap::new(peripheral::init_serial(
+    pub static ref CONSOLE: Mutex<arm_pl011::Pl011Uart> =
+        Mutex::new(peripheral::init_serial(
             kernel_phys_to_virt(peripheral::UART0_ADDR.into()).0
-        ))
-    };
+        ));
 
-    pub static ref GIC: RefCellWrap<driver::ic::gicv2::GICV2> = unsafe {
-        RefCellWrap::new(driver::ic::gicv2::GICV2::new(
+    // GICD 是共享的，GICC 和 PPI 的使能寄存器每个 cpu 有一份，地址相同
+    pub static ref GIC: Mutex<driver::ic::gicv2::GICV2> =
+        Mutex::new(driver::ic::gicv2::GICV2::new(
             kernel_phys_to_virt(interrupt::GIC_ADDR.into()).0
-        ))
-    };
+        ));
 }
 
 pub fn shutdown(_failure: bool) -> ! {
@@ -32,11 +35,11 @@ pub fn shutdown(_failure: bool) -> ! {
 }
 
 pub fn console_putchar(c: char) {
-    CONSOLE.exclusive_access().putchar(c as u8)
+    CONSOLE.lock().putchar(c as u8)
 }
 
 pub fn console_getchar() -> u8 {
-    if let Some(c) = CONSOLE.exclusive_access().getchar() {
+    if let Some(c) = CONSOLE.lock().getchar() {
         return c;
     } else {
         return 0;
@@ -44,17 +47,27 @@ pub fn console_getchar() -> u8 {
 }
 
 pub fn board_init() {
-    CONSOLE.exclusive_access().init();
-    CONSOLE.exclusive_access().ack_interrupts();
-    CONSOLE.exclusive_access().is_receive_interrupt();
+    CONSOLE.lock().init();
+    CONSOLE.lock().ack_interrupts();
+    CONSOLE.lock().is_receive_interrupt();
 
-    GIC.exclusive_access().enable(30);
-    GIC.exclusive_access().set_priority(255);
+    cpu_init();
 
     let blk_device = BlkDeviceForFs::new(
         Arc::new(Mutex::new(QemuBlk::new(
             kernel_phys_to_virt(peripheral::BLK_HEADER_ADDR.into()).0
         ))));
-    FILESYSTEM.exclusive_access().set_sfs(blk_device);
+    FILESYSTEM.lock().set_sfs(blk_device);
+    // 没有第二个磁盘时不使用交换分区
+    if let Some(swap_device) = QemuBlk::try_new(kernel_phys_to_virt(peripheral::SWAP_HEADER_ADDR.into()).0) {
+        swap::init(Arc::new(Mutex::new(swap_device)));
+    }
+}
+
+// 每个 cpu 启动时调用，使能自己的 timer 中断和核间中断
+pub fn cpu_init() {
+    GIC.lock().enable(interrupt::TIMER_IRQ);
+    GIC.lock().enable(interrupt::IPI_SGI);
+    GIC.lock().set_priority(255);
 }
 
@@ -8,3 +8,8 @@ pub fn init_serial(addr: usize) -> Pl011Uart {
 
 // blk0
 pub const BLK_HEADER_ADDR: usize = 0xA00_3E00;
+// blk1，作为交换分区
+pub const SWAP_HEADER_ADDR: usize = 0xA00_3C00;
+
+// pl031 rtc
+pub const RTC_ADDR: usize = 0x901_0000;
@@ -11,4 +11,22 @@ pub fn set_trigger(tick_per_sec: usize) {
     let new_tick = (cntpct_el0 + CLOCK_FREQ / tick_per_sec) as u64;
 
     CNTP_CVAL_EL0.set(new_tick);
-}
\ No newline at end of file
+}
+
+// 在 ns 时刻触发一次时钟中断，向上取整保证中断到来时 nanoseconds() 不小于 ns
+pub fn set_oneshot(ns: usize) {
+    CNTP_CVAL_EL0.set((ns as u128 * CLOCK_FREQ as u128).div_ceil(1_000_000_000) as u64);
+}
+
+// 不再触发时钟中断，同时清除等待中的时钟中断
+pub fn disable_trigger() {
+    CNTP_CVAL_EL0.set(u64::MAX);
+}
+
+// pl031 的 RTCDR 是 unix 时间的秒数
+pub fn rtc_nanoseconds() -> usize {
+    use crate::arch::memory::page::kernel_phys_to_virt;
+    let base = kernel_phys_to_virt(super::peripheral::RTC_ADDR.into()).0;
+    let seconds = unsafe { core::ptr::read_volatile(base as *const u32) } as usize;
+    seconds * 1_000_000_000
+}
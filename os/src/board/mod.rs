@@ -21,10 +21,27 @@ pub fn external_irq_handler() {
     inner::interrupt::external_irq_handler()
 }
 
+// 处理 idle 在 wfi 中等到的中断，idle 中中断是关闭的，需要主动清除
+pub fn idle_irq_handler() {
+    inner::interrupt::idle_irq_handler()
+}
+
+// 发送核间中断，唤醒在 wfi 中等待的 cpu
+pub fn send_ipi(cpu: usize) {
+    inner::interrupt::send_ipi(cpu)
+}
+
+pub const CPU_NUM: usize = inner::CPU_NUM;
+
 pub fn board_init() {
     inner::board_init()
 }
 
+// 其他 cpu 启动时的初始化
+pub fn cpu_init() {
+    inner::cpu_init()
+}
+
 pub fn shutdown(failure: bool) -> ! {
     inner::shutdown(failure)
 }
\ No newline at end of file
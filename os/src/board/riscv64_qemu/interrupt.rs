@@ -1,12 +1,16 @@
-use crate::{driver::ic::plic::*, println};
+use core::arch::asm;
+use riscv::register::sip;
+use crate::{arch::cpu_id, driver::ic::plic::*, println};
 
 pub const PLIC_ADDR: usize = 0xC00_0000;
 
+// 设置当前 hart 的 PLIC context，每个 hart 都需要调用
 pub fn plic_init() {
-    let plic = super::PLIC.exclusive_access();
-    plic.set_threshold(0, Level::Supervisor, 0);
-    plic.set_threshold(0, Level::Machine, 1);
-    plic.enable(0, Level::Supervisor, 10);
+    let hart = cpu_id();
+    let plic = super::PLIC.lock();
+    plic.set_threshold(hart, Level::Supervisor, 0);
+    plic.set_threshold(hart, Level::Machine, 1);
+    plic.enable(hart, Level::Supervisor, 10);
     plic.set_priority(10, 1);
 
     unsafe {
@@ -15,12 +19,33 @@ pub fn plic_init() {
 }
 
 pub fn external_irq_handler() {
-    let plic = super::PLIC.exclusive_access();
-    let irq_id = plic.claim(0, Level::Supervisor);
+    let hart = cpu_id();
+    let plic = super::PLIC.lock();
+    let irq_id = plic.claim(hart, Level::Supervisor);
     match irq_id {
+        // 中断已经被其他 hart 处理了
+        0 => {return;},
         10 => {println!("IRQ {}", irq_id);},
         _ => {println!("unsupported IRQ {}", irq_id);},
     }
 
-    plic.complete(0, Level::Supervisor, irq_id);
+    plic.complete(hart, Level::Supervisor, irq_id);
+}
+
+// 通过 SBI 发送软件中断作为核间中断
+pub fn send_ipi(cpu: usize) {
+    sbi_rt::send_ipi(sbi_rt::HartMask::from_mask_base(1, cpu));
+}
+
+// 清除 sip.SSIP，riscv crate 没有提供 sip 的写函数
+pub fn clear_ipi() {
+    unsafe { asm!("csrc sip, {}", in(reg) 1 << 1); }
+}
+
+// 时钟中断由 idle 重新设置时清除，这里只处理核间中断和外部中断
+pub fn idle_irq_handler() {
+    clear_ipi();
+    if sip::read().sext() {
+        external_irq_handler();
+    }
 }
\ No newline at end of file
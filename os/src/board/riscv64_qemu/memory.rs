@@ -3,14 +3,12 @@ pub const KERNEL_STACK_START: usize = 0x1_0000_0000;
 pub const KERNEL_STACK_SIZE: usize = 4096 * 16;
 
 // user stack - Framed
+// USER_STACK_SIZE 是 RLIMIT_STACK 的默认值，exec 时按照 RLIMIT_STACK 预留，最多 USER_STACK_MAX
+// 预留范围之下是一个保护页，用户栈在预留范围内按需向下增长
 pub const USER_STACK_START: usize = 0xFFF8_0000;
-pub const USER_STACK_SIZE: usize = 4096 * 2;
+pub const USER_STACK_SIZE: usize = 8 * 1024 * 1024;
+pub const USER_STACK_MAX: usize = 64 * 1024 * 1024;
 
-// memory allocator area - Physical memory page
-pub const KERNEL_ALLOCATOR_START: usize = 0x80400000;
-pub const ALLOCATOR_START: usize = 0x81000000;
-pub const ALLOCATOR_END: usize = 0x87000000;
-
-// dma area - Identical
+// dma area - Identical，物理内存的最后 16M 是 DMA 区域
 pub const DMA_START_ADDR: usize = 0x8700_0000;
 pub const DMA_END_ADDR: usize = 0x8800_0000;
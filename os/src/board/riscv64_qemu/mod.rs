@@ -7,23 +7,24 @@ use crate::{
     arch::memory::page::kernel_phys_to_virt, driver::{
         self, 
         block::{qemu_blk::QemuBlk, BlkDeviceForFs}, 
-    }, file::fs::FILESYSTEM, utils::type_extern::RefCellWrap
+    }, file::fs::FILESYSTEM, mm::swap
 };
 use alloc::sync::Arc;
 use lazy_static::*;
 use interrupt::PLIC_ADDR;
-use peripheral::{uart_init, UART0_ADDR, BLK_HEADER_ADDR};
-use spin::mutex::Mutex;
+use peripheral::{uart_init, UART0_ADDR, BLK_HEADER_ADDR, SWAP_HEADER_ADDR};
+use crate::sync::Mutex;
+
+// hart 的数量，需要和 linker.ld 中的 _cpu_num 一致
+pub const CPU_NUM: usize = 4;
 
 // 在这里创建一些驱动的单例
 lazy_static! {
-    pub static ref CONSOLE: RefCellWrap<ns16550a::Uart> = unsafe {
-        RefCellWrap::new(uart_init(kernel_phys_to_virt(UART0_ADDR.into()).0))
-    };
+    pub static ref CONSOLE: Mutex<ns16550a::Uart> =
+        Mutex::new(uart_init(kernel_phys_to_virt(UART0_ADDR.into()).0));
 
-    pub static ref PLIC: RefCellWrap<driver::ic::plic::PLIC> = unsafe {
-        RefCellWrap::new(driver::ic::plic::PLIC::new(kernel_phys_to_virt(PLIC_ADDR.into()).0))
-    };
+    pub static ref PLIC: Mutex<driver::ic::plic::PLIC> =
+        Mutex::new(driver::ic::plic::PLIC::new(kernel_phys_to_virt(PLIC_ADDR.into()).0));
 }
 
 pub fn board_init() {
@@ -31,7 +32,16 @@ pub fn board_init() {
         Arc::new(Mutex::new(
             QemuBlk::new(kernel_phys_to_virt(BLK_HEADER_ADDR.into()).0)
         )));
-    FILESYSTEM.exclusive_access().set_sfs(blk_dev);    
+    FILESYSTEM.lock().set_sfs(blk_dev);    
+    // 没有第二个磁盘时不使用交换分区
+    if let Some(swap_dev) = QemuBlk::try_new(kernel_phys_to_virt(SWAP_HEADER_ADDR.into()).0) {
+        swap::init(Arc::new(Mutex::new(swap_dev)));
+    }
+    interrupt::plic_init();
+}
+
+// 每个 hart 启动时调用
+pub fn cpu_init() {
     interrupt::plic_init();
 }
 
@@ -46,12 +56,12 @@ pub fn shutdown(failure: bool) -> ! {
 }
 
 pub fn console_putchar(c: char) {
-    CONSOLE.exclusive_access().put(c as u8);
+    CONSOLE.lock().put(c as u8);
 }
 
 #[allow(deprecated)]
 pub fn console_getchar() -> u8 {
-    if let Some(c) = CONSOLE.exclusive_access().get() {
+    if let Some(c) = CONSOLE.lock().get() {
         return c;
     } else {
         return 0;
@@ -17,3 +17,8 @@ pub fn uart_init(baseaddr: usize) -> ns16550a::Uart {
 }
 
 pub const BLK_HEADER_ADDR: usize = 0x1000_8000;
+// 第二个 virtio-blk 设备作为交换分区，qemu 从最高的 virtio-mmio 槽位开始分配
+pub const SWAP_HEADER_ADDR: usize = 0x1000_7000;
+
+// goldfish rtc
+pub const RTC_ADDR: usize = 0x10_1000;
@@ -11,4 +11,25 @@ pub fn nanoseconds() -> usize {
 
 pub fn set_trigger(ticks_per_sec: usize) {
     set_timer((time::read() + CLOCK_FREQ / ticks_per_sec) as u64)
-}
\ No newline at end of file
+}
+
+// 在 ns 时刻触发一次时钟中断，向上取整保证中断到来时 nanoseconds() 不小于 ns
+pub fn set_oneshot(ns: usize) {
+    set_timer((ns as u128 * CLOCK_FREQ as u128).div_ceil(1_000_000_000) as u64)
+}
+
+// 不再触发时钟中断，同时清除等待中的时钟中断
+pub fn disable_trigger() {
+    set_timer(u64::MAX)
+}
+
+// goldfish rtc 的 TIME_LOW 和 TIME_HIGH 是 unix 时间的 ns，先读低 32 位时会锁存高 32 位
+pub fn rtc_nanoseconds() -> usize {
+    use crate::arch::memory::page::kernel_phys_to_virt;
+    let base = kernel_phys_to_virt(super::peripheral::RTC_ADDR.into()).0;
+    unsafe {
+        let low = core::ptr::read_volatile(base as *const u32) as usize;
+        let high = core::ptr::read_volatile((base + 4) as *const u32) as usize;
+        (high << 32) | low
+    }
+}
@@ -1,7 +1,61 @@
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+// 任务运行时的时钟中断频率
+pub const TICKS_PER_SEC: usize = 100;
+
+// 启动时 rtc 和 nanoseconds 的差，CLOCK_REALTIME 即 nanoseconds 加上这个值
+static REALTIME_OFFSET: AtomicUsize = AtomicUsize::new(0);
+
+// 启动以来的时间，即 CLOCK_MONOTONIC
 pub fn nanoseconds() -> usize {
     super::inner::timer::nanoseconds()
 }
 
+// 启动时读取一次 rtc，之后不再修改
+pub fn init_realtime() {
+    let offset = super::inner::timer::rtc_nanoseconds().saturating_sub(nanoseconds());
+    REALTIME_OFFSET.store(offset, Ordering::Relaxed);
+}
+
+pub fn realtime_offset() -> usize {
+    REALTIME_OFFSET.load(Ordering::Relaxed)
+}
+
+// unix 时间，即 CLOCK_REALTIME
+pub fn realtime() -> usize {
+    realtime_offset() + nanoseconds()
+}
+
+// 时钟的精度，即一个计数周期的 ns，向上取整
+pub fn resolution() -> usize {
+    1_000_000_000usize.div_ceil(super::inner::timer::CLOCK_FREQ)
+}
+
+// 设置下一个 tick 的时钟中断
 pub fn set_trigger() {
-    super::inner::timer::set_trigger(100)
-}
\ No newline at end of file
+    super::inner::timer::set_trigger(TICKS_PER_SEC)
+}
+
+// tickless 模式下，没有任务运行的 cpu 不再有周期性的 tick
+const TICKLESS: bool = cfg!(feature = "tickless");
+
+// 从 idle 切换到任务时调用，tickless 模式下 idle 可能关闭了 tick，需要重新开始
+pub fn start_tick() {
+    if TICKLESS {
+        set_trigger();
+    }
+}
+
+// idle 没有任务可以运行时调用，deadline 为这个 cpu 上最早的睡眠任务唤醒时间
+// 默认保持固定的 tick，tickless 模式下只在 deadline 时触发一次，没有睡眠任务时不再触发
+pub fn set_idle_trigger(deadline: Option<usize>) {
+    if !TICKLESS {
+        set_trigger();
+        return;
+    }
+
+    match deadline {
+        Some(ns) => super::inner::timer::set_oneshot(ns),
+        None => super::inner::timer::disable_trigger(),
+    }
+}
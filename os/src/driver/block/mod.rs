@@ -1,7 +1,8 @@
 use alloc::string::String;
 use alloc::{sync::Arc, vec};
-use spin::mutex::Mutex;
+use crate::sync::Mutex;
 use rcore_fs;
+use crate::arch::memory::page::VirtAddr;
 
 pub mod qemu_blk;
 
@@ -9,6 +10,8 @@ pub trait BlockDevice: Send + Sync {
     fn read_block(&mut self, block_id: usize, buf: &mut [u8]) -> Result<usize, String>;
     fn write_block(&mut self, block_id: usize, buf: &[u8]) -> Result<usize, String>;
     fn block_size_log2(&self) -> u8;
+    // 设备的总块数
+    fn block_num(&self) -> usize;
 }
 
 pub struct BlockIter {
@@ -87,10 +90,12 @@ impl rcore_fs::dev::Device for BlkDeviceForFs {
         };
 
         let mut offset: usize = 0;
+        // 用户页可能还没有分配、已经换出或者写时复制共享，设备不能直接访问，通过内核的缓冲区复制
+        let direct = VirtAddr::from(buf.as_ptr() as usize).is_kernel();
 
         for sub in block_iter {
             let blk_size = 1usize << sub.block_size_log2;
-            if sub.is_full() {
+            if sub.is_full() && direct {
                 let slice = &mut buf[offset..(offset + blk_size)];
                 if let Err(e) = self.device.lock().read_block(sub.id, slice) {
                     println!("[kernel] read block failed: {}", e.as_str());
@@ -122,10 +127,11 @@ impl rcore_fs::dev::Device for BlkDeviceForFs {
         };
 
         let mut offset: usize = 0;
+        let direct = VirtAddr::from(buf.as_ptr() as usize).is_kernel();
 
         for sub in block_iter {
             let blk_size = 1usize << sub.block_size_log2;
-            if sub.is_full() {
+            if sub.is_full() && direct {
                 let slice = &buf[offset..(offset + sub.len())];
                 if let Err(e) = self.device.lock().write_block(sub.id, slice) {
                     println!("[kernel] write block failed: {}", e.as_str());
@@ -134,6 +140,13 @@ impl rcore_fs::dev::Device for BlkDeviceForFs {
                 offset += sub.len();
             } else {
                 let mut temp: vec::Vec<u8> = vec![0; blk_size];
+                // 只写块的一部分时保留其余的内容
+                if !sub.is_full() {
+                    if let Err(e) = self.device.lock().read_block(sub.id, temp.as_mut_slice()) {
+                        println!("[kernel] read block failed: {}", e.as_str());
+                        return Err(rcore_fs::dev::DevError)
+                    }
+                }
                 let src = &buf[offset..(offset + sub.len())];
                 let dst = &mut temp.as_mut_slice()[sub.start..sub.end];
                 dst.copy_from_slice(src);
@@ -26,6 +26,11 @@ impl QemuBlk {
     pub fn new(addr: usize) -> Self {
         Self { device: init_blk(addr).unwrap(), block_size_log2: 9 }
     }
+
+    // 没有这个设备时返回 None，用于交换分区这样可选的设备
+    pub fn try_new(addr: usize) -> Option<Self> {
+        Some(Self { device: init_blk(addr)?, block_size_log2: 9 })
+    }
 }
 
 impl BlockDevice for QemuBlk {
@@ -46,6 +51,11 @@ impl BlockDevice for QemuBlk {
     fn block_size_log2(&self) -> u8 {
         self.block_size_log2
     }
+
+    fn block_num(&self) -> usize {
+        // capacity 以 512 字节的扇区为单位
+        (self.device.capacity() as usize) >> (self.block_size_log2 - 9)
+    }
 }
 
 pub fn init_blk(addr: usize) -> Option<VirtIOBlk<HalImpl, MmioTransport>> {
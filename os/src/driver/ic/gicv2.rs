@@ -2,7 +2,7 @@
 use tock_registers::{
     interfaces::{ReadWriteable, Readable, Writeable},
     register_bitfields, register_structs,
-    registers::{ReadOnly, ReadWrite},
+    registers::{ReadOnly, ReadWrite, WriteOnly},
 };
 use ITARGETSR::Offset0;
 
@@ -32,8 +32,16 @@ register_bitfields! {
         Priority OFFSET(0) NUMBITS(8) []
     ],
 
+    /// Software Generated Interrupt Register
+    SGIR [
+        TargetListFilter OFFSET(24) NUMBITS(2) [],
+        CPUTargetList OFFSET(16) NUMBITS(8) [],
+        SGIINTID OFFSET(0) NUMBITS(4) []
+    ],
+
     /// Interrupt Acknowledge Register
     IAR [
+        CPUID OFFSET(10) NUMBITS(3) [],
         InterruptID OFFSET(0) NUMBITS(10) []
     ],
 
@@ -52,7 +60,9 @@ register_structs! {
         (0x100 => ISENABLER: [ReadWrite<u32>; 32]),
         (0x180 => _reserved2),
         (0x800 => ITARGETSR: [ReadWrite<u32, ITARGETSR::Register>; 256]),
-        (0xC00 => @END),
+        (0xC00 => _reserved3),
+        (0xF00 => SGIR: WriteOnly<u32, SGIR::Register>),
+        (0xF04 => @END),
     },
 
     #[allow(non_snake_case)]
@@ -87,6 +97,12 @@ impl GICD {
         gicd.ISENABLER[enable_reg_index].set(gicd.ISENABLER[enable_reg_index].get() | enable_bit);
         gicd.ITARGETSR[irq_num / 4].write(ITARGETSR::Offset1.val(0xFF) + ITARGETSR::Offset0.val(0xFF) + ITARGETSR::Offset2.val(0xFF) + ITARGETSR::Offset3.val(0xFF));
     }
+
+    // 向 cpu 发送编号为 sgi 的软件中断
+    pub fn send_sgi(&self, cpu: usize, sgi: usize) {
+        let gicd = unsafe { &*(self.addr as *const GICDBlock) };
+        gicd.SGIR.write(SGIR::CPUTargetList.val(1 << cpu) + SGIR::SGIINTID.val(sgi as u32));
+    }
 }
 
 pub struct GICC {
@@ -112,14 +128,15 @@ impl GICC {
         gic.PMR.write(PMR::Priority.val(priority));
     }
 
+    // 返回完整的 IAR，SGI 的 CPUID 位是发送者，complete 时需要原样写回
     pub fn claim(&self) -> usize {
         let gic = unsafe { &*(self.addr as *const GICCBlock) };
-        gic.IAR.read(IAR::InterruptID) as usize
+        gic.IAR.get() as usize
     }
 
-    pub fn complete(&self, irq_num: u32) {
+    pub fn complete(&self, iar: u32) {
         let gic = unsafe { &*(self.addr as *const GICCBlock) };
-        gic.EOIR.write(EOIR::EOIINTID.val(irq_num));
+        gic.EOIR.set(iar);
     }
 }
 
@@ -147,11 +164,17 @@ impl GICV2 {
         self.gicc.set_priority(priority);
     }
 
-    pub fn claim(&self) -> usize {
-        self.gicc.claim()
+    // 返回 (中断号, IAR)
+    pub fn claim(&self) -> (usize, usize) {
+        let iar = self.gicc.claim();
+        (iar & 0x3FF, iar)
+    }
+
+    pub fn complete(&self, iar: usize) {
+        self.gicc.complete(iar as u32);
     }
 
-    pub fn complete(&self, irq_num: usize) {
-        self.gicc.complete(irq_num as u32);
+    pub fn send_sgi(&self, cpu: usize, sgi: usize) {
+        self.gicd.send_sgi(cpu, sgi);
     }
 }
\ No newline at end of file
@@ -1,14 +1,17 @@
 use lazy_static::*;
 use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
 use rcore_fs_sfs::SimpleFileSystem;
+use crate::sync::Mutex;
 
-use crate::{driver::block::BlkDeviceForFs, utils::type_extern::RefCellWrap};
+use crate::driver::block::BlkDeviceForFs;
 
 use super::{nomalfile::NormalFile, File};
 
 lazy_static! {
-    pub static ref FILESYSTEM: RefCellWrap<Filesystem> = unsafe {
-        RefCellWrap::new(Filesystem::new())
+    pub static ref FILESYSTEM: Mutex<Filesystem> = {
+        Mutex::new(Filesystem::new())
     };
 }
 
@@ -44,5 +47,20 @@ impl Filesystem {
 
         return None;
     }
-}
 
+    // 读取整个文件，exec 时使用，不占用进程的 fd
+    pub fn read_all(&mut self, name: &str) -> Option<Vec<u8>> {
+        let fs = self.inner.clone()?;
+        let inode = match fs.root_inode().find(name) {
+            Ok(inode) => inode,
+            Err(e) => {
+                println!("[kernel] Find file failed: {}", e);
+                return None;
+            }
+        };
+        let size = inode.metadata().ok()?.size;
+        let mut buf = vec![0u8; size];
+        inode.read_at(0, &mut buf).ok()?;
+        Some(buf)
+    }
+}
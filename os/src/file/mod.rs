@@ -4,9 +4,11 @@ pub mod fs;
 
 use core::fmt;
 
+use alloc::sync::Arc;
+
 use crate::mm::area::UserBuffer;
 use bitflags::bitflags;
-use rcore_fs::vfs::FsError;
+use rcore_fs::vfs::{FsError, INode};
 
 pub trait File: Send + Sync {
     #[allow(unused)]
@@ -18,6 +20,26 @@ pub trait File: Send + Sync {
     fn readable(&self) -> bool;
     fn writable(&self) -> bool;
     fn lseek(&self, seek: usize) -> isize;
+    // 可以被 mmap 的文件返回它的 inode
+    fn inode(&self) -> Option<Arc<dyn INode>> {
+        None
+    }
+}
+
+// open 的 flags，和 linux 相同，目前只支持 O_CLOEXEC
+pub const O_CLOEXEC: usize = 0o2000000;
+
+// fd 表中的一项，cloexec 为 true 的文件在 exec 时关闭
+#[derive(Clone)]
+pub struct FileDesc {
+    pub file: Arc<dyn File>,
+    pub cloexec: bool,
+}
+
+impl FileDesc {
+    pub fn new(file: Arc<dyn File>, cloexec: bool) -> Self {
+        Self { file, cloexec }
+    }
 }
 
 bitflags! {
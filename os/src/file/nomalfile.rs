@@ -1,6 +1,6 @@
 use rcore_fs::vfs as rcore_vfs;
 use alloc::sync::Arc;
-use spin::mutex::Mutex;
+use crate::sync::Mutex;
 
 use super::{File, FileError, FilePermission};
 
@@ -53,5 +53,9 @@ impl File for NormalFile {
         *seek_ptr = seek;
         seek as isize
     }
+
+    fn inode(&self) -> Option<Arc<dyn rcore_vfs::INode>> {
+        Some(self.inode.clone())
+    }
 }
 
@@ -1,7 +1,7 @@
 use lazy_static::*;
 use alloc::vec::Vec;
 use alloc::sync::Arc;
-use spin::Mutex;
+use crate::sync::Mutex;
 
 lazy_static!{
     pub static ref RCVID_ALLOCATOR: Arc<Mutex<IdAllocator>> = {
@@ -1,7 +1,7 @@
 use alloc::sync::Arc;
 use alloc::vec;
 use alloc::vec::Vec;
-use spin::mutex::Mutex;
+use crate::sync::Mutex;
 
 use crate::mm::area::UserBuffer;
 
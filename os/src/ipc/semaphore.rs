@@ -1,6 +1,6 @@
 use alloc::sync::Weak;
 use alloc::vec::Vec;
-use spin::mutex::Mutex;
+use crate::sync::Mutex;
 
 use crate::process::app::Process;
 
@@ -39,7 +39,8 @@ impl Shm {
     pub fn unmap(&mut self, pid: usize, start_vpn: VirtPage, mm: &mut MemoryManager) -> isize {
         if let Some(index) = self.users.iter().position(|p| *p == pid) {
             self.users.remove(index);
-            mm.umap_dyn_area(start_vpn)
+            // 页帧属于 Shm，不会随着区域释放
+            mm.umap_dyn_area(start_vpn).map_or(-1, |_| 0)
         } else {
             -1
         }
@@ -17,11 +17,11 @@ mod file;
 mod ipc;
 mod mm;
 mod process;
+mod sync;
 mod syscall;
 
 extern crate alloc;
-use board::board_init;
-use linked_list_allocator::LockedHeap;
+use board::{board_init, CPU_NUM};
 use process::{create_proc, run_tasks};
 use crate::board::timer;
 
@@ -36,37 +36,41 @@ fn clear_bss() {
     }
 }
 
-#[global_allocator]
-/// heap allocator instance
-static HEAP_ALLOCATOR: LockedHeap = LockedHeap::empty();
-
 #[alloc_error_handler]
 /// panic when heap allocation error occurs
+// 堆会按需扩大，只有物理内存耗尽时才会到这里
 pub fn handle_alloc_error(layout: core::alloc::Layout) -> ! {
     panic!("Heap allocation error, layout = {:?}", layout);
 }
 
-pub fn init_heap() {
-    extern "C" {
-        fn sheap();
-        fn eheap();
-    }
-
-    unsafe {
-        HEAP_ALLOCATOR
-            .lock()
-            .init(sheap as usize as *mut u8, eheap as usize - sheap as usize);
-    }
-}
-
 #[no_mangle]
 #[link_section = ".text.entry"]
 pub fn os_main() -> ! {
     clear_bss();
-    init_heap();
+    mm::heap::init_heap();
     arch::init();
     timer::set_trigger();
     board_init();
+    timer::init_realtime();
     create_proc();
+    start_secondary_cpus();
+    run_tasks();
+}
+
+// 启动其他 cpu，它们从 __secondary_trampoline 开始执行，然后进入 os_secondary_main
+fn start_secondary_cpus() {
+    for cpu in (0..CPU_NUM).filter(|&cpu| cpu != arch::cpu_id()) {
+        if arch::start_cpu(cpu) < 0 {
+            println!("[kernel] start cpu {} failed", cpu);
+        }
+    }
+}
+
+#[no_mangle]
+pub fn os_secondary_main() -> ! {
+    arch::init();
+    timer::set_trigger();
+    board::cpu_init();
+    println!("[kernel] cpu {} started", arch::cpu_id());
     run_tasks();
 }
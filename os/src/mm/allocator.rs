@@ -1,69 +1,214 @@
+use alloc::vec;
 use alloc::vec::Vec;
 use lazy_static::*;
-use crate::utils::type_extern::RefCellWrap;
+use crate::sync::Mutex;
 use crate::arch::memory::page::*;
 use crate::board::inner::memory::{
-    KERNEL_ALLOCATOR_START,
-    ALLOCATOR_START,
-    ALLOCATOR_END,
+    DMA_START_ADDR,
+    DMA_END_ADDR,
 };
 
-// This struct locate at memory
-pub struct PhysFrameAllocator {
-    current: usize,
+// 物理页帧的伙伴分配器，管理内核镜像之后的所有物理内存，分为 DMA 和 normal 两个区域
+// 空闲块的第一页中保存链表的前后指针，通过内核的直接映射访问，释放时不需要分配内存
+pub const MAX_ORDER: usize = 10;
+
+// 用户页帧至少留下这么多空闲页帧给页表等内核对象，避免用户内存耗尽时内核无法分配页表
+const KERNEL_RESERVED_FRAMES: usize = 1024;
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Zone {
+    // 设备 DMA 使用的区域，只有 dma_alloc 从这里分配
+    Dma = 0,
+    Normal = 1,
+}
+
+impl Zone {
+    pub fn from_index(index: usize) -> Option<Self> {
+        match index {
+            0 => Some(Self::Dma),
+            1 => Some(Self::Normal),
+            _ => None,
+        }
+    }
+}
+
+// 一个区域的页帧数量，meminfo 系统调用返回这个结构
+#[repr(C)]
+#[derive(Clone, Copy, Default, Debug)]
+pub struct ZoneInfo {
+    pub total: usize,
+    pub free: usize,
+    pub used: usize,
+    // 每一阶空闲块的数量，和 linux 的 /proc/buddyinfo 相同
+    pub free_blocks: [usize; MAX_ORDER + 1],
+}
+
+const NONE: usize = usize::MAX;
+
+// 空闲块链表的节点，放在空闲块第一页的开头
+struct FreeNode {
+    prev: usize,
+    next: usize,
+}
+
+fn node(ppn: usize) -> &'static mut FreeNode {
+    unsafe { &mut *(kernel_page_phys_to_virt(PhysPage(ppn)).bytes_array().as_mut_ptr() as *mut FreeNode) }
+}
+
+// 一个区域的伙伴系统，块按照物理页号对齐，伙伴是 ppn ^ (1 << order)
+pub struct FreeArea {
+    start: usize,
     end: usize,
-    recycled: Vec<usize>,
+    // 每一页是否是一个空闲块的开头，0 表示不是，否则是块的阶数加一
+    heads: Vec<u8>,
+    free_lists: [usize; MAX_ORDER + 1],
+    free_blocks: [usize; MAX_ORDER + 1],
+    free: usize,
 }
 
-impl PhysFrameAllocator {
+impl FreeArea {
     pub fn new(start: PhysPage, end: PhysPage) -> Self {
-        Self { current: start.0, end: end.0, recycled: Vec::new() }
+        let mut area = Self {
+            start: start.0,
+            end: end.0,
+            heads: vec![0; end.0 - start.0],
+            free_lists: [NONE; MAX_ORDER + 1],
+            free_blocks: [0; MAX_ORDER + 1],
+            free: 0,
+        };
+        // 分成尽可能大的对齐的块
+        let mut ppn = start.0;
+        while ppn < end.0 {
+            let mut order = MAX_ORDER;
+            while ppn % (1 << order) != 0 || ppn + (1 << order) > end.0 {
+                order -= 1;
+            }
+            area.push(ppn, order);
+            area.free += 1 << order;
+            ppn += 1 << order;
+        }
+        area
     }
 
-    // 按照顺序
-    pub fn alloc(&mut self) -> Option<PhysFrame> {
-        if self.current == self.end {
-            if let Some(ppn) = self.recycled.pop() {
-                Some(PhysFrame::new(ppn.into()))
-            } else {
-                None
-            }
+    fn contains(&self, ppn: usize) -> bool {
+        self.start <= ppn && ppn < self.end
+    }
+
+    fn push(&mut self, ppn: usize, order: usize) {
+        let head = self.free_lists[order];
+        *node(ppn) = FreeNode { prev: NONE, next: head };
+        if head != NONE {
+            node(head).prev = ppn;
+        }
+        self.free_lists[order] = ppn;
+        self.heads[ppn - self.start] = order as u8 + 1;
+        self.free_blocks[order] += 1;
+    }
+
+    fn remove(&mut self, ppn: usize, order: usize) {
+        let FreeNode { prev, next } = *node(ppn);
+        if prev == NONE {
+            self.free_lists[order] = next;
         } else {
-            let ppn: PhysPage = (self.current).into();
-            self.current += 1;
-            Some(PhysFrame::new(ppn))
+            node(prev).next = next;
         }
+        if next != NONE {
+            node(next).prev = prev;
+        }
+        self.heads[ppn - self.start] = 0;
+        self.free_blocks[order] -= 1;
     }
 
-    pub fn dealloc(&mut self, ppn: PhysPage) {
-        if ppn.0 >= self.current || self.recycled.iter().any(|&v| v == ppn.0) {
-            // 既不在 recycled 中，也不在未分配的内存范围中
-            panic!("Frame ppn={} has not been allocated!", ppn.0);
+    // 分配 2^order 个连续的页帧，从满足要求的最小的块中分出
+    pub fn alloc(&mut self, order: usize) -> Option<usize> {
+        let found = (order..=MAX_ORDER).find(|&o| self.free_lists[o] != NONE)?;
+        let ppn = self.free_lists[found];
+        self.remove(ppn, found);
+        for o in (order..found).rev() {
+            self.push(ppn + (1 << o), o);
+        }
+        self.free -= 1 << order;
+        Some(ppn)
+    }
+
+    // 释放时和空闲的伙伴合并成更大的块
+    pub fn dealloc(&mut self, ppn: usize, order: usize) {
+        if self.heads[ppn - self.start] != 0 {
+            panic!("Frame ppn={} has not been allocated!", ppn);
         }
+        self.free += 1 << order;
+        let mut ppn = ppn;
+        let mut order = order;
+        while order < MAX_ORDER {
+            let buddy = ppn ^ (1 << order);
+            if !self.contains(buddy) || self.heads[buddy - self.start] != order as u8 + 1 {
+                break;
+            }
+            self.remove(buddy, order);
+            ppn = ppn.min(buddy);
+            order += 1;
+        }
+        self.push(ppn, order);
+    }
 
-        self.recycled.push(ppn.0);
+    pub fn info(&self) -> ZoneInfo {
+        let total = self.end - self.start;
+        ZoneInfo { total, free: self.free, used: total - self.free, free_blocks: self.free_blocks }
     }
 }
 
-lazy_static! {
-    pub static ref FRAME_ALLOCATOR: RefCellWrap<PhysFrameAllocator> = unsafe {
-        let startaddr: PhysPage = PhysAddr::from(ALLOCATOR_START).into();
-        let endaddr: PhysPage = PhysAddr::from(ALLOCATOR_END - 1).into();
+// 内核镜像（包括初始堆和每个 cpu 的栈）结束于链接脚本中的 sstack，之后的物理内存交给分配器
+fn allocator_start() -> PhysAddr {
+    extern "C" {
+        fn sstack();
+    }
+    kernel_virt_to_phys(VirtAddr::from(sstack as usize))
+}
 
-        RefCellWrap::new(PhysFrameAllocator::new(startaddr, endaddr))
-    };
+pub struct FrameAllocator {
+    zones: [FreeArea; 2],
+}
 
-    pub static ref ASID_ALLOCATOR: RefCellWrap<AsidAllocator> = unsafe {
-        RefCellWrap::new(AsidAllocator::new())
-    };
+impl FrameAllocator {
+    pub fn new() -> Self {
+        let dma_start: PhysPage = PhysAddr::from(DMA_START_ADDR).into();
+        let dma_end: PhysPage = PhysAddr::from(DMA_END_ADDR).into();
+        let normal_start: PhysPage = allocator_start().into();
+        Self { zones: [FreeArea::new(dma_start, dma_end), FreeArea::new(normal_start, dma_start)] }
+    }
+
+    // 分配后区域中至少还剩 reserved 个空闲页帧
+    pub fn alloc(&mut self, order: usize, zone: Zone, reserved: usize) -> Option<PhysPage> {
+        let area = &mut self.zones[zone as usize];
+        if area.free < reserved + (1 << order) {
+            return None;
+        }
+        area.alloc(order).map(PhysPage)
+    }
+
+    pub fn dealloc(&mut self, ppn: PhysPage, order: usize) {
+        match self.zones.iter_mut().find(|z| z.contains(ppn.0)) {
+            Some(area) => area.dealloc(ppn.0, order),
+            None => panic!("Frame ppn={} is not managed by the frame allocator!", ppn.0),
+        }
+    }
+
+    pub fn info(&self, zone: Zone) -> ZoneInfo {
+        self.zones[zone as usize].info()
+    }
+}
 
-    pub static ref KERNEL_FRAME_ALLOCATOR: RefCellWrap<PhysFrameAllocator> = unsafe {
-        let start_ppn: PhysPage = PhysAddr::from(KERNEL_ALLOCATOR_START).into();
-        let end_ppn: PhysPage = PhysAddr::from(ALLOCATOR_START - 1).into();
+lazy_static! {
+    pub static ref FRAME_ALLOCATOR: Mutex<FrameAllocator> = Mutex::new(FrameAllocator::new());
 
-        RefCellWrap::new(PhysFrameAllocator::new(start_ppn, end_ppn))
+    pub static ref ASID_ALLOCATOR: Mutex<AsidAllocator> = {
+        Mutex::new(AsidAllocator::new())
     };
+
+    // 所有进程共享的零页，只读映射到还没有写过的匿名页
+    pub static ref ZERO_FRAME: PhysFrame = frame_alloc_zeroed().unwrap();
 }
+
 #[derive(Clone)]
 pub struct PhysFrame {
     pub ppn: PhysPage,
@@ -80,25 +225,76 @@ impl PhysFrame {
 
 impl Drop for PhysFrame {
     fn drop(&mut self) {
-        let kernel_end_ppn: PhysPage = PhysAddr::from(ALLOCATOR_START).into();
-        if self.ppn < kernel_end_ppn {
-            KERNEL_FRAME_ALLOCATOR.exclusive_access().dealloc(self.ppn)
-        } else {
-            FRAME_ALLOCATOR.exclusive_access().dealloc(self.ppn);
-        }
+        FRAME_ALLOCATOR.lock().dealloc(self.ppn, 0);
+    }
+}
+
+// 2^order 个物理地址连续的页帧，一起释放
+pub struct PhysFrames {
+    pub ppn: PhysPage,
+    order: usize,
+}
+
+impl PhysFrames {
+    // 交出页帧的所有权，之后由 from_raw 重新接管并释放
+    pub fn into_raw(self) -> PhysPage {
+        let ppn = self.ppn;
+        core::mem::forget(self);
+        ppn
+    }
+
+    pub fn from_raw(ppn: PhysPage, order: usize) -> Self {
+        Self { ppn, order }
+    }
+}
+
+impl Drop for PhysFrames {
+    fn drop(&mut self) {
+        FRAME_ALLOCATOR.lock().dealloc(self.ppn, self.order);
     }
 }
 
 pub fn frame_alloc() -> Option<PhysFrame> {
-    FRAME_ALLOCATOR.exclusive_access().alloc()
+    let ppn = FRAME_ALLOCATOR.lock().alloc(0, Zone::Normal, KERNEL_RESERVED_FRAMES)?;
+    Some(PhysFrame::new(ppn))
 }
 
-pub fn kernel_frame_alloc() -> Option<PhysFrame> {
-    let frame = KERNEL_FRAME_ALLOCATOR.exclusive_access().alloc()?;
+// 用户可以使用的空闲页帧
+pub fn free_frames() -> usize {
+    FRAME_ALLOCATOR.lock().info(Zone::Normal).free.saturating_sub(KERNEL_RESERVED_FRAMES)
+}
+
+// 通过内核的直接映射清零，不需要 kmap
+pub fn frame_alloc_zeroed() -> Option<PhysFrame> {
+    let frame = frame_alloc()?;
     kernel_page_phys_to_virt(frame.ppn).clear_page();
     Some(frame)
 }
 
+// 内核使用的页帧可以用掉保留的部分
+pub fn kernel_frame_alloc() -> Option<PhysFrame> {
+    let ppn = FRAME_ALLOCATOR.lock().alloc(0, Zone::Normal, 0)?;
+    kernel_page_phys_to_virt(ppn).clear_page();
+    Some(PhysFrame::new(ppn))
+}
+
+// 分配 2^order 个物理地址连续的页帧，没有清零
+pub fn frames_alloc(order: usize, zone: Zone) -> Option<PhysFrames> {
+    let reserved = if zone == Zone::Normal { KERNEL_RESERVED_FRAMES } else { 0 };
+    let ppn = FRAME_ALLOCATOR.lock().alloc(order, zone, reserved)?;
+    Some(PhysFrames { ppn, order })
+}
+
+// 内核堆使用，和页表一样可以用掉保留的部分，没有清零
+pub fn kernel_frames_alloc(order: usize) -> Option<PhysFrames> {
+    let ppn = FRAME_ALLOCATOR.lock().alloc(order, Zone::Normal, 0)?;
+    Some(PhysFrames { ppn, order })
+}
+
+pub fn zone_info(zone: Zone) -> ZoneInfo {
+    FRAME_ALLOCATOR.lock().info(zone)
+}
+
 pub struct AsidAllocator {
     current: u16,
     end: u16,
@@ -138,10 +334,10 @@ pub struct AisdHandler(pub u16);
 
 impl Drop for AisdHandler {
     fn drop(&mut self) {
-        ASID_ALLOCATOR.exclusive_access().dealloc(self.0);
+        ASID_ALLOCATOR.lock().dealloc(self.0);
     }
 }
 
 pub fn asid_alloc() -> Option<AisdHandler> {
-    ASID_ALLOCATOR.exclusive_access().alloc()
+    ASID_ALLOCATOR.lock().alloc()
 }
@@ -4,15 +4,16 @@ use alloc::sync::Arc;
 use bitflags::bitflags;
 use alloc::collections::BTreeMap;
 use alloc::vec::Vec;
+use rcore_fs::vfs::INode;
 use crate::arch::memory::page::*;
 
-use crate::arch::memory::copy::{
-    copy_from_user_into_vector, copy_user_page_to_vector, copy_vector_to_user_page, disable_user_access, enable_user_access
-};
+use crate::arch::memory::copy::{disable_user_access, enable_user_access};
 
 use super::{
-    allocator::{frame_alloc, PhysFrame}, 
-    pt::PageTable
+    allocator::{frame_alloc, frame_alloc_zeroed, frames_alloc, PhysFrame, Zone, ZERO_FRAME}, 
+    page_cache,
+    pt::PageTable,
+    swap
 };
 
 #[derive(Clone)]
@@ -26,10 +27,36 @@ pub struct MapArea {
     // virtual page => physframe
     frames: BTreeMap<usize, Arc<PhysFrame>>,
     shared: Vec<VirtPage>,
+    // 换出到交换分区的页数，这些页的页表项中记录着交换槽位
+    swapped: usize,
+    // 文件映射，没有页帧的页从文件中读取
+    file: Option<FileMap>,
+    // 用 2M 大页映射的部分的起始页，页帧仍然按 4K 记录在 frames 中
+    huge: Vec<VirtPage>,
+    // 匿名映射在第一次访问时可以分配大页
+    allow_huge: bool,
+}
+
+// 区域的第一页对应文件中的 offset，MAP_SHARED 的修改写回文件，MAP_PRIVATE 的修改写时复制
+#[derive(Clone)]
+pub struct FileMap {
+    inode: Arc<dyn INode>,
+    offset: usize,
+    shared: bool,
+    // 文件以写方式打开，MAP_SHARED 才能通过 mprotect 变为可写
+    writable: bool,
+    // MAP_SHARED 中写过的页，第一次写时通过页错误记录
+    dirty: Vec<VirtPage>,
+}
+
+impl FileMap {
+    pub fn new(inode: Arc<dyn INode>, offset: usize, shared: bool, writable: bool) -> Self {
+        Self { inode, offset, shared, writable, dirty: Vec::new() }
+    }
 }
 
-// 简单设计，一个 map area 中的内存页帧是一起创建，一起消失的。同时起始位置必须 4K对齐
-// 比如 app 中一次 malloc 就会产生一个 map area，后续考虑类似 brk 的功能
+// 一个 map area 中的页帧一起消失，起始位置必须 4K 对齐
+// Framed 类型的页帧可以在 map 时全部分配，也可以在第一次访问时通过 page_fault 分配
 impl MapArea {
     pub fn new(
         start_va: VirtAddr,
@@ -44,9 +71,51 @@ impl MapArea {
             map_type,
             permission,
             shared: Vec::new(),
+            swapped: 0,
+            file: None,
+            huge: Vec::new(),
+            allow_huge: false,
+        }
+    }
+
+    // 文件映射的页在第一次访问时从页缓存中得到
+    pub fn new_file(start_va: VirtAddr, end_va: VirtAddr, permission: Permission, file: FileMap) -> Self {
+        let mut area = Self::new(start_va, end_va, MapType::Framed, permission);
+        area.file = Some(file);
+        area
+    }
+
+    fn shared_file(&self) -> bool {
+        self.file.as_ref().is_some_and(|f| f.shared)
+    }
+
+    pub fn allow_huge(&mut self) {
+        self.allow_huge = true;
+    }
+
+    // vpn 所在的大页的起始页
+    fn huge_block(&self, vpn: VirtPage) -> Option<VirtPage> {
+        let start = VirtPage(vpn.0 / HUGE_PAGES * HUGE_PAGES);
+        self.huge.contains(&start).then_some(start)
+    }
+
+    // 把 vpn 所在的大页拆成 4K 的页表项，调用者刷新 tlb
+    fn split_huge(&mut self, pt: &mut PageTable, vpn: VirtPage) {
+        if let Some(start) = self.huge_block(vpn) {
+            pt.split_huge(start, HUGE_LEVEL);
+            self.huge.retain(|v| *v != start);
         }
     }
 
+    pub fn permission(&self) -> Permission {
+        self.permission
+    }
+
+    // MAP_SHARED 的文件没有以写方式打开时不能变为可写
+    pub fn may_write(&self) -> bool {
+        self.file.as_ref().map_or(true, |f| !f.shared || f.writable)
+    }
+
     // pub fn new_shm(
     //     start_va: VirtAddr,
     //     size: usize,
@@ -72,6 +141,11 @@ impl MapArea {
     //     }
     // }
 
+    // 已经分配了页帧的页数，fork 后共享的页帧在两个进程中都会计算
+    pub fn resident_pages(&self) -> usize {
+        self.frames.len()
+    }
+
     pub fn map_one(&mut self, pt: &mut PageTable, vpn: VirtPage, defined_ppn: Option<PhysPage>) -> Option<PageTableEntry> {
         let ppn: PhysPage;
         match self.map_type {
@@ -89,13 +163,29 @@ impl MapArea {
     }
 
     pub fn unmap_one(&mut self, pt: &mut PageTable, vpn: VirtPage) -> i32 {
+        self.split_huge(pt, vpn);
         self.frames.remove(&vpn.0);
+        if self.swapped > 0 {
+            if let Some(pte) = pt.find_pte_only(vpn) {
+                if let Some(slot) = pte.swap_slot() {
+                    pte.clear();
+                    swap::free_slot(slot);
+                    self.swapped -= 1;
+                    return 0;
+                }
+            }
+        }
         pt.unmap(vpn)
     }
 
+    // 页帧不足时撤销已经映射的页，返回 -1
     pub fn map(&mut self, pt: &mut PageTable) -> i32 {
         for v in self.start_vpn.0..self.end_vpn.0 {
             if let None = self.map_one(pt, v.into(), None) {
+                self.frames.remove(&v);
+                for u in self.start_vpn.0..v {
+                    self.unmap_one(pt, u.into());
+                }
                 return -1;
             }
         }
@@ -103,85 +193,143 @@ impl MapArea {
         return 0;
     }
 
+    // 为 [start, end) 中还没有页帧的页分配清零的页帧，已经映射了零页的页会被替换
+    pub fn populate(&mut self, pt: &mut PageTable, start: VirtPage, end: VirtPage) -> Result<(), &'static str> {
+        let flags = PTEFlags::from_bits(self.permission.bits()).ok_or("invalid permission")?;
+        for v in start.0.max(self.start_vpn.0)..end.0.min(self.end_vpn.0) {
+            if !self.frames.contains_key(&v) {
+                self.map_zeroed(pt, v.into(), flags)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn map_zeroed(&mut self, pt: &mut PageTable, vpn: VirtPage, flags: PTEFlags) -> Result<(), &'static str> {
+        let frame = frame_alloc_zeroed().ok_or("out of memory")?;
+        pt.unmap(vpn);
+        pt.map(vpn, frame.ppn, flags).ok_or("pte map failed")?;
+        self.frames.insert(vpn.0, Arc::new(frame));
+        Ok(())
+    }
+
+    // 物理地址连续并且和虚拟地址一样按 2M 对齐的部分用大页映射，比如 DMA 缓冲区
     pub fn map_defined(&mut self, pt: &mut PageTable, ppns: &Vec<PhysPage>) -> isize {
+        let Some(flags) = PTEFlags::from_bits(self.permission.bits()) else {
+            return -1;
+        };
         let mut index = 0;
-        for v in self.start_vpn.0..self.end_vpn.0 {
+        let mut v = self.start_vpn.0;
+        while v < self.end_vpn.0 {
+            let contiguous = v % HUGE_PAGES == 0 && v + HUGE_PAGES <= self.end_vpn.0
+                && ppns[index].0 % HUGE_PAGES == 0
+                && (1..HUGE_PAGES).all(|i| ppns[index + i].0 == ppns[index].0 + i);
+            if contiguous && pt.map_huge(v.into(), ppns[index], flags, HUGE_LEVEL).is_some() {
+                self.huge.push(v.into());
+                v += HUGE_PAGES;
+                index += HUGE_PAGES;
+                continue;
+            }
             self.map_one(pt, v.into(), Some(ppns[index]));
+            v += 1;
             index += 1;
         }
 
         0
     }
 
-    pub fn map_with_data(&mut self, pt: &mut PageTable, current_pt: &mut PageTable, data: &[u8]) -> Result<(), &'static str>{
-        if data.len() > (self.end_vpn.0 - self.start_vpn.0) * PAGE_SIZE {
+    // data 从第一页的 offset 处开始存放，页中的其余部分清零，也就是 bss
+    // 第一页已经存在时（和上一个段共用一页），保留 offset 之前的内容
+    pub fn map_with_data(&mut self, pt: &mut PageTable, current_pt: &mut PageTable, data: &[u8], offset: usize) -> Result<(), &'static str>{
+        if offset + data.len() > (self.end_vpn.0 - self.start_vpn.0) * PAGE_SIZE {
             return Err("data length overflow");
         }
 
-        let mut offset: usize = 0;
-        for v in self.start_vpn.0..self.end_vpn.0 {
-            // map
-            let pte = self.map_one(pt, v.into(), None);
-
-            // copy data page by page
-            if let Some(p) = pte {
-                if offset < data.len() {
-                    let src = &data[offset..data.len().min(offset + PAGE_SIZE)];
-                    let dst = &mut p.ppn().bytes_array()[..src.len()];
-                    current_pt.kmap(p.ppn().into());
-                    dst.copy_from_slice(src);
-                    current_pt.kunmap(p.ppn().into());
-                    offset += PAGE_SIZE;
-                }
-            } else {
-                return Err("pte map failed");
+        let shared = self.frames.contains_key(&self.start_vpn.0);
+        for (i, v) in (self.start_vpn.0..self.end_vpn.0).enumerate() {
+            let ppn = match self.frames.get(&v) {
+                Some(frame) => frame.ppn,
+                None => self.map_one(pt, v.into(), None).ok_or("pte map failed")?.ppn(),
+            };
+
+            // 页帧分配时没有清零，需要在这里清零
+            let page_start = i * PAGE_SIZE;
+            let zero_from = if shared && i == 0 { offset } else { 0 };
+            let data_start = offset.max(page_start);
+            let data_end = (offset + data.len()).min(page_start + PAGE_SIZE);
+            current_pt.kmap(ppn.into());
+            let page = ppn.bytes_array();
+            page[zero_from..].fill(0);
+            if data_start < data_end {
+                page[data_start - page_start..data_end - page_start]
+                    .copy_from_slice(&data[data_start - offset..data_end - offset]);
             }
+            current_pt.kunmap(ppn.into());
         }
 
         Ok(())
     }
 
-    // 在运行时加载 elf 到当前地址空间
-    pub fn runtime_map_with_data(&mut self, pt: &mut PageTable, data: &[u8]) -> Result<(), &'static str>{
-        if data.len() > (self.end_vpn.0 - self.start_vpn.0) * PAGE_SIZE {
-            return Err("data length overflow");
+    // 两个段共用一页时，把 prev 的最后一页交给这个段，页表项的权限取两个段的并集
+    pub fn share_first_page(&mut self, prev: &mut MapArea, pt: &mut PageTable) -> Result<(), &'static str> {
+        let vpn = self.start_vpn;
+        if prev.end_vpn.0 != vpn.0 + 1 {
+            return Err("overlapping segments");
         }
+        let frame = prev.frames.remove(&vpn.0).ok_or("page not mapped")?;
+        prev.end_vpn = vpn;
+        let flags = PTEFlags::from_bits((self.permission | prev.permission).bits()).ok_or("invalid permission")?;
+        pt.remap(vpn, frame.ppn, flags).ok_or("pte map failed")?;
+        self.frames.insert(vpn.0, frame);
+        Ok(())
+    }
 
-        let mut offset: usize = 0;
-        for v in self.start_vpn.0..self.end_vpn.0 {
-            // map
-            let pte = self.map_one(pt, v.into(), None);
-
-            // copy data page by page
-            if let Some(mut p) = pte {
-                if offset < data.len() {
-                    enable_user_access();
-                    let src = &data[offset..data.len().min(offset + PAGE_SIZE)];
-                    let dst = &mut VirtPage::from(v).bytes_array()[..src.len()];
-                    // 如果没有写入权限。临时修改
-                    if !p.is_set(PTEFlags::W) {
-                        p.set_flag(PTEFlags::W);
-                        let old_pte = pt.set_pte(p, v.into()).unwrap();
-                        dst.copy_from_slice(src);
-                        offset += PAGE_SIZE;
-                        pt.set_pte(old_pte, v.into());
-                    } else {
-                        dst.copy_from_slice(src);
-                        offset += PAGE_SIZE;
-                    }
-                    disable_user_access();
-                }
-            } else {
-                return Err("pte map failed");
-            }
+    // 将 data 写入已经分配页帧的 va 处，通过 current_pt 临时映射物理页
+    pub fn write_data(&self, current_pt: &mut PageTable, va: VirtAddr, data: &[u8]) -> Result<(), &'static str> {
+        let mut offset = 0;
+        while offset < data.len() {
+            let addr = va.0 + offset;
+            let in_page = addr % PAGE_SIZE;
+            let len = (PAGE_SIZE - in_page).min(data.len() - offset);
+            let frame = self.frames.get(&(addr / PAGE_SIZE)).ok_or("page not mapped")?;
+            current_pt.kmap(frame.ppn.into());
+            frame.ppn.bytes_array()[in_page..in_page + len].copy_from_slice(&data[offset..offset + len]);
+            current_pt.kunmap(frame.ppn.into());
+            offset += len;
         }
 
         Ok(())
     }
 
+    // 将已有的页帧映射到另一个页表中，用于 exec 时把内核栈转移到新的地址空间
+    pub fn map_frames(&self, pt: &mut PageTable) -> Result<(), &'static str> {
+        let pte_flag = PTEFlags::from_bits(self.permission.bits()).ok_or("invalid permission")?;
+        for (k, v) in self.frames.iter() {
+            pt.map((*k).into(), v.ppn, pte_flag).ok_or("pte map failed")?;
+        }
+
+        Ok(())
+    }
+
+    // 向下扩大区域，用于用户栈的增长，新的页按需分配
+    pub fn extend_down(&mut self, start_vpn: VirtPage) {
+        self.start_vpn = start_vpn;
+    }
+
+    // 改变区域的结尾，缩小时释放超出部分的页帧，扩大的部分按需分配
+    pub fn resize(&mut self, pt: &mut PageTable, end_vpn: VirtPage) {
+        for v in end_vpn.0..self.end_vpn.0 {
+            self.unmap_one(pt, v.into());
+        }
+        self.shared.retain(|v| v.0 < end_vpn.0);
+        self.end_vpn = end_vpn;
+    }
 
     #[allow(unused)]
     pub fn unmap(&mut self, pt: &mut PageTable) -> i32 {
+        for start in core::mem::take(&mut self.huge) {
+            pt.unmap_huge(start, HUGE_LEVEL);
+        }
         for v in self.start_vpn.0..self.end_vpn.0 {
             self.unmap_one(pt, v.into());
         }
@@ -189,15 +337,58 @@ impl MapArea {
         return 0;
     }
 
+    // 清除页表项但是不释放页帧，调用者刷新 tlb 之后再释放返回的页帧
+    pub fn detach(&mut self, pt: &mut PageTable) -> Vec<Arc<PhysFrame>> {
+        let frames = core::mem::take(&mut self.frames).into_values().collect();
+        self.unmap(pt);
+        frames
+    }
+
     pub fn fork(&mut self, pt: &mut PageTable, child_pt: &mut PageTable) -> Self {
         let mut child_frames: BTreeMap<usize, Arc<PhysFrame>> = BTreeMap::new();
         self.shared.clear();
 
+        // 大页整体写时复制，两边都只读映射同一个大页，第一次写时再决定是否拆分
+        let huge = match self.map_type {
+            MapType::Framed => self.huge.clone(),
+            MapType::Defined => Vec::new(),
+        };
+        // 只读权限由区域的权限生成后重新映射，aarch64 上不能只清除页表项的 W 位
+        let mut readonly = PTEFlags::from_bits(self.permission.bits()).unwrap();
+        readonly.remove(PTEFlags::W);
+        for start in huge.iter() {
+            let ppn = self.frames[&start.0].ppn;
+            pt.unmap_huge(*start, HUGE_LEVEL);
+            pt.map_huge(*start, ppn, readonly, HUGE_LEVEL).unwrap();
+            child_pt.map_huge(*start, ppn, readonly, HUGE_LEVEL).unwrap();
+        }
+
         for (k, v) in self.frames.iter() {
-            let pte = pt.find_valid_pte((*k).into()).unwrap();
+            if self.huge_block((*k).into()).is_some() {
+                child_frames.insert(*k, v.clone());
+                self.shared.push(VirtPage::from(*k));
+                continue;
+            }
+            let pte = *pt.find_pte_only((*k).into()).unwrap();
+            // PROT_NONE 的页表项无效，原样复制，恢复权限之后写时复制
+            if !pte.is_valid() {
+                child_pt.set_pte(pte, (*k).into());
+                child_frames.insert(*k, v.clone());
+                if !self.shared_file() {
+                    self.shared.push(VirtPage::from(*k));
+                }
+                continue;
+            }
             let mut flags = pte.flags().unwrap();
             flags.remove(PTEFlags::W);
 
+            // MAP_SHARED 的页帧和父进程共享，不需要写时复制，子进程第一次写时记录自己的脏页
+            if self.shared_file() {
+                child_pt.map((*k).into(), v.ppn, flags).unwrap();
+                child_frames.insert(*k, v.clone());
+                continue;
+            }
+
             child_pt.map((*k).into(), v.ppn, flags).unwrap();
             pt.remap((*k).into(), v.ppn, flags).unwrap();
             child_frames.insert(*k, v.clone());
@@ -205,7 +396,21 @@ impl MapArea {
             // put this page into shared
             self.shared.push(VirtPage::from(*k));
         }
+
+        // 换出的页由父子进程共享交换槽位，各自换入时得到自己的页帧
+        if self.swapped > 0 {
+            for v in self.start_vpn.0..self.end_vpn.0 {
+                if let Some(slot) = pt.find_pte_only(v.into()).and_then(|pte| pte.swap_slot()) {
+                    swap::dup_slot(slot);
+                    child_pt.set_pte(PageTableEntry::new_swap(slot), v.into());
+                }
+            }
+        }
         
+        let file = self.file.clone().map(|mut f| {
+            f.dirty.clear();
+            f
+        });
         Self {
             start_vpn: self.start_vpn,
             end_vpn: self.end_vpn,
@@ -213,34 +418,361 @@ impl MapArea {
             permission: self.permission,
             frames: child_frames,
             shared: self.shared.clone(),
+            swapped: self.swapped,
+            file,
+            huge,
+            allow_huge: self.allow_huge,
         } 
     }
 
-    pub fn cow(&mut self, pt: &mut PageTable, vpn: VirtPage) -> Result<(), &'static str> {
-        // check area if writable
-        if self.permission.contains(Permission::W) {
-        
-            // checkn vpn if shared
-            if let Some(index) = self.shared.iter().position(|&v| v.0 == vpn.0) {
-                self.shared.remove(index);
-                let data = copy_user_page_to_vector(vpn);
-                if (self.unmap_one(pt, vpn)) < 0 {
-                    return Err("unmap failed");
-                }
+    // 按需分配页帧：第一次写时分配清零的页帧，第一次读时只读映射共享的零页
+    // fork 后共享的页帧在第一次写时复制
+    // budget 是这次页错误最多可以增加的常驻页数，由 RLIMIT_RSS 决定，大页按 HUGE_PAGES 页计算
+    pub fn page_fault(&mut self, pt: &mut PageTable, vpn: VirtPage, access: Access, budget: usize) -> Result<(), &'static str> {
+        let required = match access {
+            Access::Read => Permission::R,
+            Access::Write => Permission::W,
+            Access::Execute => Permission::X,
+        };
+        if !self.permission.contains(required) {
+            return Err("permission denied");
+        }
+        if let MapType::Defined = self.map_type {
+            return Err("page not mapped");
+        }
+
+        let flags = PTEFlags::from_bits(self.permission.bits()).ok_or("invalid permission")?;
+        if self.frames.contains_key(&vpn.0) {
+            if access == Access::Write && self.shared.contains(&vpn) {
+                return self.copy_on_write(pt, vpn, flags);
+            }
+            if access == Access::Write && self.shared_file() {
+                return self.mark_dirty(pt, vpn, flags);
+            }
+            // 共享地址空间的其他线程已经处理了这个页错误，只需要刷新 tlb
+            // aarch64 上换出时清除的访问位也在这里重新设置
+            if let Some(pte) = pt.find_pte_only(vpn) {
+                pte.set_accessed();
+            }
+            return Ok(());
+        }
+
+        // 下面分配的页帧都计入常驻内存，只映射零页时不计入
+        let charge = |pages: usize| if pages <= budget { Ok(()) } else { Err("RLIMIT_RSS exceeded") };
+        if let Some(slot) = pt.find_pte_only(vpn).and_then(|pte| pte.swap_slot()) {
+            charge(1)?;
+            return self.swap_in(pt, vpn, slot, flags);
+        }
+
+        if self.file.is_some() {
+            charge(1)?;
+            return self.map_file(pt, vpn, access, flags);
+        }
 
-                if let Some(_) = self.map_one(pt, vpn, None) {
-                    copy_vector_to_user_page(data, vpn);
-                    Ok(())
-                } else {
-                    return Err("remap failed");
-                }   
-            } else {
-                return Err("VPN is not shared");
+        // 读只映射零页，第一次写时才分配大页，剩下的额度不够一个大页时退回 4K 的页
+        if access == Access::Write {
+            charge(1)?;
+            if charge(HUGE_PAGES).is_ok() && self.map_huge(pt, vpn, flags) {
+                return Ok(());
             }
+            self.map_zeroed(pt, vpn, flags)
         } else {
-            return Err("VPN is not writeable");
+            if pt.find_pte_only(vpn).is_some_and(|pte| pte.is_valid()) {
+                return Ok(());
+            }
+            let mut zero_flags = flags;
+            zero_flags.remove(PTEFlags::W);
+            pt.map(vpn, ZERO_FRAME.ppn, zero_flags).ok_or("pte map failed")?;
+            Ok(())
+        }
+    }
+
+    // vpn 所在的 2M 完全在区域中、还没有任何页表项时，一次分配 2M 连续的页帧用大页映射
+    // 没有足够大的连续页帧时返回 false，退回 4K 的页
+    fn map_huge(&mut self, pt: &mut PageTable, vpn: VirtPage, flags: PTEFlags) -> bool {
+        let start = vpn.0 / HUGE_PAGES * HUGE_PAGES;
+        if !self.allow_huge || start < self.start_vpn.0 || start + HUGE_PAGES > self.end_vpn.0 {
+            return false;
+        }
+        if pt.find_pte_with_level(start.into(), HUGE_LEVEL, true).is_some_and(|pte| pte.is_valid()) {
+            return false;
+        }
+        let Some(frames) = frames_alloc(HUGE_PAGES.trailing_zeros() as usize, Zone::Normal) else {
+            return false;
+        };
+        let ppn = frames.ppn;
+        for i in 0..HUGE_PAGES {
+            kernel_page_phys_to_virt(ppn.add(i)).clear_page();
+        }
+        if pt.map_huge(start.into(), ppn, flags, HUGE_LEVEL).is_none() {
+            return false;
         }
+        // 拆分后每一页单独释放，伙伴分配器会把它们重新合并
+        let ppn = frames.into_raw();
+        for i in 0..HUGE_PAGES {
+            self.frames.insert(start + i, Arc::new(PhysFrame::new(ppn.add(i))));
+        }
+        self.huge.push(start.into());
+        true
     }
+
+    // 通过内核的直接映射复制页帧，只剩这个地址空间使用时直接恢复写权限
+    // 大页中的页帧都只被这个地址空间使用时整体恢复写权限，否则拆成 4K 的页，只复制写的那一页
+    fn copy_on_write(&mut self, pt: &mut PageTable, vpn: VirtPage, flags: PTEFlags) -> Result<(), &'static str> {
+        if let Some(start) = self.huge_block(vpn) {
+            let end = start.0 + HUGE_PAGES;
+            if (start.0..end).all(|v| self.frames.get(&v).is_some_and(|f| Arc::strong_count(f) == 1)) {
+                let ppn = self.frames[&start.0].ppn;
+                pt.unmap_huge(start, HUGE_LEVEL);
+                pt.map_huge(start, ppn, flags, HUGE_LEVEL).ok_or("remap failed")?;
+                self.shared.retain(|v| v.0 < start.0 || v.0 >= end);
+                return Ok(());
+            }
+            self.split_huge(pt, vpn);
+        }
+        let frame = self.frames.get(&vpn.0).ok_or("page not mapped")?;
+        let ppn = frame.ppn;
+        // 文件映射的页帧可能还在页缓存中，总是复制
+        if Arc::strong_count(frame) == 1 && self.file.is_none() {
+            pt.remap(vpn, ppn, flags).ok_or("remap failed")?;
+        } else {
+            let new_frame = frame_alloc().ok_or("out of memory")?;
+            kernel_page_phys_to_virt(new_frame.ppn).bytes_array()
+                .copy_from_slice(kernel_page_phys_to_virt(ppn).bytes_array());
+            pt.remap(vpn, new_frame.ppn, flags).ok_or("remap failed")?;
+            self.frames.insert(vpn.0, Arc::new(new_frame));
+        }
+        self.shared.retain(|&v| v != vpn);
+        Ok(())
+    }
+
+    // MAP_SHARED 只读映射页缓存中的页帧，第一次写时记录为脏页
+    // MAP_PRIVATE 读时只读映射页缓存中的页帧，写时复制一份私有的页帧
+    fn map_file(&mut self, pt: &mut PageTable, vpn: VirtPage, access: Access, flags: PTEFlags) -> Result<(), &'static str> {
+        let file = self.file.as_mut().ok_or("not a file mapping")?;
+        let frame = page_cache::get_page(&file.inode, file.offset / PAGE_SIZE + vpn.0 - self.start_vpn.0)?;
+        let mut readonly = flags;
+        readonly.remove(PTEFlags::W);
+        if access != Access::Write {
+            pt.map(vpn, frame.ppn, readonly).ok_or("pte map failed")?;
+            if !file.shared {
+                self.shared.push(vpn);
+            }
+            self.frames.insert(vpn.0, frame);
+        } else if file.shared {
+            pt.map(vpn, frame.ppn, flags).ok_or("pte map failed")?;
+            file.dirty.push(vpn);
+            self.frames.insert(vpn.0, frame);
+        } else {
+            let new_frame = frame_alloc().ok_or("out of memory")?;
+            kernel_page_phys_to_virt(new_frame.ppn).bytes_array()
+                .copy_from_slice(kernel_page_phys_to_virt(frame.ppn).bytes_array());
+            pt.map(vpn, new_frame.ppn, flags).ok_or("pte map failed")?;
+            self.frames.insert(vpn.0, Arc::new(new_frame));
+        }
+        Ok(())
+    }
+
+    fn mark_dirty(&mut self, pt: &mut PageTable, vpn: VirtPage, flags: PTEFlags) -> Result<(), &'static str> {
+        let ppn = self.frames.get(&vpn.0).ok_or("page not mapped")?.ppn;
+        let file = self.file.as_mut().ok_or("not a file mapping")?;
+        if !file.dirty.contains(&vpn) {
+            pt.remap(vpn, ppn, flags).ok_or("remap failed")?;
+            file.dirty.push(vpn);
+        }
+        Ok(())
+    }
+
+    // 把 MAP_SHARED 的脏页写回文件，不改变文件的大小
+    // 先只读映射并刷新 tlb，写回之后的修改会再次记录为脏页
+    pub fn sync(&mut self, pt: &mut PageTable) -> Result<(), &'static str> {
+        let flags = PTEFlags::from_bits(self.permission.bits()).ok_or("invalid permission")?;
+        let Some(file) = self.file.as_mut() else {
+            return Ok(());
+        };
+        if file.dirty.is_empty() {
+            return Ok(());
+        }
+
+        let mut readonly = flags;
+        readonly.remove(PTEFlags::W);
+        let dirty = core::mem::take(&mut file.dirty);
+        for vpn in dirty.iter() {
+            if let Some(frame) = self.frames.get(&vpn.0) {
+                pt.remap(*vpn, frame.ppn, readonly);
+            }
+        }
+        flush_tlb_all();
+
+        let size = file.inode.metadata().map_err(|_| "file metadata failed")?.size;
+        for vpn in dirty {
+            let Some(frame) = self.frames.get(&vpn.0) else {
+                continue;
+            };
+            let pos = file.offset + (vpn.0 - self.start_vpn.0) * PAGE_SIZE;
+            if pos < size {
+                let len = (size - pos).min(PAGE_SIZE);
+                file.inode.write_at(pos, &kernel_page_phys_to_virt(frame.ppn).bytes_array()[..len])
+                    .map_err(|_| "write file failed")?;
+            }
+        }
+        Ok(())
+    }
+
+    // 在 at 处分成两个区域，返回 [at, end)，页帧、换出的页和文件映射的偏移随之分开
+    pub fn split(&mut self, pt: &mut PageTable, at: VirtPage) -> Self {
+        self.split_huge(pt, at);
+        let (huge, rest): (Vec<VirtPage>, Vec<VirtPage>) = self.huge.iter().partition(|v| v.0 >= at.0);
+        self.huge = rest;
+        let frames = self.frames.split_off(&at.0);
+        let (shared, rest): (Vec<VirtPage>, Vec<VirtPage>) = self.shared.iter().partition(|v| v.0 >= at.0);
+        self.shared = rest;
+        let swapped = if self.swapped > 0 {
+            (at.0..self.end_vpn.0)
+                .filter(|v| pt.find_pte_only((*v).into()).is_some_and(|pte| pte.swap_slot().is_some()))
+                .count()
+        } else {
+            0
+        };
+        self.swapped -= swapped;
+        let skip = (at.0 - self.start_vpn.0) * PAGE_SIZE;
+        let file = self.file.as_mut().map(|f| {
+            let (dirty, rest): (Vec<VirtPage>, Vec<VirtPage>) = f.dirty.iter().partition(|v| v.0 >= at.0);
+            f.dirty = rest;
+            FileMap { inode: f.inode.clone(), offset: f.offset + skip, shared: f.shared, writable: f.writable, dirty }
+        });
+        let end_vpn = self.end_vpn;
+        self.end_vpn = at;
+
+        Self {
+            start_vpn: at,
+            end_vpn,
+            map_type: self.map_type,
+            permission: self.permission,
+            frames,
+            shared,
+            swapped,
+            file,
+            huge,
+            allow_huge: self.allow_huge,
+        }
+    }
+
+    // 改变区域的权限并重写页表项，调用者刷新 tlb
+    // 写时复制的页和没有写过的 MAP_SHARED 页仍然只读，第一次写时由 page_fault 处理，零页在下次访问时重新映射
+    // 没有任何访问权限（PROT_NONE）时页表项置为无效，但是保留页帧和物理页号
+    // 大页先拆成 4K 的页
+    pub fn set_permission(&mut self, pt: &mut PageTable, permission: Permission) -> Result<(), &'static str> {
+        for start in core::mem::take(&mut self.huge) {
+            pt.split_huge(start, HUGE_LEVEL);
+        }
+        let flags = PTEFlags::from_bits(permission.bits()).ok_or("invalid permission")?;
+        let mut readonly = flags;
+        readonly.remove(PTEFlags::W);
+        let none = !permission.intersects(Permission::R | Permission::W | Permission::X);
+        for v in self.start_vpn.0..self.end_vpn.0 {
+            let vpn: VirtPage = v.into();
+            let Some(pte) = pt.find_pte_only(vpn) else {
+                continue;
+            };
+            if pte.swap_slot().is_some() {
+                continue;
+            }
+            let ppn = match self.frames.get(&v) {
+                Some(frame) => frame.ppn,
+                None if matches!(self.map_type, MapType::Framed) => {
+                    pte.clear();
+                    continue;
+                }
+                None if pte.is_valid() || pte.ppn().0 != 0 => pte.ppn(),
+                None => continue,
+            };
+            let cow = self.shared.contains(&vpn)
+                || self.file.as_ref().is_some_and(|f| f.shared && !f.dirty.contains(&vpn));
+            let mut new_pte = PageTableEntry::new(ppn, if cow { readonly } else { flags } | PTEFlags::V);
+            if none {
+                new_pte.clear_flag(PTEFlags::V);
+            }
+            *pte = new_pte;
+        }
+        self.permission = permission;
+        Ok(())
+    }
+
+    // 选出最多 n 个可以换出的页，把它们的页表项换成交换槽位，返回页帧和槽位
+    // 调用者刷新 tlb 之后再写入交换分区，然后释放页帧
+    // second_chance 时跳过最近访问过的页并清除访问位，被其他地址空间共享的页帧换出后也不能释放，总是跳过
+    pub fn swap_out(&mut self, pt: &mut PageTable, n: usize, second_chance: bool) -> Vec<(VirtPage, Arc<PhysFrame>, usize)> {
+        let mut victims = Vec::new();
+        if let MapType::Defined = self.map_type {
+            return victims;
+        }
+        // MAP_SHARED 的页属于文件，不换出到交换分区
+        if self.shared_file() {
+            return victims;
+        }
+
+        // 大页不换出
+        let vpns: Vec<usize> = self.frames.iter()
+            .filter(|(k, f)| Arc::strong_count(f) == 1 && self.huge_block((**k).into()).is_none())
+            .map(|(k, _)| *k)
+            .collect();
+        for v in vpns {
+            if victims.len() >= n {
+                break;
+            }
+            let Some(pte) = pt.find_pte_only(v.into()) else {
+                continue;
+            };
+            if !pte.is_valid() {
+                continue;
+            }
+            if second_chance && pte.is_accessed() {
+                pte.clear_accessed();
+                continue;
+            }
+            let Some(slot) = swap::alloc_slot() else {
+                break;
+            };
+            *pte = PageTableEntry::new_swap(slot);
+            let frame = self.frames.remove(&v).unwrap();
+            // 换入时得到的是私有的页帧，不再需要写时复制
+            self.shared.retain(|s| s.0 != v);
+            self.swapped += 1;
+            victims.push((v.into(), frame, slot));
+        }
+
+        victims
+    }
+
+    // 写入交换分区失败时恢复原来的映射
+    pub fn swap_restore(&mut self, pt: &mut PageTable, vpn: VirtPage, frame: Arc<PhysFrame>, slot: usize) {
+        swap::free_slot(slot);
+        if let Some(flags) = PTEFlags::from_bits(self.permission.bits()) {
+            pt.set_pte(PageTableEntry::new(frame.ppn, flags | PTEFlags::V), vpn);
+        }
+        self.frames.insert(vpn.0, frame);
+        self.swapped -= 1;
+    }
+
+    // 从交换分区读回页，槽位被 fork 出的其他地址空间共享时只减少引用计数
+    fn swap_in(&mut self, pt: &mut PageTable, vpn: VirtPage, slot: usize, flags: PTEFlags) -> Result<(), &'static str> {
+        let frame = frame_alloc().ok_or("out of memory")?;
+        swap::read_page(slot, frame.ppn)?;
+        pt.map(vpn, frame.ppn, flags).ok_or("pte map failed")?;
+        swap::free_slot(slot);
+        self.frames.insert(vpn.0, Arc::new(frame));
+        self.swapped -= 1;
+        Ok(())
+    }
+}
+
+// 页错误的访问类型
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Access {
+    Read,
+    Write,
+    Execute,
 }
 
 #[derive(Copy, Clone, Debug)]
@@ -261,6 +793,7 @@ bitflags! {
     }
 }
 
+// read 和 write 先把用户数据复制到内核的缓冲区，文件只通过 UserBuffer 访问这个缓冲区
 pub struct UserBuffer {
     // 为了不在 unsafe 中使用，采用引用的方式，'static 生命周期相当于告诉编译器不要去检查
     pub buffer: &'static mut [u8]
@@ -283,7 +816,7 @@ impl UserBuffer {
     }
 
     pub fn copy_to_vector(&self) -> Vec<u8> {
-        copy_from_user_into_vector(self.buffer.as_ptr(), self.buffer.len())
+        self.buffer.to_vec()
     }
 }
 
@@ -1,7 +1,7 @@
 use alloc::{
     sync::Arc, vec::Vec, vec
 };
-use spin::mutex::Mutex;
+use crate::sync::Mutex;
 use crate::arch::memory::page::VirtPage;
 
 #[derive(Clone)]
@@ -1,28 +1,16 @@
-// dma 区域主要是为了 virtio，暂定物理内存位置为 0x87000000 ~ 0x88000000 16M
+// dma 区域主要是为了 virtio，由页帧分配器的 DMA 区域分配，暂定物理内存位置为 0x87000000 ~ 0x88000000 16M
 
-use crate::utils::type_extern::RefCellWrap;
-use crate::arch::memory::page::{
-    PhysPage, PhysAddr, VirtAddr, VirtPage, PAGE_SIZE
-};
-use crate::board::inner::memory::{DMA_START_ADDR, DMA_END_ADDR};
-use super::buddy::BuddyAllocator;
-use lazy_static::*;
-
-lazy_static! {
-    pub static ref DMA_ALLOCATOR: RefCellWrap<BuddyAllocator> = unsafe {
-        let start_ppn: PhysPage = PhysAddr::from(DMA_START_ADDR).into();
-        let pn = (DMA_END_ADDR - DMA_START_ADDR) / PAGE_SIZE;
-
-        RefCellWrap::new(BuddyAllocator::new(5, start_ppn.0.into(), pn))
-    };
-}
+use crate::arch::memory::page::{PhysAddr, PhysPage};
+use super::allocator::{frames_alloc, PhysFrames, Zone};
 
+// 返回物理地址，页数向上取整到 2 的幂
 pub fn dma_alloc(pn: usize) -> Option<usize> {
-    let start = DMA_ALLOCATOR.exclusive_access().alloc(pn)?;
-    Some(VirtAddr::from(start).0)
+    let frames = frames_alloc(pn.next_power_of_two().trailing_zeros() as usize, Zone::Dma)?;
+    // 由 dma_dealloc 释放
+    Some(PhysAddr::from(frames.into_raw()).0)
 }
 
 pub fn dma_dealloc(addr: usize, pn: usize) {
-    let vpn: VirtPage = VirtAddr::from(addr).into();
-    DMA_ALLOCATOR.exclusive_access().dealloc(vpn, pn)
-}
\ No newline at end of file
+    let ppn: PhysPage = PhysAddr::from(addr).into();
+    drop(PhysFrames::from_raw(ppn, pn.next_power_of_two().trailing_zeros() as usize));
+}
@@ -1,4 +1,20 @@
 use xmas_elf::ElfFile;
+use xmas_elf::header::{Class, Machine, Type};
+use xmas_elf::program::{ProgramHeader, Type as PhType};
+
+use crate::arch::memory::page::PAGE_SIZE;
+use crate::board::inner::memory::{USER_STACK_MAX, USER_STACK_START};
+
+#[cfg(feature = "riscv64_qemu")]
+const MACHINE: Machine = Machine::RISC_V;
+#[cfg(feature = "aarch64_qemu")]
+const MACHINE: Machine = Machine::AArch64;
+
+// 64 位 elf 的程序头大小
+const PH_ENTRY_SIZE: usize = 56;
+
+// PT_GNU_STACK 的类型，xmas_elf 中没有定义
+const PT_GNU_STACK: u32 = 0x6474_e551;
 
 pub fn parse(data: &[u8]) -> Result<ElfFile, &'static str> {
     let elf = ElfFile::new(data)?;
@@ -7,6 +23,108 @@ pub fn parse(data: &[u8]) -> Result<ElfFile, &'static str> {
     if magic != [0x7f, 0x45, 0x4c, 0x46] {
         return Err("Magic number invalid");
     }
+    if header.pt1.class() != Class::SixtyFour {
+        return Err("not a 64-bit elf");
+    }
+    if header.pt2.machine().as_machine() != MACHINE {
+        return Err("elf machine mismatch");
+    }
+    match header.pt2.type_().as_type() {
+        Type::Executable | Type::SharedObject => {}
+        _ => return Err("elf is not executable"),
+    }
+
+    // 程序头表必须完整地在文件中，否则 xmas_elf 读取时会越界
+    let ph_offset = header.pt2.ph_offset() as usize;
+    let ph_count = header.pt2.ph_count() as usize;
+    if header.pt2.ph_entry_size() as usize != PH_ENTRY_SIZE {
+        return Err("invalid program header size");
+    }
+    let ph_end = ph_count.checked_mul(PH_ENTRY_SIZE)
+        .and_then(|size| size.checked_add(ph_offset))
+        .ok_or("invalid program header table")?;
+    if ph_end > data.len() {
+        return Err("program header table out of file");
+    }
+
+    for i in 0..ph_count {
+        let ph = elf.program_header(i as u16)?;
+        if ph.get_type()? == PhType::Load {
+            check_load(&ph, data.len())?;
+        }
+    }
 
     Ok(elf)
-}
\ No newline at end of file
+}
+
+// 检查 PT_LOAD 段的数据在文件中，并且可以放在用户地址空间中
+fn check_load(ph: &ProgramHeader, len: usize) -> Result<(), &'static str> {
+    let offset = ph.offset() as usize;
+    let file_size = ph.file_size() as usize;
+    let vaddr = ph.virtual_addr() as usize;
+    let mem_size = ph.mem_size() as usize;
+    let align = ph.align() as usize;
+
+    if file_size > mem_size {
+        return Err("segment file size larger than memory size");
+    }
+    if offset.checked_add(file_size).map_or(true, |end| end > len) {
+        return Err("segment out of file");
+    }
+    // 不能和用户栈的预留范围以及下面的保护页重叠
+    if vaddr.checked_add(mem_size).map_or(true, |end| end > USER_STACK_START - USER_STACK_MAX - PAGE_SIZE) {
+        return Err("segment out of user space");
+    }
+    // 对齐要求 vaddr 和 offset 模 align 相等，这样才能按页映射
+    if align > 1 && (!align.is_power_of_two() || vaddr % align != offset % align) {
+        return Err("segment misaligned");
+    }
+    Ok(())
+}
+
+// 只有 ET_DYN 才能加载到任意的基址
+pub fn is_pie(elf: &ElfFile) -> bool {
+    elf.header.pt2.type_().as_type() == Type::SharedObject
+}
+
+// 动态加载器的路径，没有 PT_INTERP 时返回 None
+pub fn interp<'a>(elf: &ElfFile<'a>) -> Result<Option<&'a str>, &'static str> {
+    for ph in elf.program_iter() {
+        if ph.get_type()? == PhType::Interp {
+            let start = ph.offset() as usize;
+            let end = start.checked_add(ph.file_size() as usize).ok_or("invalid interp")?;
+            let path = elf.input.get(start..end).ok_or("interp out of file")?;
+            // 以 \0 结尾
+            let path = path.split(|&b| b == 0).next().unwrap_or(&[]);
+            let path = core::str::from_utf8(path).map_err(|_| "invalid interp")?;
+            return Ok(Some(path));
+        }
+    }
+    Ok(None)
+}
+
+// PT_GNU_STACK 决定用户栈是否可执行，没有时不可执行
+pub fn stack_executable(elf: &ElfFile) -> bool {
+    for ph in elf.program_iter() {
+        if let Ok(PhType::OsSpecific(PT_GNU_STACK)) = ph.get_type() {
+            return ph.flags().is_execute();
+        }
+    }
+    false
+}
+
+// 所有 PT_LOAD 段占用的页范围 [start, end)，用来确定 PIE 的基址
+pub fn load_range(elf: &ElfFile) -> Result<(usize, usize), &'static str> {
+    let mut start = usize::MAX;
+    let mut end = 0;
+    for ph in elf.program_iter() {
+        if ph.get_type()? == PhType::Load {
+            start = start.min(ph.virtual_addr() as usize / PAGE_SIZE * PAGE_SIZE);
+            end = end.max((ph.virtual_addr() + ph.mem_size()) as usize);
+        }
+    }
+    if start > end {
+        return Err("no loadable segment");
+    }
+    Ok((start, end.div_ceil(PAGE_SIZE) * PAGE_SIZE))
+}
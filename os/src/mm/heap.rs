@@ -0,0 +1,247 @@
+// 内核堆
+// - 不超过 SLAB_MAX 的分配（Arc<Mutex<Process>>、Msg、BTreeMap 的节点等）按大小放到 SLAB_SIZES 中的一个对象缓存，
+//   每个 slab 是一个页帧，页的开头是 SlabHeader，后面是大小相同的对象
+// - 更大的分配使用链表堆，开始时只有链接脚本预留的 sheap..eheap，不够时从页帧分配器申请新的区域
+// slab 和新的区域都通过内核的直接映射访问，不需要修改页表
+use core::alloc::{GlobalAlloc, Layout};
+use core::mem::size_of;
+use core::ptr::{null_mut, NonNull};
+use linked_list_allocator::Heap;
+
+use crate::arch::memory::page::{kernel_phys_to_virt, kernel_virt_to_phys, PhysAddr, VirtAddr, PAGE_SIZE};
+use crate::sync::Mutex;
+use super::allocator::{kernel_frames_alloc, PhysFrames, FRAME_ALLOCATOR, MAX_ORDER};
+
+const SLAB_SIZES: [usize; 8] = [8, 16, 32, 64, 128, 256, 512, 1024];
+const SLAB_MAX: usize = 1024;
+// 链表堆每次至少扩大 2^GROW_ORDER 页
+const GROW_ORDER: usize = 4;
+// 区域用固定大小的数组保存，扩大堆的时候不需要再分配内存
+const MAX_REGIONS: usize = 64;
+
+#[global_allocator]
+static HEAP_ALLOCATOR: KernelHeap = KernelHeap::new();
+
+// slab 的第一个对象之前是这个结构
+#[repr(C)]
+struct SlabHeader {
+    // 空闲对象的链表，对象的前 8 个字节是下一个空闲对象的地址，0 表示结束
+    free: usize,
+    inuse: usize,
+    // 还有空闲对象的 slab 组成的双向链表
+    prev: usize,
+    next: usize,
+}
+
+fn header(slab: usize) -> &'static mut SlabHeader {
+    unsafe { &mut *(slab as *mut SlabHeader) }
+}
+
+struct SlabCache {
+    size: usize,
+    partial: usize,
+    slabs: usize,
+}
+
+impl SlabCache {
+    const fn new(size: usize) -> Self {
+        Self { size, partial: 0, slabs: 0 }
+    }
+
+    // 对象按自己的大小对齐，小于 SlabHeader 的对象从 SlabHeader 之后开始
+    fn new_slab(&mut self) -> Option<usize> {
+        let ppn = kernel_frames_alloc(0)?.into_raw();
+        let slab = kernel_phys_to_virt(ppn.into()).0;
+        let first = slab + self.size.max(size_of::<SlabHeader>());
+        let mut obj = first;
+        while obj + self.size < slab + PAGE_SIZE {
+            unsafe { *(obj as *mut usize) = obj + self.size };
+            obj += self.size;
+        }
+        unsafe { *(obj as *mut usize) = 0 };
+        *header(slab) = SlabHeader { free: first, inuse: 0, prev: 0, next: 0 };
+        self.slabs += 1;
+        Some(slab)
+    }
+
+    fn push(&mut self, slab: usize) {
+        let h = header(slab);
+        h.prev = 0;
+        h.next = self.partial;
+        if self.partial != 0 {
+            header(self.partial).prev = slab;
+        }
+        self.partial = slab;
+    }
+
+    fn remove(&mut self, slab: usize) {
+        let h = header(slab);
+        if h.prev == 0 {
+            self.partial = h.next;
+        } else {
+            header(h.prev).next = h.next;
+        }
+        if h.next != 0 {
+            header(h.next).prev = h.prev;
+        }
+    }
+
+    fn alloc(&mut self) -> *mut u8 {
+        if self.partial == 0 {
+            match self.new_slab() {
+                Some(slab) => self.push(slab),
+                None => return null_mut(),
+            }
+        }
+        let slab = self.partial;
+        let h = header(slab);
+        let obj = h.free;
+        h.free = unsafe { *(obj as *const usize) };
+        h.inuse += 1;
+        if h.free == 0 {
+            self.remove(slab);
+        }
+        obj as *mut u8
+    }
+
+    // 空的 slab 还给页帧分配器，但是保留最后一个有空闲对象的 slab，避免反复申请和释放
+    fn dealloc(&mut self, ptr: *mut u8) {
+        let obj = ptr as usize;
+        let slab = obj & !(PAGE_SIZE - 1);
+        let h = header(slab);
+        let full = h.free == 0;
+        unsafe { *(obj as *mut usize) = h.free };
+        h.free = obj;
+        h.inuse -= 1;
+        if full {
+            self.push(slab);
+        }
+        if h.inuse == 0 && (h.prev != 0 || h.next != 0) {
+            self.remove(slab);
+            self.slabs -= 1;
+            drop(PhysFrames::from_raw(kernel_virt_to_phys(VirtAddr(slab)).into(), 0));
+        }
+    }
+}
+
+struct LargeHeap {
+    // 第一个区域是 sheap..eheap，之后的区域是从页帧分配器申请的 2^orders[i] 页
+    regions: [Heap; MAX_REGIONS],
+    orders: [usize; MAX_REGIONS],
+    count: usize,
+}
+
+impl LargeHeap {
+    const fn new() -> Self {
+        const EMPTY: Heap = Heap::empty();
+        Self { regions: [EMPTY; MAX_REGIONS], orders: [0; MAX_REGIONS], count: 0 }
+    }
+
+    fn add_region(&mut self, start: usize, size: usize, order: usize) {
+        unsafe { self.regions[self.count].init(start as *mut u8, size) };
+        self.orders[self.count] = order;
+        self.count += 1;
+    }
+
+    // 所有区域都不够时扩大堆，区域至少能放下 layout
+    fn alloc(&mut self, layout: Layout) -> *mut u8 {
+        for heap in self.regions[..self.count].iter_mut() {
+            if let Ok(ptr) = heap.allocate_first_fit(layout) {
+                return ptr.as_ptr();
+            }
+        }
+        if self.count == MAX_REGIONS {
+            return null_mut();
+        }
+        let pages = (layout.size() + layout.align() + PAGE_SIZE - 1) / PAGE_SIZE + 1;
+        let order = (pages.next_power_of_two().trailing_zeros() as usize).max(GROW_ORDER);
+        if order > MAX_ORDER {
+            return null_mut();
+        }
+        let Some(frames) = kernel_frames_alloc(order) else {
+            return null_mut();
+        };
+        let start = kernel_phys_to_virt(PhysAddr::from(frames.into_raw())).0;
+        self.add_region(start, PAGE_SIZE << order, order);
+        match self.regions[self.count - 1].allocate_first_fit(layout) {
+            Ok(ptr) => ptr.as_ptr(),
+            Err(_) => null_mut(),
+        }
+    }
+
+    // 扩大出来的区域空了就还给页帧分配器
+    fn dealloc(&mut self, ptr: *mut u8, layout: Layout) {
+        let Some(i) = self.regions[..self.count].iter().position(|h| h.bottom() <= ptr && ptr < h.top()) else {
+            panic!("Heap dealloc {:p} is not in the kernel heap", ptr);
+        };
+        unsafe { self.regions[i].deallocate(NonNull::new_unchecked(ptr), layout) };
+        if i > 0 && self.regions[i].used() == 0 {
+            let ppn = kernel_virt_to_phys(VirtAddr(self.regions[i].bottom() as usize)).into();
+            drop(PhysFrames::from_raw(ppn, self.orders[i]));
+            self.count -= 1;
+            self.regions.swap(i, self.count);
+            self.orders.swap(i, self.count);
+            self.regions[self.count] = Heap::empty();
+        }
+    }
+}
+
+pub struct KernelHeap {
+    slabs: [Mutex<SlabCache>; SLAB_SIZES.len()],
+    large: Mutex<LargeHeap>,
+}
+
+impl KernelHeap {
+    const fn new() -> Self {
+        Self {
+            slabs: [
+                Mutex::new(SlabCache::new(SLAB_SIZES[0])),
+                Mutex::new(SlabCache::new(SLAB_SIZES[1])),
+                Mutex::new(SlabCache::new(SLAB_SIZES[2])),
+                Mutex::new(SlabCache::new(SLAB_SIZES[3])),
+                Mutex::new(SlabCache::new(SLAB_SIZES[4])),
+                Mutex::new(SlabCache::new(SLAB_SIZES[5])),
+                Mutex::new(SlabCache::new(SLAB_SIZES[6])),
+                Mutex::new(SlabCache::new(SLAB_SIZES[7])),
+            ],
+            large: Mutex::new(LargeHeap::new()),
+        }
+    }
+}
+
+// 分配和释放时由 layout 决定使用哪个对象缓存，没有合适的缓存时返回 None
+fn slab_index(layout: Layout) -> Option<usize> {
+    let size = layout.size().max(layout.align());
+    if size > SLAB_MAX {
+        return None;
+    }
+    SLAB_SIZES.iter().position(|&s| s >= size)
+}
+
+// 锁会禁止抢占，中断处理函数分配内存时不会和被打断的分配死锁
+unsafe impl GlobalAlloc for KernelHeap {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        match slab_index(layout) {
+            Some(i) => self.slabs[i].lock().alloc(),
+            None => self.large.lock().alloc(layout),
+        }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        match slab_index(layout) {
+            Some(i) => self.slabs[i].lock().dealloc(ptr),
+            None => self.large.lock().dealloc(ptr, layout),
+        }
+    }
+}
+
+// 页帧分配器初始化时需要分配内存，必须在这里完成，不能等到第一次扩大堆时在持有堆的锁的情况下初始化
+pub fn init_heap() {
+    extern "C" {
+        fn sheap();
+        fn eheap();
+    }
+
+    HEAP_ALLOCATOR.large.lock().add_region(sheap as usize, eheap as usize - sheap as usize, 0);
+    lazy_static::initialize(&FRAME_ALLOCATOR);
+}
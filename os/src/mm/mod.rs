@@ -4,27 +4,86 @@ pub mod area;
 pub mod elf;
 pub mod buddy;
 pub mod dma;
+pub mod heap;
+pub mod page_cache;
+pub mod stack;
+pub mod swap;
 
+use alloc::collections::BTreeMap;
+use alloc::string::String;
 use alloc::sync::{Arc, Weak};
 use alloc::vec::Vec;
-use area::{MapArea, Permission, MapType};
+use area::{Access, FileMap, MapArea, Permission, MapType};
 use crate::arch::memory::page::{
-    PhysAddr, PhysPage, VirtAddr, VirtPage, PAGE_SIZE
+    flush_tlb_all, PhysAddr, PhysPage, VirtAddr, VirtPage, HUGE_PAGES, PAGE_SIZE
 };
+use allocator::PhysFrame;
 use buddy::BuddyAllocator;
 use pt::PageTable;
-use spin::rwlock::RwLock;
+use crate::sync::{Mutex, RwLock};
 
 use crate::arch::context::TrapContext;
+use crate::file::fs::FILESYSTEM;
+use crate::utils::random::random;
+use rcore_fs::vfs::INode;
+use xmas_elf::ElfFile;
 use crate::board::inner::memory::*;
 
+// 每个线程一个内核栈，slot 0 是主线程的内核栈，栈顶为 KERNEL_STACK_START
+// 其他线程的内核栈依次向上排列，相邻内核栈之间留一个保护页
+pub const MAX_THREADS: usize = 64;
+
+// ET_DYN 的加载基址，在 [base, base + PIE_RANDOM_SIZE) 中随机选择，elf 最大也是 PIE_RANDOM_SIZE
+// 加上后面的 mmap 区域也不会和动态加载器以及 kmap 使用的物理地址重叠
+const PIE_BASE: usize = 0x2000_0000;
+const INTERP_BASE: usize = 0x3000_0000;
+const PIE_RANDOM_SIZE: usize = 0x0400_0000;
+
+// brk 堆紧跟在最后一个段之后，最大 MAX_HEAP_SIZE，之后留一个保护页再放 mmap 区域
+pub const MAX_HEAP_SIZE: usize = 0x0400_0000;
+const MMAP_PAGES: usize = 16 * 1000;
+
+// 文件映射的 flags，和 linux 相同
+pub const MAP_SHARED: usize = 0x01;
+pub const MAP_PRIVATE: usize = 0x02;
+
+// ET_EXEC 加载到 elf 中指定的地址，ET_DYN 加载到随机的基址
+fn load_base(elf: &ElfFile, region: usize) -> Result<usize, &'static str> {
+    if !elf::is_pie(elf) {
+        return Ok(0);
+    }
+    let (start, end) = elf::load_range(elf)?;
+    if end - start > PIE_RANDOM_SIZE {
+        return Err("pie too large");
+    }
+    let base = region + random() % (PIE_RANDOM_SIZE / PAGE_SIZE) * PAGE_SIZE;
+    // 第一个段的地址不一定是 0
+    base.checked_sub(start).ok_or("invalid pie address")
+}
+
+pub fn kernel_stack_top(slot: usize) -> usize {
+    KERNEL_STACK_START + slot * (KERNEL_STACK_SIZE + PAGE_SIZE)
+}
+
 // The memory manager for a process
 pub struct MemoryManager {
     pub pt: PageTable,
-    kernel_stack_area: MapArea,
+    // slot -> kernel stack area
+    kernel_stacks: BTreeMap<usize, MapArea>,
     app_areas: Vec<Arc<RwLock<MapArea>>>,
     // buddy allocator for the dynamic mmap
     buddy_alloctor: Option<BuddyAllocator>,
+    // 堆的起始地址和当前的 brk，堆所在的 map area 也在 app_areas 中
+    heap_start: usize,
+    brk: usize,
+    // mmap 分配的虚拟地址范围，起始页 => 结束页，mprotect 可能把一次映射分成几个区域
+    dyn_ranges: BTreeMap<usize, usize>,
+    // W^X：为 false 时用户页不能同时可写和可执行
+    allow_wx: bool,
+    // 用户栈可以增长到的最低页，下面一页是保护页，没有用户栈时为 0
+    stack_limit: usize,
+    // RLIMIT_RSS 允许的常驻页数，页错误分配页帧时检查
+    rss_limit: usize,
 
     // legacy member, not used now
     _kernel_area: Vec<MapArea>,
@@ -32,50 +91,106 @@ pub struct MemoryManager {
 
 impl MemoryManager {
     pub fn new(if_kernel: bool) -> Self {
-        let mut pt = PageTable::new();
+        let pt = PageTable::new();
         let app_areas: Vec<Arc<RwLock<MapArea>>> = Vec::with_capacity(8);
         let _kernel_area = Vec::new();
 
-        let mut kernel_stack_area = MapArea::new(
-            (KERNEL_STACK_START- KERNEL_STACK_SIZE).into(), 
-            (KERNEL_STACK_START).into(),
-            MapType::Framed, 
-            Permission::R | Permission::W
-        );
-
-        if !if_kernel {
-            kernel_stack_area.map(&mut pt);
-        }
-        
-        Self {
+        let mut mm = Self {
             pt,
-            kernel_stack_area,
+            kernel_stacks: BTreeMap::new(),
             app_areas,
             buddy_alloctor: None,
+            heap_start: 0,
+            brk: 0,
+            dyn_ranges: BTreeMap::new(),
+            allow_wx: false,
+            stack_limit: 0,
+            rss_limit: usize::MAX,
             _kernel_area,
+        };
+
+        if !if_kernel {
+            mm.map_kernel_stack(0);
+        }
+
+        mm
+    }
+
+    // 被进程持有的地址空间都登记到 swap 中，内存不足时从中换出页
+    pub fn into_shared(self) -> Arc<Mutex<Self>> {
+        let mm = Arc::new(Mutex::new(self));
+        swap::register(&mm);
+        mm
+    }
+
+    fn map_kernel_stack(&mut self, slot: usize) {
+        let top = kernel_stack_top(slot);
+        let mut area = MapArea::new(
+            (top - KERNEL_STACK_SIZE).into(),
+            top.into(),
+            MapType::Framed,
+            Permission::R | Permission::W
+        );
+        area.map(&mut self.pt);
+        self.kernel_stacks.insert(slot, area);
+    }
+
+    // 为新线程分配内核栈，返回 slot
+    pub fn alloc_kernel_stack(&mut self) -> Option<usize> {
+        let slot = (1..MAX_THREADS).find(|s| !self.kernel_stacks.contains_key(s))?;
+        self.map_kernel_stack(slot);
+        Some(slot)
+    }
+
+    // 线程退出后回收内核栈，调用时不能运行在这个栈上
+    pub fn dealloc_kernel_stack(&mut self, slot: usize) {
+        if let Some(mut area) = self.kernel_stacks.remove(&slot) {
+            area.unmap(&mut self.pt);
         }
     }
 
     fn alloc(&mut self, pn: usize) -> Option<(VirtPage, VirtPage)> {
         if let Some(allocator) = &mut self.buddy_alloctor {
             let start = allocator.alloc(pn)?;
+            self.dyn_ranges.insert(start.0, start.0 + pn);
             Some((start, start.add(pn)))
         } else {
             None
         }
     }
 
-    fn dealloc(&mut self, area: &Arc<RwLock<MapArea>>) {
-        let vpn = area.read().start_vpn;
-        let pn = area.read().end_vpn.0 - area.read().start_vpn.0;
+    fn dealloc(&mut self, vpn: VirtPage, pn: usize) {
         if let Some(allocator) = &mut self.buddy_alloctor {
             allocator.dealloc(vpn, pn)
         }
     }
 
+    pub fn allow_wx(&self) -> bool {
+        self.allow_wx
+    }
+
+    pub fn set_allow_wx(&mut self, allow: bool) {
+        self.allow_wx = allow;
+    }
+
+    // limit 是 RLIMIT_RSS 的软限制，单位是字节
+    pub fn set_rss_limit(&mut self, limit: usize) {
+        self.rss_limit = limit / PAGE_SIZE;
+    }
+
+    // 进程没有允许时，拒绝同时可写和可执行的用户页
+    fn deny_wx(&self, permission: Permission) -> bool {
+        if !self.allow_wx && permission.contains(Permission::W | Permission::X) {
+            println!("[kernel] writable and executable mapping denied (W^X)");
+            return true;
+        }
+        false
+    }
+
     // return kernel stack pointer
     pub fn push_context(&mut self, ctx: TrapContext, current_pt: &mut PageTable) -> usize {
-        let sp_pa = self.pt.find_pte(self.kernel_stack_area.end_vpn.prev()).unwrap().ppn().next();
+        let kernel_stack_end = self.kernel_stacks[&0].end_vpn;
+        let sp_pa = self.pt.find_pte(kernel_stack_end.prev()).unwrap().ppn().next();
 
         let trap_ctx_ptr = (PhysAddr::from(sp_pa).0 - core::mem::size_of::<TrapContext>()) as *mut TrapContext;
         current_pt.kmap(PhysAddr(trap_ctx_ptr as usize));
@@ -84,20 +199,21 @@ impl MemoryManager {
         }
         current_pt.kunmap(PhysAddr(trap_ctx_ptr as usize));
 
-        let trap_ctx_ptr_va = VirtAddr::from(self.kernel_stack_area.end_vpn).0 - core::mem::size_of::<TrapContext>();
+        let trap_ctx_ptr_va = VirtAddr::from(kernel_stack_end).0 - core::mem::size_of::<TrapContext>();
         trap_ctx_ptr_va as usize
     }
 
-    pub fn runtime_pull_context(&mut self) -> TrapContext {
-        let trap_ctx_ptr = (KERNEL_STACK_START - core::mem::size_of::<TrapContext>()) as *mut TrapContext;
+    // 读写当前页表中 slot 对应内核栈上的 TrapContext
+    pub fn runtime_pull_context(&mut self, slot: usize) -> TrapContext {
+        let trap_ctx_ptr = (kernel_stack_top(slot) - core::mem::size_of::<TrapContext>()) as *mut TrapContext;
         unsafe { 
             let ctx = (*trap_ctx_ptr).clone();
             ctx
         }
     }
 
-    pub fn runtime_push_context(&mut self, ctx: TrapContext) -> usize {
-        let trap_ctx_ptr = (KERNEL_STACK_START - core::mem::size_of::<TrapContext>()) as *mut TrapContext;
+    pub fn runtime_push_context(&mut self, slot: usize, ctx: TrapContext) -> usize {
+        let trap_ctx_ptr = (kernel_stack_top(slot) - core::mem::size_of::<TrapContext>()) as *mut TrapContext;
         unsafe {
             *trap_ctx_ptr = ctx;
         }
@@ -105,66 +221,170 @@ impl MemoryManager {
         trap_ctx_ptr as usize
     }
     
-    pub fn load_elf(&mut self, current_pt: &mut PageTable, data: &[u8], runtime: bool) -> Result<(usize, usize), &'static str>{
-        // 根据 elf 文件生成 MapArea
-        let elf = elf::parse(data)?;
-        let ph_count = elf.header.pt2.ph_count();
-        let mut offset = VirtPage(0);
-        for i in 0..ph_count {
-            let ph = elf.program_header(i)?;
+    // 加载 elf 的 PT_LOAD 段，base 是 PIE 的加载基址，返回程序头的地址和最后一个段的结束页
+    fn load_segments(&mut self, current_pt: &mut PageTable, elf: &ElfFile, base: usize) -> Result<(usize, VirtPage), &'static str> {
+        let ph_offset = elf.header.pt2.ph_offset();
+        let mut phdr = 0;
+        let mut last: Option<Arc<RwLock<MapArea>>> = None;
+        let mut last_end = 0;
+        for ph in elf.program_iter() {
             let ph_type = ph.get_type()?;
-            if ph_type == xmas_elf::program::Type::Load {
-                let start_va: VirtAddr = (ph.virtual_addr() as usize).into();
-                let end_va: VirtAddr = ((ph.virtual_addr() + ph.mem_size()) as usize).into();
-                let mut permission = Permission::U;
-                let ph_flags = ph.flags();
-                if ph_flags.is_read() {
-                    permission |= Permission::R;
-                }
-                if ph_flags.is_write() {
-                    permission |= Permission::W;
-                }
-                if ph_flags.is_execute() {
-                    permission |= Permission::X;
-                }
-                let mut area = MapArea::new(start_va, end_va, MapType::Framed, permission);
-                // copy data from elf into map area
-                if runtime == true {
-                    area.runtime_map_with_data(
-                        &mut self.pt, 
-                        &elf.input[ph.offset() as usize..(ph.offset() + ph.file_size()) as usize])?;
-                } else {
-                    area.map_with_data(
-                        &mut self.pt, current_pt,
-                        &elf.input[ph.offset() as usize..(ph.offset() + ph.file_size()) as usize])?;
+            if ph_type == xmas_elf::program::Type::Phdr {
+                phdr = base + ph.virtual_addr() as usize;
+            }
+            if ph_type != xmas_elf::program::Type::Load || ph.mem_size() == 0 {
+                continue;
+            }
+            // 程序头在这个段中时，计算它被加载到的地址
+            if phdr == 0 && ph.offset() <= ph_offset && ph_offset < ph.offset() + ph.file_size() {
+                phdr = base + (ph.virtual_addr() + ph_offset - ph.offset()) as usize;
+            }
+
+            // 段的起始地址不需要页对齐，数据从第一页的页内偏移处开始存放
+            let start = base + ph.virtual_addr() as usize;
+            let end = start + ph.mem_size() as usize;
+            if start < last_end {
+                return Err("segments overlap or not in order");
+            }
+            let start_va: VirtAddr = (start / PAGE_SIZE * PAGE_SIZE).into();
+            let mut permission = Permission::U;
+            let ph_flags = ph.flags();
+            if ph_flags.is_read() {
+                permission |= Permission::R;
+            }
+            if ph_flags.is_write() {
+                permission |= Permission::W;
+            }
+            if ph_flags.is_execute() {
+                permission |= Permission::X;
+            }
+            if self.deny_wx(permission) {
+                return Err("segment is writable and executable");
+            }
+            let mut area = MapArea::new(start_va, end.into(), MapType::Framed, permission);
+            // 和上一个段共用第一页
+            if let Some(prev) = &last {
+                if prev.read().end_vpn.0 > area.start_vpn.0 {
+                    if self.deny_wx(permission | prev.read().permission()) {
+                        return Err("writable and executable segments share a page");
+                    }
+                    area.share_first_page(&mut prev.write(), &mut self.pt)?;
                 }
-                offset = area.end_vpn;
-                self.app_areas.push(Arc::new(RwLock::new(area)));
             }
+            // copy data from elf into map area, 剩余部分是 bss，会被清零
+            area.map_with_data(
+                &mut self.pt, current_pt,
+                &elf.input[ph.offset() as usize..(ph.offset() + ph.file_size()) as usize],
+                start % PAGE_SIZE)?;
+            last_end = end;
+            let area = Arc::new(RwLock::new(area));
+            self.app_areas.push(area.clone());
+            last = Some(area);
         }
 
-        // 添加一个保护页
-        let start_vpn = offset.next();
-        // 预留 512M，也就是 32*1000 个页
-        self.buddy_alloctor = Some(BuddyAllocator::new(10, start_vpn, 32*1000));
+        Ok((phdr, VirtAddr::from(last_end).into()))
+    }
+
+    // stack_size 是为用户栈预留的大小，由 RLIMIT_STACK 决定，开始时只映射放参数的页
+    // 用户栈上按照 System V ABI 放入 argv、envp 和 auxv，返回的 sp 指向 argc
+    // ET_DYN 加载到随机的基址，有 PT_INTERP 时同时加载动态加载器，从它的入口开始运行
+    pub fn load_elf(
+        &mut self, current_pt: &mut PageTable, data: &[u8], stack_size: usize,
+        argv: &[String], envp: &[String]
+    ) -> Result<(usize, usize), &'static str>{
+        // 根据 elf 文件生成 MapArea
+        let elf = elf::parse(data)?;
+        let base = load_base(&elf, PIE_BASE)?;
+        let (phdr, offset) = self.load_segments(current_pt, &elf, base)?;
+        let prog_entry = base + elf.header.pt2.entry_point() as usize;
+
+        let mut entry = prog_entry;
+        let mut interp_base = 0;
+        if let Some(path) = elf::interp(&elf)? {
+            // 根目录下的文件，忽略开头的 /
+            let interp_data = FILESYSTEM.lock().read_all(path.trim_start_matches('/')).ok_or("interpreter not found")?;
+            let interp = elf::parse(&interp_data)?;
+            if elf::interp(&interp)?.is_some() {
+                return Err("interpreter requests another interpreter");
+            }
+            interp_base = load_base(&interp, INTERP_BASE)?;
+            self.load_segments(current_pt, &interp, interp_base)?;
+            entry = interp_base + interp.header.pt2.entry_point() as usize;
+        }
+
+        // 堆开始时为空，brk 扩大时按需分配
+        let heap_start: VirtAddr = offset.next().into();
+        self.heap_start = heap_start.0;
+        self.brk = heap_start.0;
+        self.app_areas.push(Arc::new(RwLock::new(
+            MapArea::new(heap_start, heap_start, MapType::Framed, Permission::R | Permission::W | Permission::U)
+        )));
+
+        // 添加一个保护页，预留 16*1000 个页给 mmap，起始地址按 2M 对齐，2M 以上的映射可以使用大页
+        let guard = VirtPage::from(heap_start.add(MAX_HEAP_SIZE)).next().0;
+        let start_vpn = VirtPage((guard + HUGE_PAGES - 1) / HUGE_PAGES * HUGE_PAGES);
+        self.buddy_alloctor = Some(BuddyAllocator::new(10, start_vpn, MMAP_PAGES));
 
         let user_stack_top: VirtAddr = USER_STACK_START.into();
-        let user_stack_bottom: VirtAddr = user_stack_top.reduce(USER_STACK_SIZE);
+        let user_stack_bottom: VirtAddr = user_stack_top.reduce(stack_size);
+        let auxv = [
+            (stack::AT_PHDR, phdr),
+            (stack::AT_PHENT, elf.header.pt2.ph_entry_size() as usize),
+            (stack::AT_PHNUM, elf.header.pt2.ph_count() as usize),
+            (stack::AT_PAGESZ, PAGE_SIZE),
+            (stack::AT_BASE, interp_base),
+            (stack::AT_ENTRY, prog_entry),
+        ];
+        let (sp, stack_data) = stack::build(user_stack_top.0, stack_size, argv, envp, &auxv)?;
+        // PT_GNU_STACK 只决定用户栈是否可执行，其他映射仍然受 W^X 限制
+        let mut permission = Permission::R | Permission::W | Permission::U;
+        if elf::stack_executable(&elf) {
+            permission |= Permission::X;
+        }
         let mut stack_area = MapArea::new(
-            user_stack_bottom, 
+            VirtAddr::from(sp),
             user_stack_top, 
             MapType::Framed, 
-            Permission::R | Permission::W | Permission::U | Permission::X
+            permission
         );
-        stack_area.map(&mut self.pt);
+        // 只有放参数的页立即分配，其余的页在访问预留范围时由 page_fault 向下扩大区域并分配
+        stack_area.populate(&mut self.pt, VirtAddr::from(sp).into(), user_stack_top.into())?;
+        self.stack_limit = VirtPage::from(user_stack_bottom).0;
+        stack_area.write_data(current_pt, sp.into(), &stack_data)?;
         self.app_areas.push(
             Arc::new(RwLock::new(stack_area))
         );
 
-        Ok((user_stack_top.0 - 0x100, elf.header.pt2.entry_point() as usize))
+        Ok((sp, entry))
+    }
+
+    // exec 结束其他线程之前调用，把 old 的所有内核栈映射到新的页表中，页帧由两边同时持有
+    // 这一步失败时 old 不受影响，之后只需要用 retain_kernel_stacks 去掉不再需要的内核栈
+    pub fn share_kernel_stacks(&mut self, old: &Self, slot: usize) -> Result<(), &'static str> {
+        if !old.kernel_stacks.contains_key(&slot) {
+            return Err("kernel stack not found");
+        }
+        for (&s, area) in old.kernel_stacks.iter() {
+            area.map_frames(&mut self.pt)?;
+            self.kernel_stacks.insert(s, area.clone());
+        }
+        Ok(())
+    }
+
+    // 只保留 keep 返回 true 的内核栈，不会失败
+    pub fn retain_kernel_stacks(&mut self, keep: impl Fn(usize) -> bool) {
+        let slots: Vec<usize> = self.kernel_stacks.keys().copied().filter(|&s| !keep(s)).collect();
+        for slot in slots {
+            self.dealloc_kernel_stack(slot);
+        }
+    }
+
+    pub fn has_kernel_stack(&self, slot: usize) -> bool {
+        self.kernel_stacks.contains_key(&slot)
     }
 
-    pub fn fork(&mut self, parent: &mut Self) {
+    // ctx 为子进程返回用户态时的上下文，子进程只有一个线程，使用 slot 0 的内核栈
+    pub fn fork(&mut self, parent: &mut Self, ctx: TrapContext) {
         // append kernel stack
         #[cfg(feature = "riscv64_qemu")]
         self.add_kernel_pt();
@@ -176,61 +396,186 @@ impl MemoryManager {
             self.app_areas.push(Arc::new(RwLock::new(new_area)));
         }
 
-        let ctx = parent.runtime_pull_context();
         let kernel_stack_pa = self.pt.translate_ceil(
             // 所有的 memory area 都是一个左闭右开的范围，所以 end_vpn 是不被包括在内的
             // 也就是说 end_vpn 的起始位置就是地址范围的右端，同样是不被包括在内
-            self.kernel_stack_area.end_vpn.into()
+            self.kernel_stacks[&0].end_vpn.into()
         ).unwrap();
         // 由于 kernel stack start 是不被包括在内的，所以需要 -1 后的地址才是实际需要 map 的虚拟地址
         parent.pt.kmap(kernel_stack_pa.reduce(1));
         let trap_ctx_ptr = (kernel_stack_pa.0 - core::mem::size_of::<TrapContext>()) as *mut TrapContext;
         unsafe {
             *trap_ctx_ptr = ctx;
-            (*trap_ctx_ptr).x[10] = 0;
         }
         parent.pt.kunmap(kernel_stack_pa.reduce(1));
         self.buddy_alloctor = parent.buddy_alloctor.clone();
+        self.heap_start = parent.heap_start;
+        self.brk = parent.brk;
+        self.dyn_ranges = parent.dyn_ranges.clone();
+        self.allow_wx = parent.allow_wx;
+        self.stack_limit = parent.stack_limit;
+        self.rss_limit = parent.rss_limit;
     }
 
     pub fn root_ppn(&self) -> PhysPage {
         self.pt.root_ppn()
     }
 
-    pub fn cow(&mut self, vpn: VirtPage) -> Result<(), &'static str> {
-        for m in self.app_areas.iter_mut() {
-            if m.read().start_vpn.0 <= vpn.0 && vpn.0 < m.read().end_vpn.0 {
-                return m.write().cow(&mut self.pt, vpn)
+    // 找到 vpn 所在的 map area，由它按需分配页帧、写时复制或者从交换分区换入
+    // 空闲页帧不足时先换出一些页，返回这次页错误是否需要读交换分区
+    // 常驻页数超过 RLIMIT_RSS 时不再分配页帧，页错误失败
+    pub fn page_fault(&mut self, vpn: VirtPage, access: Access) -> Result<bool, &'static str> {
+        swap::reclaim(self);
+        let budget = self.rss_limit.saturating_sub(self.resident_pages());
+        let area = match self.app_areas.iter().find(|a| {
+            let a = a.read();
+            a.start_vpn.0 <= vpn.0 && vpn.0 < a.end_vpn.0
+        }) {
+            Some(area) => area.clone(),
+            None => self.grow_stack(vpn)?,
+        };
+        let major = self.pt.find_pte_only(vpn).is_some_and(|pte| pte.swap_slot().is_some());
+        let r = area.write().page_fault(&mut self.pt, vpn, access, budget);
+        r.map(|_| major)
+    }
+
+    // 访问用户栈下面还没有映射的页时，在预留范围内把最低的用户栈区域向下扩大到 vpn
+    // 预留范围下面的保护页永远不会映射，访问它说明栈溢出
+    fn grow_stack(&mut self, vpn: VirtPage) -> Result<Arc<RwLock<MapArea>>, &'static str> {
+        let top = VirtPage::from(VirtAddr::from(USER_STACK_START)).0;
+        if self.stack_limit == 0 || vpn.0 + 1 < self.stack_limit || vpn.0 >= top {
+            return Err("address not mapped");
+        }
+        if vpn.0 < self.stack_limit {
+            println!("[kernel] user stack overflow: {:#x} is in the guard page below the {}K stack",
+                VirtAddr::from(vpn).0, (top - self.stack_limit) * PAGE_SIZE / 1024);
+            return Err("stack overflow");
+        }
+
+        // mprotect 可能把用户栈分成了几个区域，扩大最低的那个
+        let area = self.app_areas.iter()
+            .filter(|a| {
+                let start = a.read().start_vpn.0;
+                vpn.0 < start && start < top
+            })
+            .min_by_key(|a| a.read().start_vpn.0)
+            .cloned()
+            .ok_or("address not mapped")?;
+        area.write().extend_down(vpn);
+        Ok(area)
+    }
+
+    // 换出最多 n 个页，返回换出的页数
+    // 第一遍跳过最近访问过的页并清除访问位，不够时第二遍再换出这些页
+    pub fn swap_out(&mut self, n: usize) -> usize {
+        let mut victims = Vec::new();
+        for second_chance in [true, false] {
+            for (i, area) in self.app_areas.iter().enumerate() {
+                if victims.len() >= n {
+                    break;
+                }
+                let pages = area.write().swap_out(&mut self.pt, n - victims.len(), second_chance);
+                victims.extend(pages.into_iter().map(|p| (i, p)));
             }
         }
 
-        println!("[kernel] vpn {} is not in memoryset", vpn.0);
-        Err("vpn is not in this memory set")
+        // 其他 cpu 可能还缓存着旧的页表项，刷新之后页的内容才不会再改变
+        flush_tlb_all();
+        let mut count = 0;
+        for (i, (vpn, frame, slot)) in victims {
+            if swap::write_page(slot, frame.ppn).is_ok() {
+                count += 1;
+            } else {
+                self.app_areas[i].write().swap_restore(&mut self.pt, vpn, frame, slot);
+            }
+        }
+        count
     }
 
+    // 用户地址空间的页数，包括还没有分配页帧的页
+    pub fn total_pages(&self) -> usize {
+        self.app_areas.iter().map(|a| {
+            let a = a.read();
+            a.end_vpn.0 - a.start_vpn.0
+        }).sum()
+    }
+
+    // 用户地址空间中常驻内存的页数
+    pub fn resident_pages(&self) -> usize {
+        self.app_areas.iter().map(|a| a.read().resident_pages()).sum()
+    }
+
+    // 退出时 MAP_SHARED 的脏页写回文件
     pub fn unmap_app(&mut self) {
         for area in self.app_areas.iter_mut() {
-            area.write().unmap(&mut self.pt);
+            let mut area = area.write();
+            if let Err(e) = area.sync(&mut self.pt) {
+                println!("[kernel] write back file mapping failed: {}", e);
+            }
+            area.unmap(&mut self.pt);
         }
 
         self.app_areas.clear();
     }
 
+    pub fn heap(&self) -> (usize, usize) {
+        (self.heap_start, self.brk)
+    }
+
+    // 超出 [heap_start, heap_start + MAX_HEAP_SIZE] 时不改变 brk，总是返回当前的 brk
+    // 缩小时释放超出部分的页帧，调用者需要刷新 tlb
+    // mprotect 可能把堆分成了几个区域，超出新结尾的区域整个释放，最后一个区域随 brk 伸缩
+    pub fn brk(&mut self, addr: usize) -> usize {
+        if self.heap_start == 0 || addr < self.heap_start || addr > self.heap_start + MAX_HEAP_SIZE {
+            return self.brk;
+        }
+        let start_vpn: VirtPage = VirtAddr::from(self.heap_start).into();
+        let limit = start_vpn.0 + MAX_HEAP_SIZE / PAGE_SIZE;
+        let end_vpn: VirtPage = VirtAddr::from(addr + PAGE_SIZE - 1).into();
+        let pt = &mut self.pt;
+        let mut last: Option<Arc<RwLock<MapArea>>> = None;
+        self.app_areas.retain(|a| {
+            let mut area = a.write();
+            if area.start_vpn.0 < start_vpn.0 || area.start_vpn.0 >= limit {
+                return true;
+            }
+            if area.start_vpn.0 > start_vpn.0 && area.start_vpn.0 >= end_vpn.0 {
+                area.unmap(pt);
+                return false;
+            }
+            if last.as_ref().map_or(true, |l| l.read().start_vpn.0 < area.start_vpn.0) {
+                last = Some(a.clone());
+            }
+            true
+        });
+        if let Some(area) = last {
+            let mut area = area.write();
+            let end = VirtPage(end_vpn.0.max(area.start_vpn.0));
+            area.resize(pt, end);
+            self.brk = addr;
+        }
+        self.brk
+    }
+
     pub fn mmap(&mut self, size: usize, permission: usize) -> Option<Weak<RwLock<MapArea>>> {
         assert_eq!(size % PAGE_SIZE, 0);
 
         let mut p = Permission::from_bits_truncate((permission as u8) << 1);
         p.insert(Permission::U);
+        if self.deny_wx(p) {
+            return None;
+        }
 
         let (start_vpn, end_vpn) = self.alloc(size / PAGE_SIZE)?;
 
+        // 匿名映射不立即分配页帧，第一次访问时由 page_fault 分配，对齐的 2M 尽量使用大页
         let mut new_area = MapArea::new(
             start_vpn.into(),
             end_vpn.into(),
             MapType::Framed,
             p
         );
-        new_area.map(&mut self.pt);
+        new_area.allow_huge();
         let area_ptr = Arc::new(RwLock::new(new_area));
         let weak_ptr = Arc::downgrade(&area_ptr);
         self.app_areas.push(area_ptr);
@@ -238,6 +583,112 @@ impl MemoryManager {
         Some(weak_ptr)
     }
 
+    // 映射文件中从 offset 开始的 size 字节，offset 需要页对齐，页在第一次访问时读取
+    // writable 表示文件以写方式打开
+    pub fn mmap_file(&mut self, inode: Arc<dyn INode>, offset: usize, size: usize, permission: usize, shared: bool, writable: bool) -> Option<usize> {
+        if offset % PAGE_SIZE != 0 {
+            return None;
+        }
+
+        let mut p = Permission::from_bits_truncate((permission as u8) << 1);
+        p.insert(Permission::U);
+        if self.deny_wx(p) {
+            return None;
+        }
+        let (start_vpn, end_vpn) = self.alloc(size.div_ceil(PAGE_SIZE))?;
+        let new_area = MapArea::new_file(start_vpn.into(), end_vpn.into(), p, FileMap::new(inode, offset, shared, writable));
+        self.app_areas.push(Arc::new(RwLock::new(new_area)));
+        Some(VirtAddr::from(start_vpn).0)
+    }
+
+    // 把 [start, end) 中的文件映射的脏页写回文件，没有映射时返回 -1
+    pub fn msync(&mut self, start: VirtPage, end: VirtPage) -> isize {
+        let mut found = false;
+        for area in self.app_areas.iter() {
+            let mut area = area.write();
+            if area.end_vpn.0 <= start.0 || end.0 <= area.start_vpn.0 {
+                continue;
+            }
+            found = true;
+            if let Err(e) = area.sync(&mut self.pt) {
+                println!("[kernel] msync failed: {}", e);
+                return -1;
+            }
+        }
+
+        if found { 0 } else { -1 }
+    }
+
+    // 把 [start, end) 的权限改为 permission，跨过边界的区域先分开，调用者刷新 tlb
+    // 范围中有没有映射的页、违反 W^X 或者 MAP_SHARED 的文件不可写时返回 -1
+    pub fn mprotect(&mut self, start: VirtPage, end: VirtPage, permission: usize) -> isize {
+        let mut p = Permission::from_bits_truncate((permission as u8) << 1);
+        p.insert(Permission::U);
+        // 页表项不能只写不读
+        if p.contains(Permission::W) {
+            p.insert(Permission::R);
+        }
+        if self.deny_wx(p) {
+            return -1;
+        }
+
+        let mut ranges: Vec<(usize, usize)> = Vec::new();
+        for area in self.app_areas.iter() {
+            let area = area.read();
+            if area.end_vpn.0 <= start.0 || end.0 <= area.start_vpn.0 {
+                continue;
+            }
+            if p.contains(Permission::W) && !area.may_write() {
+                println!("[kernel] mprotect: shared file mapping is not writable");
+                return -1;
+            }
+            ranges.push((area.start_vpn.0, area.end_vpn.0));
+        }
+        ranges.sort();
+        let mut next = start.0;
+        for (s, e) in ranges {
+            if s > next {
+                break;
+            }
+            next = next.max(e);
+        }
+        if next < end.0 {
+            println!("[kernel] mprotect: address {:#x} not mapped", VirtAddr::from(VirtPage(next)).0);
+            return -1;
+        }
+
+        let mut i = 0;
+        while i < self.app_areas.len() {
+            let area = self.app_areas[i].clone();
+            let (s, e) = {
+                let a = area.read();
+                (a.start_vpn.0, a.end_vpn.0)
+            };
+            if e <= start.0 || end.0 <= s {
+                i += 1;
+                continue;
+            }
+            // 先分出前面不改变的部分，下一次循环处理后面的部分
+            if s < start.0 {
+                let upper = area.write().split(&mut self.pt, start);
+                self.app_areas.insert(i + 1, Arc::new(RwLock::new(upper)));
+                i += 1;
+                continue;
+            }
+            if end.0 < e {
+                let upper = area.write().split(&mut self.pt, end);
+                self.app_areas.insert(i + 1, Arc::new(RwLock::new(upper)));
+            }
+            if let Err(e) = area.write().set_permission(&mut self.pt, p) {
+                println!("[kernel] mprotect failed: {}", e);
+                return -1;
+            }
+            i += 1;
+        }
+
+        0
+    }
+
     pub fn mmap_with_addr(&mut self, pa: PhysAddr, size: usize, permission: usize, user: bool) -> isize {
         assert_eq!(size % PAGE_SIZE, 0);
 
@@ -258,20 +709,36 @@ impl MemoryManager {
         self.map_defined(&ppns, p, user)
     }
 
-    pub fn umap_dyn_area(&mut self, start_vpn: VirtPage) -> isize {
-        if let Some(index) = self.app_areas.iter().position(|a| a.read().start_vpn == start_vpn) {
-            let area  = self.app_areas.remove(index);
-            area.write().unmap(&mut self.pt);
-            self.dealloc(&area);
-            return 0;
-        }
-
-        // not find
-        println!("[kernel] can't find map area start with {:#x}", start_vpn.0);
-        -1
+    // 释放一次 mmap 得到的所有区域，包括 mprotect 分出来的部分
+    // 返回区域中的页帧，其他 cpu 的 tlb 中可能还有旧的映射，调用者刷新 tlb 之后再释放
+    pub fn umap_dyn_area(&mut self, start_vpn: VirtPage) -> Option<Vec<Arc<PhysFrame>>> {
+        let Some(end) = self.dyn_ranges.remove(&start_vpn.0) else {
+            // not find
+            println!("[kernel] can't find map area start with {:#x}", start_vpn.0);
+            return None;
+        };
+
+        let pt = &mut self.pt;
+        let mut frames = Vec::new();
+        self.app_areas.retain(|a| {
+            let mut area = a.write();
+            if area.start_vpn.0 < start_vpn.0 || area.start_vpn.0 >= end {
+                return true;
+            }
+            if let Err(e) = area.sync(pt) {
+                println!("[kernel] write back file mapping failed: {}", e);
+            }
+            frames.append(&mut area.detach(pt));
+            false
+        });
+        self.dealloc(start_vpn, end - start_vpn.0);
+        Some(frames)
     }
 
     pub fn map_defined(&mut self, ppns: &Vec<PhysPage>, permission: Permission, user: bool) -> isize {
+        if user && self.deny_wx(permission) {
+            return -1;
+        }
         if let Some(vpns) = self.alloc(ppns.len()) {
             let mut new_area = MapArea::new(
                 vpns.0.into(), 
@@ -292,21 +759,22 @@ impl MemoryManager {
         }
     }
 
+    // 内核空间的直接映射，和 trampoline 中建立的内核页表一样用 4 个 1G 的大页映射低 4G 物理地址
     #[cfg(feature = "riscv64_qemu")]
     pub fn add_kernel_pt(&mut self) {
-        use crate::arch::memory::page::{PTEFlags, PageTableEntry};
-
-        self.pt.set_pte_with_level(
-            PageTableEntry::new(PhysPage(0), PTEFlags::X | PTEFlags::R | PTEFlags::W | PTEFlags::V), 
-            VirtAddr(0xFFFF_FFFF_0000_0000).into(), 0);
-        self.pt.set_pte_with_level(
-            PageTableEntry::new(PhysPage(0x40000), PTEFlags::X | PTEFlags::R | PTEFlags::W | PTEFlags::V), 
-            VirtAddr(0xFFFF_FFFF_4000_0000).into(), 0);
-        self.pt.set_pte_with_level(
-            PageTableEntry::new(PhysPage(0x80000), PTEFlags::X | PTEFlags::R | PTEFlags::W | PTEFlags::V), 
-            VirtAddr(0xFFFF_FFFF_8000_0000).into(), 0);
-        self.pt.set_pte_with_level(
-            PageTableEntry::new(PhysPage(0xC0000), PTEFlags::X | PTEFlags::R | PTEFlags::W | PTEFlags::V), 
-            VirtAddr(0xFFFF_FFFF_C000_0000).into(), 0);        
+        use crate::arch::memory::page::{kernel_phys_to_virt, level_pages, PTEFlags};
+
+        let pages = level_pages(0);
+        for i in 0..4 {
+            let ppn = PhysPage(i * pages);
+            self.pt.map_huge(kernel_phys_to_virt(ppn.into()).into(), ppn, PTEFlags::X | PTEFlags::R | PTEFlags::W, 0);
+        }
+    }
+}
+
+// 释放换出的页占用的交换槽位，页帧和页表随着 MapArea 和 PageTable 一起释放
+impl Drop for MemoryManager {
+    fn drop(&mut self) {
+        self.unmap_app();
     }
 }
@@ -0,0 +1,36 @@
+/*
+    文件映射的页缓存，同一个文件的同一页在所有地址空间中共享一个页帧
+    页帧由映射它的 MapArea 持有，这里只保存弱引用，没有映射时页帧被释放，下次访问重新读取
+    和 read/write 系统调用不一致，它们直接读写文件
+*/
+
+use alloc::collections::BTreeMap;
+use alloc::sync::{Arc, Weak};
+use lazy_static::*;
+use rcore_fs::vfs::INode;
+use crate::arch::memory::page::{kernel_page_phys_to_virt, PAGE_SIZE};
+use crate::sync::Mutex;
+
+use super::allocator::{frame_alloc_zeroed, PhysFrame};
+
+lazy_static! {
+    // (inode 编号, 文件中的页号) => 页帧
+    static ref PAGE_CACHE: Mutex<BTreeMap<(usize, usize), Weak<PhysFrame>>> = Mutex::new(BTreeMap::new());
+}
+
+// 返回文件第 index 页的页帧，不在缓存中时通过 read_at 读取，超出文件结尾的部分为 0
+// 持有锁读取，避免两个地址空间同时读取同一页得到不同的页帧
+pub fn get_page(inode: &Arc<dyn INode>, index: usize) -> Result<Arc<PhysFrame>, &'static str> {
+    let id = inode.metadata().map_err(|_| "file metadata failed")?.inode;
+    let mut cache = PAGE_CACHE.lock();
+    if let Some(frame) = cache.get(&(id, index)).and_then(|f| f.upgrade()) {
+        return Ok(frame);
+    }
+
+    let frame = Arc::new(frame_alloc_zeroed().ok_or("out of memory")?);
+    inode.read_at(index * PAGE_SIZE, kernel_page_phys_to_virt(frame.ppn).bytes_array())
+        .map_err(|_| "read file failed")?;
+    cache.retain(|_, f| f.strong_count() > 0);
+    cache.insert((id, index), Arc::downgrade(&frame));
+    Ok(frame)
+}
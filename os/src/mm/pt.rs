@@ -4,9 +4,13 @@ use core::borrow::BorrowMut;
 use alloc::vec::Vec;
 use super::allocator::{kernel_frame_alloc, PhysFrame};
 use crate::arch::memory::page::{
-    kernel_phys_to_virt, kernel_virt_to_phys, root_ppn, PTEFlags, PageTableEntry, PhysAddr, PhysPage, VirtAddr, VirtPage
+    kernel_page_phys_to_virt, kernel_phys_to_virt, kernel_virt_to_phys, level_pages, root_ppn,
+    PTEFlags, PageTableEntry, PhysAddr, PhysPage, VirtAddr, VirtPage, HUGE_LEVEL, PAGE_SIZE
 };
 
+// 叶子页表的级别，根页表是 0 级
+const LAST_LEVEL: usize = HUGE_LEVEL + 1;
+
 // Every app has it's own page table
 pub struct PageTable {
     // level-1 page table memory address
@@ -39,72 +43,22 @@ impl PageTable {
     // 而叶子页表中的页表项指向的是 text, data 等段，这是页表类无法管理的，也无需负责管理
     // 而且还有一个原因是，根据虚拟页号查询时，得到树干页表中的页表项没有意义，因为使用者根本
     // 不想关心你的页表是如何管理的，只需要给他们对应的物理页帧就可以了
+    // vpn 在大页中时返回大页的页表项
     pub fn find_pte(&mut self, vpn: VirtPage) -> Option<&mut PageTableEntry> {
-        let idx = vpn.index();
-        let mut vpn: VirtPage = kernel_phys_to_virt(self.root.into()).into();
-        for (k, v) in idx.iter().enumerate() {
-            // TODO: 需要考虑下如何在虚地址模式下访问页表
-            let pte = vpn.pte_array()[*v].borrow_mut();
-            if k == (idx.len() - 1) {
-                // 从叶子页表中获得了 PTE，直接返回
-                return Some(pte)
-            } else {
-                if !pte.is_valid() {
-                    let frame = kernel_frame_alloc().unwrap();
-                    // 创建一个树干页表
-                    *pte = PageTableEntry::new(frame.ppn, PTEFlags::V | PTEFlags::T);
-                    self.frames.push(frame);
-                }
-            }
-            vpn = kernel_phys_to_virt(pte.ppn().into()).into();
-        }
-        None
+        walk(self.root, vpn, LAST_LEVEL, Some(&mut self.frames)).map(|(pte, _)| pte)
     }
 
     pub fn find_pte_only(&self, vpn: VirtPage) -> Option<&mut PageTableEntry> {
-        let idx = vpn.index();
-        let mut vpn: VirtPage = kernel_phys_to_virt(self.root.into()).into();
-        for (k, v) in idx.iter().enumerate() {
-            // TODO: 需要考虑下如何在虚地址模式下访问页表
-            let pte = vpn.pte_array()[*v].borrow_mut();
-            if k == (idx.len() - 1) {
-                // 从叶子页表中获得了 PTE，直接返回
-                return Some(pte)
-            } else {
-                if !pte.is_valid() {
-                    return None;
-                }
-            }
-            vpn = kernel_phys_to_virt(pte.ppn().into()).into();
-        }
-        None
+        walk(self.root, vpn, LAST_LEVEL, None).map(|(pte, _)| pte)
     }
 
-    #[allow(unused)]
+    // level 级页表中 vpn 对应的页表项，途中遇到大页时返回 None
     pub fn find_pte_with_level(&mut self, vpn: VirtPage, level: usize, readonly: bool) -> Option<&mut PageTableEntry> {
-        let idx = vpn.index();
-        let mut vpn: VirtPage = kernel_phys_to_virt(self.root.into()).into();
-        for (k, v) in idx.iter().enumerate() {
-            let pte = vpn.pte_array()[*v].borrow_mut();
-            if k == level {
-                return Some(pte);
-            } else {
-                if !pte.is_valid() {
-                    if readonly {
-                        return  None;
-                    } else {
-                        let frame = kernel_frame_alloc().unwrap();
-                        *pte = PageTableEntry::new(frame.ppn, PTEFlags::V);
-                    }
-                }                
-            }
-
-            vpn = kernel_phys_to_virt(pte.ppn().into()).into();
-        }
-
-        None
+        let frames = if readonly { None } else { Some(&mut self.frames) };
+        walk(self.root, vpn, level, frames).and_then(|(pte, k)| (k == level).then_some(pte))
     }
 
+    #[allow(unused)]
     pub fn find_valid_pte(&mut self, vpn: VirtPage) -> Option<PageTableEntry> {
         let pte = self.find_pte(vpn).unwrap();
         if pte.is_valid() {
@@ -127,7 +81,10 @@ impl PageTable {
 
     #[allow(unused)]
     pub fn unmap(&mut self, vpn: VirtPage) -> i32 {
-        let pte = self.find_pte(vpn).unwrap();
+        // 按需分配的页可能从来没有映射过，不需要为它创建树干页表
+        let Some(pte) = self.find_pte_only(vpn) else {
+            return -1;
+        };
         if !pte.is_valid() {
             // not used. don't need unmap
             return -1;
@@ -147,6 +104,7 @@ impl PageTable {
         self.map(vpn, ppn, flags)
     }
 
+    #[allow(unused)]
     pub fn set_pte(&mut self, new_pte: PageTableEntry, vpn: VirtPage) -> Option<PageTableEntry> {
         let pte = self.find_pte(vpn)?;
         let old_pte = pte.clone();
@@ -154,12 +112,57 @@ impl PageTable {
         Some(old_pte)
     }
 
-    #[allow(unused)]
-    pub fn set_pte_with_level(&mut self, new_pte: PageTableEntry, vpn: VirtPage, level: usize) -> Option<PageTableEntry> {
+    // 用 level 级页表中的一个页表项映射 level_pages(level) 个页，vpn 和 ppn 都需要按大页对齐
+    // 这个位置已经有映射或者下一级页表时返回 None
+    pub fn map_huge(&mut self, vpn: VirtPage, ppn: PhysPage, flags: PTEFlags, level: usize) -> Option<PageTableEntry> {
+        let pages = level_pages(level);
+        if vpn.0 % pages != 0 || ppn.0 % pages != 0 {
+            return None;
+        }
         let pte = self.find_pte_with_level(vpn, level, false)?;
-        let old_pte = pte.clone();
-        *pte = new_pte;
-        Some(old_pte)
+        if pte.is_valid() {
+            return None;
+        }
+        *pte = PageTableEntry::new_block(ppn, flags | PTEFlags::V);
+        Some(*pte)
+    }
+
+    pub fn unmap_huge(&mut self, vpn: VirtPage, level: usize) -> i32 {
+        let Some(pte) = self.find_pte_with_level(vpn, level, true) else {
+            return -1;
+        };
+        if !pte.is_block() {
+            return -1;
+        }
+
+        pte.clear();
+        return 0;
+    }
+
+    // 把 level 级的大页拆成下一级的页表项，物理页和权限不变，调用者刷新 tlb
+    // vpn 不在 level 级的大页中时返回 false
+    pub fn split_huge(&mut self, vpn: VirtPage, level: usize) -> bool {
+        let Some((pte, k)) = walk(self.root, vpn, level, None) else {
+            return false;
+        };
+        if k != level || !pte.is_block() {
+            return false;
+        }
+
+        let flags = pte.flags().unwrap();
+        let ppn = pte.ppn();
+        let pages = level_pages(level + 1);
+        let frame = kernel_frame_alloc().unwrap();
+        for (i, entry) in kernel_page_phys_to_virt(frame.ppn).pte_array().iter_mut().enumerate() {
+            *entry = if level + 1 == LAST_LEVEL {
+                PageTableEntry::new(ppn.add(i * pages), flags)
+            } else {
+                PageTableEntry::new_block(ppn.add(i * pages), flags)
+            };
+        }
+        *pte = PageTableEntry::new(frame.ppn, PTEFlags::V | PTEFlags::T);
+        self.frames.push(frame);
+        true
     }
 
     pub fn root_ppn(&self) -> PhysPage {
@@ -169,8 +172,8 @@ impl PageTable {
     #[allow(unused)]
     pub fn translate(&self, va: VirtAddr) -> Option<PhysAddr> {
         let vp = VirtPage::from(va);
-        if let Some(pte) = self.find_pte_only(vp) {
-            let pa = pte.ppn().0 << 12 | (va.0 & ((1<<12) - 1));
+        if let Some((pte, level)) = walk(self.root, vp, LAST_LEVEL, None) {
+            let pa = pte.ppn().0 << 12 | (va.0 & (level_pages(level) * PAGE_SIZE - 1));
             return  Some(pa.into());
         }
         None
@@ -220,6 +223,28 @@ impl Iterator for PageTable {
     }
 }
 
+// 从根页表开始找到 level 级页表中 vpn 对应的页表项，途中遇到大页时返回大页的页表项和它的级别
+// frames 不为空时创建途中缺少的页表，页帧放进 frames
+fn walk(root: PhysPage, vpn: VirtPage, level: usize, mut frames: Option<&mut Vec<PhysFrame>>) -> Option<(&'static mut PageTableEntry, usize)> {
+    let idx = vpn.index();
+    let mut table: VirtPage = kernel_phys_to_virt(root.into()).into();
+    for (k, v) in idx.iter().enumerate() {
+        let pte = &mut table.pte_array()[*v];
+        if k == level || pte.is_block() {
+            return Some((pte, k));
+        }
+        if !pte.is_valid() {
+            let frames = frames.as_mut()?;
+            let frame = kernel_frame_alloc().unwrap();
+            // 创建一个树干页表
+            *pte = PageTableEntry::new(frame.ppn, PTEFlags::V | PTEFlags::T);
+            frames.push(frame);
+        }
+        table = kernel_phys_to_virt(pte.ppn().into()).into();
+    }
+    None
+}
+
 pub fn translate(va: VirtAddr) -> Option<PhysAddr> {
     if va.is_kernel() {
         Some(kernel_virt_to_phys(va))
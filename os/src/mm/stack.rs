@@ -0,0 +1,74 @@
+// exec 时用户栈的初始内容，和 System V ABI 相同
+// 从 sp 开始依次是 argc、argv 指针、NULL、envp 指针、NULL、auxv，栈顶保存字符串和随机数
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::utils::random::random;
+
+// auxv 的类型，和 linux 相同
+pub const AT_NULL: usize = 0;
+pub const AT_PHDR: usize = 3;
+pub const AT_PHENT: usize = 4;
+pub const AT_PHNUM: usize = 5;
+pub const AT_PAGESZ: usize = 6;
+pub const AT_BASE: usize = 7;
+pub const AT_ENTRY: usize = 9;
+pub const AT_RANDOM: usize = 25;
+
+// 参数和环境变量最多占用用户栈的 1/4，和 linux 相同
+const ARG_MAX_RATIO: usize = 4;
+
+const WORD: usize = core::mem::size_of::<usize>();
+
+fn random_bytes() -> [u8; 16] {
+    let mut bytes = [0u8; 16];
+    for b in bytes.chunks_mut(WORD) {
+        b.copy_from_slice(&random().to_ne_bytes());
+    }
+    bytes
+}
+
+// 生成栈顶 top 以下的初始内容，返回 sp 和 [sp, top) 的数据
+// auxv 不需要包括 AT_RANDOM 和 AT_NULL，值为 0 的项不会放入栈中
+pub fn build(top: usize, stack_size: usize, argv: &[String], envp: &[String], auxv: &[(usize, usize)])
+    -> Result<(usize, Vec<u8>), &'static str>
+{
+    let strings: usize = argv.iter().chain(envp.iter()).map(|s| s.len() + 1).sum();
+    let auxv: Vec<(usize, usize)> = auxv.iter().copied().filter(|&(_, v)| v != 0).collect();
+    let words = 1 + argv.len() + 1 + envp.len() + 1 + (auxv.len() + 2) * 2;
+    // 加上 16 字节的随机数和对齐
+    if 16 + strings + words * WORD + 0xf > stack_size / ARG_MAX_RATIO {
+        return Err("argument list too long");
+    }
+    let random = top - 16;
+    let strings_start = random - strings;
+    let sp = (strings_start - words * WORD) & !0xf;
+
+    let mut data = vec![0u8; top - sp];
+    let mut ptrs: Vec<usize> = Vec::with_capacity(words);
+    ptrs.push(argv.len());
+
+    // 字符串从 strings_start 开始依次存放
+    let mut addr = strings_start;
+    for list in [argv, envp] {
+        for s in list {
+            let off = addr - sp;
+            data[off..off + s.len()].copy_from_slice(s.as_bytes());
+            ptrs.push(addr);
+            addr += s.len() + 1;
+        }
+        ptrs.push(0);
+    }
+
+    data[random - sp..].copy_from_slice(&random_bytes());
+    for &(t, v) in auxv.iter().chain([(AT_RANDOM, random), (AT_NULL, 0)].iter()) {
+        ptrs.push(t);
+        ptrs.push(v);
+    }
+
+    for (i, p) in ptrs.iter().enumerate() {
+        data[i * WORD..(i + 1) * WORD].copy_from_slice(&p.to_ne_bytes());
+    }
+    Ok((sp, data))
+}
@@ -0,0 +1,153 @@
+/*
+    交换分区，使用单独的 virtio-blk 设备，每一页占用一个槽位
+    空闲页帧不足时，按照页表项的访问位从各个地址空间中换出最近没有访问过的匿名页
+    换出的页在无效的页表项中记录槽位，再次访问时通过页错误换入
+*/
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+use alloc::sync::{Arc, Weak};
+use alloc::vec;
+use alloc::vec::Vec;
+use lazy_static::*;
+use crate::arch::memory::page::{kernel_page_phys_to_virt, PhysPage, PAGE_SIZE};
+use crate::driver::block::BlockDevice;
+use crate::sync::Mutex;
+
+use super::allocator::free_frames;
+use super::MemoryManager;
+
+// 空闲页帧少于 LOW_WATERMARK 时，处理页错误之前先换出 RECLAIM_BATCH 个页
+const LOW_WATERMARK: usize = 256;
+const RECLAIM_BATCH: usize = 64;
+
+pub struct Swap {
+    device: Option<Arc<Mutex<dyn BlockDevice>>>,
+    // 一页占用的块数
+    page_blocks: usize,
+    // 每个槽位的引用计数，0 表示空闲，fork 后父子进程共享换出的页
+    slots: Vec<u16>,
+    // 从这里开始查找空闲的槽位
+    next: usize,
+}
+
+impl Swap {
+    pub fn new() -> Self {
+        Self { device: None, page_blocks: 0, slots: Vec::new(), next: 0 }
+    }
+
+    fn alloc(&mut self) -> Option<usize> {
+        let n = self.slots.len();
+        for i in 0..n {
+            let slot = (self.next + i) % n;
+            if self.slots[slot] == 0 {
+                self.slots[slot] = 1;
+                self.next = slot + 1;
+                return Some(slot);
+            }
+        }
+
+        None
+    }
+
+    fn dup(&mut self, slot: usize) {
+        assert!(self.slots[slot] > 0, "swap slot {} is free", slot);
+        self.slots[slot] += 1;
+    }
+
+    fn free(&mut self, slot: usize) {
+        assert!(self.slots[slot] > 0, "swap slot {} is free", slot);
+        self.slots[slot] -= 1;
+    }
+}
+
+lazy_static! {
+    static ref SWAP: Mutex<Swap> = Mutex::new(Swap::new());
+    // 所有用户地址空间，换出时从 HAND 开始依次扫描
+    static ref ADDRESS_SPACES: Mutex<Vec<Weak<Mutex<MemoryManager>>>> = Mutex::new(Vec::new());
+}
+
+static HAND: AtomicUsize = AtomicUsize::new(0);
+
+pub fn init(device: Arc<Mutex<dyn BlockDevice>>) {
+    let (block_size, block_num) = {
+        let d = device.lock();
+        (1usize << d.block_size_log2(), d.block_num())
+    };
+    let pages = block_num * block_size / PAGE_SIZE;
+    let mut swap = SWAP.lock();
+    swap.page_blocks = PAGE_SIZE / block_size;
+    swap.slots = vec![0; pages];
+    swap.next = 0;
+    swap.device = Some(device);
+    println!("[kernel] swap enabled, {} pages", pages);
+}
+
+pub fn register(mm: &Arc<Mutex<MemoryManager>>) {
+    let mut spaces = ADDRESS_SPACES.lock();
+    spaces.retain(|s| s.strong_count() > 0);
+    spaces.push(Arc::downgrade(mm));
+}
+
+pub fn alloc_slot() -> Option<usize> {
+    SWAP.lock().alloc()
+}
+
+pub fn dup_slot(slot: usize) {
+    SWAP.lock().dup(slot)
+}
+
+pub fn free_slot(slot: usize) {
+    SWAP.lock().free(slot)
+}
+
+fn device(slot: usize) -> Result<(Arc<Mutex<dyn BlockDevice>>, usize), &'static str> {
+    let swap = SWAP.lock();
+    let device = swap.device.clone().ok_or("no swap device")?;
+    Ok((device, slot * swap.page_blocks))
+}
+
+// 通过内核的直接映射读写页帧
+pub fn write_page(slot: usize, ppn: PhysPage) -> Result<(), &'static str> {
+    let (device, block) = device(slot)?;
+    let r = device.lock().write_block(block, kernel_page_phys_to_virt(ppn).bytes_array());
+    r.map(|_| ()).map_err(|e| {
+        println!("[kernel] swap write failed: {}", e);
+        "swap write failed"
+    })
+}
+
+pub fn read_page(slot: usize, ppn: PhysPage) -> Result<(), &'static str> {
+    let (device, block) = device(slot)?;
+    let r = device.lock().read_block(block, kernel_page_phys_to_virt(ppn).bytes_array());
+    r.map(|_| ()).map_err(|e| {
+        println!("[kernel] swap read failed: {}", e);
+        "swap read failed"
+    })
+}
+
+// 空闲页帧不足时从所有地址空间中换出页，current 是调用者已经持有锁的地址空间
+// 其他地址空间的锁被持有时跳过，避免和同时处理页错误的 cpu 死锁
+pub fn reclaim(current: &mut MemoryManager) {
+    if SWAP.lock().device.is_none() || free_frames() >= LOW_WATERMARK {
+        return;
+    }
+
+    let spaces: Vec<Arc<Mutex<MemoryManager>>> = ADDRESS_SPACES.lock().iter()
+        .filter_map(|s| s.upgrade())
+        .collect();
+    let start = HAND.fetch_add(1, Ordering::Relaxed);
+    let mut count = 0;
+    for i in 0..spaces.len() {
+        if count >= RECLAIM_BATCH {
+            break;
+        }
+        if let Some(mut mm) = spaces[(start + i) % spaces.len()].try_lock() {
+            count += mm.swap_out(RECLAIM_BATCH - count);
+        }
+    }
+    if count < RECLAIM_BATCH {
+        current.swap_out(RECLAIM_BATCH - count);
+    }
+    // 这里可能是最后一个引用，释放地址空间时需要 SWAP 的锁，所以不能在持有锁时 drop
+    drop(spaces);
+}
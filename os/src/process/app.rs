@@ -1,5 +1,5 @@
 use core::borrow::BorrowMut;
-use core::cell::RefMut;
+use core::mem::size_of;
 use core::arch::asm;
 use core::ops::{BitAnd, BitOr};
 
@@ -11,126 +11,213 @@ use crate::ipc::id::RcvidHandler;
 use crate::ipc::server::{Msg, Server};
 use crate::ipc::pipe::Pipe;
 use crate::file::stdio::{Stdin, Stdout};
-use crate::file::File;
+use crate::file::{File, FileDesc, O_CLOEXEC};
 use crate::ipc::semaphore::Semaphore;
 use crate::ipc::shm::Shm;
 use crate::mm::allocator::{asid_alloc, AisdHandler};
-use crate::mm::area::UserBuffer;
-use crate::arch::memory::page::{enable_va, flush_tlb, PhysAddr, VirtAddr, VirtPage, PAGE_SIZE};
+use crate::mm::area::{Access, UserBuffer};
+use crate::arch::{cpu_id, disable_irq, irq_restore, irq_save};
+use crate::arch::memory::page::{enable_kernel_va, enable_va, flush_tlb, PhysAddr, VirtAddr, VirtPage, PAGE_SIZE};
 use crate::mm::pt::PageTable;
-use crate::mm::MemoryManager;
+use crate::mm::{kernel_stack_top, MemoryManager, MAP_PRIVATE, MAP_SHARED};
 use crate::arch::context::__switch;
 use crate::arch::context::TrapContext;
-use crate::arch::context::SwitchContext;
-use crate::board::timer::nanoseconds;
-use crate::utils::type_extern::RefCellWrap;
+use crate::arch::context::{read_tls, SwitchContext};
+use crate::arch::memory::copy::{copy_from_user_into_vector, copy_usize_with_user, copy_vector_to_user, copy_with_user};
+use crate::arch::wait_for_interrupt;
+use crate::board::timer::{self, nanoseconds, realtime_offset};
+use crate::board::{idle_irq_handler, send_ipi, CPU_NUM};
 
+use rcore_fs::vfs::FileType;
 use alloc::borrow::ToOwned;
+use alloc::boxed::Box;
 use alloc::string::String;
 use alloc::sync::{Arc, Weak};
 use alloc::collections::BTreeMap;
-use bitflags::Flags;
-use spin::mutex::Mutex;
+use bitflags::{bitflags, Flags};
+use crate::sync::{preempt_count, Mutex, MutexGuard};
 use alloc::{format, vec};
 use alloc::vec::Vec;
 
 use super::pid::{self, PidHandler};
-use crate::ipc::signal::{self, SignalAction, SignalFlags, SIG_NUM};
+use super::rlimit::*;
+use super::rusage::*;
+use super::spawn::{apply_actions, SpawnAction};
+use super::timer::*;
+use super::scheduler::{new_scheduler, notify_wakeup, take_wakeups, ClassScheduler, SchedEntity, SchedParam, SchedPolicy, Scheduler, NICE_MAX, NICE_MIN};
+use crate::ipc::signal::{self, SignalAction, SignalFlags, SIGALRM, SIGCHLD, SIGCONT, SIGKILL, SIGPROF, SIGSTOP, SIGTSTP, SIGVTALRM, SIGXCPU, SIG_NUM};
 
+type CurrentMm = Option<(Arc<Mutex<MemoryManager>>, usize)>;
+const NO_MM: Mutex<CurrentMm> = Mutex::new(None);
+
+// 每个 cpu 上正在运行的线程的地址空间和 asid，在 activate 时设置，回到 idle 后清除
+static CURRENT_MM: [Mutex<CurrentMm>; CPU_NUM] = [NO_MM; CPU_NUM];
+
+// 所有 cpu 共享一个 TaskManager，通过自旋锁互斥访问
+// 系统调用中可以被中断打断，但是持有锁时中断是关闭的，不会在中断中再次获取，在 __switch 之前必须释放锁
 pub struct TaskManager {
-    inner: RefCellWrap<AppManagerInner>,
+    inner: Mutex<AppManagerInner>,
 }
 
 impl TaskManager {
-    pub unsafe fn new() -> Self {
+    pub fn new() -> Self {
         Self {
-            inner: RefCellWrap::new(AppManagerInner::new())
+            inner: Mutex::new(AppManagerInner::new())
         }
     }
 
-    pub fn inner_access(&self) -> RefMut<'_, AppManagerInner> {
-        self.inner.exclusive_access()
+    pub fn inner_access(&self) -> MutexGuard<'_, AppManagerInner> {
+        self.inner.lock()
     }
 
+    // 每个 cpu 的 idle 控制流，从自己的运行队列中选择任务运行
     pub fn run_task(&self) -> ! {
-        use ProcessStatus::*;
+        self.inner_access().cpu_online();
         loop {
             let mut inner = self.inner_access();
             // check semaphore
             inner.check_sem();
+            inner.run_timers();
 
             let idle_ctx = inner.idle_ctx();
-            let next = inner.next_task().unwrap();
-            let next_ctx_ptr = next.lock().ctx_ptr();
-            let next_status = next.lock().status;
-            match next_status {
-                READY => unsafe {
-                    let tick = next.lock().tick;
-                    next.lock().set_status(RUNNING(tick));
-                    next.lock().activate();
-                    // TODO: 需要考虑下这个地方，因为切换页表后，执行 __switch 似乎有点问题，但是 kernel 使用 identical 模式，似乎又是没问题的
-                    drop(next);
-                    drop(inner);
+            if let Some(next) = inner.next_task() {
+                let next_ctx_ptr = next.lock().ctx_ptr();
+                let tick = next.lock().tick;
+                next.lock().set_status(ProcessStatus::RUNNING(tick));
+                next.lock().activate();
+                // TODO: 需要考虑下这个地方，因为切换页表后，执行 __switch 似乎有点问题，但是 kernel 使用 identical 模式，似乎又是没问题的
+                drop(next);
+                drop(inner);
 
-                    unsafe { __switch(idle_ctx, next_ctx_ptr); }
-                },
-                RUNNING(_) => unsafe {
-                    next.lock().set_status(SLEEP(nanoseconds(), 0));
-                    next.lock().activate();
-                    drop(next);
-                    drop(inner);
-                    unsafe { __switch(idle_ctx, next_ctx_ptr); }
-                }
-                SLEEP(a, b) => unsafe {
-                    let c = nanoseconds();
-                    if a + b < c {
-                        let tick = next.lock().tick;
-                        next.lock().set_status(RUNNING(tick));
-                        next.lock().activate();
-                        drop(next);
-                        drop(inner);
-                        unsafe { __switch(idle_ctx, next_ctx_ptr); }
-                    } else {
-                        if a > c {
-                            next.lock().set_status(SLEEP(c, 0));
-                        }
-                        continue;
-                    }
-                }
-                _ => {
-                    continue;
-                },
+                timer::start_tick();
+                unsafe { __switch(idle_ctx, next_ctx_ptr); }
+
+                // 任务退出后，它的页表可能被其他 cpu 上的父进程回收，先切换到内核页表
+                enable_kernel_va();
+                let mm = CURRENT_MM[cpu_id()].lock().take();
+                drop(mm);
+                // back to idle, give the task back to scheduler
+                self.inner_access().put_prev();
+            } else if inner.steal_task() {
+                // 从其他 cpu 取到了任务，下一轮运行它
+                continue;
+            } else {
+                // 没有可以运行的任务，设置好时钟中断后等待中断唤醒
+                // idle 中中断是关闭的，但是中断到来时 wfi 仍然会返回，再由 idle_irq_handler 处理
+                let deadline = inner.cpu().timers.next_deadline();
+                drop(inner);
+                timer::set_idle_trigger(deadline);
+                wait_for_interrupt();
+                idle_irq_handler();
             }
         }
     }
 
+    // idle 中中断是关闭的，切换前关闭中断，切换回来后恢复
     pub fn back_to_idle(&self) {
+        debug_assert_eq!(preempt_count(), 0, "switch while holding a lock");
+        let irq = irq_save();
         let mut inner = self.inner_access();
         let idle_ctx = inner.idle_ctx();
-        let current = inner.current_task(false).unwrap();
+        let current = inner.current_task().unwrap();
+        current.lock().count_switch();
         let current_ctx_ptr = current.lock().ctx_ptr();
         drop(current);
         drop(inner);
         unsafe { __switch(current_ctx_ptr, idle_ctx); }
+        irq_restore(irq);
+    }
+
+    // 从用户态 trap 进入内核
+    pub fn trap_enter(&self) {
+        let inner = self.inner_access();
+        if let Some(current) = inner.current_task() {
+            current.lock().charge_utime(nanoseconds());
+        }
+    }
+
+    // 从 trap 返回用户态
+    pub fn trap_exit(&self) {
+        let inner = self.inner_access();
+        if let Some(current) = inner.current_task() {
+            current.lock().charge_stime(nanoseconds());
+        }
     }
 
+    pub fn tick(&self, from_user: bool) {
+        let mut inner = self.inner_access();
+        let mut resched = inner.tick();
+        // 内核态只抢占仍在运行的任务，已经退出、停止或者正准备睡眠的任务等系统调用返回后再切换
+        if !from_user {
+            resched = resched && inner.current_task().is_some_and(|c| matches!(c.lock().status, ProcessStatus::RUNNING(_)));
+        }
+        drop(inner);
+
+        if resched {
+            self.back_to_idle();
+        }
+    }
+
+    // duration 为 0 时只是让出 cpu
     pub fn sleep(&self, duration: usize) {
+        if duration > 0 {
+            self.sleep_until(nanoseconds() + duration);
+            return;
+        }
+
+        let inner = self.inner_access();
+        let current = inner.current_task().unwrap();
+        current.lock().set_status(ProcessStatus::READY);
+        drop(current);
+        drop(inner);
+
+        self.back_to_idle();
+    }
+
+    // 睡眠到 MONOTONIC 时间 deadline，被信号打断时返回 -2
+    pub fn sleep_until(&self, deadline: usize) -> isize {
         let mut inner = self.inner_access();
-        let current = inner.current_task(false).unwrap();
-        current.lock().set_status(ProcessStatus::SLEEP(nanoseconds(), duration));
+        let now = nanoseconds();
+        if deadline <= now {
+            return 0;
+        }
+        let current = inner.current_task().unwrap();
+        if current.lock().signal_pending() {
+            return -2;
+        }
+        current.lock().set_status(ProcessStatus::SLEEP(now, deadline - now));
+        inner.cpu().timers.add_sleeper(deadline, &current);
         drop(current);
         drop(inner);
 
         self.back_to_idle();
+        if nanoseconds() < deadline {
+            -2
+        } else {
+            0
+        }
     }
 
     pub fn exit(&self, exit_code: isize) -> ! {
+        self.exit_with_status(exit_status(exit_code))
+    }
+
+    // status 是 wait 返回给父进程的状态
+    fn exit_with_status(&self, status: isize) -> ! {
+        disable_irq();
+        // 当前页表仍然是这个线程的，可以直接写用户地址
+        // 写用户地址可能产生页错误，所以在获取 TaskManager 的锁之前完成
+        let current = self.inner_access().current_task().unwrap();
+        let clear_child_tid = current.lock().clear_child_tid();
+        if clear_child_tid != 0 {
+            // 和 linux 一样忽略无效的地址
+            copy_usize_with_user(0, clear_child_tid as *mut usize);
+        }
         let mut inner = self.inner_access();
-        let current = inner.current_task(false).unwrap();
         let idle_ctx = inner.idle_ctx();
         let current_ctx_ptr = current.lock().ctx_ptr();
-        current.lock().set_status(ProcessStatus::EXITED(exit_code));
-        let pid = current.lock().pid.clone();
+        current.lock().set_status(ProcessStatus::EXITED(status));
+        inner.release_vfork(&current);
         drop(current);
         drop(inner);
         unsafe {
@@ -139,29 +226,161 @@ impl TaskManager {
         }
     }
 
+    // 结束线程组中的所有线程
+    pub fn exit_group(&self, exit_code: isize) -> ! {
+        let status = exit_status(exit_code);
+        let mut inner = self.inner_access();
+        inner.kill_threads(status);
+        drop(inner);
+        self.exit_with_status(status)
+    }
+
+    // 被信号结束，整个线程组都退出
+    pub fn exit_by_signal(&self, signal: usize) -> ! {
+        let status = signal_status(signal);
+        let mut inner = self.inner_access();
+        inner.kill_threads(status);
+        drop(inner);
+        self.exit_with_status(status)
+    }
+
     pub fn fork(&self) -> isize {
         let mut inner = self.inner_access();
-        inner.fork()
+        inner.clone_task(CloneFlags::empty(), 0, 0, 0)
     }
 
-    pub fn exec(&self, elf: &[u8]) -> isize {
+    pub fn clone_task(&self, flags: CloneFlags, stack: usize, parent_tid: usize, child_tid: usize, tls: usize) -> isize {
         let mut inner = self.inner_access();
-        inner.exec(elf)
+        let pid = inner.clone_task(flags, stack, child_tid, tls);
+        drop(inner);
+        // 释放锁之后再写用户地址
+        if pid > 0 && flags.contains(CloneFlags::PARENT_SETTID) {
+            copy_usize_with_user(pid as usize, parent_tid as *mut usize);
+        }
+        if pid > 0 && flags.contains(CloneFlags::VFORK) {
+            self.vfork_wait(pid as usize);
+        }
+        pid
     }
 
-    pub fn create_initproc(&self, tick: usize) -> isize {
+    // vfork 即共享地址空间的 fork，父线程阻塞到子进程 exec 或者退出，这之前子进程使用父进程的栈
+    pub fn vfork(&self) -> isize {
+        self.clone_task(CloneFlags::VM | CloneFlags::VFORK, 0, 0, 0, 0)
+    }
+
+    // 和 linux 一样不能被信号打断，收到信号唤醒后继续等待
+    fn vfork_wait(&self, pid: usize) {
+        loop {
+            let mut inner = self.inner_access();
+            if inner.vfork_done(pid) {
+                return;
+            }
+            let current = inner.current_task().unwrap();
+            let mut p = current.lock();
+            p.set_status(ProcessStatus::WAITING);
+            p.wait_child = true;
+            drop(p);
+            drop(current);
+            drop(inner);
+            self.back_to_idle();
+        }
+    }
+
+    pub fn spawn(&self, path: &str, argv: &[String], envp: &[String], actions: &[SpawnAction], pgroup: Option<usize>) -> isize {
         let mut inner = self.inner_access();
-        inner.create_initproc(tick)
+        inner.spawn(path, argv, envp, actions, pgroup)
     }
 
-    pub fn cow(&self, vpn: VirtPage) -> Result<(), &'static str> {
+    pub fn kthread_create(&self, entry: fn(), start: usize) -> isize {
         let mut inner = self.inner_access();
-        inner.cow(vpn)
+        inner.kthread_create(entry, start)
     }
 
-    pub fn wait(&self, pid: isize) -> isize {
+    pub fn kthread_entry(&self) -> Option<fn()> {
         let mut inner = self.inner_access();
-        inner.wait(pid)
+        inner.current_task()?.lock().kthread_entry()
+    }
+
+    pub fn exec(&self, path: &str, argv: &[String], envp: &[String]) -> isize {
+        let mut inner = self.inner_access();
+        inner.exec(path, argv, envp)
+    }
+
+    pub fn create_initproc(&self, tick: usize) -> isize {
+        let mut inner = self.inner_access();
+        inner.create_initproc(tick)
+    }
+
+    // 只通过 CURRENT_MM 访问地址空间，不获取 TaskManager 和 Process 的锁
+    // 所以内核持有这些锁时访问用户地址产生的页错误也可以处理
+    pub fn page_fault(&self, vpn: VirtPage, access: Access) -> Result<(), &'static str> {
+        let (mm, asid) = CURRENT_MM[cpu_id()].lock().clone().ok_or("no address space")?;
+        let major = mm.lock().page_fault(vpn, access)?;
+        // 其他 cpu 上共享地址空间的线程可能还缓存着旧的页表项
+        flush_tlb(asid);
+        // 持有锁时产生的页错误不计入统计
+        if preempt_count() == 0 {
+            if let Some(current) = self.inner_access().current_task() {
+                let mut current = current.lock();
+                if major {
+                    current.usage.majflt += 1;
+                } else {
+                    current.usage.minflt += 1;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // 等待子进程退出，返回子进程的 pid，status 不为空时写入退出状态，rusage 不为空时写入子进程的资源统计
+    // 没有匹配的子进程返回 -1，WNOHANG 时子进程都还在运行返回 0，被信号打断返回 -2
+    // pid 为 0 时等待同一进程组的子进程，小于 -1 时等待进程组 -pid 中的子进程
+    pub fn wait(&self, pid: isize, status: *mut i32, options: WaitOptions, rusage: *mut RUsage) -> isize {
+        loop {
+            let mut inner = self.inner_access();
+            let current = inner.current_task().unwrap();
+            current.lock().wait_child = false;
+            let r = inner.wait(pid, options);
+            match r {
+                WaitResult::Exited(child, code, _) | WaitResult::Changed(child, code) => {
+                    // 写用户地址可能产生页错误，先释放锁
+                    drop(current);
+                    drop(inner);
+                    // 子进程已经回收，写入失败时仍然返回 -1
+                    let code = code as i32;
+                    if !status.is_null() && !unsafe { copy_with_user(status as *mut u8, &code as *const i32 as *const u8, size_of::<i32>()) } {
+                        return -1;
+                    }
+                    // 只有回收的子进程有统计，停止和继续运行时为 0
+                    if !rusage.is_null() {
+                        let usage = match r {
+                            WaitResult::Exited(_, _, ref usage) => RUsage::from(usage),
+                            _ => RUsage::default(),
+                        };
+                        if !unsafe { copy_with_user(rusage as *mut u8, &usage as *const RUsage as *const u8, size_of::<RUsage>()) } {
+                            return -1;
+                        }
+                    }
+                    return child as isize;
+                }
+                WaitResult::NoChild => return -1,
+                WaitResult::Running => {
+                    if options.contains(WaitOptions::WNOHANG) {
+                        return 0;
+                    }
+                    if current.lock().signal_pending() {
+                        return -2;
+                    }
+                    // 子进程退出或者收到信号时被唤醒，唤醒后重新检查
+                    let mut p = current.lock();
+                    p.set_status(ProcessStatus::WAITING);
+                    p.wait_child = true;
+                }
+            }
+            drop(current);
+            drop(inner);
+            self.back_to_idle();
+        }
     }
 
     pub fn write(&self, fd: usize, buf: *mut u8, len: usize) -> isize {
@@ -169,7 +388,7 @@ impl TaskManager {
         inner.write(fd, buf, len)
     }
 
-    pub fn create_pipe(&self, size: usize) -> (usize, usize) {
+    pub fn create_pipe(&self, size: usize) -> Option<(usize, usize)> {
         let mut inner = self.inner_access();
         inner.create_pipe(size)
     }
@@ -180,9 +399,9 @@ impl TaskManager {
         return inner.read(fd, buf, len);
     }
 
-    pub fn open(&self, name: String) -> isize {
+    pub fn open(&self, name: String, flags: usize) -> isize {
         let mut inner = self.inner_access();
-        inner.open(name)
+        inner.open(name, flags)
     }
 
     pub fn lseek(&self, fd: usize, seek: usize) -> isize {
@@ -212,53 +431,191 @@ impl TaskManager {
     }
 
     pub fn signal_handler(&self) -> SignalCode {
-        let mut inner = self.inner.exclusive_access();
+        let mut inner = self.inner_access();
         inner.signal_check()
     }
 
     pub fn save_trap_ctx(&self) {
-        let mut inner = self.inner.exclusive_access();
+        let mut inner = self.inner_access();
         inner.save_trap_ctx()
     }
 
     pub fn sigreturn(&self) -> isize {
-        let mut inner = self.inner.exclusive_access();
+        let mut inner = self.inner_access();
         inner.sigreturn()
     }
 
     pub fn getpid(&self) -> usize {
-        let mut inner = self.inner.exclusive_access();
+        let mut inner = self.inner_access();
         inner.getpid()
     }
 
+    pub fn getppid(&self) -> usize {
+        let mut inner = self.inner_access();
+        inner.getppid()
+    }
+
+    pub fn setpgid(&self, pid: usize, pgid: usize) -> isize {
+        let mut inner = self.inner_access();
+        inner.setpgid(pid, pgid)
+    }
+
+    pub fn getpgid(&self, pid: usize) -> isize {
+        let mut inner = self.inner_access();
+        inner.getpgid(pid)
+    }
+
+    pub fn setsid(&self) -> isize {
+        let mut inner = self.inner_access();
+        inner.setsid()
+    }
+
+    pub fn getsid(&self, pid: usize) -> isize {
+        let mut inner = self.inner_access();
+        inner.getsid(pid)
+    }
+
+    pub fn kill(&self, pid: isize, signal: usize) -> isize {
+        let mut inner = self.inner_access();
+        inner.kill(pid, signal)
+    }
+
+    // 收到停止信号，停止整个线程组，收到 SIGCONT 后从这里返回
+    pub fn stop(&self, signal: usize) {
+        let mut inner = self.inner_access();
+        inner.stop_group(signal);
+        drop(inner);
+        self.back_to_idle();
+    }
+
+    pub fn gettid(&self) -> usize {
+        let mut inner = self.inner_access();
+        inner.gettid()
+    }
+
+    pub fn clock_gettime(&self, clock: usize) -> Option<usize> {
+        let mut inner = self.inner_access();
+        inner.clock_gettime(clock)
+    }
+
+    pub fn set_timer(&self, id: TimerId, value: usize, interval: usize, abstime: bool) -> Option<(usize, usize)> {
+        let mut inner = self.inner_access();
+        inner.set_timer(id, value, interval, abstime)
+    }
+
+    pub fn get_timer(&self, id: TimerId) -> Option<(usize, usize)> {
+        let mut inner = self.inner_access();
+        inner.get_timer(id)
+    }
+
+    pub fn timer_create(&self, clock: usize, signal: usize) -> isize {
+        let mut inner = self.inner_access();
+        inner.timer_create(clock, signal)
+    }
+
+    pub fn timer_delete(&self, id: usize) -> isize {
+        let mut inner = self.inner_access();
+        inner.timer_delete(id)
+    }
+
+    pub fn timer_getoverrun(&self, id: usize) -> isize {
+        let mut inner = self.inner_access();
+        inner.timer_getoverrun(id)
+    }
+
+    pub fn getrusage(&self, who: isize) -> Option<Usage> {
+        let mut inner = self.inner_access();
+        inner.getrusage(who)
+    }
+
+    pub fn times(&self) -> Tms {
+        let mut inner = self.inner_access();
+        inner.times()
+    }
+
+    pub fn set_priority(&self, pid: usize, nice: isize) -> isize {
+        let mut inner = self.inner_access();
+        inner.set_priority(pid, nice)
+    }
+
+    pub fn get_priority(&self, pid: usize) -> isize {
+        let mut inner = self.inner_access();
+        inner.get_priority(pid)
+    }
+
+    pub fn set_scheduler(&self, pid: usize, policy: usize, param: SchedParam) -> isize {
+        let mut inner = self.inner_access();
+        inner.set_scheduler(pid, policy, param)
+    }
+
+    pub fn get_scheduler(&self, pid: usize) -> Option<SchedPolicy> {
+        let mut inner = self.inner_access();
+        inner.get_scheduler(pid)
+    }
+
+    pub fn prlimit(&self, pid: usize, resource: usize, new: Option<RLimit>) -> Option<RLimit> {
+        let mut inner = self.inner_access();
+        inner.prlimit(pid, resource, new)
+    }
+
     pub fn mmap(&self, size: usize, permission: usize) -> isize {
-        let mut inner = self.inner.exclusive_access();
+        let mut inner = self.inner_access();
         inner.mmap(size, permission)
     }
 
     pub fn ummap(&self, addr: usize) -> isize {
-        let mut inner = self.inner.exclusive_access();
+        let mut inner = self.inner_access();
         inner.ummap(addr)
     }
 
+    pub fn mmap_file(&self, fd: usize, offset: usize, len: usize, permission: usize, flags: usize) -> isize {
+        let mut inner = self.inner_access();
+        inner.mmap_file(fd, offset, len, permission, flags)
+    }
+
+    pub fn msync(&self, addr: usize, len: usize) -> isize {
+        let mut inner = self.inner_access();
+        inner.msync(addr, len)
+    }
+
+    pub fn brk(&self, addr: usize) -> isize {
+        let mut inner = self.inner_access();
+        inner.brk(addr)
+    }
+
+    pub fn mprotect(&self, addr: usize, len: usize, permission: usize) -> isize {
+        let mut inner = self.inner_access();
+        inner.mprotect(addr, len, permission)
+    }
+
+    pub fn allow_wx(&self) -> bool {
+        let mut inner = self.inner_access();
+        inner.allow_wx()
+    }
+
+    pub fn set_allow_wx(&self, allow: bool) {
+        let mut inner = self.inner_access();
+        inner.set_allow_wx(allow)
+    }
+
     pub fn mmap_with_addr(&self, pa: usize, size: usize, permission: usize, user: bool) -> isize {
-        let mut inner = self.inner.exclusive_access();
+        let mut inner = self.inner_access();
         inner.mmap_with_addr(pa, size, permission, user)
     }
 
     pub fn create_or_open_shm(&self, name: String, size: usize, permission: usize) -> isize {
         assert_eq!(size % PAGE_SIZE, 0);
-        let mut inner = self.inner.exclusive_access();
+        let mut inner = self.inner_access();
         inner.create_or_open_shm(name, size / PAGE_SIZE, permission)
     }
 
     pub fn open_sem(&self, name: String) -> isize {
-        let mut inner = self.inner.exclusive_access();
+        let mut inner = self.inner_access();
         inner.open_sem(name)
     }
 
     pub fn wait_sem(&self, name: String) -> isize {
-        let mut inner = self.inner.exclusive_access();
+        let mut inner = self.inner_access();
         let r = inner.wait_sem(name);
         drop(inner);
 
@@ -270,23 +627,23 @@ impl TaskManager {
     }
 
     pub fn raise_sem(&self, name: String) -> isize {
-        let mut inner = self.inner.exclusive_access();
+        let mut inner = self.inner_access();
         inner.raise_sem(name)
     }
 
     pub fn create_server(&self, name: String) -> isize {
-        let mut inner = self.inner.exclusive_access();
+        let mut inner = self.inner_access();
         inner.create_server(name)
     }
 
     // return coid
     pub fn connect_server(&self, name: String) -> isize {
-        let mut inner = self.inner.exclusive_access();
+        let mut inner = self.inner_access();
         inner.connect_server(name)
     }
 
     pub fn request(&self, coid: usize, data: Arc<Vec<u8>>) -> Option<Arc<Vec<u8>>> {
-        let mut inner = self.inner.exclusive_access();
+        let mut inner = self.inner_access();
         let rcvid = inner.send_request(coid, data);
         if rcvid < 0 {
             return None;
@@ -294,7 +651,7 @@ impl TaskManager {
         drop(inner);
 
         loop {
-            let mut inner = self.inner.exclusive_access();
+            let mut inner = self.inner_access();
             // waiting for response
             if let Some(msg) = inner.recv_response(rcvid as usize) {
                 if msg.rcvid() == rcvid as usize {
@@ -315,7 +672,7 @@ impl TaskManager {
                 return None;
             }
 
-            let mut inner = self.inner.exclusive_access();
+            let mut inner = self.inner_access();
             if let Some(msg) = inner.recv_request(name.to_owned()) {
                 return Some((msg.rcvid(), msg.data()));
             } else {
@@ -326,20 +683,50 @@ impl TaskManager {
     }
 
     pub fn reply_request(&self, rcvid: usize, data: Arc<Vec<u8>>) -> isize {
-        let mut inner = self.inner.exclusive_access();
+        let mut inner = self.inner_access();
         inner.send_response(rcvid, data)
     }
 }
 
+// 每个 cpu 的调度状态，任务创建时分配到任务最少的 cpu 上，空闲的 cpu 可以从其他 cpu 拉走普通（CFS）任务
+pub struct Cpu {
+    // 正在运行的任务，回到 idle 后清空
+    current: Option<Weak<Mutex<Process>>>,
+    // 这个 cpu 的运行队列
+    scheduler: ClassScheduler,
+    idle_ctx: SwitchContext,
+    // 这个 cpu 上睡眠的任务和定时器，由时钟中断和 idle 处理
+    timers: TimerQueue,
+    // 已经进入 run_task，可以分配任务
+    online: bool,
+    // 分配到这个 cpu 上还没有退出的任务数，包括阻塞的任务
+    nr_running: usize,
+}
+
+impl Cpu {
+    fn new() -> Self {
+        Self {
+            current: None,
+            scheduler: ClassScheduler::new(new_scheduler()),
+            // idle process is a unstop loop process
+            idle_ctx: SwitchContext::new(0, 0),
+            timers: TimerQueue::new(),
+            online: false,
+            nr_running: 0,
+        }
+    }
+}
+
 pub struct AppManagerInner {
-    started: bool,
-    current: usize,
+    // 下标为 cpu id，创建后不再改变长度，idle_ctx 的地址是固定的
+    cpus: Vec<Cpu>,
     
     // 这里存储的是 initproc 的实例
     initproc: Option<Arc<Mutex<Process>>>,
-    // 存储 process 的 weak pointer, 用于调度
+    // 内核线程没有父进程，由这里持有
+    kthreads: Vec<Arc<Mutex<Process>>>,
+    // 存储 process 的 weak pointer, 用于按 pid 查找进程
     tasks: Vec<Weak<Mutex<Process>>>,
-    idle_ctx: SwitchContext,
     // name -> shm
     // 目前简单考虑，命名 ipc 的 key 都使用数字，后面考虑支持字符串
     named_shm: BTreeMap<String, Shm>,
@@ -354,13 +741,13 @@ pub struct AppManagerInner {
 
 impl AppManagerInner {
     pub fn new() -> Self {
+        let mut cpus = Vec::with_capacity(CPU_NUM);
+        cpus.resize_with(CPU_NUM, Cpu::new);
         AppManagerInner {
-            started: false,
-            current: 0,
+            cpus,
             initproc: None,
+            kthreads: Vec::new(),
             tasks: Vec::new(),
-            // idle process is a unstop loop process
-            idle_ctx: SwitchContext::new(0, 0),
             named_shm: BTreeMap::new(),
             named_sem: BTreeMap::new(),
             named_srv: BTreeMap::new(),
@@ -370,220 +757,1093 @@ impl AppManagerInner {
         }
     }
 
-    // pub fn app(&self)
-    fn task(&mut self, id: usize) -> Result<Arc<Mutex<Process>>, &'static str> {
-        if self.tasks.len() > 0 {
-            if let Some(task) = self.tasks[id].upgrade() {
-                return Ok(task);
-            } else {
-                self.tasks.remove(id);
-                return Err("task instance not exists now.")
+    // pid 为 0 时返回当前进程
+    fn find_task(&mut self, pid: usize) -> Option<Arc<Mutex<Process>>> {
+        if pid == 0 {
+            return self.current_task();
+        }
+
+        self.tasks.iter()
+            .filter_map(|t| t.upgrade())
+            .find(|t| t.lock().pid.0 == pid)
+    }
+
+    // 当前 cpu 的调度状态
+    fn cpu(&mut self) -> &mut Cpu {
+        &mut self.cpus[cpu_id()]
+    }
+
+    pub fn cpu_online(&mut self) {
+        self.cpu().online = true;
+    }
+
+    // get idle ctx
+    pub fn idle_ctx(&mut self) -> *mut SwitchContext {
+        &mut self.cpu().idle_ctx as *mut _
+    }
+
+    // 新任务放到任务最少的 cpu 上，还没有其他 cpu 启动时放到当前 cpu
+    fn enqueue(&mut self, task: &Arc<Mutex<Process>>) {
+        let cpu = (0..self.cpus.len())
+            .filter(|&i| self.cpus[i].online)
+            .min_by_key(|&i| self.cpus[i].nr_running)
+            .unwrap_or(cpu_id());
+        task.lock().cpu = cpu;
+        self.cpus[cpu].nr_running += 1;
+        self.cpus[cpu].scheduler.add(Arc::downgrade(task));
+        // 那个 cpu 可能没有任务在 wfi 中等待
+        if cpu != cpu_id() {
+            send_ipi(cpu);
+        }
+    }
+
+    // 当前 cpu 没有可以运行的任务时，从任务最多的 cpu 上取一个可运行的任务放到自己的运行队列中
+    // 只有一个任务的 cpu 很快就会运行它，不从那里取
+    fn steal_task(&mut self) -> bool {
+        let this = cpu_id();
+        let mut busiest: Vec<usize> = (0..self.cpus.len())
+            .filter(|&i| i != this && self.cpus[i].nr_running > 1)
+            .collect();
+        busiest.sort_by_key(|&i| core::cmp::Reverse(self.cpus[i].nr_running));
+        for cpu in busiest {
+            if let Some(task) = self.cpus[cpu].scheduler.steal() {
+                self.cpus[cpu].nr_running -= 1;
+                self.cpus[this].nr_running += 1;
+                task.lock().cpu = this;
+                self.cpus[this].scheduler.add(Arc::downgrade(&task));
+                return true;
+            }
+        }
+        false
+    }
+
+    // return app id, if create failed, return -1
+    // only initproc is created, other's created by fork
+    pub fn create_initproc(&mut self, tick: usize) -> isize {
+        // just add a process at the tail
+        let mut initproc = Process::new(tick);
+
+        // initialize kernel pt
+        #[cfg(feature = "riscv64_qemu")]
+        initproc.mm().lock().add_kernel_pt();
+
+        // read elf from fs
+        let Some(buf) = FILESYSTEM.lock().read_all("shell") else {
+            println!("[kernel] open file failed");
+            return -2;
+        };
+
+        // load elf
+        let r = initproc.load_elf(&mut self.kernel_mm.pt, buf.as_slice(), &[String::from("shell")], &[]);
+        if let Err(e) = r {
+            println!("[kernel] initproc load elf error: {}", e);
+            return -3;
+        }
+        println!("[kernel] initproc load elf success");
+        let initproc_arc = Arc::new(Mutex::new(initproc));
+        initproc_arc.lock().set_signalmask(SignalFlags::SIGINT);
+        self.enqueue(&initproc_arc);
+        self.tasks.push(Arc::downgrade(&initproc_arc));
+        self.initproc = Some(initproc_arc);
+        0
+    }
+
+    fn current_task(&self) -> Option<Arc<Mutex<Process>>> {
+        self.cpus[cpu_id()].current.as_ref()?.upgrade()
+    }
+
+    // 把这个 cpu 上被唤醒的任务交给调度器
+    fn wakeup(&mut self, cpu: usize) {
+        for pid in take_wakeups(cpu) {
+            self.cpus[cpu].scheduler.wakeup(pid);
+        }
+    }
+
+    fn next_task(&mut self) -> Option<Arc<Mutex<Process>>> {
+        self.wakeup(cpu_id());
+        let cpu = self.cpu();
+        let next = cpu.scheduler.fetch()?;
+        cpu.current = Some(Arc::downgrade(&next));
+        next.lock().on_cpu = true;
+        next.lock().cputime_start = nanoseconds();
+        Some(next)
+    }
+
+    fn put_prev(&mut self) {
+        let cpu = self.cpu();
+        if let Some(current) = cpu.current.take().and_then(|c| c.upgrade()) {
+            current.lock().charge_stime(nanoseconds());
+            cpu.scheduler.put_prev(&current);
+            // 已经回到 idle，不再使用这个线程的内核栈，可以回收了
+            current.lock().on_cpu = false;
+            if current.lock().exited() {
+                self.release_thread(&current);
+                let tgid = current.lock().tgid;
+                self.try_zombie(tgid);
+            }
+        }
+    }
+
+    // 线程组的所有线程都退出并且离开 cpu 后，进程变成僵尸进程，等待父进程回收
+    // 僵尸进程只保留 pid 和退出状态，它的子进程交给 initproc
+    fn try_zombie(&mut self, tgid: usize) {
+        let Some(leader) = self.find_task(tgid) else {
+            return;
+        };
+        let (parent, children) = {
+            let mut l = leader.lock();
+            if !matches!(l.status, ProcessStatus::EXITED(_)) || l.on_cpu || !l.threads.is_empty() {
+                return;
+            }
+            l.set_zombie();
+            (l.parent, core::mem::take(&mut l.children))
+        };
+
+        self.reparent(children);
+        if let Some(parent) = parent {
+            self.notify_parent(parent);
+        }
+    }
+
+    // 孤儿进程交给 initproc，由 initproc 回收
+    fn reparent(&mut self, children: BTreeMap<usize, Arc<Mutex<Process>>>) {
+        if children.is_empty() {
+            return;
+        }
+        let Some(init) = self.initproc.clone() else {
+            return;
+        };
+        if init.lock().exited() {
+            println!("[kernel] initproc exited, orphans can't be reaped");
+            return;
+        }
+
+        let init_pid = init.lock().pid.0;
+        let mut zombie = false;
+        for (pid, child) in children {
+            let mut c = child.lock();
+            c.parent = Some(init_pid);
+            for t in c.threads.values() {
+                t.lock().parent = Some(init_pid);
+            }
+            zombie |= c.zombie();
+            drop(c);
+            init.lock().children.insert(pid, child);
+        }
+
+        // 已经是僵尸进程的孤儿，需要通知 initproc 回收
+        if zombie {
+            self.notify_parent(init_pid);
+        }
+    }
+
+    // 给父进程发送 SIGCHLD，并唤醒父进程中等待子进程的线程
+    fn notify_parent(&mut self, parent: usize) {
+        for t in self.tasks.iter().filter_map(|t| t.upgrade()) {
+            let mut p = t.lock();
+            if p.tgid != parent {
+                continue;
+            }
+            if p.pid.0 == parent {
+                p.send_sigchld();
+            }
+            p.wake_waiter();
+        }
+    }
+
+    // 回收已经退出的线程，主线程由父进程 wait 回收
+    fn release_thread(&mut self, task: &Arc<Mutex<Process>>) {
+        let (pid, tgid, cpu) = {
+            let p = task.lock();
+            (p.pid.0, p.tgid, p.cpu)
+        };
+        // 退出的线程不再计入所在 cpu 的任务数
+        self.cpus[cpu].nr_running -= 1;
+
+        if task.lock().kthread_entry().is_some() {
+            self.kthreads.retain(|t| !Arc::ptr_eq(t, task));
+            return;
+        }
+
+        if pid == tgid {
+            return;
+        }
+
+        task.lock().release_kernel_stack();
+        let usage = task.lock().usage;
+        if let Some(leader) = self.find_task(tgid) {
+            let mut l = leader.lock();
+            l.threads.remove(&pid);
+            l.exited_usage.add(&usage);
+        }
+    }
+
+    // 结束当前线程组中除当前线程以外的所有线程
+    pub fn kill_threads(&mut self, exit_code: isize) {
+        let current = self.current_task().unwrap();
+        let tgid = current.lock().tgid;
+        let others: Vec<Arc<Mutex<Process>>> = self.tasks.iter()
+            .filter_map(|t| t.upgrade())
+            .filter(|t| !Arc::ptr_eq(t, &current) && t.lock().tgid == tgid)
+            .collect();
+
+        for t in others.iter() {
+            if t.lock().exited() {
+                continue;
+            }
+            let cpu = t.lock().cpu;
+            t.lock().set_status(ProcessStatus::EXITED(exit_code));
+            self.cpus[cpu].scheduler.dequeue(t);
+            // 正在其他 cpu 上运行的线程，等它回到 idle 时再回收
+            if !t.lock().on_cpu {
+                self.release_thread(t);
             }
         }
+    }
+
+    // 处理当前 cpu 上到期的定时器，唤醒睡眠时间已到的任务，发送定时器的信号
+    pub fn run_timers(&mut self) {
+        let now = nanoseconds();
+        for (deadline, event) in self.cpu().timers.expire(now) {
+            match event {
+                TimerEvent::Wakeup(task) => {
+                    let Some(task) = task.upgrade() else {
+                        continue;
+                    };
+                    let mut p = task.lock();
+                    // 已经被提前唤醒，可能又开始了新的睡眠
+                    if let ProcessStatus::SLEEP(start, duration) = p.status {
+                        if start + duration == deadline {
+                            p.set_status(ProcessStatus::READY);
+                        }
+                    }
+                }
+                TimerEvent::Timer(timers, tgid, id) => {
+                    self.fire_timer(timers, tgid, id, deadline, now);
+                }
+            }
+        }
+    }
+
+    fn fire_timer(&mut self, timers: Weak<Mutex<ProcessTimers>>, tgid: usize, id: TimerId, deadline: usize, now: usize) {
+        let Some(timers) = timers.upgrade() else {
+            return;
+        };
+        let (signal, next) = {
+            let mut t = timers.lock();
+            let Some(timer) = t.get(id) else {
+                return;
+            };
+            // 定时器已经被重新设置或者停止了
+            if timer.expire != deadline {
+                return;
+            }
+            let signal = timer.fire(now);
+            (signal, timer.armed().then_some(timer.expire))
+        };
+
+        if let Some(next) = next {
+            self.cpu().timers.add(next, TimerEvent::Timer(Arc::downgrade(&timers), tgid, id));
+        }
+        if let (Some(signal), Some(leader)) = (signal, self.find_task(tgid)) {
+            self.send_signal(&leader, signal);
+        }
+    }
+
+    // 线程组的统计，包括已经退出的线程
+    fn group_usage(&self, tgid: usize, now: usize) -> Usage {
+        let mut usage = Usage::default();
+        for t in self.thread_group(tgid) {
+            let p = t.lock();
+            if p.pid.0 == tgid {
+                usage.add(&p.usage(now));
+                usage.add(&p.exited_usage);
+            } else if !p.exited() {
+                usage.add(&p.usage(now));
+            }
+        }
+        usage
+    }
+
+    fn group_cputime(&self, tgid: usize, now: usize) -> usize {
+        self.group_usage(tgid, now).cputime()
+    }
+
+    // 时钟中断中检查 cpu 时间的定时器
+    fn check_cpu_timers(&mut self, current: &Arc<Mutex<Process>>, now: usize) {
+        let (tgid, tid, thread_time, timers) = {
+            let p = current.lock();
+            (p.tgid, p.pid.0, p.cputime(now), p.timers.clone())
+        };
+        if !timers.lock().cpu_armed() {
+            return;
+        }
+        let usage = self.group_usage(tgid, now);
+        let signals = timers.lock().expire_cpu(usage.cputime(), usage.utime, thread_time, tid);
+        if let Some(leader) = self.find_task(tgid) {
+            for signal in signals {
+                self.send_signal(&leader, signal);
+            }
+        }
+    }
+
+    // 时钟中断中按线程组的 cpu 时间检查 RLIMIT_CPU
+    fn check_cpu_limit(&mut self, current: &Arc<Mutex<Process>>, now: usize) {
+        let (tgid, rlimits) = {
+            let p = current.lock();
+            (p.tgid, p.rlimits.clone())
+        };
+        if rlimits.lock().cur(RLIMIT_CPU) == RLIM_INFINITY {
+            return;
+        }
+        let seconds = self.group_cputime(tgid, now) / NSEC_PER_SEC;
+        let signal = rlimits.lock().check_cpu(seconds);
+        if let (Some(signal), Some(leader)) = (signal, self.find_task(tgid)) {
+            self.send_signal(&leader, signal);
+        }
+    }
+
+    // 时钟中断时调用，返回 true 表示需要切换到其他任务
+    pub fn tick(&mut self) -> bool {
+        self.run_timers();
+        self.wakeup(cpu_id());
+        if let Some(current) = self.current_task() {
+            // 被其他 cpu 上的线程结束或者停止了
+            if current.lock().exited() || current.lock().stopped() {
+                return true;
+            }
+            let now = nanoseconds();
+            current.lock().charge_stime(now);
+            self.check_cpu_timers(&current, now);
+            self.check_cpu_limit(&current, now);
+            self.cpu().scheduler.tick(&current)
+        } else {
+            true
+        }
+    }
+
+    // fork 即不共享任何资源的 clone
+    pub fn clone_task(&mut self, flags: CloneFlags, stack: usize, child_tid: usize, tls: usize) -> isize {
+        // 和 linux 一样，线程必须共享信号处理函数，共享信号处理函数必须共享地址空间
+        if (flags.contains(CloneFlags::THREAD) && !flags.contains(CloneFlags::SIGHAND))
+            || (flags.contains(CloneFlags::SIGHAND) && !flags.contains(CloneFlags::VM)) {
+            return -1;
+        }
+
+        let current = self.current_task().unwrap();
+        if !self.allow_new_task(&current) {
+            println!("[kernel] clone exceeds RLIMIT_NPROC");
+            return -1;
+        }
+        let r = current.lock().clone_task(flags, stack, tls);
+        let child = match r {
+            Ok(child) => child,
+            Err(e) => {
+                println!("[kernel] clone failed: {}", e);
+                return -1;
+            }
+        };
+
+        let (tid, tgid) = {
+            let p = child.lock();
+            (p.pid.0, p.tgid)
+        };
+        if flags.contains(CloneFlags::CHILD_CLEARTID) {
+            child.lock().set_clear_child_tid(child_tid);
+        }
+        if flags.contains(CloneFlags::VFORK) {
+            child.lock().vfork_parent = Some(Arc::downgrade(&current));
+        }
+
+        if flags.contains(CloneFlags::THREAD) {
+            // 线程由主线程持有
+            if let Some(leader) = self.find_task(tgid) {
+                leader.lock().threads.insert(tid, child.clone());
+            }
+        } else {
+            // 子进程属于整个线程组，由主线程持有，任何线程都可以 wait
+            let ptgid = current.lock().tgid;
+            if let Some(leader) = self.find_task(ptgid) {
+                leader.lock().children.insert(tid, child.clone());
+            }
+        }
+
+        // 顺便清理掉已经被释放的进程
+        self.tasks.retain(|t| t.strong_count() > 0);
+        self.enqueue(&child);
+        self.tasks.push(Arc::downgrade(&child));
+        tid as isize
+    }
+
+    // 和 linux 一样，RLIMIT_NPROC 限制的是所有用户任务的数量，包括线程
+    fn allow_new_task(&self, current: &Arc<Mutex<Process>>) -> bool {
+        let nproc = self.tasks.iter()
+            .filter_map(|t| t.upgrade())
+            .filter(|t| t.lock().kthread_entry().is_none())
+            .count();
+        current.lock().rlimits.lock().allow(RLIMIT_NPROC, nproc + 1)
+    }
+
+    // vfork 的子进程 exec 或者退出后，唤醒阻塞的父线程
+    fn release_vfork(&mut self, task: &Arc<Mutex<Process>>) {
+        let parent = task.lock().vfork_parent.take();
+        if let Some(parent) = parent.and_then(|p| p.upgrade()) {
+            parent.lock().wake_waiter();
+        }
+    }
+
+    // 子进程已经不再使用父进程的地址空间
+    fn vfork_done(&mut self, pid: usize) -> bool {
+        match self.find_task(pid) {
+            Some(child) => child.lock().vfork_parent.is_none(),
+            None => true,
+        }
+    }
+
+    // 不复制当前进程，直接从 path 创建子进程，返回子进程的 pid
+    // pgroup 不为 None 时子进程加入这个进程组，0 表示以子进程为首进程的新进程组
+    pub fn spawn(&mut self, path: &str, argv: &[String], envp: &[String], actions: &[SpawnAction], pgroup: Option<usize>) -> isize {
+        let current = self.current_task().unwrap();
+        if !self.allow_new_task(&current) {
+            println!("[kernel] spawn exceeds RLIMIT_NPROC");
+            return -1;
+        }
+        let (ptgid, sid) = {
+            let c = current.lock();
+            (c.tgid, c.sid)
+        };
+        if let Some(pgid) = pgroup.filter(|&g| g != 0) {
+            if !self.group_exists(pgid, sid) {
+                println!("[kernel] spawn {} failed: process group {} not found", path, pgid);
+                return -1;
+            }
+        }
+
+        let Some(elf) = FILESYSTEM.lock().read_all(path) else {
+            println!("[kernel] spawn {} failed: file not found", path);
+            return -1;
+        };
+        let r = current.lock().spawn(&elf, argv, envp, actions);
+        let mut child = match r {
+            Ok(child) => child,
+            Err(e) => {
+                println!("[kernel] spawn {} failed: {}", path, e);
+                return -1;
+            }
+        };
+        let pid = child.pid.0;
+        if let Some(pgid) = pgroup {
+            child.pgid = if pgid == 0 { pid } else { pgid };
+        }
+
+        let child = Arc::new(Mutex::new(child));
+        if let Some(leader) = self.find_task(ptgid) {
+            leader.lock().children.insert(pid, child.clone());
+        }
+        self.tasks.retain(|t| t.strong_count() > 0);
+        self.enqueue(&child);
+        self.tasks.push(Arc::downgrade(&child));
+        pid as isize
+    }
+
+    pub fn kthread_create(&mut self, entry: fn(), start: usize) -> isize {
+        let kthread = Arc::new(Mutex::new(Process::new_kthread(1, entry, start)));
+        let pid = kthread.lock().pid.0;
+        self.enqueue(&kthread);
+        self.tasks.push(Arc::downgrade(&kthread));
+        self.kthreads.push(kthread);
+        pid as isize
+    }
+
+    // 成功时返回新的用户栈地址，它作为系统调用的返回值写入 a0/x0，_start 从这里读取 argc、argv 和 envp
+    pub fn exec(&mut self, path: &str, argv: &[String], envp: &[String]) -> isize {
+        // 只支持主线程 exec，其他线程会被结束
+        let current = self.current_task().unwrap();
+        let (pid, tgid) = {
+            let p = current.lock();
+            (p.pid.0, p.tgid)
+        };
+        if pid != tgid {
+            println!("[kernel] exec is only supported in main thread");
+            return -1;
+        }
+
+        // 先在新的地址空间中加载，失败时进程保持原样
+        let Some(elf) = FILESYSTEM.lock().read_all(path) else {
+            println!("[kernel] exec {} failed: file not found", path);
+            return -1;
+        };
+        let r = current.lock().load_image(&elf, argv, envp);
+        let image = match r {
+            Ok(image) => image,
+            Err(e) => {
+                println!("[kernel] exec {} failed: {}", path, e);
+                return -1;
+            }
+        };
+        // 之后不会再失败，可以结束其他线程
+        self.kill_threads(0);
+
+        let sp = current.lock().exec(image);
+        self.release_vfork(&current);
+        sp as isize
+    }
+
+    pub fn wait(&mut self, pid: isize, options: WaitOptions) -> WaitResult {
+        let (tgid, pgid) = {
+            let c = self.current_task().unwrap();
+            let c = c.lock();
+            (c.tgid, c.pgid)
+        };
+        let r = match self.find_task(tgid) {
+            Some(leader) => leader.lock().wait(pid, pgid, options),
+            None => WaitResult::NoChild,
+        };
+        if let WaitResult::Exited(..) = r {
+            // 被回收的进程已经释放，顺便清理掉它的弱引用
+            self.tasks.retain(|t| t.strong_count() > 0);
+        }
+        r
+    }
+
+    pub fn write(&mut self, fd: usize, buf: *mut u8, len: usize) -> isize {
+        let current = self.current_task().unwrap();
+        let r = current.lock().write(fd, buf, len);
+        if r > 0 {
+            current.lock().usage.write_bytes(r as usize);
+        }
+        r
+    }
+
+    pub fn create_pipe(&mut self, size: usize) -> Option<(usize, usize)> {
+        self.current_task().unwrap().lock().create_pipe(size)
+    }
+
+    pub fn read(&mut self, fd: usize, buf: *mut u8, len: usize) -> isize {
+        let current = self.current_task().unwrap();
+        let r = current.lock().read(fd, buf, len);
+        if r > 0 {
+            current.lock().usage.read_bytes(r as usize);
+        }
+        r
+    }
+
+    pub fn open(&mut self, name: String, flags: usize) -> isize {
+        self.current_task().unwrap().lock().open(name.as_str(), flags)
+    }
+
+    pub fn lseek(&mut self, fd: usize, seek: usize) -> isize {
+        self.current_task().unwrap().lock().lseek(fd, seek)
+    }
+
+    pub fn filesize(&mut self, fd: usize) -> isize{
+        self.current_task().unwrap().lock().filesize(fd)
+    }
+
+    pub fn sigaction(&mut self, signal: usize, handler: usize) -> isize {
+        self.current_task().unwrap().lock().sigaction(signal, SignalAction::new(signal, handler))
+    }
+
+    pub fn set_signal(&mut self, pid: Option<usize>, signal: usize) -> isize {
+        if let Some(pid) = pid {
+            let task = self.tasks.iter()
+                .filter_map(|t| t.upgrade())
+                .find(|t| t.lock().pid.0 == pid);
+            if let Some(t) = task {
+                return self.send_signal(&t, signal);
+            }
+        } else {
+            return self.current_task().unwrap().lock().set_signal(signal);
+        }
+
+        -1
+    }
+
+    // 和 linux 的 kill 相同，pid 大于 0 时发给这个进程，为 0 时发给当前进程组
+    // 为 -1 时发给除 initproc 以外的所有进程，小于 -1 时发给进程组 -pid
+    pub fn kill(&mut self, pid: isize, signal: usize) -> isize {
+        if pid > 0 {
+            return self.set_signal(Some(pid as usize), signal);
+        }
+
+        let pgid = match pid {
+            0 => self.current_task().unwrap().lock().pgid,
+            -1 => 0,
+            _ => (-pid) as usize,
+        };
+        let init = self.initproc.as_ref().map(|i| i.lock().pid.0);
+        let targets: Vec<Arc<Mutex<Process>>> = self.tasks.iter()
+            .filter_map(|t| t.upgrade())
+            .filter(|t| {
+                let p = t.lock();
+                p.pid.0 == p.tgid && !p.exited() && p.kthread_entry().is_none()
+                    && if pid == -1 { Some(p.pid.0) != init } else { p.pgid == pgid }
+            })
+            .collect();
+
+        if targets.is_empty() {
+            return -1;
+        }
+        for t in targets.iter() {
+            self.send_signal(t, signal);
+        }
+        0
+    }
+
+    fn send_signal(&mut self, task: &Arc<Mutex<Process>>, signal: usize) -> isize {
+        let (r, tgid) = {
+            let mut p = task.lock();
+            let r = p.set_signal(signal);
+            // 打断阻塞的 wait 和睡眠，让信号尽快被处理
+            if p.signal_pending() {
+                p.wake_waiter();
+                if let ProcessStatus::SLEEP(..) = p.status {
+                    p.set_status(ProcessStatus::READY);
+                }
+            }
+            (r, p.tgid)
+        };
+
+        // SIGCONT 让停止的进程继续运行，SIGKILL 也要唤醒停止的进程，让它退出
+        if signal == SIGCONT || signal == SIGKILL {
+            self.continue_group(tgid);
+        }
+        r
+    }
+
+    // 线程组中的所有线程
+    fn thread_group(&self, tgid: usize) -> Vec<Arc<Mutex<Process>>> {
+        self.tasks.iter()
+            .filter_map(|t| t.upgrade())
+            .filter(|t| t.lock().tgid == tgid)
+            .collect()
+    }
+
+    // 停止当前线程组中可以运行的线程，并通知父进程
+    pub fn stop_group(&mut self, signal: usize) {
+        let tgid = self.current_task().unwrap().lock().tgid;
+        for t in self.thread_group(tgid) {
+            let mut p = t.lock();
+            if let ProcessStatus::READY | ProcessStatus::RUNNING(_) | ProcessStatus::SLEEP(..) = p.status {
+                p.set_status(ProcessStatus::STOPPED);
+            }
+        }
+
+        let Some(leader) = self.find_task(tgid) else {
+            return;
+        };
+        let parent = {
+            let mut l = leader.lock();
+            l.stop_signal = Some(signal);
+            l.continued = false;
+            l.parent
+        };
+        if let Some(parent) = parent {
+            self.notify_parent(parent);
+        }
+    }
+
+    // 继续运行停止的线程组，并通知父进程
+    fn continue_group(&mut self, tgid: usize) {
+        let mut stopped = false;
+        for t in self.thread_group(tgid) {
+            let mut p = t.lock();
+            if p.stopped() {
+                p.set_status(ProcessStatus::READY);
+                stopped = true;
+            }
+        }
+        if !stopped {
+            return;
+        }
+
+        let Some(leader) = self.find_task(tgid) else {
+            return;
+        };
+        let parent = {
+            let mut l = leader.lock();
+            l.stop_signal = None;
+            l.continued = true;
+            l.parent
+        };
+        if let Some(parent) = parent {
+            self.notify_parent(parent);
+        }
+    }
+
+    // pid 和 pgid 为 0 时表示当前进程，只能修改自己或者子进程，不能修改会话首进程
+    // 加入已有的进程组时，这个进程组必须在同一个会话中
+    pub fn setpgid(&mut self, pid: usize, pgid: usize) -> isize {
+        let (cur, sid) = {
+            let c = self.current_task().unwrap();
+            let c = c.lock();
+            (c.tgid, c.sid)
+        };
+        let pid = if pid == 0 { cur } else { pid };
+        let pgid = if pgid == 0 { pid } else { pgid };
+
+        let Some(target) = self.find_task(pid) else {
+            return -1;
+        };
+        let (tgid, parent, tsid) = {
+            let t = target.lock();
+            (t.tgid, t.parent, t.sid)
+        };
+        if tgid != pid || (tgid != cur && parent != Some(cur)) || tsid != sid || tsid == tgid {
+            return -1;
+        }
+        if pgid != pid && !self.group_exists(pgid, sid) {
+            return -1;
+        }
+
+        for t in self.thread_group(tgid) {
+            t.lock().pgid = pgid;
+        }
+        0
+    }
+
+    pub fn getpgid(&mut self, pid: usize) -> isize {
+        match self.find_task(pid) {
+            Some(t) => t.lock().pgid as isize,
+            None => -1,
+        }
+    }
+
+    // 创建新的会话和进程组，进程组首进程不能创建会话
+    pub fn setsid(&mut self) -> isize {
+        let tgid = self.current_task().unwrap().lock().tgid;
+        let in_use = self.tasks.iter()
+            .filter_map(|t| t.upgrade())
+            .any(|t| t.lock().pgid == tgid);
+        if in_use {
+            return -1;
+        }
+
+        for t in self.thread_group(tgid) {
+            let mut p = t.lock();
+            p.pgid = tgid;
+            p.sid = tgid;
+        }
+        tgid as isize
+    }
+
+    pub fn getsid(&mut self, pid: usize) -> isize {
+        match self.find_task(pid) {
+            Some(t) => t.lock().sid as isize,
+            None => -1,
+        }
+    }
+
+    // 会话 sid 中是否有进程组 pgid
+    fn group_exists(&self, pgid: usize, sid: usize) -> bool {
+        self.tasks.iter()
+            .filter_map(|t| t.upgrade())
+            .any(|t| {
+                let p = t.lock();
+                p.pgid == pgid && p.sid == sid && !p.exited()
+            })
+    }
+
+    pub fn set_signalmask(&mut self, mask: SignalFlags) -> isize {
+        self.current_task().unwrap().lock().set_signalmask(mask)
+    }
 
-        return Err("tasks not created now.")
+    pub fn signal_check(&mut self) -> SignalCode {
+        self.current_task().unwrap().lock().signal_check()
     }
 
+    pub fn save_trap_ctx(&mut self) {
+        self.current_task().unwrap().lock().save_trap_ctx()
+    }
 
-    // get idle ctx
-    pub fn idle_ctx(&mut self) -> *mut SwitchContext {
-        &mut self.idle_ctx as *mut _
+    pub fn sigreturn(&mut self) -> isize {
+        self.current_task().unwrap().lock().sigreturn()
     }
 
-    // return app id, if create failed, return -1
-    // only initproc is created, other's created by fork
-    pub fn create_initproc(&mut self, tick: usize) -> isize {
-        // just add a process at the tail
-        let mut initproc = Process::new(tick);
+    pub fn getpid(&mut self) -> usize {
+        self.current_task().unwrap().lock().tgid
+    }
 
-        // initialize kernel pt
-        #[cfg(feature = "riscv64_qemu")]
-        initproc.mm.add_kernel_pt();
+    // initproc 和内核线程没有父进程，返回 0
+    pub fn getppid(&mut self) -> usize {
+        self.current_task().unwrap().lock().parent.unwrap_or(0)
+    }
 
-        // read elf from fs
-        let fd = initproc.open("shell");
-        if fd < 0 {
-            println!("[kernel] open file failed");
-            return -2;
-        }
-        let mut size = initproc.filesize(fd as usize);
-        let mut buf: Vec<u8> = Vec::new();
-        buf.resize(size as usize, 0);
-        size = initproc.read(fd as usize, buf.as_mut_ptr(), size as usize);
+    pub fn gettid(&mut self) -> usize {
+        self.current_task().unwrap().lock().pid.0
+    }
 
-        // load elf
-        let r = initproc.load_elf(&mut self.kernel_mm.pt, buf.as_slice());
-        if let Err(e) = r {
-            println!("[kernel] initproc load elf error: {}", e);
-            return -3;
+    // 读取时钟，不支持的 clock 返回 None
+    pub fn clock_gettime(&mut self, clock: usize) -> Option<usize> {
+        let now = nanoseconds();
+        let current = self.current_task().unwrap();
+        match clock {
+            CLOCK_REALTIME => Some(timer::realtime()),
+            CLOCK_MONOTONIC => Some(now),
+            CLOCK_PROCESS_CPUTIME_ID => {
+                let tgid = current.lock().tgid;
+                Some(self.group_cputime(tgid, now))
+            }
+            CLOCK_THREAD_CPUTIME_ID => Some(current.lock().cputime(now)),
+            _ => None,
         }
-        println!("[kernel] initproc load elf success");
-        let initproc_arc = Arc::new(Mutex::new(initproc));
-        initproc_arc.lock().set_signalmask(SignalFlags::SIGINT);
-        self.tasks.push(Arc::downgrade(&initproc_arc));
-        self.initproc = Some(initproc_arc);
-        0
     }
 
-    fn current_task(&mut self, must: bool) -> Option<Arc<Mutex<Process>>> {
-        match self.task(self.current) {
-            Ok(p) => {
-                Some(p)
-            }
-            Err(e) => {
-                // println!("[kernel] get task {} failed, {}", self.current, e);
-                if must == false {
-                    return self.next_task()
-                } else {
-                    return None;
-                }
-            } 
+    // 定时器所用时钟的当前值，REALTIME 的定时器也使用 MONOTONIC 时间
+    fn timer_now(&self, timer: &IntervalTimer, tgid: usize) -> usize {
+        let now = nanoseconds();
+        match timer.clock {
+            CLOCK_PROCESS_CPUTIME_ID => self.group_cputime(tgid, now),
+            CLOCK_PROCESS_USERTIME => self.group_usage(tgid, now).utime,
+            CLOCK_THREAD_CPUTIME_ID => self.tasks.iter()
+                .filter_map(|t| t.upgrade())
+                .find(|t| t.lock().pid.0 == timer.tid)
+                .map_or(0, |t| t.lock().cputime(now)),
+            _ => now,
         }
     }
 
-    fn next_task(&mut self) -> Option<Arc<Mutex<Process>>> {
-        assert!(self.tasks.len() > 0, "The app vector is empty!!!");
-        if self.started {
-            // When the next api be called, there must be at least one apps in vector
-            let next = (self.current + 1) % self.tasks.len();
-            self.current = next;
-            match self.task(self.current) {
-                Ok(p) => {
-                    Some(p)
-                }
-                Err(e) => {
-                    // println!("[kernel] get task {} failed, {}", self.current, e);
-                    self.next_task()
-                } 
-            }
+    // 设置定时器，value 为 0 时停止，返回之前的剩余时间和间隔，没有这个定时器时返回 None
+    // abstime 为 true 时 value 是时钟上的绝对时间
+    pub fn set_timer(&mut self, id: TimerId, value: usize, interval: usize, abstime: bool) -> Option<(usize, usize)> {
+        let (tgid, timers) = {
+            let current = self.current_task().unwrap();
+            let p = current.lock();
+            (p.tgid, p.timers.clone())
+        };
+        let mut t = timers.lock();
+        let timer = t.get(id)?;
+        let now = self.timer_now(timer, tgid);
+        let old = (timer.remaining(now), timer.interval);
+
+        timer.interval = interval;
+        timer.overrun = 0;
+        timer.expire = if value == 0 {
+            0
+        } else if !abstime {
+            now + value
+        } else if timer.clock == CLOCK_REALTIME {
+            value.saturating_sub(realtime_offset()).max(1)
         } else {
-            self.started = true;
-            Some(self.task(0).unwrap())
+            value
+        };
+
+        // cpu 时间的定时器在时钟中断中检查，其他的放到当前 cpu 的定时器队列中
+        let queued = timer.armed() && !timer.cpu_clock();
+        let expire = timer.expire;
+        drop(t);
+        if queued {
+            self.cpu().timers.add(expire, TimerEvent::Timer(Arc::downgrade(&timers), tgid, id));
         }
+        Some(old)
     }
 
-    pub fn fork(&mut self) -> isize {
-        let (child, pid) = self.current_task(true).unwrap().lock().fork();
-        self.tasks.push(child);
-        pid as isize
+    // 返回定时器的剩余时间和间隔
+    pub fn get_timer(&mut self, id: TimerId) -> Option<(usize, usize)> {
+        let (tgid, timers) = {
+            let current = self.current_task().unwrap();
+            let p = current.lock();
+            (p.tgid, p.timers.clone())
+        };
+        let mut t = timers.lock();
+        let timer = t.get(id)?;
+        let now = self.timer_now(timer, tgid);
+        Some((timer.remaining(now), timer.interval))
     }
 
-    pub fn exec(&mut self, elf: &[u8]) -> isize {
-        match self.current_task(true).unwrap().lock().exec(elf) {
-            Ok(_) => {return 0;}
-            Err(e) => {
-                println!("[kernel] exec failed {}", e);
-                return  -1;
-            }
+    // signal 为 0 时到期不发送信号，返回 timer id
+    pub fn timer_create(&mut self, clock: usize, signal: usize) -> isize {
+        if clock > CLOCK_THREAD_CPUTIME_ID || signal >= SIG_NUM {
+            return -1;
         }
+        let current = self.current_task().unwrap();
+        let p = current.lock();
+        let timer = IntervalTimer::new(clock, signal, p.pid.0);
+        let id = p.timers.lock().create(timer);
+        id as isize
     }
 
-    pub fn cow(&mut self, vpn: VirtPage) -> Result<(), &'static str> {
-        self.current_task(true).unwrap().lock().cow(vpn)
+    pub fn timer_delete(&mut self, id: usize) -> isize {
+        let current = self.current_task().unwrap();
+        let timers = current.lock().timers.clone();
+        if timers.lock().delete(id) {
+            0
+        } else {
+            -1
+        }
     }
 
-    pub fn wait(&mut self, pid: isize) -> isize {
-        self.current_task(true).unwrap().lock().wait(pid)
+    pub fn timer_getoverrun(&mut self, id: usize) -> isize {
+        let current = self.current_task().unwrap();
+        let timers = current.lock().timers.clone();
+        let mut t = timers.lock();
+        match t.get(TimerId::Posix(id)) {
+            Some(timer) => timer.overrun as isize,
+            None => -1,
+        }
     }
 
-    pub fn write(&mut self, fd: usize, buf: *mut u8, len: usize) -> isize {
-        self.current_task(true).unwrap().lock().write(fd, buf, len)
+    // RUSAGE_SELF 是整个线程组的统计，不支持的 who 返回 None
+    pub fn getrusage(&mut self, who: isize) -> Option<Usage> {
+        let now = nanoseconds();
+        let current = self.current_task().unwrap();
+        let tgid = current.lock().tgid;
+        match who {
+            RUSAGE_SELF => {
+                let mut usage = self.group_usage(tgid, now);
+                usage.maxrss = usage.maxrss.max(current.lock().rss());
+                Some(usage)
+            }
+            RUSAGE_THREAD => {
+                let p = current.lock();
+                let mut usage = p.usage(now);
+                usage.maxrss = usage.maxrss.max(p.rss());
+                Some(usage)
+            }
+            RUSAGE_CHILDREN => self.find_task(tgid).map(|l| l.lock().children_usage),
+            _ => None,
+        }
     }
 
-    pub fn create_pipe(&mut self, size: usize) -> (usize, usize) {
-        self.current_task(true).unwrap().lock().create_pipe(size)
+    pub fn times(&mut self) -> Tms {
+        let now = nanoseconds();
+        let tgid = self.current_task().unwrap().lock().tgid;
+        let usage = self.group_usage(tgid, now);
+        let children = self.find_task(tgid).map_or(Usage::default(), |l| l.lock().children_usage);
+        Tms::new(&usage, &children)
     }
 
-    pub fn read(&mut self, fd: usize, buf: *mut u8, len: usize) -> isize {
-        self.current_task(true).unwrap().lock().read(fd, buf, len)
+    pub fn set_priority(&mut self, pid: usize, nice: isize) -> isize {
+        if let Some(task) = self.find_task(pid) {
+            task.lock().nice = nice.clamp(NICE_MIN, NICE_MAX);
+            let cpu = task.lock().cpu;
+            self.cpus[cpu].scheduler.reprioritize(&task);
+            0
+        } else {
+            -1
+        }
     }
 
-    pub fn open(&mut self, name: String) -> isize {
-        self.current_task(true).unwrap().lock().open(name.as_str())
+    // 和 linux 系统调用一样，返回 20 - nice，避免与错误码混淆
+    pub fn get_priority(&mut self, pid: usize) -> isize {
+        if let Some(task) = self.find_task(pid) {
+            20 - task.lock().nice
+        } else {
+            -1
+        }
     }
 
-    pub fn lseek(&mut self, fd: usize, seek: usize) -> isize {
-        self.current_task(true).unwrap().lock().lseek(fd, seek)
-    }
+    pub fn set_scheduler(&mut self, pid: usize, policy: usize, param: SchedParam) -> isize {
+        let policy = match SchedPolicy::from_param(policy, &param) {
+            Some(policy) => policy,
+            None => return -1,
+        };
 
-    pub fn filesize(&mut self, fd: usize) -> isize{
-        self.current_task(true).unwrap().lock().filesize(fd)
+        if let Some(task) = self.find_task(pid) {
+            let (cpu, running) = {
+                let p = task.lock();
+                (p.cpu, p.on_cpu)
+            };
+            self.cpus[cpu].scheduler.set_policy(&task, policy, running)
+        } else {
+            -1
+        }
     }
 
-    pub fn sigaction(&mut self, signal: usize, handler: usize) -> isize {
-        self.current_task(true).unwrap().lock().sigaction(signal, SignalAction::new(signal, handler))
+    pub fn get_scheduler(&mut self, pid: usize) -> Option<SchedPolicy> {
+        Some(self.find_task(pid)?.lock().policy)
     }
 
-    pub fn set_signal(&mut self, pid: Option<usize>, signal: usize) -> isize {
-        if let Some(pid) = pid {
-            for task in (&self.tasks).into_iter() {
-                if let Some(t) = task.upgrade() {
-                    if t.lock().pid.0 == pid {
-                        return t.lock().set_signal(signal)
-                    }
-                }
-            }
+    // pid 为 0 时是当前进程，new 不为空时设置新的限制，返回之前的限制，失败时返回 None
+    pub fn prlimit(&mut self, pid: usize, resource: usize, new: Option<RLimit>) -> Option<RLimit> {
+        let task = if pid == 0 {
+            self.current_task()?
         } else {
-            return self.current_task(true).unwrap().lock().set_signal(signal);
+            self.find_task(pid)?
+        };
+        let (rlimits, mm) = {
+            let t = task.lock();
+            (t.rlimits.clone(), t.mm())
+        };
+        let mut limits = rlimits.lock();
+        let old = limits.get(resource)?;
+        if let Some(new) = new {
+            if let Err(e) = limits.set(resource, new) {
+                println!("[kernel] setrlimit failed: {}", e);
+                return None;
+            }
+            // RLIMIT_RSS 在页错误中检查，记录到地址空间中
+            if resource == RLIMIT_RSS {
+                drop(limits);
+                mm.lock().set_rss_limit(new.rlim_cur);
+            }
         }
+        Some(old)
+    }
 
-        -1
+    pub fn mmap(&mut self, size: usize, permission: usize) -> isize {
+        self.current_task().unwrap().lock().mmap(size, permission)
     }
 
-    pub fn set_signalmask(&mut self, mask: SignalFlags) -> isize {
-        self.current_task(true).unwrap().lock().set_signalmask(mask)
+    pub fn ummap(&mut self, addr: usize) -> isize {
+        self.current_task().unwrap().lock().ummap(addr.into())
     }
 
-    pub fn signal_check(&mut self) -> SignalCode {
-        self.current_task(true).unwrap().lock().signal_check()
+    pub fn mmap_file(&mut self, fd: usize, offset: usize, len: usize, permission: usize, flags: usize) -> isize {
+        self.current_task().unwrap().lock().mmap_file(fd, offset, len, permission, flags)
     }
 
-    pub fn save_trap_ctx(&mut self) {
-        self.current_task(true).unwrap().lock().save_trap_ctx()
+    pub fn msync(&mut self, addr: usize, len: usize) -> isize {
+        self.current_task().unwrap().lock().msync(addr, len)
     }
 
-    pub fn sigreturn(&mut self) -> isize {
-        self.current_task(true).unwrap().lock().sigreturn()
+    pub fn brk(&mut self, addr: usize) -> isize {
+        self.current_task().unwrap().lock().brk(addr)
     }
 
-    pub fn getpid(&mut self) -> usize {
-        self.current_task(true).unwrap().lock().pid.0
+    pub fn mprotect(&mut self, addr: usize, len: usize, permission: usize) -> isize {
+        self.current_task().unwrap().lock().mprotect(addr, len, permission)
     }
 
-    pub fn mmap(&mut self, size: usize, permission: usize) -> isize {
-        self.current_task(true).unwrap().lock().mmap(size, permission)
+    pub fn allow_wx(&mut self) -> bool {
+        self.current_task().unwrap().lock().mm().lock().allow_wx()
     }
 
-    pub fn ummap(&mut self, addr: usize) -> isize {
-        self.current_task(true).unwrap().lock().ummap(addr.into())
+    pub fn set_allow_wx(&mut self, allow: bool) {
+        self.current_task().unwrap().lock().mm().lock().set_allow_wx(allow)
     }
 
     pub fn mmap_with_addr(&mut self, pa: usize, size: usize, permission: usize, user: bool) -> isize {
-        self.current_task(true).unwrap().lock().mmap_with_addr(pa.into(), size, permission, user)
+        self.current_task().unwrap().lock().mmap_with_addr(pa.into(), size, permission, user)
     }
     
     pub fn create_or_open_shm(&mut self, name: String, pn: usize, permission: usize) -> isize {
-        let current_task = self.current_task(true).unwrap();
-        let pid = current_task.lock().pid.0;
+        let current_task = self.current_task().unwrap();
+        // 同一进程的线程共享地址空间，用 tgid 区分
+        let pid = current_task.lock().tgid;
+        let mm = current_task.lock().mm();
         if let Some(shm) = self.named_shm.get_mut(&name) {
             // map with process memory manager
-            shm.map(pid, &mut current_task.lock().mm)
+            shm.map(pid, &mut mm.lock())
         } else {
             // create a shm
             let mut shm = Shm::new(pn, permission);
-            let r = shm.map(pid, &mut current_task.lock().mm);
+            let r = shm.map(pid, &mut mm.lock());
             self.named_shm.insert(name, shm);
             r
         }
     }
 
     pub fn close_shm(&mut self, addr: usize, name: String) -> isize {
-        let current_task = self.current_task(true).unwrap();
-        let pid = current_task.lock().pid.0;
+        let current_task = self.current_task().unwrap();
+        let pid = current_task.lock().tgid;
+        let mm = current_task.lock().mm();
         if let Some(shm) = self.named_shm.get_mut(&name) {
             // map with process memory manager
             let start_vpn: VirtPage = VirtAddr::from(addr).into();
-            shm.unmap(pid, start_vpn, &mut current_task.lock().mm);
+            shm.unmap(pid, start_vpn, &mut mm.lock());
+            // 之后 remove_shm 会释放页帧
+            flush_tlb(current_task.lock().asid.0 as usize);
             0
         } else {
             println!("[kernel] This shm is not exist");
@@ -618,7 +1878,7 @@ impl AppManagerInner {
     }
 
     pub fn wait_sem(&mut self, name: String) -> isize {
-        let current_task = self.current_task(true).unwrap();
+        let current_task = self.current_task().unwrap();
         if let Some(sem) = self.named_sem.get_mut(&name) {
             current_task.lock().set_status(ProcessStatus::WAITING);
             sem.lock().wait(Arc::downgrade(&current_task));
@@ -662,7 +1922,7 @@ impl AppManagerInner {
             println!("[kernel] server {} already exists", name.as_str());
             return -1;
         } else {
-            let proc: Weak<Mutex<Process>> = Arc::downgrade(&self.current_task(true).unwrap());
+            let proc: Weak<Mutex<Process>> = Arc::downgrade(&self.current_task().unwrap());
             let srv: Arc<Mutex<Server>> = Arc::new(Mutex::new(Server::new()));
             self.named_srv.insert(name, srv);
             0
@@ -727,164 +1987,555 @@ impl AppManagerInner {
     }
 }
 
+// 和 linux 的 task_struct 一样，Process 是调度的基本单位，也就是一个线程
+// 同一线程组（进程）中的线程通过 Arc 共享地址空间、文件和信号处理函数，每个线程有自己的内核栈和上下文
 pub struct Process {
+    // 每次被调度运行时可以使用的时间片数量
     pub tick: usize,
+    pub nice: isize,
+    pub policy: SchedPolicy,
+    pub se: SchedEntity,
     pub status: ProcessStatus,
+    // 线程 id，主线程的 pid 也就是进程 id
     pub pid: PidHandler,
+    // 线程组 id，即主线程的 pid，getpid 返回这个值
+    pub tgid: usize,
+    // 父进程的 pid，父进程退出后变成 initproc
     pub parent: Option<usize>,
+    // 进程组和会话，同一线程组中的线程相同
+    pub pgid: usize,
+    pub sid: usize,
+    // 主线程使用，停止后还没有被 wait(WUNTRACED) 报告的停止信号
+    stop_signal: Option<usize>,
+    // 主线程使用，继续运行后还没有被 wait(WCONTINUED) 报告
+    continued: bool,
     pub children: BTreeMap<usize, Arc<Mutex<Self>>>,
-    
+    // 只有主线程使用，持有同一线程组中的其他线程
+    pub threads: BTreeMap<usize, Arc<Mutex<Self>>>,
+    // CLONE_CHILD_CLEARTID，线程退出时将这个用户地址清零，用于 join
+    clear_child_tid: usize,
+    // 内核线程的入口，用户线程为 None
+    kthread_entry: Option<fn()>,
+    // 所在的 cpu，即在哪个 cpu 的运行队列中
+    pub cpu: usize,
+    // 正在 cpu 上运行，包括回到 idle 但是还没有放回运行队列的时候，这时不能回收它的内核栈和页表
+    pub on_cpu: bool,
+    // 在 wait 中阻塞等待子进程，用来和信号量的 WAITING 区分
+    pub wait_child: bool,
+    // vfork 创建的子进程在 exec 或者退出之前，父线程一直阻塞，之后通过这里唤醒它
+    vfork_parent: Option<Weak<Mutex<Self>>>,
+    // 线程的资源使用统计，cpu 时间在 trap 进出、时钟中断和回到 idle 时结算
+    pub usage: Usage,
+    // 本次开始运行或者上次结算的时间
+    pub cputime_start: usize,
+    // 主线程使用，已经回收的其他线程的统计
+    pub exited_usage: Usage,
+    // 主线程使用，已经被 wait 回收的子进程的统计，包括子进程回收的子进程
+    pub children_usage: Usage,
+    // setitimer 和 timer_create 的定时器，线程组共享
+    pub timers: Arc<Mutex<ProcessTimers>>,
+    // 资源限制，线程组共享，fork 时复制
+    pub rlimits: Arc<Mutex<ResourceLimits>>,
+
     ctx: SwitchContext,
-    mm: MemoryManager,
-    asid: AisdHandler,
-    fds: Vec<Option<Arc<dyn File>>>,
+    // 内核栈的 slot，参考 mm::kernel_stack_top
+    kstack: usize,
+    mm: Arc<Mutex<MemoryManager>>,
+    asid: Arc<AisdHandler>,
+    fds: Arc<Mutex<Vec<Option<FileDesc>>>>,
     signals: SignalFlags,
     signals_mask: SignalFlags,
-    signal_actions: Vec<Option<SignalAction>>,
+    signal_actions: Arc<Mutex<Vec<Option<SignalAction>>>>,
     trap_ctx_backup: Option<TrapContext>,
 }
 
 impl Process {
     // new 只会创建一个完全空白，无法运行的进程，需要 load_elf 才可使用
     pub fn new(tick: usize) -> Self {
+        let pid = pid::alloc().unwrap();
+        let tgid = pid.0;
         Process {
             tick,
+            nice: 0,
+            policy: SchedPolicy::Normal,
+            se: SchedEntity::default(),
             status: ProcessStatus::UNINIT,
-            pid: pid::alloc().unwrap(),
+            pid,
+            tgid,
             parent: None,
+            pgid: tgid,
+            sid: tgid,
+            stop_signal: None,
+            continued: false,
             children: BTreeMap::new(),
+            threads: BTreeMap::new(),
+            clear_child_tid: 0,
+            kthread_entry: None,
+            cpu: 0,
+            on_cpu: false,
+            wait_child: false,
+            vfork_parent: None,
+            usage: Usage::default(),
+            cputime_start: 0,
+            exited_usage: Usage::default(),
+            children_usage: Usage::default(),
+            timers: Arc::new(Mutex::new(ProcessTimers::new())),
+            rlimits: Arc::new(Mutex::new(ResourceLimits::new())),
             ctx: SwitchContext::bare(),
-            mm: MemoryManager::new(false),
-            asid: asid_alloc().unwrap(),
-            fds: vec![
+            kstack: 0,
+            mm: MemoryManager::new(false).into_shared(),
+            asid: Arc::new(asid_alloc().unwrap()),
+            fds: Arc::new(Mutex::new(vec![
                 // 0 -> stdin
-                Some(Arc::new(Stdin)),
+                Some(FileDesc::new(Arc::new(Stdin), false)),
                 // 1 -> stdout
-                Some(Arc::new(Stdout)),
+                Some(FileDesc::new(Arc::new(Stdout), false)),
                 // 2 -> stderr
                 None,
-            ],
+            ])),
             signals: SignalFlags::empty(),
             signals_mask: SignalFlags::all(),
-            signal_actions: vec![None; SIG_NUM],
+            signal_actions: Arc::new(Mutex::new(vec![None; SIG_NUM])),
             trap_ctx_backup: None,
         }
     }
 
-    pub fn fork(&mut self) -> (Weak<Mutex<Self>>, usize) {
-        let mut mm = MemoryManager::new(false);
-        mm.fork(&mut self.mm);
-        let switch_ctx = SwitchContext::new_with_restore_addr_and_kernel_stack_sp(
-            crate::board::inner::memory::KERNEL_STACK_START
-        );
-        let pid = pid::alloc().unwrap();
-        let key = pid.0;
-        let tick = self.tick;
-        let fds = self.fds.clone();
-        let signals =  self.signals;
-        let signals_mask = self.signals_mask;
-        let signal_actions = self.signal_actions.clone();
-
-        let child = Arc::new(Mutex::new(
+    // 内核线程只运行在内核态，从 start 开始执行 entry，需要主动让出 cpu
+    pub fn new_kthread(tick: usize, entry: fn(), start: usize) -> Self {
+        let mut kthread = Self::new(tick);
+
+        #[cfg(feature = "riscv64_qemu")]
+        kthread.mm.lock().add_kernel_pt();
+
+        kthread.kthread_entry = Some(entry);
+        kthread.ctx = SwitchContext::new(start, kernel_stack_top(0));
+        kthread.set_status(ProcessStatus::READY);
+        kthread
+    }
+
+    pub fn kthread_entry(&self) -> Option<fn()> {
+        self.kthread_entry
+    }
+
+    // 创建新的线程或者进程，由 flags 决定和当前线程共享哪些资源
+    // stack 不为 0 时作为新线程的用户栈，返回的新任务由调用者放到 children 或者 threads 中
+    pub fn clone_task(&mut self, flags: CloneFlags, stack: usize, tls: usize) -> Result<Arc<Mutex<Self>>, &'static str> {
+        let pid = pid::alloc().ok_or("pid exhausted")?;
+        let mut trap_ctx = self.mm.lock().runtime_pull_context(self.kstack);
+        trap_ctx.set_ret(0);
+        if stack != 0 {
+            trap_ctx.set_sp(stack);
+        }
+        if flags.contains(CloneFlags::SETTLS) {
+            trap_ctx.set_tls(tls);
+        }
+
+        let (mm, asid, kstack) = if flags.contains(CloneFlags::VM) {
+            // 共享页表，在当前页表中分配新的内核栈，并直接写入 TrapContext
+            let mut mm = self.mm.lock();
+            let slot = mm.alloc_kernel_stack().ok_or("too many threads")?;
+            flush_tlb(self.asid.0 as usize);
+            mm.runtime_push_context(slot, trap_ctx);
+            drop(mm);
+            (self.mm.clone(), self.asid.clone(), slot)
+        } else {
+            let mut mm = MemoryManager::new(false);
+            mm.fork(&mut self.mm.lock(), trap_ctx);
+            (mm.into_shared(), Arc::new(asid_alloc().unwrap()), 0)
+        };
+
+        let mut switch_ctx = SwitchContext::new_with_restore_addr_and_kernel_stack_sp(kernel_stack_top(kstack));
+        switch_ctx.set_tls(if flags.contains(CloneFlags::SETTLS) { tls } else { read_tls() });
+
+        let fds = if flags.contains(CloneFlags::FILES) {
+            self.fds.clone()
+        } else {
+            Arc::new(Mutex::new(self.fds.lock().clone()))
+        };
+        let signal_actions = if flags.contains(CloneFlags::SIGHAND) {
+            self.signal_actions.clone()
+        } else {
+            Arc::new(Mutex::new(self.signal_actions.lock().clone()))
+        };
+        // 定时器属于进程，fork 的子进程不继承
+        let timers = if flags.contains(CloneFlags::THREAD) {
+            self.timers.clone()
+        } else {
+            Arc::new(Mutex::new(ProcessTimers::new()))
+        };
+        let rlimits = if flags.contains(CloneFlags::THREAD) {
+            self.rlimits.clone()
+        } else {
+            Arc::new(Mutex::new(self.rlimits.lock().fork()))
+        };
+
+        // 线程和创建者同属一个线程组，父进程也相同
+        let (tgid, parent, signals) = if flags.contains(CloneFlags::THREAD) {
+            (self.tgid, self.parent, SignalFlags::empty())
+        } else {
+            (pid.0, Some(self.tgid), self.signals)
+        };
+
+        // deadline 任务的带宽经过了准入控制，子进程不能继承，退回普通任务
+        let policy = match self.policy {
+            SchedPolicy::Deadline(..) => SchedPolicy::Normal,
+            policy => policy,
+        };
+
+        Ok(Arc::new(Mutex::new(
             Self {
-                tick,
+                tick: self.tick,
+                nice: self.nice,
+                policy,
+                // 子进程继承 vruntime，避免通过 fork 获得更多的 cpu 时间
+                se: SchedEntity { vruntime: self.se.vruntime, ..SchedEntity::default() },
                 status: ProcessStatus::READY,
                 pid,
-                parent: Some(self.pid.0),
+                tgid,
+                parent,
+                pgid: self.pgid,
+                sid: self.sid,
+                stop_signal: None,
+                continued: false,
                 children: BTreeMap::new(),
+                threads: BTreeMap::new(),
+                clear_child_tid: 0,
+                kthread_entry: None,
+                cpu: 0,
+                on_cpu: false,
+                wait_child: false,
+                vfork_parent: None,
+                usage: Usage::default(),
+                cputime_start: 0,
+                exited_usage: Usage::default(),
+                children_usage: Usage::default(),
+                timers,
+                rlimits,
                 ctx: switch_ctx,
+                kstack,
                 mm,
-                asid: asid_alloc().unwrap(),
+                asid,
                 fds,
                 signals,
-                signals_mask,
+                signals_mask: self.signals_mask,
                 signal_actions,
                 trap_ctx_backup: None,
             }
-        ));
-
-        let weak = Arc::downgrade(&child);
-        self.children.insert(key, child);
-        (weak, key)
+        )))
     }
 
-    pub fn exec(&mut self, elf: &[u8]) -> Result<(), &'static str> {
-        // unmap all app area, for load elf again
-        self.mm.unmap_app();
-        let mut empty = PageTable::new();
-        let (sp, pc) = self.mm.load_elf(&mut empty, &elf, true)?;
-        drop(empty);
-        let trap_ctx = TrapContext::new(pc, sp);
-        let kernel_sp = self.mm.runtime_push_context(trap_ctx);
+    // 相当于 fork 之后马上 exec，但是不复制当前的地址空间，返回的子进程由调用者放到 children 中
+    // 子进程继承 fd 表、信号屏蔽字、资源限制和调度参数，fd 表执行 actions 后关闭 O_CLOEXEC 的文件
+    pub fn spawn(&self, elf: &[u8], argv: &[String], envp: &[String], actions: &[SpawnAction]) -> Result<Self, &'static str> {
+        let mut child = Self::new(self.tick);
+        #[cfg(feature = "riscv64_qemu")]
+        child.mm.lock().add_kernel_pt();
+
+        child.rlimits = Arc::new(Mutex::new(self.rlimits.lock().fork()));
+        let mut fds = self.fds.lock().clone();
+        apply_actions(&mut fds, actions, &child.rlimits.lock())?;
+        child.fds = Arc::new(Mutex::new(fds));
+        child.close_on_exec();
+
+        // 当前运行在自己的页表上，通过它临时映射子进程的物理页
+        child.load_elf(&mut self.mm.lock().pt, elf, argv, envp)?;
+
+        child.parent = Some(self.tgid);
+        child.pgid = self.pgid;
+        child.sid = self.sid;
+        child.nice = self.nice;
+        // 和 fork 一样，deadline 任务退回普通任务
+        child.policy = match self.policy {
+            SchedPolicy::Deadline(..) => SchedPolicy::Normal,
+            policy => policy,
+        };
+        child.se.vruntime = self.se.vruntime;
+        child.signals_mask = self.signals_mask;
+        Ok(child)
+    }
+
+    // 在新的地址空间中加载 elf，返回新的地址空间、asid、用户栈和入口地址
+    // exec 中可能失败的步骤都在这里完成，失败时原来的地址空间不受影响
+    pub fn load_image(&self, elf: &[u8], argv: &[String], envp: &[String]) -> Result<(MemoryManager, Option<AisdHandler>, usize, usize), &'static str> {
+        let mut old = self.mm.lock();
+        let mut mm = MemoryManager::new(true);
+        #[cfg(feature = "riscv64_qemu")]
+        mm.add_kernel_pt();
+        let stack_size = self.rlimits.lock().stack_size();
+        mm.set_rss_limit(self.rlimits.lock().cur(RLIMIT_RSS));
+        // 当前运行在旧的页表上，通过它临时映射新地址空间的物理页
+        let (sp, pc) = mm.load_elf(&mut old.pt, elf, stack_size, argv, envp)?;
+        // 当前线程还运行在内核栈上，先把所有内核栈映射到新的页表中，exec 时再去掉不需要的
+        mm.share_kernel_stacks(&old, self.kstack)?;
+        // 地址空间被共享时 exec 需要新的 asid，结束其他线程之后共享的数量只会减少
+        let asid = if Arc::strong_count(&self.mm) > 1 {
+            Some(asid_alloc().ok_or("asid exhausted")?)
+        } else {
+            None
+        };
+        Ok((mm, asid, sp, pc))
+    }
+
+    // 切换到 load_image 加载的地址空间，返回用户栈地址，这一步不会失败
+    pub fn exec(&mut self, image: (MemoryManager, Option<AisdHandler>, usize, usize)) -> usize {
+        let (mut mm, asid, sp, pc) = image;
+        let mut trap_ctx = TrapContext::new(pc, sp);
+        trap_ctx.set_ret(sp);
+
+        // 其他线程已经退出，但是地址空间仍然可能被 vfork 的父进程或者 CLONE_VM 创建的进程共享
+        // 这时使用新的 mm 和 asid，旧的地址空间留给其他进程
+        let kernel_sp = match asid {
+            Some(asid) if Arc::strong_count(&self.mm) > 1 => {
+                let old = self.mm.clone();
+                let mut old = old.lock();
+                // 只带走当前线程的内核栈，其他的仍然属于共享地址空间的线程
+                let kstack = self.kstack;
+                mm.retain_kernel_stacks(|s| s == kstack);
+                let kernel_sp = mm.runtime_push_context(self.kstack, trap_ctx);
+                self.mm = mm.into_shared();
+                self.asid = Arc::new(asid);
+                self.activate();
+                // 已经切换页表，可以从旧的地址空间中去掉这个内核栈
+                old.dealloc_kernel_stack(self.kstack);
+                kernel_sp
+            }
+            _ => {
+                let mut old = self.mm.lock();
+                // 已经回收的线程的内核栈不再需要
+                mm.retain_kernel_stacks(|s| old.has_kernel_stack(s));
+                let kernel_sp = mm.runtime_push_context(self.kstack, trap_ctx);
+                let old_mm = core::mem::replace(&mut *old, mm);
+                enable_va(self.asid.0 as usize, old.root_ppn().0);
+                drop(old);
+                // 切换页表后才能释放旧的地址空间
+                drop(old_mm);
+                kernel_sp
+            }
+        };
         self.ctx = SwitchContext::new_with_restore_addr(kernel_sp);
         self.set_status(ProcessStatus::READY);
+        // 和 linux 一样，exec 后 timer_create 的定时器被删除，setitimer 的定时器保留
+        self.timers.lock().clear_posix();
+        self.close_on_exec();
 
         // flush tlb
         flush_tlb(self.asid.0 as usize);
-        Ok(())
+        sp
     }
 
-    // 等待子进程结束，如果结束，回收子进程资源
-    // TODO: 在 task manager 中已经释放的进程去掉
-    pub fn wait(&mut self, pid: isize) -> isize {
-        let mut result = -1;
-
-        for (k, v) in self.children.clone().iter().map(|child| child) {
-            if pid == -1 || (pid as usize) == v.lock().pid.0 {
-                match v.lock().status {
-                    ProcessStatus::EXITED(_) => {
-                        // drop the child process instance, recycle its resources
-                        self.children.remove(k);
-                        result = 0;
-                    }
-                    _ => {
-                        // not finished
-                        result = -2;
-                        break;
-                    }
+    // 不再和其他进程共享 fd 表，并关闭设置了 O_CLOEXEC 的文件
+    fn close_on_exec(&mut self) {
+        let mut fds = self.fds.lock().clone();
+        for fd in fds.iter_mut() {
+            if fd.as_ref().is_some_and(|f| f.cloexec) {
+                *fd = None;
+            }
+        }
+        self.fds = Arc::new(Mutex::new(fds));
+    }
+
+    // 查找可以回收的子进程，pid 为 -1 时匹配任意子进程，找到后回收子进程资源
+    // 只有僵尸进程可以被回收，主线程退出后还要等其他线程都退出才会变成僵尸进程
+    // pgid 是调用者的进程组，pid 为 0 时使用
+    // WUNTRACED 和 WCONTINUED 时也报告停止和继续运行的子进程，但是不回收
+    pub fn wait(&mut self, pid: isize, pgid: usize, options: WaitOptions) -> WaitResult {
+        let mut found = false;
+        let mut zombie = None;
+        for (&k, v) in self.children.iter() {
+            let mut child = v.lock();
+            let matched = match pid {
+                -1 => true,
+                0 => child.pgid == pgid,
+                p if p > 0 => p as usize == k,
+                p => child.pgid == (-p) as usize,
+            };
+            if !matched {
+                continue;
+            }
+            found = true;
+
+            if let ProcessStatus::ZOMBIE(status) = child.status {
+                // 和 linux 一样，返回的统计包括子进程回收的子进程
+                let mut usage = child.usage;
+                usage.add(&child.children_usage);
+                zombie = Some((k, status, usage));
+                break;
+            }
+            if options.contains(WaitOptions::WUNTRACED) {
+                if let Some(signal) = child.stop_signal.take() {
+                    return WaitResult::Changed(k, stop_status(signal));
                 }
             }
+            if options.contains(WaitOptions::WCONTINUED) && child.continued {
+                child.continued = false;
+                return WaitResult::Changed(k, CONTINUED_STATUS);
+            }
         }
 
-        result
+        if let Some((k, status, usage)) = zombie {
+            // drop the child process instance, recycle its resources
+            self.children.remove(&k);
+            self.children_usage.add(&usage);
+            WaitResult::Exited(k, status, usage)
+        } else if found {
+            WaitResult::Running
+        } else {
+            WaitResult::NoChild
+        }
+    }
+
+    // 唤醒阻塞在 wait 中的线程
+    pub fn wake_waiter(&mut self) {
+        if self.wait_child {
+            self.wait_child = false;
+            if let ProcessStatus::WAITING = self.status {
+                self.set_status(ProcessStatus::READY);
+            }
+        }
+    }
+
+    // 和 linux 一样，SIGCHLD 默认忽略，只有注册了处理函数时才发送
+    pub fn send_sigchld(&mut self) {
+        if self.signal_actions.lock()[SIGCHLD].is_some() {
+            self.set_signal(SIGCHLD);
+        }
     }
 
     pub fn set_status(&mut self, status: ProcessStatus) {
+        // 线程可能在其他 cpu 上被 exit_group 结束，不能再改变状态
+        if self.exited() {
+            return;
+        }
+        // 停止的线程只能被 SIGCONT 继续或者被结束，不能被 sleep 等操作覆盖
+        if self.stopped() && !matches!(status, ProcessStatus::READY | ProcessStatus::EXITED(_)) {
+            return;
+        }
+        // 唤醒其他 cpu 上阻塞的任务时，那个 cpu 可能正在 wfi 中等待，通过 ipi 唤醒它
+        let blocked = matches!(self.status, ProcessStatus::SLEEP(..) | ProcessStatus::WAITING | ProcessStatus::STOPPED);
+        // 调度器只在收到通知时把阻塞的任务放回运行队列
+        let wakeup = !self.runnable() && matches!(status, ProcessStatus::READY);
         self.status = status;
+        if wakeup {
+            notify_wakeup(self.cpu, self.pid.0);
+        }
+        if blocked && matches!(status, ProcessStatus::READY) && self.cpu != cpu_id() {
+            send_ipi(self.cpu);
+        }
     }
 
-    pub fn ctx_ptr(&mut self) -> *mut SwitchContext {
-        self.ctx.borrow_mut() as *mut _
+    pub fn stopped(&self) -> bool {
+        matches!(self.status, ProcessStatus::STOPPED)
     }
-    
-    pub fn load_elf(&mut self, current_pt: &mut PageTable, data: &[u8]) -> Result<(), &'static str> {
-        // 解析 elf 文件到 mm 中
-        // 请注意，这里的 sp 是用户栈 sp，而不是 app 对应的内核栈的 app
-        let (sp, pc) = self.mm.load_elf(current_pt, data, false)?;
 
-        // 根据获取的 app pc 和 sp 创建 TrapContext
-        let trap_ctx = TrapContext::new(pc, sp);
+    pub fn exited(&self) -> bool {
+        matches!(self.status, ProcessStatus::EXITED(_) | ProcessStatus::ZOMBIE(_))
+    }
 
-        // 将 TrapContext push 到 kernel stack 中，并且更新 switch context
-        let kernel_sp = self.mm.push_context(trap_ctx, current_pt);
-        self.ctx = SwitchContext::new_with_restore_addr(kernel_sp);
+    pub fn zombie(&self) -> bool {
+        matches!(self.status, ProcessStatus::ZOMBIE(_))
+    }
 
-        self.set_status(ProcessStatus::READY);
-        Ok(())
+    // 进入僵尸状态，释放地址空间和文件，只保留 pid 和退出状态
+    // 这时所有线程都已经离开 cpu，不会再使用这个页表
+    pub fn set_zombie(&mut self) {
+        if let ProcessStatus::EXITED(status) = self.status {
+            self.status = ProcessStatus::ZOMBIE(status);
+        }
+        // 地址空间释放前记录最大的常驻内存，其他线程的统计已经在回收时合并
+        let rss = self.rss();
+        self.usage.maxrss = self.usage.maxrss.max(rss);
+        let exited = self.exited_usage;
+        self.usage.add(&exited);
+        self.exited_usage = Usage::default();
+        self.fds = Arc::new(Mutex::new(Vec::new()));
+        // 定时器队列中只保存 weak 指针，释放后不会再触发
+        self.timers = Arc::new(Mutex::new(ProcessTimers::new()));
+        // CLONE_VM 创建的子进程可能还在使用这个地址空间
+        if Arc::strong_count(&self.mm) == 1 {
+            self.mm.lock().unmap_app();
+            flush_tlb(self.asid.0 as usize);
+        } else {
+            // 共享的地址空间中只回收自己的内核栈
+            self.release_kernel_stack();
+        }
+    }
+
+    pub fn set_clear_child_tid(&mut self, addr: usize) {
+        self.clear_child_tid = addr;
+    }
+
+    pub fn clear_child_tid(&self) -> usize {
+        self.clear_child_tid
+    }
+
+    // 退出后释放线程的内核栈，不能在这个线程的内核栈上调用
+    pub fn release_kernel_stack(&mut self) {
+        self.mm.lock().dealloc_kernel_stack(self.kstack);
+    }
+
+    // 从用户态 trap 进入内核，结算用户态时间
+    pub fn charge_utime(&mut self, now: usize) {
+        self.usage.utime += now.saturating_sub(self.cputime_start);
+        self.cputime_start = now;
+    }
+
+    // 返回用户态、时钟中断和回到 idle 时，结算内核态时间
+    pub fn charge_stime(&mut self, now: usize) {
+        self.usage.stime += now.saturating_sub(self.cputime_start);
+        self.cputime_start = now;
+    }
+
+    // 到 now 为止的统计，正在运行还没有结算的部分算作内核态时间
+    pub fn usage(&self, now: usize) -> Usage {
+        let mut usage = self.usage;
+        if self.on_cpu {
+            usage.stime += now.saturating_sub(self.cputime_start);
+        }
+        usage
+    }
+
+    pub fn cputime(&self, now: usize) -> usize {
+        self.usage(now).cputime()
+    }
+
+    // 当前的常驻内存，单位是 KB
+    pub fn rss(&self) -> usize {
+        self.mm.lock().resident_pages() * PAGE_SIZE / 1024
+    }
+
+    // 切换回 idle 时调用，仍然是 RUNNING 说明是被抢占的
+    pub fn count_switch(&mut self) {
+        if let ProcessStatus::RUNNING(_) = self.status {
+            self.usage.nivcsw += 1;
+        } else {
+            self.usage.nvcsw += 1;
+        }
     }
 
-    pub fn runtime_load_elf(&mut self, data: &[u8]) -> Result<(), &'static str> {
+    // 是否可以被调度运行，睡眠的任务由定时器队列到期后设置为 READY
+    pub fn runnable(&self) -> bool {
+        matches!(self.status, ProcessStatus::READY | ProcessStatus::RUNNING(_))
+    }
+
+    pub fn ctx_ptr(&mut self) -> *mut SwitchContext {
+        self.ctx.borrow_mut() as *mut _
+    }
+    
+    pub fn load_elf(&mut self, current_pt: &mut PageTable, data: &[u8], argv: &[String], envp: &[String]) -> Result<(), &'static str> {
+        let mut mm = self.mm.lock();
         // 解析 elf 文件到 mm 中
         // 请注意，这里的 sp 是用户栈 sp，而不是 app 对应的内核栈的 app
-        let mut empty = PageTable::new();
-        let (sp, pc) = self.mm.load_elf(&mut empty, data, true)?;
+        let stack_size = self.rlimits.lock().stack_size();
+        mm.set_rss_limit(self.rlimits.lock().cur(RLIMIT_RSS));
+        let (sp, pc) = mm.load_elf(current_pt, data, stack_size, argv, envp)?;
 
-        // 根据获取的 app pc 和 sp 创建 TrapContext
-        let trap_ctx = TrapContext::new(pc, sp);
+        // 根据获取的 app pc 和 sp 创建 TrapContext，和 exec 一样 a0/x0 中是用户栈地址
+        let mut trap_ctx = TrapContext::new(pc, sp);
+        trap_ctx.set_ret(sp);
 
         // 将 TrapContext push 到 kernel stack 中，并且更新 switch context
-        let kernel_sp = self.mm.push_context(trap_ctx, &mut empty);
-        drop(empty);
+        let kernel_sp = mm.push_context(trap_ctx, current_pt);
+        drop(mm);
         self.ctx = SwitchContext::new_with_restore_addr(kernel_sp);
 
         self.set_status(ProcessStatus::READY);
@@ -892,20 +2543,28 @@ impl Process {
     }
 
     // 使能虚地址模式，并且将该进程的页表写到 satp 中
+    // 切换到这个线程的地址空间，同时记录到 CURRENT_MM 中供页错误使用
     pub fn activate(&mut self) {
-        enable_va(self.asid.0 as usize, self.mm.root_ppn().0)
+        *CURRENT_MM[cpu_id()].lock() = Some((self.mm.clone(), self.asid.0 as usize));
+        enable_va(self.asid.0 as usize, self.mm.lock().root_ppn().0)
+    }
+
+    pub fn mm(&self) -> Arc<Mutex<MemoryManager>> {
+        self.mm.clone()
     }
 
-    // 出现页错误时，copy on write
-    pub fn cow(&mut self, vpn: VirtPage) -> Result<(), &'static str> {
-        self.mm.cow(vpn)
+    fn file(&self, fd: usize) -> Option<Arc<dyn File>> {
+        self.fds.lock().get(fd).cloned().flatten().map(|f| f.file)
     }
 
-    // write
+    // 用户数据先复制到内核的缓冲区，文件的读写不直接访问用户地址，访问无效的用户地址时返回 -1
     pub fn write(&self, fd: usize, buf: *mut u8, len: usize) -> isize {
-        let user_buf = UserBuffer::new_from_raw(buf, len);
-        if let Some(file) = &self.fds[fd] {
+        if let Some(file) = self.file(fd) {
             if file.writable() {
+                let Some(mut data) = copy_from_user_into_vector(buf, len) else {
+                    return -1;
+                };
+                let user_buf = UserBuffer::new_from_raw(data.as_mut_ptr(), len);
                 // TODO: return relative error code
                 return file.write(&user_buf).unwrap() as isize;
             } else {
@@ -918,20 +2577,33 @@ impl Process {
         return -2;
     }
 
-    pub fn create_pipe(&mut self, size: usize) -> (usize, usize)  {
+    // 超过 RLIMIT_NOFILE 时返回 None
+    pub fn create_pipe(&mut self, size: usize) -> Option<(usize, usize)>  {
+        let mut fds = self.fds.lock();
+        if !self.rlimits.lock().allow(RLIMIT_NOFILE, fds.len() + 2) {
+            return None;
+        }
         let (read_pipe, write_pipe) = Pipe::new(size);
-        self.fds.push(Some(read_pipe));
-        let read_fd = self.fds.len() - 1;
-        self.fds.push(Some(write_pipe));
-        let write_fd = self.fds.len() - 1;
-        (read_fd, write_fd)
+        fds.push(Some(FileDesc::new(read_pipe, false)));
+        let read_fd = fds.len() - 1;
+        fds.push(Some(FileDesc::new(write_pipe, false)));
+        let write_fd = fds.len() - 1;
+        Some((read_fd, write_fd))
     }
 
     pub fn read(&self, fd: usize, buf: *mut u8, len: usize) -> isize {
-        let mut user_buf = UserBuffer::new_from_raw(buf, len);
-        if let Some(file) = &self.fds[fd] {
+        if let Some(file) = self.file(fd) {
             if file.readable() {
-                return file.read(&mut user_buf).unwrap() as isize;
+                let mut data = Vec::new();
+                if data.try_reserve_exact(len).is_err() {
+                    return -1;
+                }
+                data.resize(len, 0);
+                let mut user_buf = UserBuffer::new_from_raw(data.as_mut_ptr(), len);
+                let n = file.read(&mut user_buf).unwrap();
+                drop(user_buf);
+                data.truncate(n);
+                return copy_vector_to_user(data, buf).map_or(-1, |n| n as isize);
             } else {
                 println!("[kernel] {} file is None", fd);
                 return -1;
@@ -942,17 +2614,24 @@ impl Process {
         return -2;
     }
 
-    pub fn open(&mut self, name: &str) -> isize {
-        if let Some(inode) = FILESYSTEM.exclusive_access().open(name) {
-            self.fds.push(Some(inode));
-            return (self.fds.len() - 1) as isize
+    // flags 中有 O_CLOEXEC 时，exec 后关闭这个文件
+    pub fn open(&mut self, name: &str, flags: usize) -> isize {
+        // fd 从 0 开始依次分配，所以 fd 不会超过 RLIMIT_NOFILE
+        if !self.rlimits.lock().allow(RLIMIT_NOFILE, self.fds.lock().len() + 1) {
+            println!("[kernel] too many open files");
+            return -1;
+        }
+        if let Some(inode) = FILESYSTEM.lock().open(name) {
+            let mut fds = self.fds.lock();
+            fds.push(Some(FileDesc::new(inode, flags & O_CLOEXEC != 0)));
+            return (fds.len() - 1) as isize
         }
 
         -1
     }
 
     pub fn lseek(&mut self, fd: usize, seek: usize) -> isize {
-        if let Some(file) = &self.fds[fd] {
+        if let Some(file) = self.file(fd) {
             return file.lseek(seek)
         }
 
@@ -961,7 +2640,7 @@ impl Process {
     }
 
     pub fn filesize(&mut self, fd: usize) -> isize {
-        if let Some(file) = &self.fds[fd] {
+        if let Some(file) = self.file(fd) {
             if let Ok(size) = file.size() {
                 return size as isize;
             } else {
@@ -975,12 +2654,18 @@ impl Process {
 
     // signal
     pub fn sigaction(&mut self, signal: usize, action: SignalAction) -> isize {
-        self.signal_actions[signal] = Some(action);
+        self.signal_actions.lock()[signal] = Some(action);
 
         0
     }
 
     pub fn set_signal(&mut self, signal: usize) -> isize {
+        // 停止和继续信号互相抵消
+        match signal {
+            SIGCONT => self.signals.remove(SignalFlags::SIGSTOP | SignalFlags::SIGTSTP),
+            SIGSTOP | SIGTSTP => self.signals.remove(SignalFlags::SIGCONT),
+            _ => {}
+        }
         let signal = SignalFlags::from_bits_truncate(1 << signal);
         self.signals.insert(signal);
         
@@ -993,18 +2678,63 @@ impl Process {
         0
     }
 
+    // 有需要处理的信号，返回用户态时会被处理
+    pub fn signal_pending(&self) -> bool {
+        let signals = self.signals.bitand(self.signals_mask);
+        signals.check_error().is_some()
+            || self.default_kill(signals).is_some()
+            || self.stop_signal(signals).is_some()
+            || signals.first_valid().is_some_and(|v| self.signal_actions.lock()[v].is_some())
+    }
+
+    // SIGSTOP 不能被捕获，SIGTSTP 没有处理函数时默认停止
+    // 定时器的信号没有处理函数时默认结束进程
+    fn default_kill(&self, signals: SignalFlags) -> Option<usize> {
+        let actions = self.signal_actions.lock();
+        [SIGALRM, SIGVTALRM, SIGPROF, SIGXCPU].into_iter()
+            .find(|&s| signals.contains(SignalFlags::from_bits_truncate(1 << s)) && actions[s].is_none())
+    }
+
+    fn stop_signal(&self, signals: SignalFlags) -> Option<usize> {
+        if signals.contains(SignalFlags::SIGSTOP) {
+            Some(SIGSTOP)
+        } else if signals.contains(SignalFlags::SIGTSTP) && self.signal_actions.lock()[SIGTSTP].is_none() {
+            Some(SIGTSTP)
+        } else {
+            None
+        }
+    }
+
     pub fn signal_check(&mut self) -> SignalCode {
         let signals = self.signals.bitand(self.signals_mask);
         // 如果有多个信号，从低到高返回第一个找到的信号量
         if let Some(e) = signals.check_error() {
             println!("[kernel] Process {}: {}",self.pid.0, e.1);
             self.signals.remove(SignalFlags::from_bits_truncate(1 << e.0));
-            return SignalCode::KILL(e.0 as isize);
+            return SignalCode::KILL(-e.0 as usize);
         }
 
+        if let Some(signal) = self.default_kill(signals) {
+            println!("[kernel] Process {}: killed by signal {}", self.pid.0, signal);
+            self.signals.remove(SignalFlags::from_bits_truncate(1 << signal));
+            return SignalCode::KILL(signal);
+        }
+
+        if let Some(signal) = self.stop_signal(signals) {
+            self.signals.remove(SignalFlags::SIGSTOP | SignalFlags::SIGTSTP);
+            return SignalCode::STOP(signal);
+        }
+
+        // 发送 SIGCONT 时已经继续运行了，没有处理函数时忽略
+        if signals.contains(SignalFlags::SIGCONT) && self.signal_actions.lock()[SIGCONT].is_none() {
+            self.signals.remove(SignalFlags::SIGCONT);
+        }
+        let signals = self.signals.bitand(self.signals_mask);
+
         if let Some(v) = signals.first_valid() {
-            if let Some(a) = self.signal_actions[v] {
+            if let Some(a) = self.signal_actions.lock()[v] {
                 self.signals.remove(SignalFlags::from_bits_truncate(1 << v));
+                self.usage.nsignals += 1;
                 return SignalCode::Action(a);
             }
         }
@@ -1014,32 +2744,121 @@ impl Process {
 
     pub fn save_trap_ctx(&mut self) {
         // save current trap context in self memory space
-        self.trap_ctx_backup = Some(self.mm.runtime_pull_context());
+        self.trap_ctx_backup = Some(self.mm.lock().runtime_pull_context(self.kstack));
     }
 
     pub fn sigreturn(&mut self) -> isize {
         // save current trap context in self memory space
         if let Some(mut ctx) = self.trap_ctx_backup.to_owned() {
-            return self.mm.runtime_push_context(ctx) as isize
+            return self.mm.lock().runtime_push_context(self.kstack, ctx) as isize
         }
 
         -1
     }
 
+    // 超过 RLIMIT_AS 以及内存不足时返回 -1，RLIMIT_RSS 在第一次访问分配页帧时检查
     pub fn mmap(&mut self, size: usize, permission: usize) -> isize {
-        if let Some(area) = self.mm.mmap(size, permission).unwrap().upgrade() {
-            return VirtAddr::from(area.read().start_vpn).0 as isize
-        } else {
-            -1
+        let mut mm = self.mm.lock();
+        let pages = size / PAGE_SIZE;
+        if !self.rlimits.lock().allow(RLIMIT_AS, (mm.total_pages() + pages) * PAGE_SIZE) {
+            println!("[kernel] mmap exceeds RLIMIT_AS");
+            return -1;
+        }
+        match mm.mmap(size, permission).and_then(|a| a.upgrade()) {
+            Some(area) => VirtAddr::from(area.read().start_vpn).0 as isize,
+            None => -1,
         }
     }
 
+    // 清除页表项之后刷新所有 cpu 上这个地址空间的 tlb，然后才释放页帧
     pub fn ummap(&mut self, addr: VirtAddr) -> isize {
-        self.mm.umap_dyn_area(addr.into())
+        let Some(frames) = self.mm.lock().umap_dyn_area(addr.into()) else {
+            return -1;
+        };
+        flush_tlb(self.asid.0 as usize);
+        drop(frames);
+        0
+    }
+
+    // 只能映射普通文件，flags 必须是 MAP_SHARED 和 MAP_PRIVATE 之一
+    // MAP_SHARED 可写时文件也需要可写，超过 RLIMIT_AS 时返回 -1
+    pub fn mmap_file(&mut self, fd: usize, offset: usize, len: usize, permission: usize, flags: usize) -> isize {
+        let shared = flags & MAP_SHARED != 0;
+        if len == 0 || shared == (flags & MAP_PRIVATE != 0) {
+            return -1;
+        }
+        let Some(file) = self.file(fd) else {
+            return -1;
+        };
+        let Some(inode) = file.inode().filter(|i| i.metadata().is_ok_and(|m| m.type_ == FileType::File)) else {
+            println!("[kernel] mmap: fd {} is not a normal file", fd);
+            return -1;
+        };
+        // permission 的 bit 1 是 PROT_WRITE
+        if !file.readable() || (shared && permission & 0x2 != 0 && !file.writable()) {
+            return -1;
+        }
+
+        let mut mm = self.mm.lock();
+        if !self.rlimits.lock().allow(RLIMIT_AS, (mm.total_pages() + len.div_ceil(PAGE_SIZE)) * PAGE_SIZE) {
+            println!("[kernel] mmap exceeds RLIMIT_AS");
+            return -1;
+        }
+        match mm.mmap_file(inode, offset, len, permission, shared, file.writable()) {
+            Some(addr) => addr as isize,
+            None => -1,
+        }
+    }
+
+    pub fn msync(&mut self, addr: usize, len: usize) -> isize {
+        let start: VirtAddr = addr.into();
+        // len 太大时溢出，返回 -1
+        let Some(end) = addr.checked_add(len.max(1)).and_then(|e| e.checked_add(PAGE_SIZE - 1)) else {
+            return -1;
+        };
+        let end: VirtAddr = end.into();
+        self.mm.lock().msync(start.into(), end.into())
+    }
+
+    // 返回新的 brk，失败时返回原来的 brk，超过 RLIMIT_AS 时不扩大
+    pub fn brk(&mut self, addr: usize) -> isize {
+        let mut mm = self.mm.lock();
+        let (_, old) = mm.heap();
+        let pages = |a: usize| (a + PAGE_SIZE - 1) / PAGE_SIZE;
+        if addr > old {
+            let grow = pages(addr) - pages(old);
+            if !self.rlimits.lock().allow(RLIMIT_AS, (mm.total_pages() + grow) * PAGE_SIZE) {
+                println!("[kernel] brk exceeds RLIMIT_AS");
+                return old as isize;
+            }
+        }
+        let brk = mm.brk(addr);
+        if brk < old {
+            flush_tlb(self.asid.0 as usize);
+        }
+        brk as isize
+    }
+
+    // addr 需要页对齐，改变权限后刷新所有 cpu 上这个地址空间的 tlb
+    pub fn mprotect(&mut self, addr: usize, len: usize, permission: usize) -> isize {
+        if addr % PAGE_SIZE != 0 {
+            return -1;
+        }
+        if len == 0 {
+            return 0;
+        }
+        let start: VirtAddr = addr.into();
+        let Some(end) = addr.checked_add(len).and_then(|e| e.checked_add(PAGE_SIZE - 1)) else {
+            return -1;
+        };
+        let end: VirtAddr = end.into();
+        let r = self.mm.lock().mprotect(start.into(), end.into(), permission);
+        flush_tlb(self.asid.0 as usize);
+        r
     }
 
     pub fn mmap_with_addr(&mut self, pa: PhysAddr, size: usize, permission: usize, user: bool) -> isize {
-        self.mm.mmap_with_addr(pa, size, permission, user)
+        self.mm.lock().mmap_with_addr(pa, size, permission, user)
     }
 }
 
@@ -1047,16 +2866,89 @@ impl Process {
 pub enum ProcessStatus {
     UNINIT,
     READY,
-    // running status with tick number
+    // running status with remaining tick number
     RUNNING(usize),
     // sleep status with start and duration timestamp(ns) 
     SLEEP(usize, usize),
     WAITING,
+    // 被 SIGSTOP 或者 SIGTSTP 停止，收到 SIGCONT 后继续运行
+    STOPPED,
+    // 线程已经退出，参数为退出状态，即 wait 返回的 status
     EXITED(isize),
+    // 整个线程组都已经退出，资源已经释放，等待父进程回收
+    ZOMBIE(isize),
+}
+
+// wait 返回的状态，编码和 linux 相同
+// 正常退出时 8~15 位是退出码，被信号结束时低 7 位是信号
+pub fn exit_status(exit_code: isize) -> isize {
+    (exit_code & 0xff) << 8
+}
+
+pub fn signal_status(signal: usize) -> isize {
+    (signal & 0x7f) as isize
+}
+
+// 停止时低 8 位为 0x7f，8~15 位是停止信号
+pub fn stop_status(signal: usize) -> isize {
+    ((signal & 0xff) << 8 | 0x7f) as isize
+}
+
+pub const CONTINUED_STATUS: isize = 0xffff;
+
+pub enum WaitResult {
+    // 回收了子进程，(pid, status, 子进程的统计)
+    Exited(usize, isize, Usage),
+    // 子进程停止或者继续运行，不回收，(pid, status)
+    Changed(usize, isize),
+    // 有匹配的子进程，但是都还没有结束
+    Running,
+    NoChild,
 }
 
 pub enum SignalCode {
     IGNORE,
     Action(SignalAction),
-    KILL(isize),
+    // 被信号结束，参数为信号
+    KILL(usize),
+    // 被信号停止，参数为信号
+    STOP(usize),
+}
+
+bitflags! {
+    // wait 的 options，数值和 linux 相同
+    #[derive(Clone, Copy)]
+    pub struct WaitOptions: usize {
+        // 子进程都没有结束时立即返回 0
+        const WNOHANG = 1;
+        // 也报告停止的子进程
+        const WUNTRACED = 2;
+        // 也报告被 SIGCONT 继续运行的子进程
+        const WCONTINUED = 8;
+    }
+}
+
+bitflags! {
+    // clone 的参数，数值和 linux 相同，低 8 位的退出信号暂时忽略
+    #[derive(Clone, Copy)]
+    pub struct CloneFlags: usize {
+        // 共享地址空间
+        const VM = 0x100;
+        // 没有文件系统上下文，忽略
+        const FS = 0x200;
+        // 共享文件描述符表
+        const FILES = 0x400;
+        // 共享信号处理函数
+        const SIGHAND = 0x800;
+        // 父线程阻塞到子进程 exec 或者退出
+        const VFORK = 0x4000;
+        // 放到同一个线程组中
+        const THREAD = 0x10000;
+        // 设置新线程的线程局部存储寄存器
+        const SETTLS = 0x80000;
+        // 将新线程的 tid 写到父线程的 parent_tid 地址
+        const PARENT_SETTID = 0x100000;
+        // 新线程退出时将 child_tid 地址清零
+        const CHILD_CLEARTID = 0x200000;
+    }
 }
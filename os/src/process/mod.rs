@@ -2,23 +2,29 @@
 
 pub mod app;
 pub mod pid;
+pub mod rlimit;
+pub mod rusage;
+pub mod scheduler;
+pub mod spawn;
+pub mod timer;
 
 use core::usize;
 
 use alloc::{string::String, sync::Arc, vec::Vec};
 use app::*;
-use spin::mutex::Mutex;
+use crate::sync::{preempt_count, Mutex};
 
 use crate::{
-    arch::memory::page::{VirtAddr, VirtPage}, 
-    mm::area::UserBuffer, 
-    utils::type_extern::RefCellWrap
+    arch::{context::TrapContext, enable_irq, memory::{copy::copy_fixup, page::{VirtAddr, VirtPage}}}, 
+    ipc::signal::SIGSEGV,
+    mm::area::Access,
+    mm::area::UserBuffer
 };
 
 use lazy_static::*;
 
 lazy_static! {
-    static ref TASK_MANAGER: Arc<TaskManager> = unsafe { Arc::new(TaskManager::new()) };
+    static ref TASK_MANAGER: Arc<TaskManager> = Arc::new(TaskManager::new());
 }
 
 // Default create the first app, other app created by manual
@@ -35,37 +41,106 @@ pub fn fork() -> isize {
     TASK_MANAGER.fork()
 }
 
-pub fn exec(elf: &[u8]) -> isize {
-    TASK_MANAGER.exec(elf)
+pub fn vfork() -> isize {
+    TASK_MANAGER.vfork()
+}
+
+pub fn exec(path: &str, argv: &[String], envp: &[String]) -> isize {
+    TASK_MANAGER.exec(path, argv, envp)
+}
+
+pub fn spawn(path: &str, argv: &[String], envp: &[String], actions: &[spawn::SpawnAction], pgroup: Option<usize>) -> isize {
+    TASK_MANAGER.spawn(path, argv, envp, actions, pgroup)
 }
 
 pub fn exit(exit_code: isize) -> ! {
     TASK_MANAGER.exit(exit_code)
 }
 
+pub fn exit_group(exit_code: isize) -> ! {
+    TASK_MANAGER.exit_group(exit_code)
+}
+
+pub fn exit_by_signal(signal: usize) -> ! {
+    TASK_MANAGER.exit_by_signal(signal)
+}
+
+pub fn clone(flags: CloneFlags, stack: usize, parent_tid: usize, child_tid: usize, tls: usize) -> isize {
+    TASK_MANAGER.clone_task(flags, stack, parent_tid, child_tid, tls)
+}
+
+// 创建内核线程，和系统调用一样在没有持有锁时可以被时钟中断抢占
+pub fn kthread_create(entry: fn()) -> isize {
+    TASK_MANAGER.kthread_create(entry, kthread_start as usize)
+}
+
+// 内核线程第一次被调度时，__switch 返回到这里，此时中断是关闭的
+fn kthread_start() -> ! {
+    if let Some(entry) = TASK_MANAGER.kthread_entry() {
+        enable_irq();
+        entry();
+    }
+    exit(0)
+}
+
 // nano time
 pub fn sleep(duration: usize) {
     TASK_MANAGER.sleep(duration)
 }
 
+// 睡眠到 MONOTONIC 时间 deadline，被信号打断时返回 -2
+pub fn sleep_until(deadline: usize) -> isize {
+    TASK_MANAGER.sleep_until(deadline)
+}
+
 pub fn back_to_idle() {
     TASK_MANAGER.back_to_idle();
 }
 
-pub fn cow(va: usize) -> Result<(), &'static str> {
-    let vpn: VirtPage = VirtAddr::from(va).into();
-    TASK_MANAGER.cow(vpn)
+// 只在从用户态进入的 trap 中调用，结算用户态和内核态时间
+pub fn trap_enter() {
+    TASK_MANAGER.trap_enter();
+}
+
+pub fn trap_exit() {
+    TASK_MANAGER.trap_exit();
+}
+
+// timer interrupt, charge current task and reschedule if needed
+// from_user 为 false 时打断的是系统调用，只在没有持有锁时才会进入这里
+pub fn tick(from_user: bool) {
+    TASK_MANAGER.tick(from_user);
+}
+
+// 按需分配页帧或者写时复制，地址无效或者没有权限时发送 SIGSEGV
+// 内核复制用户数据时访问了无效的地址，从复制函数的出错处理处继续执行，系统调用返回 -1
+// 内核在其他地方访问了无效的用户地址时，没有持有锁就直接结束进程，持有锁时无法恢复
+pub fn page_fault(ctx: &mut TrapContext, va: usize, access: Access, from_user: bool) {
+    if let Err(e) = TASK_MANAGER.page_fault(VirtAddr::from(va).into(), access) {
+        if let Some(fixup) = copy_fixup(ctx.pc()).filter(|_| !from_user) {
+            ctx.set_pc(fixup);
+            return;
+        }
+        println!("[kernel] page fault at {:#x} ({:?}): {}, kernel killed it.", va, access, e);
+        if from_user {
+            set_signal(None, SIGSEGV);
+        } else if preempt_count() == 0 {
+            exit_by_signal(SIGSEGV);
+        } else {
+            panic!("[kernel] bad user address {:#x} accessed while holding a lock", va);
+        }
+    }
 }
 
-pub fn wait(pid: isize) -> isize {
-    TASK_MANAGER.wait(pid)
+pub fn wait(pid: isize, status: *mut i32, options: usize, rusage: *mut rusage::RUsage) -> isize {
+    TASK_MANAGER.wait(pid, status, WaitOptions::from_bits_truncate(options), rusage)
 }
 
 pub fn write(fd: usize, buf: *mut u8, len: usize) -> isize {
     TASK_MANAGER.write(fd, buf, len)
 }
 
-pub fn create_pipe(size: usize) -> (usize, usize) {
+pub fn create_pipe(size: usize) -> Option<(usize, usize)> {
     TASK_MANAGER.create_pipe(size)
 }
 
@@ -73,8 +148,8 @@ pub fn read(fd: usize, buf: *mut u8, len: usize) -> isize {
     TASK_MANAGER.read(fd, buf, len)
 }
 
-pub fn open(name: String) -> isize {
-    TASK_MANAGER.open(name)
+pub fn open(name: String, flags: usize) -> isize {
+    TASK_MANAGER.open(name, flags)
 }
 
 pub fn lseek(fd: usize, seek: usize) -> isize {
@@ -113,6 +188,90 @@ pub fn getpid() -> usize {
     TASK_MANAGER.getpid()
 }
 
+pub fn getppid() -> usize {
+    TASK_MANAGER.getppid()
+}
+
+pub fn setpgid(pid: usize, pgid: usize) -> isize {
+    TASK_MANAGER.setpgid(pid, pgid)
+}
+
+pub fn getpgid(pid: usize) -> isize {
+    TASK_MANAGER.getpgid(pid)
+}
+
+pub fn setsid() -> isize {
+    TASK_MANAGER.setsid()
+}
+
+pub fn getsid(pid: usize) -> isize {
+    TASK_MANAGER.getsid(pid)
+}
+
+pub fn kill(pid: isize, signal: usize) -> isize {
+    TASK_MANAGER.kill(pid, signal)
+}
+
+pub fn stop(signal: usize) {
+    TASK_MANAGER.stop(signal)
+}
+
+pub fn gettid() -> usize {
+    TASK_MANAGER.gettid()
+}
+
+pub fn clock_gettime(clock: usize) -> Option<usize> {
+    TASK_MANAGER.clock_gettime(clock)
+}
+
+pub fn set_timer(id: timer::TimerId, value: usize, interval: usize, abstime: bool) -> Option<(usize, usize)> {
+    TASK_MANAGER.set_timer(id, value, interval, abstime)
+}
+
+pub fn get_timer(id: timer::TimerId) -> Option<(usize, usize)> {
+    TASK_MANAGER.get_timer(id)
+}
+
+pub fn timer_create(clock: usize, signal: usize) -> isize {
+    TASK_MANAGER.timer_create(clock, signal)
+}
+
+pub fn timer_delete(id: usize) -> isize {
+    TASK_MANAGER.timer_delete(id)
+}
+
+pub fn timer_getoverrun(id: usize) -> isize {
+    TASK_MANAGER.timer_getoverrun(id)
+}
+
+pub fn getrusage(who: isize) -> Option<rusage::Usage> {
+    TASK_MANAGER.getrusage(who)
+}
+
+pub fn times() -> rusage::Tms {
+    TASK_MANAGER.times()
+}
+
+pub fn prlimit(pid: usize, resource: usize, new: Option<rlimit::RLimit>) -> Option<rlimit::RLimit> {
+    TASK_MANAGER.prlimit(pid, resource, new)
+}
+
+pub fn set_priority(pid: usize, nice: isize) -> isize {
+    TASK_MANAGER.set_priority(pid, nice)
+}
+
+pub fn get_priority(pid: usize) -> isize {
+    TASK_MANAGER.get_priority(pid)
+}
+
+pub fn set_scheduler(pid: usize, policy: usize, param: scheduler::SchedParam) -> isize {
+    TASK_MANAGER.set_scheduler(pid, policy, param)
+}
+
+pub fn get_scheduler(pid: usize) -> Option<scheduler::SchedPolicy> {
+    TASK_MANAGER.get_scheduler(pid)
+}
+
 pub fn mmap(size: usize, permission: usize) -> isize {
     TASK_MANAGER.mmap(size, permission)
 }
@@ -121,6 +280,30 @@ pub fn ummap(addr: usize) -> isize {
     TASK_MANAGER.ummap(addr)
 }
 
+pub fn mmap_file(fd: usize, offset: usize, len: usize, permission: usize, flags: usize) -> isize {
+    TASK_MANAGER.mmap_file(fd, offset, len, permission, flags)
+}
+
+pub fn msync(addr: usize, len: usize) -> isize {
+    TASK_MANAGER.msync(addr, len)
+}
+
+pub fn brk(addr: usize) -> isize {
+    TASK_MANAGER.brk(addr)
+}
+
+pub fn mprotect(addr: usize, len: usize, permission: usize) -> isize {
+    TASK_MANAGER.mprotect(addr, len, permission)
+}
+
+pub fn allow_wx() -> bool {
+    TASK_MANAGER.allow_wx()
+}
+
+pub fn set_allow_wx(allow: bool) {
+    TASK_MANAGER.set_allow_wx(allow)
+}
+
 pub fn mmap_with_addr(pa: usize, size: usize, permission: usize, user: bool) -> isize {
     TASK_MANAGER.mmap_with_addr(pa, size, permission, user)
 }
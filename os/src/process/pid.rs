@@ -1,15 +1,12 @@
 use lazy_static::*;
 use alloc::vec::Vec;
 use alloc::sync::Arc;
-use spin::Mutex;
+use crate::sync::Mutex;
 
-use crate::utils::type_extern::RefCellWrap;
-
-// 由于我们的内核目前设计就是单线程，因此可以使用低效率的自旋锁
-// 它其实和之前使用的 RefCell 效率是相同的，无非一个是报错，另一个是傻傻等待
-// 后期考虑效率，需要根据 CPU 架构使用硬件支持的锁
+// 多个 cpu 可能同时分配 pid，使用自旋锁保护
+// 内核态不会被中断打断，持有锁的 cpu 不会在中断中再次获取同一个锁
 lazy_static!{
-    pub static ref PID_ALLOCATOR: Arc<Mutex<PidAllocator>> = unsafe {
+    pub static ref PID_ALLOCATOR: Arc<Mutex<PidAllocator>> = {
         Arc::new(
             Mutex::new(PidAllocator::new())
         )
@@ -53,7 +50,7 @@ impl PidAllocator {
     }
 }
 
-#[derive(Clone)]
+// 不能 Clone，否则 drop 时同一个 pid 会被回收两次
 pub struct PidHandler(pub usize);
 
 impl Drop for PidHandler {
@@ -0,0 +1,101 @@
+use crate::arch::memory::page::PAGE_SIZE;
+use crate::board::inner::memory::{USER_STACK_MAX, USER_STACK_SIZE};
+use crate::ipc::signal::{SIGKILL, SIGXCPU};
+
+// resource，和 linux 相同，只支持下面几种
+pub const RLIMIT_CPU: usize = 0;
+pub const RLIMIT_STACK: usize = 3;
+pub const RLIMIT_RSS: usize = 5;
+pub const RLIMIT_NPROC: usize = 6;
+pub const RLIMIT_NOFILE: usize = 7;
+pub const RLIMIT_AS: usize = 9;
+pub const RLIM_NLIMITS: usize = 16;
+
+pub const RLIM_INFINITY: usize = usize::MAX;
+
+// 默认最多打开的文件数
+const NOFILE_DEFAULT: usize = 1024;
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct RLimit {
+    // 软限制，超过时分配失败或者发送信号
+    pub rlim_cur: usize,
+    // 硬限制，软限制不能超过它
+    pub rlim_max: usize,
+}
+
+impl RLimit {
+    const INFINITY: Self = Self { rlim_cur: RLIM_INFINITY, rlim_max: RLIM_INFINITY };
+
+    pub fn new(rlim_cur: usize, rlim_max: usize) -> Self {
+        Self { rlim_cur, rlim_max }
+    }
+}
+
+// 线程组共享的资源限制，fork 时复制给子进程，exec 后保留
+#[derive(Clone)]
+pub struct ResourceLimits {
+    limits: [RLimit; RLIM_NLIMITS],
+    // 上一次发送 SIGXCPU 时的 cpu 秒数，超过软限制后每秒发送一次
+    xcpu: usize,
+}
+
+impl ResourceLimits {
+    pub fn new() -> Self {
+        let mut limits = [RLimit::INFINITY; RLIM_NLIMITS];
+        limits[RLIMIT_STACK] = RLimit::new(USER_STACK_SIZE, RLIM_INFINITY);
+        limits[RLIMIT_NOFILE] = RLimit::new(NOFILE_DEFAULT, NOFILE_DEFAULT);
+        Self { limits, xcpu: 0 }
+    }
+
+    // 子进程的 cpu 时间从 0 开始，重新计算 SIGXCPU
+    pub fn fork(&self) -> Self {
+        Self { limits: self.limits, xcpu: 0 }
+    }
+
+    pub fn get(&self, resource: usize) -> Option<RLimit> {
+        self.limits.get(resource).copied()
+    }
+
+    // 没有权限的区分，和 linux 的普通用户一样不能提高硬限制
+    pub fn set(&mut self, resource: usize, new: RLimit) -> Result<(), &'static str> {
+        let old = self.limits.get_mut(resource).ok_or("invalid resource")?;
+        if new.rlim_cur > new.rlim_max {
+            return Err("soft limit exceeds hard limit");
+        }
+        if new.rlim_max > old.rlim_max {
+            return Err("can't raise hard limit");
+        }
+        *old = new;
+        Ok(())
+    }
+
+    pub fn cur(&self, resource: usize) -> usize {
+        self.limits[resource].rlim_cur
+    }
+
+    // 检查 count 个单位的资源是否超过软限制
+    pub fn allow(&self, resource: usize, count: usize) -> bool {
+        count <= self.cur(resource)
+    }
+
+    // exec 时为用户栈预留的大小，按页对齐，超过 USER_STACK_MAX 时也只预留这么多
+    pub fn stack_size(&self) -> usize {
+        self.cur(RLIMIT_STACK).clamp(PAGE_SIZE, USER_STACK_MAX) / PAGE_SIZE * PAGE_SIZE
+    }
+
+    // 时钟中断中检查线程组的 cpu 时间，单位是秒
+    // 超过软限制时每秒发送一次 SIGXCPU，超过硬限制时 SIGKILL
+    pub fn check_cpu(&mut self, seconds: usize) -> Option<usize> {
+        let limit = self.limits[RLIMIT_CPU];
+        if seconds >= limit.rlim_max {
+            Some(SIGKILL)
+        } else if seconds >= limit.rlim_cur && seconds > self.xcpu {
+            self.xcpu = seconds;
+            Some(SIGXCPU)
+        } else {
+            None
+        }
+    }
+}
@@ -0,0 +1,128 @@
+use super::timer::{TimeVal, NSEC_PER_SEC};
+
+// getrusage 的 who，和 linux 相同
+pub const RUSAGE_SELF: isize = 0;
+pub const RUSAGE_CHILDREN: isize = -1;
+pub const RUSAGE_THREAD: isize = 1;
+
+// times 使用的时钟频率，和 linux 的 USER_HZ 相同
+pub const CLOCKS_PER_SEC: usize = 100;
+
+// 读写统计的块大小，和 linux 的 ru_inblock 一样按 512 字节计算
+const BLOCK_SIZE: usize = 512;
+
+// 线程的资源使用统计，时间的单位是 ns
+#[derive(Clone, Copy, Default, Debug)]
+pub struct Usage {
+    // 用户态时间，在 trap 进入内核时结算
+    pub utime: usize,
+    // 内核态时间，在 trap 返回用户态、时钟中断和切换任务时结算
+    pub stime: usize,
+    // 最大的常驻内存，单位是 KB
+    pub maxrss: usize,
+    // 按需分配和 copy on write 的页错误
+    pub minflt: usize,
+    // 需要从交换分区读回页的页错误
+    pub majflt: usize,
+    pub inblock: usize,
+    pub oublock: usize,
+    // 执行了处理函数的信号
+    pub nsignals: usize,
+    // 主动让出 cpu，即睡眠、等待等
+    pub nvcsw: usize,
+    // 被抢占
+    pub nivcsw: usize,
+}
+
+impl Usage {
+    pub fn cputime(&self) -> usize {
+        self.utime + self.stime
+    }
+
+    // 累加另一个线程或者子进程的统计，maxrss 取最大值
+    pub fn add(&mut self, other: &Usage) {
+        self.utime += other.utime;
+        self.stime += other.stime;
+        self.maxrss = self.maxrss.max(other.maxrss);
+        self.minflt += other.minflt;
+        self.majflt += other.majflt;
+        self.inblock += other.inblock;
+        self.oublock += other.oublock;
+        self.nsignals += other.nsignals;
+        self.nvcsw += other.nvcsw;
+        self.nivcsw += other.nivcsw;
+    }
+
+    pub fn read_bytes(&mut self, len: usize) {
+        self.inblock += len.div_ceil(BLOCK_SIZE);
+    }
+
+    pub fn write_bytes(&mut self, len: usize) {
+        self.oublock += len.div_ceil(BLOCK_SIZE);
+    }
+}
+
+// linux 的 struct rusage，不支持的字段为 0
+#[repr(C)]
+#[derive(Clone, Copy, Default, Debug)]
+pub struct RUsage {
+    pub ru_utime: TimeVal,
+    pub ru_stime: TimeVal,
+    pub ru_maxrss: usize,
+    pub ru_ixrss: usize,
+    pub ru_idrss: usize,
+    pub ru_isrss: usize,
+    pub ru_minflt: usize,
+    pub ru_majflt: usize,
+    pub ru_nswap: usize,
+    pub ru_inblock: usize,
+    pub ru_oublock: usize,
+    pub ru_msgsnd: usize,
+    pub ru_msgrcv: usize,
+    pub ru_nsignals: usize,
+    pub ru_nvcsw: usize,
+    pub ru_nivcsw: usize,
+}
+
+impl From<&Usage> for RUsage {
+    fn from(u: &Usage) -> Self {
+        Self {
+            ru_utime: TimeVal::from_ns(u.utime),
+            ru_stime: TimeVal::from_ns(u.stime),
+            ru_maxrss: u.maxrss,
+            ru_minflt: u.minflt,
+            ru_majflt: u.majflt,
+            ru_inblock: u.inblock,
+            ru_oublock: u.oublock,
+            ru_nsignals: u.nsignals,
+            ru_nvcsw: u.nvcsw,
+            ru_nivcsw: u.nivcsw,
+            ..Default::default()
+        }
+    }
+}
+
+// linux 的 struct tms，单位是 1 / CLOCKS_PER_SEC 秒
+#[repr(C)]
+#[derive(Clone, Copy, Default, Debug)]
+pub struct Tms {
+    pub tms_utime: usize,
+    pub tms_stime: usize,
+    pub tms_cutime: usize,
+    pub tms_cstime: usize,
+}
+
+pub fn ns_to_clock(ns: usize) -> usize {
+    ns / (NSEC_PER_SEC / CLOCKS_PER_SEC)
+}
+
+impl Tms {
+    pub fn new(own: &Usage, children: &Usage) -> Self {
+        Self {
+            tms_utime: ns_to_clock(own.utime),
+            tms_stime: ns_to_clock(own.stime),
+            tms_cutime: ns_to_clock(children.utime),
+            tms_cstime: ns_to_clock(children.stime),
+        }
+    }
+}
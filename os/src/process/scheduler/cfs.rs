@@ -0,0 +1,208 @@
+use alloc::collections::BTreeMap;
+use alloc::sync::{Arc, Weak};
+use crate::sync::Mutex;
+
+use crate::board::timer::nanoseconds;
+use crate::process::app::Process;
+
+use super::{Scheduler, NICE_MIN};
+
+// 一个调度周期的目标长度，周期内每个可运行任务都应该运行一次
+const SCHED_LATENCY_NS: usize = 24_000_000;
+// 任务一次至少运行的时间，和时钟中断间隔一致
+const MIN_GRANULARITY_NS: usize = 10_000_000;
+const NICE_0_WEIGHT: usize = 1024;
+
+// 和 linux 的 sched_prio_to_weight 相同，相邻 nice 值之间 cpu 占比相差约 10%
+const PRIO_TO_WEIGHT: [usize; 40] = [
+    88761, 71755, 56483, 46273, 36291,
+    29154, 23254, 18705, 14949, 11916,
+    9548, 7620, 6100, 4904, 3906,
+    3121, 2501, 1991, 1586, 1277,
+    1024, 820, 655, 526, 423,
+    335, 272, 215, 172, 137,
+    110, 87, 70, 56, 45,
+    36, 29, 23, 18, 15,
+];
+
+pub fn weight(nice: isize) -> usize {
+    PRIO_TO_WEIGHT[(nice - NICE_MIN) as usize]
+}
+
+// 类似 linux CFS 的公平调度
+// 可运行的任务按照 vruntime 排序放在红黑树（这里用 BTreeMap）中，每次选择 vruntime 最小的任务运行
+// vruntime 按照 nice 对应的权重折算，权重越大，vruntime 增长越慢，得到的 cpu 时间越多
+pub struct CfsScheduler {
+    // (vruntime, pid) -> (task, weight)，正在运行的任务不在树中
+    tree: BTreeMap<(usize, usize), (Weak<Mutex<Process>>, usize)>,
+    // pid -> task，睡眠或者等待中的任务，收到唤醒通知后重新放入树中
+    blocked: BTreeMap<usize, Weak<Mutex<Process>>>,
+    // 树中任务的权重之和
+    load: usize,
+    // 单调递增，新任务和被唤醒任务的 vruntime 以此为基准
+    min_vruntime: usize,
+}
+
+impl CfsScheduler {
+    pub fn new() -> Self {
+        Self {
+            tree: BTreeMap::new(),
+            blocked: BTreeMap::new(),
+            load: 0,
+            min_vruntime: 0,
+        }
+    }
+
+    fn enqueue(&mut self, task: &Arc<Mutex<Process>>) {
+        let p = task.lock();
+        let w = weight(p.nice);
+        self.tree.insert((p.se.vruntime, p.pid.0), (Arc::downgrade(task), w));
+        self.load += w;
+    }
+
+    // 把 delta 时间按照权重折算成 vruntime
+    fn calc_delta_fair(delta: usize, w: usize) -> usize {
+        delta * NICE_0_WEIGHT / w
+    }
+
+    // 一个调度周期中任务应该运行的时间
+    fn sched_slice(&self, w: usize) -> usize {
+        let nr_running = self.tree.len() + 1;
+        let period = SCHED_LATENCY_NS.max(nr_running * MIN_GRANULARITY_NS);
+        period * w / (self.load + w)
+    }
+
+    // 结算当前任务从上次结算到现在的运行时间
+    fn update_curr(p: &mut Process, now: usize) {
+        let delta = super::update_exec(p, now);
+        p.se.vruntime += Self::calc_delta_fair(delta, weight(p.nice));
+    }
+
+    // 被唤醒的任务最多补偿半个调度周期，防止长期睡眠的任务独占 cpu
+    fn place_entity(&self, p: &mut Process) {
+        let vruntime = self.min_vruntime.saturating_sub(SCHED_LATENCY_NS / 2);
+        p.se.vruntime = p.se.vruntime.max(vruntime);
+    }
+
+    fn block(&mut self, task: &Arc<Mutex<Process>>) {
+        let pid = task.lock().pid.0;
+        self.blocked.insert(pid, Arc::downgrade(task));
+    }
+}
+
+impl Scheduler for CfsScheduler {
+    fn add(&mut self, task: Weak<Mutex<Process>>) {
+        if let Some(p) = task.upgrade() {
+            let vruntime = p.lock().se.vruntime.max(self.min_vruntime);
+            p.lock().se.vruntime = vruntime;
+            if p.lock().runnable() {
+                self.enqueue(&p);
+            } else {
+                self.block(&p);
+            }
+        }
+    }
+
+    fn fetch(&mut self) -> Option<Arc<Mutex<Process>>> {
+        let now = nanoseconds();
+        while let Some((_, (task, w))) = self.tree.pop_first() {
+            self.load -= w;
+            if let Some(p) = task.upgrade() {
+                // 在队列中被停止的任务，继续运行时会收到唤醒通知
+                if !p.lock().runnable() {
+                    self.block(&p);
+                    continue;
+                }
+
+                let mut proc = p.lock();
+                proc.se.exec_start = now;
+                proc.se.prev_sum_exec_runtime = proc.se.sum_exec_runtime;
+                self.min_vruntime = self.min_vruntime.max(proc.se.vruntime);
+                drop(proc);
+                return Some(p);
+            }
+        }
+
+        None
+    }
+
+    fn tick(&mut self, current: &Arc<Mutex<Process>>) -> bool {
+        let mut p = current.lock();
+        Self::update_curr(&mut p, nanoseconds());
+
+        let delta_exec = p.se.sum_exec_runtime - p.se.prev_sum_exec_runtime;
+        let ideal_runtime = self.sched_slice(weight(p.nice));
+        if delta_exec > ideal_runtime {
+            return true;
+        }
+
+        if delta_exec < MIN_GRANULARITY_NS {
+            return false;
+        }
+
+        // 当前任务的 vruntime 已经明显领先于树中最左边的任务
+        if let Some(((vruntime, _), _)) = self.tree.first_key_value() {
+            if p.se.vruntime > *vruntime + ideal_runtime {
+                return true;
+            }
+        }
+
+        false
+    }
+
+    fn put_prev(&mut self, task: &Arc<Mutex<Process>>) {
+        let now = nanoseconds();
+        Self::update_curr(&mut task.lock(), now);
+        if task.lock().runnable() {
+            self.enqueue(task);
+        } else if !task.lock().exited() {
+            self.block(task);
+        }
+    }
+
+    // 只处理被通知之前已经在 blocked 中的任务，还在运行的任务在 put_prev 时检查状态
+    fn wakeup(&mut self, pid: usize) {
+        let Some(task) = self.blocked.get(&pid).and_then(|t| t.upgrade()) else {
+            self.blocked.remove(&pid);
+            return;
+        };
+        if task.lock().runnable() {
+            self.blocked.remove(&pid);
+            self.place_entity(&mut task.lock());
+            self.enqueue(&task);
+        }
+    }
+
+    // 树中的任务按新的权重重新放入，不在树中的任务在放回树中时使用新的权重
+    fn reprioritize(&mut self, task: &Arc<Mutex<Process>>) {
+        let key = {
+            let p = task.lock();
+            (p.se.vruntime, p.pid.0)
+        };
+        if let Some((_, w)) = self.tree.remove(&key) {
+            self.load -= w;
+            self.enqueue(task);
+        }
+    }
+
+    // 取出 vruntime 最大的任务，它在这个 cpu 上要等最久
+    fn steal(&mut self) -> Option<Arc<Mutex<Process>>> {
+        let key = self.tree.iter().rev()
+            .find(|(_, (t, _))| t.upgrade().is_some_and(|p| p.lock().runnable()))
+            .map(|(k, _)| *k)?;
+        let (task, w) = self.tree.remove(&key)?;
+        self.load -= w;
+        task.upgrade()
+    }
+
+    fn dequeue(&mut self, task: &Arc<Mutex<Process>>) {
+        let (key, pid) = {
+            let p = task.lock();
+            ((p.se.vruntime, p.pid.0), p.pid.0)
+        };
+        if let Some((_, w)) = self.tree.remove(&key) {
+            self.load -= w;
+        }
+        self.blocked.remove(&pid);
+    }
+}
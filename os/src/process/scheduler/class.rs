@@ -0,0 +1,173 @@
+use alloc::boxed::Box;
+use alloc::sync::{Arc, Weak};
+use crate::sync::Mutex;
+
+use crate::board::timer::nanoseconds;
+use crate::process::app::Process;
+
+use super::dl::{self, DeadlineScheduler};
+use super::rt::RtScheduler;
+use super::{Scheduler, SchedPolicy};
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Class {
+    Deadline,
+    RealTime,
+    Fair,
+}
+
+impl From<SchedPolicy> for Class {
+    fn from(policy: SchedPolicy) -> Self {
+        match policy {
+            SchedPolicy::Normal => Class::Fair,
+            SchedPolicy::Fifo(_) | SchedPolicy::RoundRobin(_) => Class::RealTime,
+            SchedPolicy::Deadline(..) => Class::Deadline,
+        }
+    }
+}
+
+// 按调度类分层，和 linux 相同，deadline > 实时 > 普通
+// 只有高层没有可运行的任务时，才会从低层选择任务，高层任务就绪时会抢占低层任务
+pub struct ClassScheduler {
+    dl: DeadlineScheduler,
+    rt: RtScheduler,
+    // 普通任务的调度器，由 feature 选择
+    fair: Box<dyn Scheduler>,
+    // 当前任务是从哪一层选出来的
+    curr_class: Class,
+}
+
+impl ClassScheduler {
+    pub fn new(fair: Box<dyn Scheduler>) -> Self {
+        Self {
+            dl: DeadlineScheduler::new(),
+            rt: RtScheduler::new(),
+            fair,
+            curr_class: Class::Fair,
+        }
+    }
+
+    fn class(&mut self, class: Class) -> &mut dyn Scheduler {
+        match class {
+            Class::Deadline => &mut self.dl,
+            Class::RealTime => &mut self.rt,
+            Class::Fair => self.fair.as_mut(),
+        }
+    }
+
+    // 修改任务的调度策略，deadline 任务带宽不足时返回 -2
+    // 正在运行的任务不在任何队列中，等它回到 idle 时再放到新的调度类中
+    pub fn set_policy(&mut self, task: &Arc<Mutex<Process>>, policy: SchedPolicy, running: bool) -> isize {
+        let old = task.lock().policy;
+        if !self.dl.admit(dl::bandwidth(&old), dl::bandwidth(&policy)) {
+            println!("[kernel] deadline bandwidth exceeded");
+            return -2;
+        }
+
+        if !running {
+            self.class(old.into()).dequeue(task);
+        }
+
+        let mut p = task.lock();
+        p.policy = policy;
+        p.se.time_slice = 0;
+        p.se.dl_runtime = 0;
+        p.se.dl_deadline = 0;
+        drop(p);
+
+        if !running {
+            self.class(policy.into()).add(Arc::downgrade(task));
+        }
+        0
+    }
+}
+
+impl Scheduler for ClassScheduler {
+    fn add(&mut self, task: Weak<Mutex<Process>>) {
+        if let Some(p) = task.upgrade() {
+            let class = p.lock().policy.into();
+            self.class(class).add(task);
+        }
+    }
+
+    fn fetch(&mut self) -> Option<Arc<Mutex<Process>>> {
+        for class in [Class::Deadline, Class::RealTime, Class::Fair] {
+            if let Some(p) = self.class(class).fetch() {
+                self.curr_class = class;
+                return Some(p);
+            }
+        }
+
+        None
+    }
+
+    fn tick(&mut self, current: &Arc<Mutex<Process>>) -> bool {
+        // 运行中修改了调度策略，尽快切换到新的调度类
+        if Class::from(current.lock().policy) != self.curr_class {
+            return true;
+        }
+
+        let now = nanoseconds();
+        match self.curr_class {
+            Class::Deadline => self.dl.tick(current),
+            Class::RealTime => {
+                self.dl.earliest_deadline(now).is_some() || self.rt.tick(current)
+            }
+            Class::Fair => {
+                self.dl.earliest_deadline(now).is_some()
+                    || self.rt.highest_prio().is_some()
+                    || self.fair.tick(current)
+            }
+        }
+    }
+
+    fn reprioritize(&mut self, task: &Arc<Mutex<Process>>) {
+        // nice 值只对普通任务有效
+        if task.lock().policy == SchedPolicy::Normal {
+            self.fair.reprioritize(task);
+        }
+    }
+
+    // 只有普通任务的调度器使用唤醒通知，实时和 deadline 任务在选择任务时检查阻塞的任务
+    fn wakeup(&mut self, pid: usize) {
+        self.fair.wakeup(pid);
+    }
+
+    // 只迁移普通任务，实时和 deadline 任务留在原来的 cpu 上
+    fn steal(&mut self) -> Option<Arc<Mutex<Process>>> {
+        self.fair.steal()
+    }
+
+    fn put_prev(&mut self, task: &Arc<Mutex<Process>>) {
+        let (policy, exited) = {
+            let p = task.lock();
+            (p.policy, p.exited())
+        };
+        if exited {
+            self.dl.release(dl::bandwidth(&policy));
+        }
+
+        let class = policy.into();
+        if class == self.curr_class {
+            self.class(class).put_prev(task);
+        } else {
+            let curr_class = self.curr_class;
+            self.class(curr_class).dequeue(task);
+            if !exited {
+                self.class(class).add(Arc::downgrade(task));
+            }
+        }
+    }
+
+    // 已经退出并且不在 cpu 上运行的任务不会再经过 put_prev，在这里释放 deadline 带宽
+    fn dequeue(&mut self, task: &Arc<Mutex<Process>>) {
+        let (policy, release) = {
+            let p = task.lock();
+            (p.policy, p.exited() && !p.on_cpu)
+        };
+        if release {
+            self.dl.release(dl::bandwidth(&policy));
+        }
+        self.class(policy.into()).dequeue(task);
+    }
+}
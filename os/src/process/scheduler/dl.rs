@@ -0,0 +1,169 @@
+use alloc::collections::BTreeMap;
+use alloc::sync::{Arc, Weak};
+use alloc::vec::Vec;
+use crate::sync::Mutex;
+
+use crate::board::timer::nanoseconds;
+use crate::process::app::Process;
+
+use super::{Scheduler, SchedPolicy};
+
+// 带宽使用定点数表示，1 << BW_SHIFT 表示 100% 的 cpu
+const BW_SHIFT: usize = 20;
+// 和 linux 一样，deadline 任务最多使用 95% 的 cpu，剩下的留给其他任务
+const BW_LIMIT: usize = (1 << BW_SHIFT) * 95 / 100;
+
+// 任务申请的 cpu 带宽 runtime / period，非 deadline 任务为 0
+pub fn bandwidth(policy: &SchedPolicy) -> usize {
+    match *policy {
+        SchedPolicy::Deadline(runtime, _, period) => (runtime << BW_SHIFT) / period,
+        _ => 0,
+    }
+}
+
+// EDF 调度，总是运行绝对截止时间最早的任务
+// 每个周期任务最多运行 runtime，用完后被限流直到下一个周期开始
+// 设置 deadline 策略时做准入控制，所有任务的带宽之和不能超过 BW_LIMIT，这样在单核上 EDF 可以保证所有截止时间
+pub struct DeadlineScheduler {
+    // (绝对截止时间, pid) -> task，只包含可运行并且还有 runtime 的任务
+    tree: BTreeMap<(usize, usize), Weak<Mutex<Process>>>,
+    // 睡眠或者被限流的任务
+    blocked: Vec<Weak<Mutex<Process>>>,
+    // 已经分配出去的带宽
+    total_bw: usize,
+}
+
+impl DeadlineScheduler {
+    pub fn new() -> Self {
+        Self {
+            tree: BTreeMap::new(),
+            blocked: Vec::new(),
+            total_bw: 0,
+        }
+    }
+
+    // 准入控制，成功时从 old_bw 改为 new_bw
+    pub fn admit(&mut self, old_bw: usize, new_bw: usize) -> bool {
+        let total = self.total_bw - old_bw + new_bw;
+        if total > BW_LIMIT {
+            return false;
+        }
+
+        self.total_bw = total;
+        true
+    }
+
+    pub fn release(&mut self, bw: usize) {
+        self.total_bw -= bw;
+    }
+
+    // 进入新的周期时补充 runtime，并且重新计算截止时间
+    fn replenish(p: &mut Process, now: usize) {
+        if let SchedPolicy::Deadline(runtime, deadline, period) = p.policy {
+            let period_start = p.se.dl_deadline.saturating_sub(deadline);
+            if now >= period_start + period {
+                p.se.dl_deadline = now + deadline;
+                p.se.dl_runtime = runtime;
+            }
+        }
+    }
+
+    fn update_curr(p: &mut Process, now: usize) {
+        let delta = super::update_exec(p, now);
+        p.se.dl_runtime = p.se.dl_runtime.saturating_sub(delta);
+    }
+
+    fn enqueue(&mut self, task: &Arc<Mutex<Process>>) {
+        let p = task.lock();
+        self.tree.insert((p.se.dl_deadline, p.pid.0), Arc::downgrade(task));
+    }
+
+    fn wakeup(&mut self, now: usize) {
+        let mut i = 0;
+        while i < self.blocked.len() {
+            if let Some(task) = self.blocked[i].upgrade() {
+                let mut p = task.lock();
+                Self::replenish(&mut p, now);
+                if p.runnable() && p.se.dl_runtime > 0 {
+                    drop(p);
+                    self.enqueue(&task);
+                    self.blocked.swap_remove(i);
+                } else {
+                    i += 1;
+                }
+            } else {
+                self.blocked.swap_remove(i);
+            }
+        }
+    }
+
+    // 最早的截止时间，没有可运行的任务时返回 None
+    pub fn earliest_deadline(&mut self, now: usize) -> Option<usize> {
+        self.wakeup(now);
+        self.tree.retain(|_, t| t.strong_count() > 0);
+        self.tree.first_key_value().map(|((deadline, _), _)| *deadline)
+    }
+}
+
+impl Scheduler for DeadlineScheduler {
+    fn add(&mut self, task: Weak<Mutex<Process>>) {
+        self.blocked.push(task);
+    }
+
+    fn fetch(&mut self) -> Option<Arc<Mutex<Process>>> {
+        let now = nanoseconds();
+        self.wakeup(now);
+
+        while let Some((_, task)) = self.tree.pop_first() {
+            if let Some(p) = task.upgrade() {
+                if !p.lock().runnable() {
+                    self.blocked.push(task);
+                    continue;
+                }
+
+                p.lock().se.exec_start = now;
+                return Some(p);
+            }
+        }
+
+        None
+    }
+
+    fn tick(&mut self, current: &Arc<Mutex<Process>>) -> bool {
+        let now = nanoseconds();
+        let mut p = current.lock();
+        Self::update_curr(&mut p, now);
+
+        // runtime 用完，限流到下一个周期
+        if p.se.dl_runtime == 0 {
+            return true;
+        }
+
+        let deadline = p.se.dl_deadline;
+        drop(p);
+        matches!(self.earliest_deadline(now), Some(d) if d < deadline)
+    }
+
+    fn put_prev(&mut self, task: &Arc<Mutex<Process>>) {
+        let now = nanoseconds();
+        let mut p = task.lock();
+        Self::update_curr(&mut p, now);
+        if p.exited() {
+            return;
+        }
+
+        let ready = p.runnable() && p.se.dl_runtime > 0;
+        drop(p);
+        if ready {
+            self.enqueue(task);
+        } else {
+            self.blocked.push(Arc::downgrade(task));
+        }
+    }
+
+    fn dequeue(&mut self, task: &Arc<Mutex<Process>>) {
+        let weak = Arc::downgrade(task);
+        self.tree.retain(|_, t| !t.ptr_eq(&weak));
+        self.blocked.retain(|t| !t.ptr_eq(&weak));
+    }
+}
@@ -0,0 +1,227 @@
+pub mod rr;
+pub mod priority;
+pub mod cfs;
+pub mod rt;
+pub mod dl;
+pub mod class;
+
+use alloc::boxed::Box;
+use alloc::collections::VecDeque;
+use alloc::sync::{Arc, Weak};
+use alloc::vec::Vec;
+use crate::board::CPU_NUM;
+use crate::sync::Mutex;
+
+use super::app::{Process, ProcessStatus};
+
+pub use rr::RoundRobin;
+pub use priority::PriorityScheduler;
+pub use cfs::CfsScheduler;
+pub use rt::RtScheduler;
+pub use dl::DeadlineScheduler;
+pub use class::ClassScheduler;
+
+// setpriority/getpriority 的 which 参数，目前只支持进程
+pub const PRIO_PROCESS: usize = 0;
+
+// nice 值范围，和 linux 保持一致，越小优先级越高
+pub const NICE_MIN: isize = -20;
+pub const NICE_MAX: isize = 19;
+
+// sched_setscheduler 的调度策略，和 linux 保持一致
+pub const SCHED_NORMAL: usize = 0;
+pub const SCHED_FIFO: usize = 1;
+pub const SCHED_RR: usize = 2;
+pub const SCHED_DEADLINE: usize = 6;
+
+// 实时优先级范围，越大优先级越高
+pub const RT_PRIO_MIN: usize = 1;
+pub const RT_PRIO_MAX: usize = 99;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SchedPolicy {
+    // 普通任务，由 cfs/priority/rr 调度
+    Normal,
+    // 实时任务，参数为实时优先级
+    Fifo(usize),
+    RoundRobin(usize),
+    // runtime, deadline, period，单位 ns
+    Deadline(usize, usize, usize),
+}
+
+// sched_setscheduler 的参数
+// 和 linux 的 sched_param 不同，deadline 的参数也放在这里，省掉 sched_setattr
+#[repr(C)]
+#[derive(Clone, Copy, Default, Debug)]
+pub struct SchedParam {
+    pub sched_priority: usize,
+    pub sched_runtime: usize,
+    pub sched_deadline: usize,
+    pub sched_period: usize,
+}
+
+impl SchedPolicy {
+    // 参数不合法时返回 None
+    pub fn from_param(policy: usize, param: &SchedParam) -> Option<Self> {
+        let prio = param.sched_priority;
+        match policy {
+            SCHED_NORMAL if prio == 0 => Some(Self::Normal),
+            SCHED_FIFO if (RT_PRIO_MIN..=RT_PRIO_MAX).contains(&prio) => Some(Self::Fifo(prio)),
+            SCHED_RR if (RT_PRIO_MIN..=RT_PRIO_MAX).contains(&prio) => Some(Self::RoundRobin(prio)),
+            SCHED_DEADLINE => {
+                let runtime = param.sched_runtime;
+                let deadline = param.sched_deadline;
+                // 和 linux 一样，period 为 0 时等于 deadline
+                let period = if param.sched_period == 0 { deadline } else { param.sched_period };
+                if runtime > 0 && runtime <= deadline && deadline <= period {
+                    Some(Self::Deadline(runtime, deadline, period))
+                } else {
+                    None
+                }
+            }
+            _ => None,
+        }
+    }
+
+    pub fn policy(&self) -> usize {
+        match self {
+            Self::Normal => SCHED_NORMAL,
+            Self::Fifo(_) => SCHED_FIFO,
+            Self::RoundRobin(_) => SCHED_RR,
+            Self::Deadline(..) => SCHED_DEADLINE,
+        }
+    }
+
+    pub fn param(&self) -> SchedParam {
+        match *self {
+            Self::Normal => SchedParam::default(),
+            Self::Fifo(prio) | Self::RoundRobin(prio) => SchedParam { sched_priority: prio, ..SchedParam::default() },
+            Self::Deadline(runtime, deadline, period) => SchedParam {
+                sched_priority: 0,
+                sched_runtime: runtime,
+                sched_deadline: deadline,
+                sched_period: period,
+            },
+        }
+    }
+}
+
+// 调度器接口，TaskManager 在 idle 控制流中通过它选择下一个运行的任务
+pub trait Scheduler: Send {
+    // add a new task into run queue
+    fn add(&mut self, task: Weak<Mutex<Process>>);
+
+    // pick next runnable task, return None if no task can run now
+    fn fetch(&mut self) -> Option<Arc<Mutex<Process>>>;
+
+    // called in every timer interrupt, return true if current task should give up cpu
+    fn tick(&mut self, current: &Arc<Mutex<Process>>) -> bool;
+
+    // nice value of the task changed, move it to the right place
+    fn reprioritize(&mut self, _task: &Arc<Mutex<Process>>) {}
+
+    // the blocked task with this pid became ready, see notify_wakeup
+    fn wakeup(&mut self, _pid: usize) {}
+
+    // remove a runnable task which is not running, so that an idle cpu can run it
+    // return None if there is no such task or this scheduler doesn't migrate tasks
+    fn steal(&mut self) -> Option<Arc<Mutex<Process>>> {
+        None
+    }
+
+    // task switched back to idle, whatever it is preempted, sleeping or exited
+    fn put_prev(&mut self, _task: &Arc<Mutex<Process>>) {}
+
+    // scheduling policy of the task changed, remove it from this scheduler
+    fn dequeue(&mut self, _task: &Arc<Mutex<Process>>) {}
+}
+
+// 调度相关的统计信息，时间单位都是 ns
+#[derive(Clone, Copy, Default)]
+pub struct SchedEntity {
+    pub vruntime: usize,
+    // 本次开始运行（或上次结算）的时间
+    pub exec_start: usize,
+    pub sum_exec_runtime: usize,
+    // 本次被调度时的 sum_exec_runtime，用于计算本次运行了多久
+    pub prev_sum_exec_runtime: usize,
+    // SCHED_RR 剩余的时间片
+    pub time_slice: usize,
+    // SCHED_DEADLINE 本周期剩余的运行时间和绝对截止时间
+    pub dl_runtime: usize,
+    pub dl_deadline: usize,
+}
+
+// 从阻塞变为就绪的任务的 pid，按任务所在的 cpu 记录
+// Process::set_status 时不一定持有 TaskManager 的锁，所以单独加锁，选择任务之前交给这个 cpu 的调度器
+const NO_WAKEUP: Mutex<Vec<usize>> = Mutex::new(Vec::new());
+static WAKEUPS: [Mutex<Vec<usize>>; CPU_NUM] = [NO_WAKEUP; CPU_NUM];
+
+pub fn notify_wakeup(cpu: usize, pid: usize) {
+    WAKEUPS[cpu].lock().push(pid);
+}
+
+pub fn take_wakeups(cpu: usize) -> Vec<usize> {
+    core::mem::take(&mut *WAKEUPS[cpu].lock())
+}
+
+pub fn new_scheduler() -> Box<dyn Scheduler> {
+    #[cfg(feature = "sched_rr")]
+    return Box::new(RoundRobin::new());
+
+    #[cfg(all(feature = "sched_prio", not(feature = "sched_rr")))]
+    return Box::new(PriorityScheduler::new());
+
+    #[cfg(not(any(feature = "sched_rr", feature = "sched_prio")))]
+    return Box::new(CfsScheduler::new());
+}
+
+// 轮询一个队列，返回第一个可运行的任务，并将其放到队尾
+// 已经被释放的任务会在这里被移出队列
+fn pick(queue: &mut VecDeque<Weak<Mutex<Process>>>) -> Option<Arc<Mutex<Process>>> {
+    for _ in 0..queue.len() {
+        let task = queue.pop_front()?;
+        if let Some(p) = task.upgrade() {
+            queue.push_back(task);
+            if p.lock().runnable() {
+                return Some(p);
+            }
+        }
+    }
+
+    None
+}
+
+// 结算任务从上次结算到现在的运行时间，返回这段时间的长度
+fn update_exec(p: &mut Process, now: usize) -> usize {
+    let delta = now.saturating_sub(p.se.exec_start);
+    p.se.exec_start = now;
+    p.se.sum_exec_runtime += delta;
+    delta
+}
+
+// 从队尾开始找一个可运行并且不在 cpu 上运行的任务，把它移出队列
+fn take_runnable(queue: &mut VecDeque<Weak<Mutex<Process>>>) -> Option<Arc<Mutex<Process>>> {
+    let i = queue.iter().rposition(|t| t.upgrade().is_some_and(|p| {
+        let p = p.lock();
+        p.runnable() && !p.on_cpu
+    }))?;
+    queue.remove(i)?.upgrade()
+}
+
+fn remove(queue: &mut VecDeque<Weak<Mutex<Process>>>, task: &Arc<Mutex<Process>>) {
+    let weak = Arc::downgrade(task);
+    queue.retain(|t| !t.ptr_eq(&weak));
+}
+
+// 消耗当前任务的一个时间片，用完后需要重新调度
+fn consume_tick(current: &Arc<Mutex<Process>>) -> bool {
+    let mut p = current.lock();
+    match p.status {
+        ProcessStatus::RUNNING(t) if t > 1 => {
+            p.set_status(ProcessStatus::RUNNING(t - 1));
+            false
+        }
+        _ => true,
+    }
+}
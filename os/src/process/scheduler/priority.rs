@@ -0,0 +1,66 @@
+use alloc::collections::VecDeque;
+use alloc::sync::{Arc, Weak};
+use alloc::vec::Vec;
+use crate::sync::Mutex;
+
+use crate::process::app::Process;
+
+use super::{Scheduler, NICE_MAX, NICE_MIN};
+
+// 多级优先级队列，每个 nice 值对应一个队列
+// 总是先调度高优先级队列中的任务，同一队列中轮转
+// 注意：只要高优先级任务处于可运行状态，低优先级任务就得不到运行
+pub struct PriorityScheduler {
+    queues: Vec<VecDeque<Weak<Mutex<Process>>>>,
+}
+
+impl PriorityScheduler {
+    pub fn new() -> Self {
+        let mut queues = Vec::new();
+        queues.resize_with((NICE_MAX - NICE_MIN + 1) as usize, VecDeque::new);
+        Self { queues }
+    }
+
+    fn level(nice: isize) -> usize {
+        (nice.clamp(NICE_MIN, NICE_MAX) - NICE_MIN) as usize
+    }
+}
+
+impl Scheduler for PriorityScheduler {
+    fn add(&mut self, task: Weak<Mutex<Process>>) {
+        if let Some(p) = task.upgrade() {
+            let level = Self::level(p.lock().nice);
+            self.queues[level].push_back(task);
+        }
+    }
+
+    fn fetch(&mut self) -> Option<Arc<Mutex<Process>>> {
+        for queue in self.queues.iter_mut() {
+            if let Some(p) = super::pick(queue) {
+                return Some(p);
+            }
+        }
+
+        None
+    }
+
+    fn tick(&mut self, current: &Arc<Mutex<Process>>) -> bool {
+        super::consume_tick(current)
+    }
+
+    fn reprioritize(&mut self, task: &Arc<Mutex<Process>>) {
+        self.dequeue(task);
+        self.add(Arc::downgrade(task));
+    }
+
+    // 从优先级最低的队列开始找
+    fn steal(&mut self) -> Option<Arc<Mutex<Process>>> {
+        self.queues.iter_mut().rev().find_map(super::take_runnable)
+    }
+
+    fn dequeue(&mut self, task: &Arc<Mutex<Process>>) {
+        for queue in self.queues.iter_mut() {
+            super::remove(queue, task);
+        }
+    }
+}
@@ -0,0 +1,40 @@
+use alloc::collections::VecDeque;
+use alloc::sync::{Arc, Weak};
+use crate::sync::Mutex;
+
+use crate::process::app::Process;
+
+use super::Scheduler;
+
+// 最简单的时间片轮转，所有任务共用一个队列，忽略 nice 值
+pub struct RoundRobin {
+    queue: VecDeque<Weak<Mutex<Process>>>,
+}
+
+impl RoundRobin {
+    pub fn new() -> Self {
+        Self { queue: VecDeque::new() }
+    }
+}
+
+impl Scheduler for RoundRobin {
+    fn add(&mut self, task: Weak<Mutex<Process>>) {
+        self.queue.push_back(task);
+    }
+
+    fn fetch(&mut self) -> Option<Arc<Mutex<Process>>> {
+        super::pick(&mut self.queue)
+    }
+
+    fn tick(&mut self, current: &Arc<Mutex<Process>>) -> bool {
+        super::consume_tick(current)
+    }
+
+    fn steal(&mut self) -> Option<Arc<Mutex<Process>>> {
+        super::take_runnable(&mut self.queue)
+    }
+
+    fn dequeue(&mut self, task: &Arc<Mutex<Process>>) {
+        super::remove(&mut self.queue, task);
+    }
+}
@@ -0,0 +1,167 @@
+use alloc::collections::VecDeque;
+use alloc::sync::{Arc, Weak};
+use alloc::vec::Vec;
+use crate::sync::Mutex;
+
+use crate::board::timer::nanoseconds;
+use crate::process::app::{Process, ProcessStatus};
+
+use super::{Scheduler, SchedPolicy, RT_PRIO_MAX};
+
+// SCHED_RR 的时间片，和 linux 的默认值相同
+pub const RR_TIMESLICE_NS: usize = 100_000_000;
+
+// 实时调度，SCHED_FIFO 和 SCHED_RR
+// 每个实时优先级一个队列，总是运行最高优先级队列的第一个任务
+// FIFO 任务一直运行到主动放弃 cpu 或者被更高优先级的任务抢占
+// RR 任务在 FIFO 的基础上增加了时间片，用完后放到同优先级队列的末尾
+// 注意：没有 linux 的 rt throttling，实时任务死循环会让普通任务得不到运行
+pub struct RtScheduler {
+    // 下标即优先级，正在运行的任务不在队列中
+    queues: Vec<VecDeque<Weak<Mutex<Process>>>>,
+    // 睡眠或者等待中的任务，被唤醒后放到对应队列的末尾
+    blocked: Vec<Weak<Mutex<Process>>>,
+}
+
+impl RtScheduler {
+    pub fn new() -> Self {
+        let mut queues = Vec::new();
+        queues.resize_with(RT_PRIO_MAX + 1, VecDeque::new);
+        Self {
+            queues,
+            blocked: Vec::new(),
+        }
+    }
+
+    fn prio(p: &Process) -> usize {
+        match p.policy {
+            SchedPolicy::Fifo(prio) | SchedPolicy::RoundRobin(prio) => prio,
+            _ => 0,
+        }
+    }
+
+    fn update_curr(p: &mut Process, now: usize) {
+        let delta = super::update_exec(p, now);
+        if let SchedPolicy::RoundRobin(_) = p.policy {
+            p.se.time_slice = p.se.time_slice.saturating_sub(delta);
+        }
+    }
+
+    fn wakeup(&mut self) {
+        let mut i = 0;
+        while i < self.blocked.len() {
+            if let Some(task) = self.blocked[i].upgrade() {
+                let mut p = task.lock();
+                if p.runnable() {
+                    let prio = Self::prio(&p);
+                    drop(p);
+                    self.queues[prio].push_back(self.blocked.swap_remove(i));
+                } else {
+                    i += 1;
+                }
+            } else {
+                self.blocked.swap_remove(i);
+            }
+        }
+    }
+
+    // 当前可运行任务的最高优先级，没有则返回 None
+    pub fn highest_prio(&mut self) -> Option<usize> {
+        self.wakeup();
+        for prio in (0..=RT_PRIO_MAX).rev() {
+            self.queues[prio].retain(|t| t.strong_count() > 0);
+            if !self.queues[prio].is_empty() {
+                return Some(prio);
+            }
+        }
+
+        None
+    }
+}
+
+impl Scheduler for RtScheduler {
+    fn add(&mut self, task: Weak<Mutex<Process>>) {
+        self.blocked.push(task);
+    }
+
+    fn fetch(&mut self) -> Option<Arc<Mutex<Process>>> {
+        let now = nanoseconds();
+        self.wakeup();
+
+        for prio in (0..=RT_PRIO_MAX).rev() {
+            while let Some(task) = self.queues[prio].pop_front() {
+                if let Some(p) = task.upgrade() {
+                    let mut proc = p.lock();
+                    if !proc.runnable() {
+                        self.blocked.push(task);
+                        continue;
+                    }
+
+                    proc.se.exec_start = now;
+                    if proc.se.time_slice == 0 {
+                        proc.se.time_slice = RR_TIMESLICE_NS;
+                    }
+                    drop(proc);
+                    return Some(p);
+                }
+            }
+        }
+
+        None
+    }
+
+    fn tick(&mut self, current: &Arc<Mutex<Process>>) -> bool {
+        let now = nanoseconds();
+        let mut p = current.lock();
+        Self::update_curr(&mut p, now);
+
+        if let SchedPolicy::RoundRobin(_) = p.policy {
+            if p.se.time_slice == 0 {
+                return true;
+            }
+        }
+
+        let prio = Self::prio(&p);
+        drop(p);
+        matches!(self.highest_prio(), Some(h) if h > prio)
+    }
+
+    fn put_prev(&mut self, task: &Arc<Mutex<Process>>) {
+        let now = nanoseconds();
+        let mut p = task.lock();
+        Self::update_curr(&mut p, now);
+        let prio = Self::prio(&p);
+        let weak = Arc::downgrade(task);
+
+        match p.status {
+            // 被抢占的任务放回队首，RR 时间片用完的任务放到队尾
+            ProcessStatus::RUNNING(_) => {
+                if let SchedPolicy::RoundRobin(_) = p.policy {
+                    if p.se.time_slice == 0 {
+                        p.se.time_slice = RR_TIMESLICE_NS;
+                        self.queues[prio].push_back(weak);
+                        return;
+                    }
+                }
+                self.queues[prio].push_front(weak);
+            }
+            ProcessStatus::EXITED(_) | ProcessStatus::ZOMBIE(_) => {}
+            // yield 或者睡眠，放到队尾
+            _ => {
+                if p.runnable() {
+                    self.queues[prio].push_back(weak);
+                } else {
+                    self.blocked.push(weak);
+                }
+            }
+        }
+    }
+
+    fn dequeue(&mut self, task: &Arc<Mutex<Process>>) {
+        for queue in self.queues.iter_mut() {
+            super::remove(queue, task);
+        }
+        let weak = Arc::downgrade(task);
+        self.blocked.retain(|t| !t.ptr_eq(&weak));
+    }
+}
@@ -0,0 +1,78 @@
+// spawn 不复制父进程的地址空间，直接从文件创建子进程，参考 posix_spawn
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use crate::file::fs::FILESYSTEM;
+use crate::file::{FileDesc, O_CLOEXEC};
+
+use super::rlimit::{ResourceLimits, RLIMIT_NOFILE};
+
+// SpawnAttr 的 flags，和 glibc 的 POSIX_SPAWN_SETPGROUP 相同
+pub const SPAWN_SETPGROUP: usize = 0x2;
+
+// SpawnFileAction 的 op
+pub const SPAWN_OPEN: usize = 0;
+pub const SPAWN_CLOSE: usize = 1;
+pub const SPAWN_DUP2: usize = 2;
+
+// 用户传入的属性，指针为空时使用默认值
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SpawnAttr {
+    pub flags: usize,
+    // SPAWN_SETPGROUP 时子进程加入的进程组，0 表示以子进程为首进程的新进程组
+    pub pgroup: usize,
+    // SpawnFileAction 数组和长度
+    pub actions: usize,
+    pub actions_len: usize,
+}
+
+// 用户传入的文件操作，open 时 arg 为 flags，path 为字符串地址，dup2 时 arg 为新的 fd
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SpawnFileAction {
+    pub op: usize,
+    pub fd: usize,
+    pub arg: usize,
+    pub path: usize,
+}
+
+// 在子进程的 fd 表上依次执行，和 posix_spawn_file_actions 相同
+pub enum SpawnAction {
+    Open(usize, String, usize),
+    Close(usize),
+    Dup2(usize, usize),
+}
+
+// 任何一个操作失败时 spawn 失败
+pub fn apply_actions(fds: &mut Vec<Option<FileDesc>>, actions: &[SpawnAction], rlimits: &ResourceLimits) -> Result<(), &'static str> {
+    for action in actions {
+        match action {
+            SpawnAction::Open(fd, path, flags) => {
+                let file = FILESYSTEM.lock().open(path).ok_or("file not found")?;
+                set_fd(fds, *fd, FileDesc::new(file, flags & O_CLOEXEC != 0), rlimits)?;
+            }
+            SpawnAction::Close(fd) => {
+                let desc = fds.get_mut(*fd).ok_or("bad file descriptor")?;
+                desc.take().ok_or("bad file descriptor")?;
+            }
+            SpawnAction::Dup2(fd, newfd) => {
+                let file = fds.get(*fd).cloned().flatten().ok_or("bad file descriptor")?.file;
+                // 和 dup2 一样，新的 fd 不带 O_CLOEXEC
+                set_fd(fds, *newfd, FileDesc::new(file, false), rlimits)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn set_fd(fds: &mut Vec<Option<FileDesc>>, fd: usize, desc: FileDesc, rlimits: &ResourceLimits) -> Result<(), &'static str> {
+    if !rlimits.allow(RLIMIT_NOFILE, fd + 1) {
+        return Err("too many open files");
+    }
+    if fd >= fds.len() {
+        fds.resize(fd + 1, None);
+    }
+    fds[fd] = Some(desc);
+    Ok(())
+}
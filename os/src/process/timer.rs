@@ -0,0 +1,273 @@
+use alloc::collections::BTreeMap;
+use alloc::sync::{Arc, Weak};
+use alloc::vec::Vec;
+use crate::sync::Mutex;
+
+use super::app::Process;
+use crate::ipc::signal::{SIGALRM, SIGPROF, SIGVTALRM};
+
+// clockid，和 linux 相同
+pub const CLOCK_REALTIME: usize = 0;
+pub const CLOCK_MONOTONIC: usize = 1;
+pub const CLOCK_PROCESS_CPUTIME_ID: usize = 2;
+pub const CLOCK_THREAD_CPUTIME_ID: usize = 3;
+// ITIMER_VIRTUAL 使用的线程组用户态时间，只在内核中使用，不能用于 clock_gettime
+pub const CLOCK_PROCESS_USERTIME: usize = 16;
+
+// setitimer 的 which
+pub const ITIMER_REAL: usize = 0;
+pub const ITIMER_VIRTUAL: usize = 1;
+pub const ITIMER_PROF: usize = 2;
+
+// clock_nanosleep 和 timer_settime 的 flags
+pub const TIMER_ABSTIME: usize = 1;
+
+// sigevent 的 sigev_notify，不支持 SIGEV_THREAD
+pub const SIGEV_SIGNAL: i32 = 0;
+pub const SIGEV_NONE: i32 = 1;
+
+pub const NSEC_PER_SEC: usize = 1_000_000_000;
+
+#[repr(C)]
+#[derive(Clone, Copy, Default, Debug)]
+pub struct TimeSpec {
+    pub tv_sec: usize,
+    pub tv_nsec: usize,
+}
+
+impl TimeSpec {
+    pub fn from_ns(ns: usize) -> Self {
+        Self { tv_sec: ns / NSEC_PER_SEC, tv_nsec: ns % NSEC_PER_SEC }
+    }
+
+    // tv_nsec 不合法时返回 None
+    pub fn to_ns(&self) -> Option<usize> {
+        if self.tv_nsec >= NSEC_PER_SEC {
+            return None;
+        }
+        Some(self.tv_sec * NSEC_PER_SEC + self.tv_nsec)
+    }
+}
+
+// setitimer 使用的时间，单位是 us
+#[repr(C)]
+#[derive(Clone, Copy, Default, Debug)]
+pub struct TimeVal {
+    pub tv_sec: usize,
+    pub tv_usec: usize,
+}
+
+impl TimeVal {
+    pub fn from_ns(ns: usize) -> Self {
+        Self { tv_sec: ns / NSEC_PER_SEC, tv_usec: ns % NSEC_PER_SEC / 1000 }
+    }
+
+    pub fn to_ns(&self) -> Option<usize> {
+        if self.tv_usec >= 1_000_000 {
+            return None;
+        }
+        Some(self.tv_sec * NSEC_PER_SEC + self.tv_usec * 1000)
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Default, Debug)]
+pub struct ITimerSpec {
+    pub it_interval: TimeSpec,
+    pub it_value: TimeSpec,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Default, Debug)]
+pub struct ITimerVal {
+    pub it_interval: TimeVal,
+    pub it_value: TimeVal,
+}
+
+// 只使用 linux sigevent 的前 16 个字节
+#[repr(C)]
+#[derive(Clone, Copy, Default, Debug)]
+pub struct SigEvent {
+    pub sigev_value: usize,
+    pub sigev_signo: i32,
+    pub sigev_notify: i32,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum TimerId {
+    // setitimer 的三个定时器
+    Itimer(usize),
+    // timer_create 创建的定时器
+    Posix(usize),
+}
+
+// setitimer 和 timer_create 的定时器
+// REALTIME 和 MONOTONIC 的定时器在 cpu 的定时器队列中等待到期，到期时间统一使用 MONOTONIC 时间
+// cpu 时间的定时器在时钟中断中检查，到期时间是进程或者线程的 cpu 时间
+#[derive(Clone, Copy, Debug)]
+pub struct IntervalTimer {
+    pub clock: usize,
+    // 到期时发送的信号，0 表示不发送 (SIGEV_NONE)
+    pub signal: usize,
+    // 下一次到期的时间，0 表示没有启动
+    pub expire: usize,
+    pub interval: usize,
+    // 上一次到期时错过的次数，即 timer_getoverrun
+    pub overrun: usize,
+    // CLOCK_THREAD_CPUTIME_ID 计时的线程
+    pub tid: usize,
+}
+
+impl IntervalTimer {
+    pub fn new(clock: usize, signal: usize, tid: usize) -> Self {
+        Self { clock, signal, expire: 0, interval: 0, overrun: 0, tid }
+    }
+
+    pub fn armed(&self) -> bool {
+        self.expire != 0
+    }
+
+    pub fn cpu_clock(&self) -> bool {
+        matches!(self.clock, CLOCK_PROCESS_CPUTIME_ID | CLOCK_THREAD_CPUTIME_ID | CLOCK_PROCESS_USERTIME)
+    }
+
+    // 到期后的处理，返回需要发送的信号
+    // 周期定时器在上一次的到期时间上累加间隔，而不是从现在开始，所以不会累积误差
+    pub fn fire(&mut self, now: usize) -> Option<usize> {
+        if self.interval > 0 {
+            let missed = now.saturating_sub(self.expire) / self.interval;
+            self.overrun = missed;
+            self.expire += (missed + 1) * self.interval;
+        } else {
+            self.overrun = 0;
+            self.expire = 0;
+        }
+        (self.signal != 0).then_some(self.signal)
+    }
+
+    // 距离到期还有多久，没有启动时为 0
+    pub fn remaining(&self, now: usize) -> usize {
+        if self.armed() {
+            self.expire.saturating_sub(now).max(1)
+        } else {
+            0
+        }
+    }
+}
+
+// 线程组共享的定时器，和 linux 一样，fork 不继承，exec 时删除 timer_create 的定时器
+pub struct ProcessTimers {
+    itimers: [IntervalTimer; 3],
+    posix: BTreeMap<usize, IntervalTimer>,
+    next_id: usize,
+}
+
+impl ProcessTimers {
+    pub fn new() -> Self {
+        Self {
+            itimers: [
+                IntervalTimer::new(CLOCK_MONOTONIC, SIGALRM, 0),
+                IntervalTimer::new(CLOCK_PROCESS_USERTIME, SIGVTALRM, 0),
+                IntervalTimer::new(CLOCK_PROCESS_CPUTIME_ID, SIGPROF, 0),
+            ],
+            posix: BTreeMap::new(),
+            next_id: 0,
+        }
+    }
+
+    pub fn get(&mut self, id: TimerId) -> Option<&mut IntervalTimer> {
+        match id {
+            TimerId::Itimer(which) => self.itimers.get_mut(which),
+            TimerId::Posix(id) => self.posix.get_mut(&id),
+        }
+    }
+
+    pub fn create(&mut self, timer: IntervalTimer) -> usize {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.posix.insert(id, timer);
+        id
+    }
+
+    pub fn delete(&mut self, id: usize) -> bool {
+        self.posix.remove(&id).is_some()
+    }
+
+    pub fn clear_posix(&mut self) {
+        self.posix.clear();
+    }
+
+    // 有启动的 cpu 时间定时器，没有时时钟中断中不需要统计线程组的 cpu 时间
+    pub fn cpu_armed(&self) -> bool {
+        self.itimers.iter().chain(self.posix.values()).any(|t| t.armed() && t.cpu_clock())
+    }
+
+    // 检查 cpu 时间的定时器，返回需要发送的信号
+    // process_time 和 user_time 是线程组的总 cpu 时间和用户态时间，thread_time 是线程 tid 的 cpu 时间
+    pub fn expire_cpu(&mut self, process_time: usize, user_time: usize, thread_time: usize, tid: usize) -> Vec<usize> {
+        let mut signals = Vec::new();
+        for timer in self.itimers.iter_mut().chain(self.posix.values_mut()) {
+            if !timer.armed() || !timer.cpu_clock() {
+                continue;
+            }
+            let now = match timer.clock {
+                CLOCK_THREAD_CPUTIME_ID if timer.tid == tid => thread_time,
+                CLOCK_THREAD_CPUTIME_ID => continue,
+                CLOCK_PROCESS_USERTIME => user_time,
+                _ => process_time,
+            };
+            if now >= timer.expire {
+                signals.extend(timer.fire(now));
+            }
+        }
+        signals
+    }
+}
+
+pub enum TimerEvent {
+    // 睡眠的任务到期
+    Wakeup(Weak<Mutex<Process>>),
+    // 进程的定时器到期，参数为线程组 id
+    Timer(Weak<Mutex<ProcessTimers>>, usize, TimerId),
+}
+
+// 定时器队列，按到期时间排序，每个 cpu 一个
+// 任务被信号等提前唤醒或者定时器被重新设置时，不会从队列中删除，到期时发现已经过期就忽略
+pub struct TimerQueue {
+    // (到期时间, 序号) -> event
+    queue: BTreeMap<(usize, usize), TimerEvent>,
+    seq: usize,
+}
+
+impl TimerQueue {
+    pub fn new() -> Self {
+        Self { queue: BTreeMap::new(), seq: 0 }
+    }
+
+    pub fn add(&mut self, deadline: usize, event: TimerEvent) {
+        self.seq += 1;
+        self.queue.insert((deadline, self.seq), event);
+    }
+
+    pub fn add_sleeper(&mut self, deadline: usize, task: &Arc<Mutex<Process>>) {
+        self.add(deadline, TimerEvent::Wakeup(Arc::downgrade(task)));
+    }
+
+    // 取出所有到期的事件
+    pub fn expire(&mut self, now: usize) -> Vec<(usize, TimerEvent)> {
+        let mut events = Vec::new();
+        while let Some(entry) = self.queue.first_entry() {
+            if entry.key().0 > now {
+                break;
+            }
+            let ((deadline, _), event) = entry.remove_entry();
+            events.push((deadline, event));
+        }
+        events
+    }
+
+    // 最早的到期时间，idle 时用来设置下一次时钟中断
+    pub fn next_deadline(&self) -> Option<usize> {
+        self.queue.first_key_value().map(|(k, _)| k.0)
+    }
+}
@@ -0,0 +1,7 @@
+// 内核使用的锁，在 spin 的锁外面维护每个 cpu 的 preempt 计数
+// 持有锁时关闭中断，所以中断处理函数不会在同一个 cpu 上再次获取已经持有的锁，内核也只会在没有持有锁时被抢占
+mod mutex;
+mod preempt;
+
+pub use mutex::{Mutex, MutexGuard, RwLock};
+pub use preempt::preempt_count;
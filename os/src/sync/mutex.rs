@@ -0,0 +1,130 @@
+use core::mem::ManuallyDrop;
+use core::ops::{Deref, DerefMut};
+
+use super::preempt::{preempt_disable, preempt_enable};
+
+// 和 spin::Mutex 的用法相同，获取锁之前禁止抢占，释放锁之后恢复
+pub struct Mutex<T: ?Sized> {
+    inner: spin::Mutex<T>,
+}
+
+pub struct MutexGuard<'a, T: ?Sized + 'a> {
+    // 必须先释放锁再恢复抢占
+    guard: ManuallyDrop<spin::MutexGuard<'a, T>>,
+}
+
+impl<T> Mutex<T> {
+    pub const fn new(data: T) -> Self {
+        Self { inner: spin::Mutex::new(data) }
+    }
+}
+
+impl<T: ?Sized> Mutex<T> {
+    pub fn lock(&self) -> MutexGuard<'_, T> {
+        preempt_disable();
+        MutexGuard { guard: ManuallyDrop::new(self.inner.lock()) }
+    }
+
+    // 锁已经被持有时返回 None，不会等待
+    pub fn try_lock(&self) -> Option<MutexGuard<'_, T>> {
+        preempt_disable();
+        match self.inner.try_lock() {
+            Some(guard) => Some(MutexGuard { guard: ManuallyDrop::new(guard) }),
+            None => {
+                preempt_enable();
+                None
+            }
+        }
+    }
+}
+
+impl<T: Default> Default for Mutex<T> {
+    fn default() -> Self {
+        Self::new(T::default())
+    }
+}
+
+impl<'a, T: ?Sized> Deref for MutexGuard<'a, T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        &self.guard
+    }
+}
+
+impl<'a, T: ?Sized> DerefMut for MutexGuard<'a, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.guard
+    }
+}
+
+impl<'a, T: ?Sized> Drop for MutexGuard<'a, T> {
+    fn drop(&mut self) {
+        unsafe { ManuallyDrop::drop(&mut self.guard); }
+        preempt_enable();
+    }
+}
+
+// 和 spin::RwLock 的用法相同，读写都禁止抢占
+pub struct RwLock<T: ?Sized> {
+    inner: spin::RwLock<T>,
+}
+
+pub struct RwLockReadGuard<'a, T: ?Sized + 'a> {
+    guard: ManuallyDrop<spin::RwLockReadGuard<'a, T>>,
+}
+
+pub struct RwLockWriteGuard<'a, T: ?Sized + 'a> {
+    guard: ManuallyDrop<spin::RwLockWriteGuard<'a, T>>,
+}
+
+impl<T> RwLock<T> {
+    pub const fn new(data: T) -> Self {
+        Self { inner: spin::RwLock::new(data) }
+    }
+}
+
+impl<T: ?Sized> RwLock<T> {
+    pub fn read(&self) -> RwLockReadGuard<'_, T> {
+        preempt_disable();
+        RwLockReadGuard { guard: ManuallyDrop::new(self.inner.read()) }
+    }
+
+    pub fn write(&self) -> RwLockWriteGuard<'_, T> {
+        preempt_disable();
+        RwLockWriteGuard { guard: ManuallyDrop::new(self.inner.write()) }
+    }
+}
+
+impl<'a, T: ?Sized> Deref for RwLockReadGuard<'a, T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        &self.guard
+    }
+}
+
+impl<'a, T: ?Sized> Drop for RwLockReadGuard<'a, T> {
+    fn drop(&mut self) {
+        unsafe { ManuallyDrop::drop(&mut self.guard); }
+        preempt_enable();
+    }
+}
+
+impl<'a, T: ?Sized> Deref for RwLockWriteGuard<'a, T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        &self.guard
+    }
+}
+
+impl<'a, T: ?Sized> DerefMut for RwLockWriteGuard<'a, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.guard
+    }
+}
+
+impl<'a, T: ?Sized> Drop for RwLockWriteGuard<'a, T> {
+    fn drop(&mut self) {
+        unsafe { ManuallyDrop::drop(&mut self.guard); }
+        preempt_enable();
+    }
+}
@@ -0,0 +1,37 @@
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+use crate::arch::{cpu_id, irq_restore, irq_save};
+use crate::board::CPU_NUM;
+
+struct Preempt {
+    // 嵌套的次数，不为 0 时中断是关闭的
+    count: AtomicUsize,
+    // 第一次 preempt_disable 之前中断是否打开，计数回到 0 时恢复
+    irq: AtomicBool,
+}
+
+const PREEMPT_INIT: Preempt = Preempt { count: AtomicUsize::new(0), irq: AtomicBool::new(false) };
+
+// 下标为 cpu id，只会被自己的 cpu 访问
+static PREEMPT: [Preempt; CPU_NUM] = [PREEMPT_INIT; CPU_NUM];
+
+// 关闭中断并且禁止抢占，可以嵌套
+pub fn preempt_disable() {
+    let irq = irq_save();
+    let p = &PREEMPT[cpu_id()];
+    if p.count.fetch_add(1, Ordering::Relaxed) == 0 {
+        p.irq.store(irq, Ordering::Relaxed);
+    }
+}
+
+pub fn preempt_enable() {
+    let p = &PREEMPT[cpu_id()];
+    if p.count.fetch_sub(1, Ordering::Relaxed) == 1 {
+        irq_restore(p.irq.load(Ordering::Relaxed));
+    }
+}
+
+// 当前 cpu 上持有的锁的数量，为 0 时才可以切换任务
+pub fn preempt_count() -> usize {
+    PREEMPT[cpu_id()].count.load(Ordering::Relaxed)
+}
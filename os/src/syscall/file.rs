@@ -11,9 +11,12 @@ pub fn sys_read(fd: usize, buf: *mut u8, len: usize) -> isize {
     read(fd, buf, len)
 }
 
-pub fn sys_open(name: *const i8, _len: usize) -> isize {
-    let str = copy_str_with_user(name);
-    open(str)
+// flags 只支持 O_CLOEXEC
+pub fn sys_open(name: *const i8, flags: usize) -> isize {
+    let Some(str) = copy_str_with_user(name) else {
+        return -1;
+    };
+    open(str, flags)
 }
 
 pub fn sys_lseek(fd: usize, seek: usize) -> isize {
@@ -25,11 +28,11 @@ pub fn sys_size(fd: usize) -> isize {
 }
 
 pub fn sys_create_pipe(buf: *mut usize) -> isize {
-    unsafe {
-        let arr = core::slice::from_raw_parts_mut(buf, 2);
-        let (read_end, write_end) = create_pipe(4096);
-        copy_usize_with_user(read_end, &mut arr[0]);
-        copy_usize_with_user(write_end, &mut arr[1]);
-        0
+    let Some((read_end, write_end)) = create_pipe(4096) else {
+        return -1;
+    };
+    if !copy_usize_with_user(read_end, buf) || !copy_usize_with_user(write_end, buf.wrapping_add(1)) {
+        return -1;
     }
+    0
 } 
\ No newline at end of file
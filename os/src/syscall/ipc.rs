@@ -21,53 +21,73 @@ use crate::{
 };
 
 pub fn sys_shm_open(name: *const i8, size: usize, permission: usize) -> isize {
-    let str = copy_str_with_user(name);
+    let Some(str) = copy_str_with_user(name) else {
+        return -1;
+    };
     shm_open(str, size, permission)
 }
 
 pub fn sys_sem_open(name: *const i8) -> isize {
-    let str = copy_str_with_user(name);
+    let Some(str) = copy_str_with_user(name) else {
+        return -1;
+    };
     sem_open(str)
 }
 
 pub fn sys_sem_wait(name: *const i8) -> isize {
-    let str = copy_str_with_user(name);
+    let Some(str) = copy_str_with_user(name) else {
+        return -1;
+    };
     sem_wait(str)
 }
 
 pub fn sys_sem_raise(name: *const i8) -> isize {
-    let str = copy_str_with_user(name);
+    let Some(str) = copy_str_with_user(name) else {
+        return -1;
+    };
     sem_raise(str)
 }
 
 pub fn sys_create_server(name: *const i8) -> isize {
-    let str = copy_str_with_user(name);
+    let Some(str) = copy_str_with_user(name) else {
+        return -1;
+    };
     create_server(str)
 }
 
 pub fn sys_connect_server(name: *const i8) -> isize {
-    let str = copy_str_with_user(name);
+    let Some(str) = copy_str_with_user(name) else {
+        return -1;
+    };
     connect_server(str)
 }
 
 pub fn sys_request(coid: usize, req: *const u8, req_len: usize, resp: *mut u8) -> isize {
-    let req_data = copy_from_user_into_vector(req, req_len);
+    let Some(req_data) = copy_from_user_into_vector(req, req_len) else {
+        return -1;
+    };
     if let Some(resp_data) = request(coid, Arc::new(req_data)) {
         // 确保数据在内核堆中已经被丢弃释放
         let raw_vec = Arc::try_unwrap(resp_data).unwrap();
-        copy_vector_to_user(raw_vec, resp) as isize
+        copy_vector_to_user(raw_vec, resp).map_or(-1, |len| len as isize)
     } else {
         -1
     }
 }
 
 pub fn sys_recv_request(name: *const i8, req: *mut u8, req_len: *mut usize, timeout_ms: usize) -> isize {
-    let str = copy_str_with_user(name);
+    let Some(str) = copy_str_with_user(name) else {
+        return -1;
+    };
     if let Some(req_data) = recv_request(str, timeout_ms) {
         // 确保数据在内核堆中已经被丢弃释放
         let raw_vec = Arc::try_unwrap(req_data.1).unwrap();
-        let len = copy_vector_to_user(raw_vec, req);
-        copy_usize_with_user(len, req_len);
+        let Some(len) = copy_vector_to_user(raw_vec, req) else {
+            return -1;
+        };
+        if !copy_usize_with_user(len, req_len) {
+            return -1;
+        }
         req_data.0 as isize
     } else {
         -1
@@ -75,6 +95,8 @@ pub fn sys_recv_request(name: *const i8, req: *mut u8, req_len: *mut usize, time
 }
 
 pub fn sys_replay_request(rcvid: usize, resp: *const u8, resp_len: usize) -> isize {
-    let resp_data = copy_from_user_into_vector(resp, resp_len);
+    let Some(resp_data) = copy_from_user_into_vector(resp, resp_len) else {
+        return -1;
+    };
     reply_request(rcvid, Arc::new(resp_data))
 }
\ No newline at end of file
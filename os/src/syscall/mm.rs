@@ -1,4 +1,13 @@
-use crate::process::{mmap, mmap_with_addr, ummap};
+use core::mem::size_of;
+
+use crate::arch::memory::copy::copy_with_user;
+use crate::mm::allocator::{zone_info, Zone, ZoneInfo};
+use crate::process::{allow_wx, brk, mmap, mmap_file, mmap_with_addr, mprotect, msync, set_allow_wx, ummap};
+
+// prctl 只支持 W^X 的选项，和 linux 的 MDWE 不同，默认就不允许同时可写和可执行的页
+const PR_SET_MDWE: usize = 65;
+const PR_GET_MDWE: usize = 66;
+const PR_MDWE_REFUSE_EXEC_GAIN: usize = 1;
 
 pub fn sys_mmap(size: usize, permission: usize) -> isize {
     mmap(size, permission)
@@ -8,6 +17,52 @@ pub fn sys_ummap(addr: usize) -> isize {
     ummap(addr)
 }
 
+// 返回映射的地址，flags 为 MAP_SHARED 或者 MAP_PRIVATE
+pub fn sys_mmap_file(fd: usize, offset: usize, len: usize, permission: usize, flags: usize) -> isize {
+    mmap_file(fd, offset, len, permission, flags)
+}
+
+// 把 [addr, addr + len) 所在的文件映射的脏页写回文件
+pub fn sys_msync(addr: usize, len: usize) -> isize {
+    msync(addr, len)
+}
+
+// 改变 [addr, addr + len) 的权限，addr 需要页对齐
+pub fn sys_mprotect(addr: usize, len: usize, permission: usize) -> isize {
+    mprotect(addr, len, permission)
+}
+
+// PR_SET_MDWE 的参数为 0 时允许同时可写和可执行的页，fork 时继承，exec 时恢复默认
+pub fn sys_prctl(option: usize, arg: usize) -> isize {
+    match option {
+        PR_SET_MDWE => {
+            set_allow_wx(arg & PR_MDWE_REFUSE_EXEC_GAIN == 0);
+            0
+        }
+        PR_GET_MDWE => if allow_wx() { 0 } else { PR_MDWE_REFUSE_EXEC_GAIN as isize },
+        _ => -1,
+    }
+}
+
+// zone 为 0 (DMA) 或 1 (Normal)，把这个区域的页帧统计写入 info
+pub fn sys_meminfo(zone: usize, info: *mut ZoneInfo) -> isize {
+    match Zone::from_index(zone) {
+        Some(zone) => {
+            let v = zone_info(zone);
+            if !unsafe { copy_with_user(info as *mut u8, &v as *const ZoneInfo as *const u8, size_of::<ZoneInfo>()) } {
+                return -1;
+            }
+            0
+        }
+        None => -1,
+    }
+}
+
+// addr 为 0 时返回当前的 brk
+pub fn sys_brk(addr: usize) -> isize {
+    brk(addr)
+}
+
 pub fn sys_mmap_with_addr(pa: usize, size: usize, permission: usize) -> isize {
     mmap_with_addr(pa, size, permission, true)
 }
\ No newline at end of file
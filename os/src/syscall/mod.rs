@@ -10,6 +10,16 @@ const SYSCALL_SIZE: usize = 5;
 const SYSCALL_MMAP: usize = 9;
 const SYSCALL_UMMAP: usize = 10;
 const SYSCALL_MMAP_WITH_ADDR: usize = 11;
+// linux 的 mmap 是 9，这里已经被匿名映射使用，文件映射使用 mincore 的 27
+const SYSCALL_MMAP_FILE: usize = 27;
+const SYSCALL_MSYNC: usize = 26;
+// linux x86_64 的 mprotect 是 10，已经被 SYSCALL_UMMAP 使用，riscv64/aarch64 的 226 也被使用，这里用 pkey_mprotect 的 329
+const SYSCALL_MPROTECT: usize = 329;
+const SYSCALL_PRCTL: usize = 157;
+// linux 的 sysinfo 是 99，这里返回页帧分配器一个区域的统计
+const SYSCALL_MEMINFO: usize = 99;
+// linux x86_64 的 brk 是 12，这里已经被 SYSCALL_SIG 使用，和 riscv64/aarch64 一样使用 214
+const SYSCALL_BRK: usize = 214;
 const SYSCALL_SIG: usize = 12;
 const SYSCALL_SIGACTION: usize = 13;
 const SYSCALL_SIGPROCMASK: usize = 14;
@@ -17,9 +27,14 @@ const SYSCALL_SIGRETURN: usize = 15;
 const SYSCALL_PIPE: usize = 22;
 const SYSCALL_YIELD: usize = 24;
 const SYSCALL_NANOSLEEP: usize = 35;
+const SYSCALL_GETITIMER: usize = 36;
+const SYSCALL_ALARM: usize = 37;
+const SYSCALL_SETITIMER: usize = 38;
 const SYSCALL_GETPID: usize = 39;
+const SYSCALL_CLONE: usize = 56;
 const SYSCALL_FORK: usize = 57;
-const SYSCALL_EXEC: usize = 59;
+const SYSCALL_VFORK: usize = 58;
+const SYSCALL_EXECVE: usize = 59;
 const SYSCALL_EXIT: usize = 60;
 const SYSCALL_WAIT: usize = 61;
 const SYSCALL_KILL: usize = 62;
@@ -32,27 +47,69 @@ const SYSCALL_SRV_CONNECT: usize = 91;
 const SYSCALL_SRV_REQUEST: usize = 92;
 const SYSCALL_SRV_RECV: usize = 93;
 const SYSCALL_SRV_REPLY: usize = 94;
+const SYSCALL_SPAWN: usize = 95;
+const SYSCALL_GETRLIMIT: usize = 97;
+const SYSCALL_GETRUSAGE: usize = 98;
+const SYSCALL_TIMES: usize = 100;
+const SYSCALL_SETPGID: usize = 109;
+const SYSCALL_GETPPID: usize = 110;
+const SYSCALL_SETSID: usize = 112;
+const SYSCALL_GETPGID: usize = 121;
+const SYSCALL_GETSID: usize = 124;
+const SYSCALL_GETPRIORITY: usize = 140;
+const SYSCALL_SETPRIORITY: usize = 141;
+const SYSCALL_SCHED_GETPARAM: usize = 143;
+const SYSCALL_SCHED_SETSCHEDULER: usize = 144;
+const SYSCALL_SCHED_GETSCHEDULER: usize = 145;
+const SYSCALL_SETRLIMIT: usize = 160;
+const SYSCALL_GETTID: usize = 186;
+const SYSCALL_TIMER_CREATE: usize = 222;
+const SYSCALL_TIMER_SETTIME: usize = 223;
+const SYSCALL_TIMER_GETTIME: usize = 224;
+const SYSCALL_TIMER_GETOVERRUN: usize = 225;
+const SYSCALL_TIMER_DELETE: usize = 226;
+const SYSCALL_CLOCK_GETTIME: usize = 228;
+const SYSCALL_CLOCK_GETRES: usize = 229;
+const SYSCALL_CLOCK_NANOSLEEP: usize = 230;
+const SYSCALL_EXIT_GROUP: usize = 231;
+const SYSCALL_PRLIMIT64: usize = 302;
+const SYSCALL_GETCPU: usize = 309;
 
 mod file;
 mod process;
 mod mm;
 mod ipc;
+mod time;
 
 use file::*;
 use process::*;
 use mm::*;
 use ipc::*;
+use time::*;
 
-pub fn syscall(id: usize, args: [usize; 4]) -> isize {
+use crate::mm::allocator::ZoneInfo;
+use crate::process::rlimit::RLimit;
+use crate::process::rusage::{RUsage, Tms};
+use crate::process::scheduler::SchedParam;
+use crate::process::spawn::SpawnAttr;
+use crate::process::timer::{ITimerSpec, ITimerVal, SigEvent, TimeSpec};
+
+pub fn syscall(id: usize, args: [usize; 6]) -> isize {
     match id {
         SYSCALL_READ => sys_read(args[0], args[1] as *mut u8, args[2]),
         SYSCALL_WRITE => sys_write(args[0], args[1] as *const u8, args[2]),
-        SYSCALL_OPEN => sys_open(args[0] as *const i8, args[1] as usize),
+        SYSCALL_OPEN => sys_open(args[0] as *const i8, args[1]),
         SYSCALL_LSEEK => sys_lseek(args[0], args[1]),
         SYSCALL_SIZE => sys_size(args[0]),
         SYSCALL_MMAP => sys_mmap(args[0] as usize, args[1] as usize),
         SYSCALL_UMMAP => sys_ummap(args[0]),
         SYSCALL_MMAP_WITH_ADDR => sys_mmap_with_addr(args[0], args[1], args[2]),
+        SYSCALL_MMAP_FILE => sys_mmap_file(args[0], args[1], args[2], args[3], args[4]),
+        SYSCALL_MSYNC => sys_msync(args[0], args[1]),
+        SYSCALL_BRK => sys_brk(args[0]),
+        SYSCALL_MPROTECT => sys_mprotect(args[0], args[1], args[2]),
+        SYSCALL_PRCTL => sys_prctl(args[0], args[1]),
+        SYSCALL_MEMINFO => sys_meminfo(args[0], args[1] as *mut ZoneInfo),
         SYSCALL_SIG => sys_set_signal(args[0], args[1]),
         SYSCALL_SIGACTION => sys_sigaction(args[0], args[1]),
         SYSCALL_SIGPROCMASK => sys_set_signalmask(args[0]),
@@ -61,11 +118,21 @@ pub fn syscall(id: usize, args: [usize; 4]) -> isize {
         SYSCALL_YIELD => {sys_yield(); 0},
         SYSCALL_NANOSLEEP => {sys_nanosleep(args[0] as usize); 0},
         SYSCALL_FORK => {sys_fork()},
-        SYSCALL_EXEC => {sys_exec(args[0] as *mut u8, args[1])},
-        SYSCALL_WAIT => {sys_wait(args[0] as isize)},
+        SYSCALL_VFORK => sys_vfork(),
+        SYSCALL_SPAWN => sys_spawn(args[0] as *const i8, args[1] as *const usize, args[2] as *const usize, args[3] as *const SpawnAttr),
+        SYSCALL_EXECVE => sys_execve(args[0] as *const i8, args[1] as *const usize, args[2] as *const usize),
+        SYSCALL_WAIT => sys_wait(args[0] as isize, args[1] as *mut i32, args[2], args[3] as *mut RUsage),
         SYSCALL_PIPE => sys_create_pipe(args[0] as *mut usize),
         SYSCALL_GETPID => sys_getpid(),
-        SYSCALL_KILL => sys_kill(args[0] as usize, args[1] as usize),
+        SYSCALL_GETPPID => sys_getppid(),
+        SYSCALL_CLONE => sys_clone(args[0], args[1], args[2], args[3], args[4]),
+        SYSCALL_GETTID => sys_gettid(),
+        SYSCALL_EXIT_GROUP => sys_exit_group(args[0] as isize),
+        SYSCALL_KILL => sys_kill(args[0] as isize, args[1]),
+        SYSCALL_SETPGID => sys_setpgid(args[0], args[1]),
+        SYSCALL_GETPGID => sys_getpgid(args[0]),
+        SYSCALL_SETSID => sys_setsid(),
+        SYSCALL_GETSID => sys_getsid(args[0]),
         SYSCALL_SHM_OPEN => sys_shm_open(args[0] as *const i8, args[1], args[2]),
         SYSCALL_SEM_OPEN => sys_sem_open(args[0] as *const i8),
         SYSCALL_SEM_WAIT => sys_sem_wait(args[0] as *const i8),
@@ -75,6 +142,28 @@ pub fn syscall(id: usize, args: [usize; 4]) -> isize {
         SYSCALL_SRV_REQUEST => sys_request(args[0], args[1] as *const u8, args[2], args[3] as *mut u8),
         SYSCALL_SRV_RECV => sys_recv_request(args[0] as *const i8, args[1] as *mut u8, args[2] as *mut usize, args[3]),
         SYSCALL_SRV_REPLY => sys_replay_request(args[0], args[1] as *const u8, args[2]),
+        SYSCALL_GETPRIORITY => sys_getpriority(args[0], args[1]),
+        SYSCALL_SETPRIORITY => sys_setpriority(args[0], args[1], args[2] as isize),
+        SYSCALL_SCHED_GETPARAM => sys_sched_getparam(args[0], args[1] as *mut SchedParam),
+        SYSCALL_SCHED_SETSCHEDULER => sys_sched_setscheduler(args[0], args[1], args[2] as *const SchedParam),
+        SYSCALL_SCHED_GETSCHEDULER => sys_sched_getscheduler(args[0]),
+        SYSCALL_GETCPU => sys_getcpu(args[0] as *mut usize, args[1] as *mut usize),
+        SYSCALL_CLOCK_GETTIME => sys_clock_gettime(args[0], args[1] as *mut TimeSpec),
+        SYSCALL_CLOCK_GETRES => sys_clock_getres(args[0], args[1] as *mut TimeSpec),
+        SYSCALL_CLOCK_NANOSLEEP => sys_clock_nanosleep(args[0], args[1], args[2] as *const TimeSpec, args[3] as *mut TimeSpec),
+        SYSCALL_ALARM => sys_alarm(args[0]),
+        SYSCALL_SETITIMER => sys_setitimer(args[0], args[1] as *const ITimerVal, args[2] as *mut ITimerVal),
+        SYSCALL_GETITIMER => sys_getitimer(args[0], args[1] as *mut ITimerVal),
+        SYSCALL_TIMER_CREATE => sys_timer_create(args[0], args[1] as *const SigEvent, args[2] as *mut usize),
+        SYSCALL_TIMER_SETTIME => sys_timer_settime(args[0], args[1], args[2] as *const ITimerSpec, args[3] as *mut ITimerSpec),
+        SYSCALL_TIMER_GETTIME => sys_timer_gettime(args[0], args[1] as *mut ITimerSpec),
+        SYSCALL_TIMER_GETOVERRUN => sys_timer_getoverrun(args[0]),
+        SYSCALL_TIMER_DELETE => sys_timer_delete(args[0]),
+        SYSCALL_TIMES => sys_times(args[0] as *mut Tms),
+        SYSCALL_GETRUSAGE => sys_getrusage(args[0] as isize, args[1] as *mut RUsage),
+        SYSCALL_GETRLIMIT => sys_getrlimit(args[0], args[1] as *mut RLimit),
+        SYSCALL_SETRLIMIT => sys_setrlimit(args[0], args[1] as *const RLimit),
+        SYSCALL_PRLIMIT64 => sys_prlimit(args[0], args[1], args[2] as *const RLimit, args[3] as *mut RLimit),
         _ => panic!("Unsupported syscall id: {}", id),
     }
 }
\ No newline at end of file
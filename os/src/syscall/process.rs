@@ -1,12 +1,26 @@
+use core::mem::size_of;
+
+use alloc::string::String;
+use alloc::vec::Vec;
+
 use crate::{
-    arch::memory::copy::copy_from_user_into_vector, 
-    mm::area::UserBuffer, process::*
+    arch::{cpu_id, memory::copy::{copy_str_with_user, copy_usize_with_user, copy_with_user}}, 
+    mm::area::UserBuffer, process::*,
+    process::app::CloneFlags,
+    process::rlimit::RLimit,
+    process::rusage::RUsage,
+    process::spawn::*,
+    process::scheduler::{SchedParam, PRIO_PROCESS}
 };
 
 pub fn sys_exit(code: isize) -> ! {
     exit(code as isize);
 }
 
+pub fn sys_exit_group(code: isize) -> ! {
+    exit_group(code)
+}
+
 pub fn sys_yield() {
     sleep(0);
 }
@@ -21,13 +35,91 @@ pub fn sys_fork() -> isize {
     fork()
 }
 
-pub fn sys_exec(addr: *mut u8, len: usize) -> isize {
-    let user_buf = copy_from_user_into_vector(addr, len);
-    exec(&user_buf.as_slice())
+// argv 和 envp 最多的字符串个数
+const MAX_ARG_STRINGS: usize = 256;
+
+// spawn 最多的文件操作个数
+const MAX_SPAWN_ACTIONS: usize = 64;
+
+// 读取用户态以 NULL 结尾的字符串指针数组，数组指针为空时返回空数组
+// 参数太多或者访问了无效的用户地址时返回 None
+fn copy_str_array(ptr: *const usize) -> Option<Vec<String>> {
+    let mut strs = Vec::new();
+    if ptr.is_null() {
+        return Some(strs);
+    }
+    for i in 0..MAX_ARG_STRINGS {
+        let mut addr = 0usize;
+        if !unsafe { copy_with_user(&mut addr as *mut usize as *mut u8, ptr.wrapping_add(i) as *const u8, size_of::<usize>()) } {
+            return None;
+        }
+        if addr == 0 {
+            return Some(strs);
+        }
+        strs.push(copy_str_with_user(addr as *const i8)?);
+    }
+    None
 }
 
-pub fn sys_wait(pid: isize) -> isize {
-    wait(pid)
+pub fn sys_execve(path: *const i8, argv: *const usize, envp: *const usize) -> isize {
+    let Some(path) = copy_str_with_user(path) else {
+        return -1;
+    };
+    let (Some(argv), Some(envp)) = (copy_str_array(argv), copy_str_array(envp)) else {
+        println!("[kernel] exec {}: too many or invalid arguments", path);
+        return -1;
+    };
+    exec(&path, &argv, &envp)
+}
+
+// vfork 的子进程和父进程共享用户栈，用户态需要在子进程中直接 exec 或者 exit，不能从调用者返回
+pub fn sys_vfork() -> isize {
+    vfork()
+}
+
+// attr 为空时使用默认属性，成功时返回子进程的 pid
+pub fn sys_spawn(path: *const i8, argv: *const usize, envp: *const usize, attr: *const SpawnAttr) -> isize {
+    let Some(path) = copy_str_with_user(path) else {
+        return -1;
+    };
+    let (Some(argv), Some(envp)) = (copy_str_array(argv), copy_str_array(envp)) else {
+        println!("[kernel] spawn {}: too many or invalid arguments", path);
+        return -1;
+    };
+    let mut a = SpawnAttr::default();
+    if !attr.is_null() && !unsafe { copy_with_user(&mut a as *mut SpawnAttr as *mut u8, attr as *const u8, size_of::<SpawnAttr>()) } {
+        return -1;
+    }
+    if a.actions_len > MAX_SPAWN_ACTIONS {
+        println!("[kernel] spawn {}: too many file actions", path);
+        return -1;
+    }
+
+    let mut actions = Vec::with_capacity(a.actions_len);
+    for i in 0..a.actions_len {
+        let mut action = SpawnFileAction::default();
+        let ptr = (a.actions as *const SpawnFileAction).wrapping_add(i);
+        if !unsafe { copy_with_user(&mut action as *mut SpawnFileAction as *mut u8, ptr as *const u8, size_of::<SpawnFileAction>()) } {
+            return -1;
+        }
+        actions.push(match action.op {
+            SPAWN_OPEN => match copy_str_with_user(action.path as *const i8) {
+                Some(path) => SpawnAction::Open(action.fd, path, action.arg),
+                None => return -1,
+            },
+            SPAWN_CLOSE => SpawnAction::Close(action.fd),
+            SPAWN_DUP2 => SpawnAction::Dup2(action.fd, action.arg),
+            _ => return -1,
+        });
+    }
+
+    let pgroup = if a.flags & SPAWN_SETPGROUP != 0 { Some(a.pgroup) } else { None };
+    spawn(&path, &argv, &envp, &actions, pgroup)
+}
+
+// 即 wait4，rusage 不为空时写入回收的子进程的资源统计
+pub fn sys_wait(pid: isize, status: *mut i32, options: usize, rusage: *mut RUsage) -> isize {
+    wait(pid, status, options, rusage)
 }
 
 pub fn sys_sigaction(signal: usize, handler: usize) -> isize {
@@ -51,6 +143,120 @@ pub fn sys_getpid() -> isize {
     getpid() as isize
 }
 
-pub fn sys_kill(pid: usize, signal: usize) -> isize {
-    set_signal(Some(pid), signal)
-}
\ No newline at end of file
+pub fn sys_getppid() -> isize {
+    getppid() as isize
+}
+
+pub fn sys_gettid() -> isize {
+    gettid() as isize
+}
+
+// 和 linux 不同，cpu 和 node 都按 usize 写入，node 总是 0
+pub fn sys_getcpu(cpu: *mut usize, node: *mut usize) -> isize {
+    if !cpu.is_null() && !copy_usize_with_user(cpu_id(), cpu) {
+        return -1;
+    }
+    if !node.is_null() && !copy_usize_with_user(0, node) {
+        return -1;
+    }
+    0
+}
+
+// 参数顺序和 linux x86_64 相同
+pub fn sys_clone(flags: usize, stack: usize, parent_tid: usize, child_tid: usize, tls: usize) -> isize {
+    clone(CloneFlags::from_bits_truncate(flags), stack, parent_tid, child_tid, tls)
+}
+
+pub fn sys_kill(pid: isize, signal: usize) -> isize {
+    kill(pid, signal)
+}
+
+pub fn sys_setpgid(pid: usize, pgid: usize) -> isize {
+    setpgid(pid, pgid)
+}
+
+pub fn sys_getpgid(pid: usize) -> isize {
+    getpgid(pid)
+}
+
+pub fn sys_setsid() -> isize {
+    setsid()
+}
+
+pub fn sys_getsid(pid: usize) -> isize {
+    getsid(pid)
+}
+
+// who 为 0 表示当前进程
+pub fn sys_setpriority(which: usize, who: usize, prio: isize) -> isize {
+    if which != PRIO_PROCESS {
+        return -1;
+    }
+    set_priority(who, prio)
+}
+
+pub fn sys_getpriority(which: usize, who: usize) -> isize {
+    if which != PRIO_PROCESS {
+        return -1;
+    }
+    get_priority(who)
+}
+
+// pid 为 0 表示当前进程，deadline 任务的参数也通过 param 传入
+pub fn sys_sched_setscheduler(pid: usize, policy: usize, param: *const SchedParam) -> isize {
+    let mut p = SchedParam::default();
+    if !unsafe { copy_with_user(&mut p as *mut _ as *mut u8, param as *const u8, size_of::<SchedParam>()) } {
+        return -1;
+    }
+    set_scheduler(pid, policy, p)
+}
+
+pub fn sys_sched_getscheduler(pid: usize) -> isize {
+    match get_scheduler(pid) {
+        Some(policy) => policy.policy() as isize,
+        None => -1,
+    }
+}
+
+pub fn sys_sched_getparam(pid: usize, param: *mut SchedParam) -> isize {
+    match get_scheduler(pid) {
+        Some(policy) => {
+            let p = policy.param();
+            if !unsafe { copy_with_user(param as *mut u8, &p as *const _ as *const u8, size_of::<SchedParam>()) } {
+                return -1;
+            }
+            0
+        }
+        None => -1,
+    }
+}
+
+// pid 为 0 表示当前进程，new 不为空时设置新的限制，old 不为空时写入之前的限制
+pub fn sys_prlimit(pid: usize, resource: usize, new: *const RLimit, old: *mut RLimit) -> isize {
+    let new = if new.is_null() {
+        None
+    } else {
+        let mut limit = RLimit::new(0, 0);
+        if !unsafe { copy_with_user(&mut limit as *mut _ as *mut u8, new as *const u8, size_of::<RLimit>()) } {
+            return -1;
+        }
+        Some(limit)
+    };
+    match prlimit(pid, resource, new) {
+        Some(limit) => {
+            if !old.is_null() && !unsafe { copy_with_user(old as *mut u8, &limit as *const _ as *const u8, size_of::<RLimit>()) } {
+                return -1;
+            }
+            0
+        }
+        None => -1,
+    }
+}
+
+pub fn sys_getrlimit(resource: usize, limit: *mut RLimit) -> isize {
+    sys_prlimit(0, resource, core::ptr::null(), limit)
+}
+
+pub fn sys_setrlimit(resource: usize, limit: *const RLimit) -> isize {
+    sys_prlimit(0, resource, limit, core::ptr::null_mut())
+}
@@ -0,0 +1,161 @@
+use core::mem::size_of;
+
+use crate::{
+    arch::memory::copy::copy_with_user,
+    board::timer::{nanoseconds, realtime_offset, resolution},
+    ipc::signal::{SIGALRM, SIG_NUM},
+    process::{clock_gettime, get_timer, getrusage, set_timer, sleep_until, timer_create, timer_delete, timer_getoverrun, times},
+    process::rusage::{ns_to_clock, RUsage, Tms},
+    process::timer::*,
+};
+
+// 访问无效的用户地址时返回 None
+fn read_user<T: Default>(src: *const T) -> Option<T> {
+    let mut v = T::default();
+    unsafe { copy_with_user(&mut v as *mut T as *mut u8, src as *const u8, size_of::<T>()) }.then_some(v)
+}
+
+// dst 为空时不写入，返回 0，访问无效的用户地址时返回 -1
+fn write_user<T>(v: T, dst: *mut T) -> isize {
+    if !dst.is_null() && !unsafe { copy_with_user(dst as *mut u8, &v as *const T as *const u8, size_of::<T>()) } {
+        return -1;
+    }
+    0
+}
+
+pub fn sys_clock_gettime(clock: usize, tp: *mut TimeSpec) -> isize {
+    match clock_gettime(clock) {
+        Some(ns) => write_user(TimeSpec::from_ns(ns), tp),
+        None => -1,
+    }
+}
+
+pub fn sys_clock_getres(clock: usize, res: *mut TimeSpec) -> isize {
+    if clock > CLOCK_THREAD_CPUTIME_ID {
+        return -1;
+    }
+    write_user(TimeSpec::from_ns(resolution()), res)
+}
+
+// 只支持 REALTIME 和 MONOTONIC，被信号打断时返回 -2，相对时间的睡眠会在 rem 中写入剩余时间
+// 周期任务使用 TIMER_ABSTIME 在上一次的唤醒时间上累加周期，不会累积误差
+pub fn sys_clock_nanosleep(clock: usize, flags: usize, req: *const TimeSpec, rem: *mut TimeSpec) -> isize {
+    let Some(ns) = read_user(req).and_then(|t| t.to_ns()) else {
+        return -1;
+    };
+    let abstime = flags & TIMER_ABSTIME != 0;
+    let deadline = match clock {
+        CLOCK_MONOTONIC if abstime => ns,
+        CLOCK_REALTIME if abstime => ns.saturating_sub(realtime_offset()),
+        CLOCK_MONOTONIC | CLOCK_REALTIME => nanoseconds() + ns,
+        _ => return -1,
+    };
+
+    let r = sleep_until(deadline);
+    if r < 0 && !abstime && write_user(TimeSpec::from_ns(deadline.saturating_sub(nanoseconds())), rem) < 0 {
+        return -1;
+    }
+    r
+}
+
+// 返回上一个 alarm 剩余的秒数
+pub fn sys_alarm(seconds: usize) -> isize {
+    match set_timer(TimerId::Itimer(ITIMER_REAL), seconds * NSEC_PER_SEC, 0, false) {
+        Some((remaining, _)) => remaining.div_ceil(NSEC_PER_SEC) as isize,
+        None => -1,
+    }
+}
+
+pub fn sys_setitimer(which: usize, new: *const ITimerVal, old: *mut ITimerVal) -> isize {
+    let Some(v) = read_user(new) else {
+        return -1;
+    };
+    let (Some(value), Some(interval)) = (v.it_value.to_ns(), v.it_interval.to_ns()) else {
+        return -1;
+    };
+    match set_timer(TimerId::Itimer(which), value, interval, false) {
+        Some((remaining, interval)) => {
+            write_user(ITimerVal { it_interval: TimeVal::from_ns(interval), it_value: TimeVal::from_ns(remaining) }, old)
+        }
+        None => -1,
+    }
+}
+
+pub fn sys_getitimer(which: usize, cur: *mut ITimerVal) -> isize {
+    match get_timer(TimerId::Itimer(which)) {
+        Some((remaining, interval)) => {
+            write_user(ITimerVal { it_interval: TimeVal::from_ns(interval), it_value: TimeVal::from_ns(remaining) }, cur)
+        }
+        None => -1,
+    }
+}
+
+// sevp 为空时和 linux 一样到期发送 SIGALRM
+pub fn sys_timer_create(clock: usize, sevp: *const SigEvent, timerid: *mut usize) -> isize {
+    let signal = if sevp.is_null() {
+        SIGALRM
+    } else {
+        let Some(ev) = read_user(sevp) else {
+            return -1;
+        };
+        match ev.sigev_notify {
+            SIGEV_NONE => 0,
+            SIGEV_SIGNAL if ev.sigev_signo > 0 && (ev.sigev_signo as usize) < SIG_NUM => ev.sigev_signo as usize,
+            _ => return -1,
+        }
+    };
+
+    let id = timer_create(clock, signal);
+    if id >= 0 {
+        write_user(id as usize, timerid)
+    } else {
+        id
+    }
+}
+
+pub fn sys_timer_settime(id: usize, flags: usize, new: *const ITimerSpec, old: *mut ITimerSpec) -> isize {
+    let Some(v) = read_user(new) else {
+        return -1;
+    };
+    let (Some(value), Some(interval)) = (v.it_value.to_ns(), v.it_interval.to_ns()) else {
+        return -1;
+    };
+    match set_timer(TimerId::Posix(id), value, interval, flags & TIMER_ABSTIME != 0) {
+        Some((remaining, interval)) => {
+            write_user(ITimerSpec { it_interval: TimeSpec::from_ns(interval), it_value: TimeSpec::from_ns(remaining) }, old)
+        }
+        None => -1,
+    }
+}
+
+pub fn sys_timer_gettime(id: usize, cur: *mut ITimerSpec) -> isize {
+    match get_timer(TimerId::Posix(id)) {
+        Some((remaining, interval)) => {
+            write_user(ITimerSpec { it_interval: TimeSpec::from_ns(interval), it_value: TimeSpec::from_ns(remaining) }, cur)
+        }
+        None => -1,
+    }
+}
+
+pub fn sys_timer_getoverrun(id: usize) -> isize {
+    timer_getoverrun(id)
+}
+
+pub fn sys_timer_delete(id: usize) -> isize {
+    timer_delete(id)
+}
+
+// 返回启动以来的 clock 数，单位是 1 / CLOCKS_PER_SEC 秒
+pub fn sys_times(buf: *mut Tms) -> isize {
+    if write_user(times(), buf) < 0 {
+        return -1;
+    }
+    ns_to_clock(nanoseconds()) as isize
+}
+
+pub fn sys_getrusage(who: isize, usage: *mut RUsage) -> isize {
+    match getrusage(who) {
+        Some(u) => write_user(RUsage::from(&u), usage),
+        None => -1,
+    }
+}
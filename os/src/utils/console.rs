@@ -1,5 +1,9 @@
 use crate::board::console_putchar;
 use core::fmt::{self, Write};
+use crate::sync::Mutex;
+
+// 多个 cpu 同时打印时，保证一次 print 的内容不会被打断
+static PRINT_LOCK: Mutex<()> = Mutex::new(());
 
 struct Stdout;
 
@@ -13,6 +17,7 @@ impl Write for Stdout {
 }
 
 pub fn print(args: fmt::Arguments) {
+    let _guard = PRINT_LOCK.lock();
     Stdout.write_fmt(args).unwrap();
 }
 
@@ -1,5 +1,6 @@
 #[macro_use]
 pub mod console;
 pub mod panic;
-pub mod type_extern;
-pub mod bits;
\ No newline at end of file
+pub mod bits;
+pub mod random;
+
@@ -0,0 +1,17 @@
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use crate::board::timer::nanoseconds;
+
+static STATE: AtomicU64 = AtomicU64::new(0);
+
+// 没有硬件随机数，用 splitmix64 生成伪随机数，每次混入当前时间
+// 用于 AT_RANDOM 和 PIE 的加载基址，不能用于密码学
+pub fn random() -> usize {
+    let x = STATE.fetch_add(0x9e37_79b9_7f4a_7c15, Ordering::Relaxed)
+        .wrapping_add(0x9e37_79b9_7f4a_7c15)
+        ^ nanoseconds() as u64;
+    let mut z = x;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    (z ^ (z >> 31)) as usize
+}
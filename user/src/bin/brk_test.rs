@@ -0,0 +1,43 @@
+#![no_std]
+#![no_main]
+
+extern crate alloc;
+
+use alloc::vec::Vec;
+use ffos_app::syscall::*;
+use ffos_app::{heap_size, sbrk};
+
+#[macro_use]
+extern crate ffos_app;
+
+#[no_mangle]
+fn main() -> i32 {
+    println!("brk test");
+
+    let start = sys_brk(0);
+    println!("brk starts at {:#x}, heap {}K", start, heap_size() / 1024);
+
+    // 堆上的数据在 brk 缩小前后都可以访问
+    let old = sbrk(4096 * 4);
+    let p = old as *mut usize;
+    unsafe {
+        p.write_volatile(0x1234);
+        p.add(4096 * 4 / 8 - 1).write_volatile(0x5678);
+    }
+    println!("sbrk(16K) -> {:#x}, brk now {:#x}", old, sys_brk(0));
+    println!("shrink -> {:#x}, read {:#x}", sbrk(-4096 * 2), unsafe { p.read_volatile() });
+    println!("over the limit -> {} (should be -1)", sbrk(1 << 40));
+
+    // 全局分配器在内存不足时自动通过 brk 扩大
+    let mut vecs: Vec<Vec<u8>> = Vec::new();
+    for i in 0..16 {
+        let mut v = Vec::with_capacity(1024 * 1024);
+        v.resize(1024 * 1024, i as u8);
+        vecs.push(v);
+    }
+    let sum: usize = vecs.iter().map(|v| v[v.len() - 1] as usize).sum();
+    println!("allocated 16M, sum {} (should be 120), heap {}K, brk {:#x}", sum, heap_size() / 1024, sys_brk(0));
+
+    println!("brk test done");
+    0
+}
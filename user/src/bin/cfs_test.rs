@@ -0,0 +1,56 @@
+#![no_std]
+#![no_main]
+
+use core::sync::atomic::{AtomicUsize, AtomicIsize, Ordering};
+use ffos_app::syscall::*;
+use ffos_app::signal::*;
+
+#[macro_use]
+extern crate ffos_app;
+
+static COUNT: AtomicUsize = AtomicUsize::new(0);
+static NICE: AtomicIsize = AtomicIsize::new(0);
+
+fn handler() {
+    // cpu share of each child should be close to the ratio of weights, 1024 : 335 : 110
+    println!(
+        "child {} nice {} count {}",
+        sys_getpid(),
+        NICE.load(Ordering::Relaxed),
+        COUNT.load(Ordering::Relaxed)
+    );
+    sys_exit(0);
+}
+
+#[no_mangle]
+fn main() -> i32 {
+    println!("cfs fairness test");
+    let nices: [isize; 3] = [0, 5, 10];
+    let mut pids: [usize; 3] = [0; 3];
+    for (i, n) in nices.iter().enumerate() {
+        let pid = sys_fork();
+        if pid == 0 {
+            NICE.store(ffos_app::nice(*n), Ordering::Relaxed);
+            sys_sigaction(SIGUSR1, handler as usize);
+            loop {
+                COUNT.fetch_add(1, Ordering::Relaxed);
+            }
+        } else if pid > 0 {
+            pids[i] = pid as usize;
+        } else {
+            println!("fork failed");
+            return -1;
+        }
+    }
+
+    sys_nanosleep(5_000_000_000);
+    for pid in pids {
+        sys_kill(pid, SIGUSR1);
+    }
+
+    for pid in pids {
+        sys_wait(pid);
+    }
+    println!("cfs fairness test done");
+    0
+}
@@ -0,0 +1,112 @@
+#![no_std]
+#![no_main]
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+use ffos_app::syscall::*;
+use ffos_app::signal::*;
+use ffos_app::{wifsignaled, wtermsig};
+
+#[macro_use]
+extern crate ffos_app;
+
+const PERIOD: usize = 50_000_000;
+
+static ALARM_COUNT: AtomicUsize = AtomicUsize::new(0);
+static TIMER_COUNT: AtomicUsize = AtomicUsize::new(0);
+static PROF_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+fn alarm_handler() {
+    ALARM_COUNT.fetch_add(1, Ordering::Relaxed);
+    sys_sigreturn();
+}
+
+fn timer_handler() {
+    TIMER_COUNT.fetch_add(1, Ordering::Relaxed);
+    sys_sigreturn();
+}
+
+fn prof_handler() {
+    PROF_COUNT.fetch_add(1, Ordering::Relaxed);
+    sys_sigreturn();
+}
+
+fn now(clock: usize) -> usize {
+    let mut ts = TimeSpec::default();
+    sys_clock_gettime(clock, &mut ts);
+    ts.to_ns()
+}
+
+#[no_mangle]
+fn main() -> i32 {
+    println!("clock test");
+
+    // 1. 读取各个时钟
+    let mut res = TimeSpec::default();
+    sys_clock_getres(CLOCK_MONOTONIC, &mut res);
+    println!("realtime {}s, monotonic {}ns, resolution {}ns", now(CLOCK_REALTIME) / 1_000_000_000, now(CLOCK_MONOTONIC), res.tv_nsec);
+    println!("process cputime {}ns, thread cputime {}ns", now(CLOCK_PROCESS_CPUTIME_ID), now(CLOCK_THREAD_CPUTIME_ID));
+    println!("invalid clock -> {} (should be -1)", sys_clock_gettime(100, &mut res));
+
+    // 2. 绝对时间的周期睡眠，每次在上一次的唤醒时间上累加周期，不会累积误差
+    let start = now(CLOCK_MONOTONIC);
+    let mut next = start;
+    let mut max_late = 0;
+    for _ in 0..10 {
+        next += PERIOD;
+        sys_clock_nanosleep(CLOCK_MONOTONIC, TIMER_ABSTIME, &TimeSpec::from_ns(next), None);
+        max_late = max_late.max(now(CLOCK_MONOTONIC) - next);
+    }
+    let drift = now(CLOCK_MONOTONIC) - (start + 10 * PERIOD);
+    println!("10 periods of {}ms, max late {}us, drift {}us", PERIOD / 1_000_000, max_late / 1000, drift / 1000);
+
+    // 3. ITIMER_REAL 周期发送 SIGALRM
+    sys_sigaction(SIGALRM, alarm_handler as usize);
+    let tv = TimeVal { tv_sec: 0, tv_usec: 100_000 };
+    sys_setitimer(ITIMER_REAL, &ITimerVal { it_interval: tv, it_value: tv }, None);
+    while ALARM_COUNT.load(Ordering::Relaxed) < 5 {
+        sys_nanosleep(1_000_000_000);
+    }
+    let mut old = ITimerVal::default();
+    sys_setitimer(ITIMER_REAL, &ITimerVal::default(), Some(&mut old));
+    println!("SIGALRM received {} times, interval was {}us", ALARM_COUNT.load(Ordering::Relaxed), old.it_interval.tv_usec);
+
+    // 4. timer_create 的定时器发送 SIGUSR1
+    sys_sigaction(SIGUSR1, timer_handler as usize);
+    let ev = SigEvent { sigev_value: 0, sigev_signo: SIGUSR1 as i32, sigev_notify: SIGEV_SIGNAL };
+    let mut id = 0;
+    sys_timer_create(CLOCK_MONOTONIC, Some(&ev), &mut id);
+    let spec = ITimerSpec { it_interval: TimeSpec::from_ns(PERIOD), it_value: TimeSpec::from_ns(PERIOD) };
+    sys_timer_settime(id, 0, &spec, None);
+    while TIMER_COUNT.load(Ordering::Relaxed) < 5 {
+        sys_nanosleep(1_000_000_000);
+    }
+    let mut cur = ITimerSpec::default();
+    sys_timer_gettime(id, &mut cur);
+    println!("timer {} fired {} times, overrun {}, next in {}us", id, TIMER_COUNT.load(Ordering::Relaxed),
+        sys_timer_getoverrun(id), cur.it_value.to_ns() / 1000);
+    println!("timer_delete -> {}, again -> {} (should be -1)", sys_timer_delete(id), sys_timer_delete(id));
+
+    // 5. ITIMER_PROF 按 cpu 时间计时，忙等时才会到期
+    sys_sigaction(SIGPROF, prof_handler as usize);
+    let tv = TimeVal { tv_sec: 0, tv_usec: 30_000 };
+    sys_setitimer(ITIMER_PROF, &ITimerVal { it_interval: TimeVal::default(), it_value: tv }, None);
+    let cpu_start = now(CLOCK_PROCESS_CPUTIME_ID);
+    while PROF_COUNT.load(Ordering::Relaxed) == 0 {
+        core::hint::spin_loop();
+    }
+    println!("SIGPROF after {}ms cpu time", (now(CLOCK_PROCESS_CPUTIME_ID) - cpu_start) / 1_000_000);
+
+    // 6. 没有处理函数时 SIGALRM 结束进程
+    let pid = sys_fork();
+    if pid == 0 {
+        sys_alarm(1);
+        loop {
+            sys_nanosleep(1_000_000_000);
+        }
+    }
+    let mut status: i32 = 0;
+    sys_waitpid(pid, &mut status, 0);
+    println!("alarm child killed {} by signal {}", wifsignaled(status), wtermsig(status));
+    println!("clock test done");
+    0
+}
@@ -0,0 +1,37 @@
+#![no_std]
+#![no_main]
+
+use ffos_app::syscall::*;
+use ffos_app::create_pipe;
+
+#[macro_use]
+extern crate ffos_app;
+
+// 没有映射的用户地址，系统调用访问它时返回 -1，进程不会被结束
+const BAD: usize = 8;
+
+fn bad_buf() -> &'static mut [u8] {
+    unsafe { core::slice::from_raw_parts_mut(BAD as *mut u8, 16) }
+}
+
+#[no_mangle]
+fn main() -> i32 {
+    println!("efault test");
+    let pid = sys_fork();
+    if pid == 0 {
+        return 3;
+    }
+    // 内核持有锁时写 status 也不会 panic，子进程已经被回收
+    println!("waitpid bad status: {} (should be -1)", sys_waitpid(pid, unsafe { &mut *(BAD as *mut i32) }, 0));
+    let mut status = 0;
+    println!("waitpid again: {} (should be -1)", sys_waitpid(pid, &mut status, 0));
+
+    println!("write bad buffer: {} (should be -1)", sys_write(1, bad_buf()));
+    let mut fds = [0usize; 2];
+    create_pipe(&mut fds);
+    sys_write(fds[1], b"efault");
+    println!("read into bad buffer: {} (should be -1)", sys_read(fds[0], bad_buf()));
+    println!("clock_gettime bad tp: {} (should be -1)", sys_clock_gettime(CLOCK_MONOTONIC, unsafe { &mut *(BAD as *mut TimeSpec) }));
+    println!("efault test done");
+    0
+}
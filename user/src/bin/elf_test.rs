@@ -0,0 +1,38 @@
+#![no_std]
+#![no_main]
+
+use ffos_app::syscall::*;
+use ffos_app::{execve, getauxval};
+
+#[macro_use]
+extern crate ffos_app;
+
+// .data 后面紧跟 .bss，bss 的第一页和 data 共用，需要内核清零
+const DATA_LEN: usize = 100;
+const BSS_LEN: usize = 4096 * 4 + 123;
+static mut DATA: [u8; DATA_LEN] = [0x5a; DATA_LEN];
+static mut BSS: [u8; BSS_LEN] = [0; BSS_LEN];
+
+extern "C" {
+    fn _start();
+}
+
+#[no_mangle]
+fn main() -> i32 {
+    println!("elf test");
+
+    // 用户栈只有 8K，逐个字节读取
+    let data_ok = (0..DATA_LEN).all(|i| unsafe { core::ptr::read_volatile(core::ptr::addr_of!(DATA[i])) } == 0x5a);
+    let bss_ok = (0..BSS_LEN).all(|i| unsafe { core::ptr::read_volatile(core::ptr::addr_of!(BSS[i])) } == 0);
+    println!("data initialized {}, bss zeroed {}", data_ok, bss_ok);
+
+    // 静态链接的程序没有动态加载器，AT_BASE 为 0
+    let entry = getauxval(AT_ENTRY);
+    println!("AT_ENTRY {:#x} is _start {}, AT_BASE {:#x} (should be 0)", entry, entry == _start as usize, getauxval(AT_BASE));
+    println!("AT_PHNUM {}, AT_PHENT {} (should be 56)", getauxval(AT_PHNUM), getauxval(AT_PHENT));
+
+    // 加载失败时进程继续运行
+    println!("exec missing file -> {} (should be -1)", execve("not_exist", &["not_exist"], &[]));
+    println!("elf test done");
+    if data_ok && bss_ok { 0 } else { 1 }
+}
@@ -0,0 +1,61 @@
+#![no_std]
+#![no_main]
+
+extern crate alloc;
+
+use alloc::format;
+use ffos_app::syscall::*;
+use ffos_app::{args, envs, execve, getauxval, wexitstatus};
+
+#[macro_use]
+extern crate ffos_app;
+
+fn print_auxv() {
+    println!("AT_PAGESZ {}, AT_ENTRY {:#x}, AT_PHDR {:#x}, AT_PHNUM {}",
+        getauxval(AT_PAGESZ), getauxval(AT_ENTRY), getauxval(AT_PHDR), getauxval(AT_PHNUM));
+    let random = getauxval(AT_RANDOM) as *const u8;
+    let bytes = unsafe { core::slice::from_raw_parts(random, 16) };
+    println!("AT_RANDOM {:02x?}", bytes);
+}
+
+// exec 后的新程序，检查参数、环境变量和 O_CLOEXEC
+fn child(keep: usize, cloexec: usize) -> i32 {
+    println!("argv: {:?}", args());
+    println!("envp: {:?}", envs());
+    print_auxv();
+    let keep_size = sys_filesize(keep);
+    let cloexec_size = sys_filesize(cloexec);
+    println!("fd {} size {} (should be > 0), O_CLOEXEC fd {} size {} (should be < 0)", keep, keep_size, cloexec, cloexec_size);
+    if keep_size > 0 && cloexec_size < 0 { 0 } else { 1 }
+}
+
+#[no_mangle]
+fn main() -> i32 {
+    let argv = args();
+    if argv.len() == 4 && argv[1] == "child" {
+        return child(argv[2].parse().unwrap(), argv[3].parse().unwrap());
+    }
+
+    println!("exec test");
+    println!("argv: {:?}", argv);
+    print_auxv();
+
+    // exec 失败时进程不受影响，继续运行
+    println!("exec missing file -> {} (should be -1)", execve("not_exist", &["not_exist"], &[]));
+
+    let keep = sys_open("hello_world\0", 0) as usize;
+    let cloexec = sys_open("hello_world\0", O_CLOEXEC) as usize;
+    let pid = sys_fork();
+    if pid == 0 {
+        let keep = format!("{}", keep);
+        let cloexec = format!("{}", cloexec);
+        execve("exec_test", &["exec_test", "child", &keep, &cloexec], &["HOME=/", "USER=root"]);
+        println!("exec failed");
+        sys_exit(-1);
+    }
+    let mut status = 0;
+    sys_waitpid(pid, &mut status, 0);
+    println!("child exit {}", wexitstatus(status));
+    println!("exec test done");
+    0
+}
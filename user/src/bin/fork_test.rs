@@ -1,7 +1,8 @@
 #![no_std]
 #![no_main]
 
-use ffos_app::syscall::{sys_fork, sys_wait, sys_yield};
+use ffos_app::syscall::{sys_fork, sys_waitpid};
+use ffos_app::wexitstatus;
 
 #[macro_use]
 extern crate ffos_app;
@@ -12,16 +13,12 @@ fn main() -> i32 {
     let pid = sys_fork();
     if pid == 0 {
         println!("child process");
+        return 3;
     } else if pid > 0 {
         println!("parent process");
-        loop {
-            if sys_wait(pid as usize) < 0 {
-                sys_yield();
-            } else {
-                println!("child process done");
-                break;
-            }
-        }
+        let mut status: i32 = 0;
+        sys_waitpid(pid, &mut status, 0);
+        println!("child process done, exit code {}", wexitstatus(status));
     } else {
         println!("fork failed");
     }
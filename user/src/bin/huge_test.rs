@@ -0,0 +1,78 @@
+#![no_std]
+#![no_main]
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+use ffos_app::syscall::*;
+use ffos_app::signal::SIGSEGV;
+use ffos_app::{normal_free, run_child, wexitstatus, wifsignaled, wtermsig};
+
+#[macro_use]
+extern crate ffos_app;
+
+const PAGE_SIZE: usize = 4096;
+// 4M 的映射正好是两个 2M 的大页
+const SIZE: usize = 4 * 1024 * 1024;
+
+static ADDR: AtomicUsize = AtomicUsize::new(0);
+
+fn minflt() -> usize {
+    let mut u = RUsage::default();
+    sys_getrusage(RUSAGE_SELF, &mut u);
+    u.ru_minflt
+}
+
+fn buf() -> &'static mut [u8] {
+    unsafe { core::slice::from_raw_parts_mut(ADDR.load(Ordering::Relaxed) as *mut u8, SIZE) }
+}
+
+// 写时复制只复制写的那一页，其他页仍然和父进程共享
+fn cow_child() -> i32 {
+    let b = buf();
+    let before = minflt();
+    b[0] = 0xAA;
+    b[PAGE_SIZE] = 0xBB;
+    println!("child: 2 writes, {} page faults, page 2 {} (should be 2)", minflt() - before, b[2 * PAGE_SIZE]);
+    b[0] as i32
+}
+
+fn write_child() -> i32 {
+    buf()[PAGE_SIZE] = 1;
+    0
+}
+
+#[no_mangle]
+fn main() -> i32 {
+    println!("huge page test");
+    let free = normal_free();
+    let addr = sys_mmap(SIZE, PROT_READ | PROT_WRITE);
+    if addr < 0 {
+        println!("mmap failed");
+        return -1;
+    }
+    ADDR.store(addr as usize, Ordering::Relaxed);
+    let b = buf();
+
+    // 每个 2M 的大页只产生一次页错误
+    let before = minflt();
+    for i in 0..SIZE / PAGE_SIZE {
+        b[i * PAGE_SIZE] = i as u8;
+    }
+    println!("touch {} pages: {} page faults (should be 2)", SIZE / PAGE_SIZE, minflt() - before);
+
+    // fork 之后第一次写时拆成 4K 的页
+    let status = run_child(cow_child);
+    println!("child exited {:#x} (should be 0xaa), parent sees {} {} (should be 0 1)", wexitstatus(status), b[0], b[PAGE_SIZE]);
+
+    // mprotect 一页时拆分大页，内容不变
+    println!("mprotect page 1 r: {} (should be 0)", sys_mprotect(addr as usize + PAGE_SIZE, PAGE_SIZE, PROT_READ));
+    println!("after split: {} {} {} (should be 0 1 2)", b[0], b[PAGE_SIZE], b[2 * PAGE_SIZE]);
+    let status = run_child(write_child);
+    println!("write read-only page: signaled {} by {} (should be {})", wifsignaled(status), wtermsig(status), SIGSEGV);
+    b[2 * PAGE_SIZE] = 7;
+    println!("write page 2: {} (should be 7)", b[2 * PAGE_SIZE]);
+
+    sys_ummap(addr as usize);
+    println!("frames not returned after munmap: {}", free.saturating_sub(normal_free()));
+    println!("huge page test done");
+    0
+}
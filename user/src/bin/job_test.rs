@@ -0,0 +1,65 @@
+#![no_std]
+#![no_main]
+
+use ffos_app::syscall::*;
+use ffos_app::signal::*;
+use ffos_app::{killpg, wifcontinued, wifsignaled, wifstopped, wstopsig, wtermsig};
+
+#[macro_use]
+extern crate ffos_app;
+
+const CHILD_NUM: usize = 2;
+
+#[no_mangle]
+fn main() -> i32 {
+    let pid = sys_getpid() as usize;
+    println!("job test, pid {} pgid {} sid {}", pid, sys_getpgid(0), sys_getsid(0));
+
+    // 1. 进程组首进程不能创建会话，子进程可以
+    println!("setsid as group leader -> {} (should be -1)", sys_setsid());
+    let child = sys_fork();
+    if child == 0 {
+        let sid = sys_setsid();
+        println!("child setsid -> {}, pgid {} sid {}", sid, sys_getpgid(0), sys_getsid(0));
+        return 0;
+    }
+    sys_wait(child as usize);
+
+    // 2. 子进程放到一个新的进程组中，用负数 pid 发送信号给整个进程组
+    let mut pgid = 0;
+    for i in 0..CHILD_NUM {
+        let child = sys_fork();
+        if child == 0 {
+            loop {
+                sys_nanosleep(100_000_000);
+            }
+        }
+        if i == 0 {
+            pgid = child as usize;
+        }
+        println!("setpgid({}, {}) -> {}", child, pgid, sys_setpgid(child as usize, pgid));
+    }
+
+    // 3. 停止和继续
+    sys_nanosleep(200_000_000);
+    killpg(pgid, SIGTSTP);
+    let mut status: i32 = 0;
+    for _ in 0..CHILD_NUM {
+        let r = sys_waitpid(-(pgid as isize), &mut status, WUNTRACED);
+        println!("child {} stopped {} by signal {}", r, wifstopped(status), wstopsig(status));
+    }
+    killpg(pgid, SIGCONT);
+    for _ in 0..CHILD_NUM {
+        let r = sys_waitpid(-(pgid as isize), &mut status, WCONTINUED);
+        println!("child {} continued {}", r, wifcontinued(status));
+    }
+
+    // 4. 结束整个进程组
+    killpg(pgid, SIGINT);
+    for _ in 0..CHILD_NUM {
+        let r = sys_waitpid(-(pgid as isize), &mut status, 0);
+        println!("child {} killed {} by signal {}", r, wifsignaled(status), wtermsig(status));
+    }
+    println!("job test done");
+    0
+}
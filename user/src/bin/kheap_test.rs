@@ -0,0 +1,43 @@
+#![no_std]
+#![no_main]
+
+use ffos_app::syscall::*;
+use ffos_app::{create_pipe, normal_free, run_child, wexitstatus};
+
+#[macro_use]
+extern crate ffos_app;
+
+// 每个管道在内核堆中有 4K 的缓冲区，加起来超过开始时 1M 的内核堆
+const PIPES: usize = 500;
+
+// 子进程退出时关闭所有管道
+fn child() -> i32 {
+    let before = normal_free();
+    let mut fds = [0usize; 2];
+    for i in 0..PIPES {
+        if create_pipe(&mut fds) < 0 {
+            println!("create pipe {} failed", i);
+            return -1;
+        }
+    }
+    // 最后一个管道仍然可以读写
+    sys_write(fds[1], b"heap");
+    let mut buf = [0u8; 4];
+    sys_read(fds[0], &mut buf);
+    println!("{} pipes: heap grew by {} frames, last pipe read {:?}", PIPES, before - normal_free(), core::str::from_utf8(&buf).unwrap());
+    0
+}
+
+#[no_mangle]
+fn main() -> i32 {
+    println!("kheap test");
+    let before = normal_free();
+    let status = run_child(child);
+    println!("child exited {} (should be 0)", wexitstatus(status));
+
+    // 空的 slab 和扩大出来的堆区域都还给了页帧分配器
+    let after = normal_free();
+    println!("frames not returned after exit: {}", before.saturating_sub(after));
+    println!("kheap test done");
+    0
+}
@@ -0,0 +1,71 @@
+#![no_std]
+#![no_main]
+
+use ffos_app::syscall::*;
+use ffos_app::{report, run_child, usage, wexitstatus};
+
+#[macro_use]
+extern crate ffos_app;
+
+const PAGE_SIZE: usize = 4096;
+const MMAP_SIZE: usize = 4 * 1024 * 1024;
+
+#[no_mangle]
+fn main() -> i32 {
+    println!("lazy test");
+
+    let before = usage();
+    let addr = sys_mmap(MMAP_SIZE, 0x3);
+    if addr < 0 {
+        println!("mmap failed");
+        return -1;
+    }
+    let buf = unsafe { core::slice::from_raw_parts_mut(addr as *mut u8, MMAP_SIZE) };
+    let mapped = usage();
+    println!("mmap 4M: maxrss {}K -> {}K", before.ru_maxrss, mapped.ru_maxrss);
+
+    // 没有写过的页都映射到零页
+    let sum: usize = buf.iter().step_by(PAGE_SIZE).map(|&b| b as usize).sum();
+    let read = usage();
+    println!("read every page: sum {}, maxrss {}K, minflt +{}", sum, read.ru_maxrss, read.ru_minflt - mapped.ru_minflt);
+
+    for i in (0..MMAP_SIZE).step_by(PAGE_SIZE) {
+        buf[i] = 1;
+    }
+    let written = usage();
+    println!("write every page: maxrss {}K, minflt +{}", written.ru_maxrss, written.ru_minflt - read.ru_minflt);
+
+    // fork 后写时复制，父进程看不到子进程的修改
+    let pid = sys_fork();
+    if pid == 0 {
+        buf[0] = 2;
+        sys_exit(buf[0] as i32);
+    }
+    let mut status = 0;
+    sys_waitpid(pid, &mut status, 0);
+    println!("child wrote {}, parent still reads {}", wexitstatus(status), buf[0]);
+
+    report("unmapped address", run_child(|| {
+        unsafe { core::ptr::write_volatile(0x10 as *mut u8, 1); }
+        0
+    }));
+    report("write read-only mmap", run_child(|| {
+        let addr = sys_mmap(PAGE_SIZE, 0x1);
+        unsafe { core::ptr::write_volatile(addr as *mut u8, 1); }
+        0
+    }));
+    // RLIMIT_RSS 在页错误分配页帧时检查，mmap 时不检查
+    report("exceed RLIMIT_RSS", run_child(|| {
+        let rss = usage().ru_maxrss * 1024 / PAGE_SIZE;
+        sys_setrlimit(RLIMIT_RSS, &RLimit { rlim_cur: (rss + 16) * PAGE_SIZE, rlim_max: RLIM_INFINITY });
+        let addr = sys_mmap(64 * PAGE_SIZE, 0x3);
+        println!("mmap 64 pages with 16 left: {}", if addr > 0 { "ok" } else { "failed" });
+        for i in 0..64 {
+            unsafe { core::ptr::write_volatile((addr as usize + i * PAGE_SIZE) as *mut u8, 1); }
+        }
+        0
+    }));
+
+    println!("lazy test done");
+    0
+}
@@ -0,0 +1,52 @@
+#![no_std]
+#![no_main]
+
+use ffos_app::syscall::*;
+
+#[macro_use]
+extern crate ffos_app;
+
+const PAGE_SIZE: usize = 4096;
+const PAGES: usize = 1024;
+
+fn info(zone: usize) -> ZoneInfo {
+    let mut info = ZoneInfo::default();
+    sys_meminfo(zone, &mut info);
+    info
+}
+
+fn show(name: &str, zone: usize) {
+    let i = info(zone);
+    println!("{}: total {} free {} used {}", name, i.total, i.free, i.used);
+    println!("  buddyinfo {:?}", i.free_blocks);
+}
+
+#[no_mangle]
+fn main() -> i32 {
+    println!("meminfo test");
+    show("dma", ZONE_DMA);
+    show("normal", ZONE_NORMAL);
+    let mut i = ZoneInfo::default();
+    println!("invalid zone: {} (should be -1)", sys_meminfo(2, &mut i));
+
+    // 访问 mmap 的页之后 normal 区域的空闲页帧减少，munmap 之后恢复
+    let before = info(ZONE_NORMAL).free;
+    let addr = sys_mmap(PAGES * PAGE_SIZE, PROT_READ | PROT_WRITE);
+    if addr < 0 {
+        println!("mmap failed");
+        return -1;
+    }
+    let buf = unsafe { core::slice::from_raw_parts_mut(addr as *mut u8, PAGES * PAGE_SIZE) };
+    for i in (0..buf.len()).step_by(PAGE_SIZE) {
+        buf[i] = 1;
+    }
+    let touched = info(ZONE_NORMAL).free;
+    println!("after touch: {} fewer free frames (should be >= {})", before - touched, PAGES);
+    sys_ummap(addr as usize);
+    let after = info(ZONE_NORMAL).free;
+    println!("after munmap: {} frames back (should be >= {})", after - touched, PAGES);
+
+    show("normal", ZONE_NORMAL);
+    println!("meminfo test done");
+    0
+}
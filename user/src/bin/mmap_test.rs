@@ -0,0 +1,71 @@
+#![no_std]
+#![no_main]
+
+use ffos_app::syscall::*;
+use ffos_app::wexitstatus;
+
+#[macro_use]
+extern crate ffos_app;
+
+// 映射这个程序自己的 elf 文件，e_ident 的填充字节（9~15）是 0，修改后再恢复
+const PATH: &str = "mmap_test\0";
+const PAD: usize = 9;
+const LEN: usize = 8192;
+
+fn read_file(fd: usize, pos: usize) -> u8 {
+    let mut buf = [0u8; 1];
+    sys_lseek(fd, pos);
+    sys_read(fd, &mut buf);
+    buf[0]
+}
+
+fn map(fd: usize, flags: usize) -> &'static mut [u8] {
+    let addr = sys_mmap_file(fd, 0, LEN, PROT_READ | PROT_WRITE, flags);
+    if addr < 0 {
+        panic!("mmap file failed");
+    }
+    unsafe { core::slice::from_raw_parts_mut(addr as *mut u8, LEN) }
+}
+
+#[no_mangle]
+fn main() -> i32 {
+    println!("mmap test");
+    let fd = sys_open(PATH, 0);
+    if fd < 0 {
+        println!("open {} failed", PATH);
+        return -1;
+    }
+    let fd = fd as usize;
+
+    // 按需从文件读取，内容和 read 得到的相同
+    let private = map(fd, MAP_PRIVATE);
+    println!("magic {:?} (should be [127, 69, 76, 70]), byte 4096 {} == {}", &private[..4], private[4096], read_file(fd, 4096));
+
+    // MAP_PRIVATE 的修改不会写回文件
+    private[PAD] = 0x11;
+    println!("private write: mapped {:#x}, file {} (should be 0)", private[PAD], read_file(fd, PAD));
+
+    // MAP_SHARED 的修改在 msync 后写回文件，fork 的子进程和父进程共享页帧
+    let shared = map(fd, MAP_SHARED);
+    let pid = sys_fork();
+    if pid == 0 {
+        shared[PAD] = 0x42;
+        sys_exit(0);
+    }
+    let mut status = 0;
+    sys_waitpid(pid, &mut status, 0);
+    println!("child exited {}, parent sees {:#x} (should be 0x42)", wexitstatus(status), shared[PAD]);
+    sys_msync(shared.as_ptr() as usize, LEN);
+    println!("after msync: file {:#x} (should be 0x42)", read_file(fd, PAD));
+
+    // munmap 时也会写回
+    shared[PAD] = 0;
+    sys_ummap(shared.as_ptr() as usize);
+    println!("after munmap: file {} (should be 0)", read_file(fd, PAD));
+
+    println!("invalid: unaligned offset {}, bad flags {} (should be -1 -1)",
+        sys_mmap_file(fd, 1, LEN, PROT_READ, MAP_PRIVATE), sys_mmap_file(fd, 0, LEN, PROT_READ, 0));
+
+    println!("mmap test done");
+    0
+}
@@ -0,0 +1,107 @@
+#![no_std]
+#![no_main]
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+use ffos_app::syscall::*;
+use ffos_app::{report, run_child};
+
+#[macro_use]
+extern crate ffos_app;
+
+const PAGE_SIZE: usize = 4096;
+
+// 子进程访问的地址
+static ADDR: AtomicUsize = AtomicUsize::new(0);
+
+// li a0, 42; ret
+#[cfg(target_arch = "riscv64")]
+const CODE: [u32; 2] = [0x02a00513, 0x00008067];
+// mov w0, #42; ret
+#[cfg(target_arch = "aarch64")]
+const CODE: [u32; 2] = [0x52800540, 0xd65f03c0];
+
+fn write_addr() -> i32 {
+    unsafe { (ADDR.load(Ordering::Relaxed) as *mut u8).write_volatile(1) };
+    0
+}
+
+fn read_addr() -> i32 {
+    unsafe { (ADDR.load(Ordering::Relaxed) as *const u8).read_volatile() as i32 }
+}
+
+fn call_addr() -> i32 {
+    let f: fn() -> i32 = unsafe { core::mem::transmute(ADDR.load(Ordering::Relaxed)) };
+    f()
+}
+
+// 写入指令之后同步指令缓存，qemu 并不模拟指令缓存
+fn write_code(addr: usize) {
+    let code = unsafe { core::slice::from_raw_parts_mut(addr as *mut u32, CODE.len()) };
+    code.copy_from_slice(&CODE);
+    #[cfg(target_arch = "riscv64")]
+    unsafe { core::arch::asm!("fence.i") };
+    #[cfg(target_arch = "aarch64")]
+    unsafe { core::arch::asm!("dsb ish", "isb") };
+}
+
+#[no_mangle]
+fn main() -> i32 {
+    println!("mprotect test");
+
+    // W^X：默认不允许同时可写和可执行
+    println!("mdwe {} (should be {})", sys_prctl(PR_GET_MDWE, 0), PR_MDWE_REFUSE_EXEC_GAIN);
+    println!("mmap rwx: {} (should be -1)", sys_mmap(PAGE_SIZE, PROT_READ | PROT_WRITE | PROT_EXEC));
+
+    let addr = sys_mmap(3 * PAGE_SIZE, PROT_READ | PROT_WRITE);
+    if addr < 0 {
+        println!("mmap failed");
+        return -1;
+    }
+    let addr = addr as usize;
+    let buf = unsafe { core::slice::from_raw_parts_mut(addr as *mut u8, 3 * PAGE_SIZE) };
+    buf.fill(7);
+
+    // 只改变中间一页，区域被分成三个
+    let middle = addr + PAGE_SIZE;
+    println!("mprotect middle r: {} (should be 0)", sys_mprotect(middle, PAGE_SIZE, PROT_READ));
+    println!("mprotect rwx: {} (should be -1)", sys_mprotect(addr, PAGE_SIZE, PROT_READ | PROT_WRITE | PROT_EXEC));
+    println!("mprotect unaligned: {} (should be -1)", sys_mprotect(addr + 1, PAGE_SIZE, PROT_READ));
+    println!("mprotect overflow: {} (should be -1)", sys_mprotect(addr, usize::MAX, PROT_READ));
+    ADDR.store(middle, Ordering::Relaxed);
+    report("write read-only page", run_child(write_addr));
+    ADDR.store(addr, Ordering::Relaxed);
+    println!("write first page: exit {} (should be 0)", run_child(write_addr) >> 8);
+
+    // PROT_NONE 保留页的内容，恢复权限后还能读到
+    sys_mprotect(middle, PAGE_SIZE, 0);
+    ADDR.store(middle, Ordering::Relaxed);
+    report("read PROT_NONE page", run_child(read_addr));
+    sys_mprotect(addr, 3 * PAGE_SIZE, PROT_READ | PROT_WRITE);
+    buf[2 * PAGE_SIZE] = 8;
+    println!("after restore: {} {} {} (should be 7 7 8)", buf[0], buf[PAGE_SIZE], buf[2 * PAGE_SIZE]);
+
+    // JIT：先写入指令，再改为只读可执行
+    write_code(addr);
+    ADDR.store(addr, Ordering::Relaxed);
+    report("execute writable page", run_child(call_addr));
+    sys_mprotect(addr, PAGE_SIZE, PROT_READ | PROT_EXEC);
+    println!("jit returned {} (should be 42)", call_addr());
+    report("write executable page", run_child(write_addr));
+
+    // munmap 释放 mprotect 分出来的所有区域
+    println!("munmap: {} (should be 0)", sys_ummap(addr));
+
+    // 主动允许之后可以映射同时可写和可执行的页
+    sys_prctl(PR_SET_MDWE, 0);
+    let rwx = sys_mmap(PAGE_SIZE, PROT_READ | PROT_WRITE | PROT_EXEC);
+    if rwx < 0 {
+        println!("mmap rwx after opt-in failed");
+        return -1;
+    }
+    write_code(rwx as usize);
+    ADDR.store(rwx as usize, Ordering::Relaxed);
+    println!("rwx page returned {} (should be 42)", call_addr());
+
+    println!("mprotect test done");
+    0
+}
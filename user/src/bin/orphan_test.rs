@@ -0,0 +1,35 @@
+#![no_std]
+#![no_main]
+
+use ffos_app::syscall::*;
+
+#[macro_use]
+extern crate ffos_app;
+
+const ORPHAN_NUM: usize = 3;
+
+#[no_mangle]
+fn main() -> i32 {
+    println!("orphan test, pid {} parent {}", sys_getpid(), sys_getppid());
+    let pid = sys_fork();
+    if pid == 0 {
+        // 子进程创建几个孙进程后直接退出，孙进程变成孤儿
+        for _ in 0..ORPHAN_NUM {
+            if sys_fork() == 0 {
+                let ppid = sys_getppid();
+                sys_nanosleep(500_000_000);
+                // 父进程已经退出，应该变成 initproc
+                println!("orphan {} parent {} -> {}", sys_getpid(), ppid, sys_getppid());
+                return 0;
+            }
+        }
+        return 0;
+    }
+
+    sys_wait(pid as usize);
+    println!("middle process {} reaped", pid);
+    // 孤儿进程由 initproc 回收，pid 可以被重新分配
+    sys_nanosleep(1_000_000_000);
+    println!("orphan test done");
+    0
+}
@@ -0,0 +1,36 @@
+#![no_std]
+#![no_main]
+
+use ffos_app::syscall::sys_fork;
+
+#[macro_use]
+extern crate ffos_app;
+
+#[no_mangle]
+fn main() -> i32 {
+    println!("priority test");
+    let mut i: usize = 0;
+    let pid = sys_fork();
+    if pid == 0 {
+        // child runs as a batch job, only runs when parent sleeps
+        println!("child process nice {}", ffos_app::nice(10));
+        loop {
+            i = i + 1;
+            if i % 100_000_000 == 0 {
+                println!("low priority child, number {}", i);
+            }
+        }
+    } else if pid > 0 {
+        println!("parent process nice {}", ffos_app::nice(0));
+        loop {
+            i = i + 1;
+            if i % 100_000_000 == 0 {
+                println!("high priority parent, number {}", i);
+                ffos_app::syscall::sys_nanosleep(1_000_000_000);
+            }
+        }
+    } else {
+        println!("fork failed");
+    }
+    0
+}
@@ -0,0 +1,94 @@
+#![no_std]
+#![no_main]
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+use ffos_app::syscall::*;
+use ffos_app::signal::*;
+use ffos_app::{run_child, wexitstatus, wifsignaled, wtermsig};
+
+#[macro_use]
+extern crate ffos_app;
+
+static XCPU: AtomicUsize = AtomicUsize::new(0);
+
+fn xcpu_handler() {
+    let n = XCPU.fetch_add(1, Ordering::Relaxed) + 1;
+    println!("child got SIGXCPU {}", n);
+    sys_sigreturn();
+}
+
+fn limit(cur: usize, max: usize) -> RLimit {
+    RLimit { rlim_cur: cur, rlim_max: max }
+}
+
+fn nofile() -> i32 {
+    sys_setrlimit(RLIMIT_NOFILE, &limit(8, 8));
+    let mut fds = [0usize; 2];
+    let mut pipes = 0;
+    while sys_create_pipe(&mut fds) == 0 {
+        pipes += 1;
+    }
+    println!("RLIMIT_NOFILE 8: created {} pipes, last fd {}", pipes, fds[1]);
+    pipes
+}
+
+fn address_space() -> i32 {
+    sys_setrlimit(RLIMIT_AS, &limit(1024 * 1024, RLIM_INFINITY));
+    let mut pages = 0;
+    while sys_mmap(4096, 0x3) > 0 {
+        pages += 1;
+    }
+    println!("RLIMIT_AS 1M: mapped {} pages before failure", pages);
+    pages
+}
+
+fn nproc() -> i32 {
+    sys_setrlimit(RLIMIT_NPROC, &limit(1, 1));
+    let pid = sys_fork();
+    if pid == 0 {
+        sys_exit(0);
+    }
+    println!("RLIMIT_NPROC 1: fork -> {} (should be -1)", pid);
+    0
+}
+
+fn cpu() -> i32 {
+    sys_sigaction(SIGXCPU, xcpu_handler as usize);
+    sys_setrlimit(RLIMIT_CPU, &limit(1, 3));
+    loop {
+        core::hint::spin_loop();
+    }
+}
+
+#[no_mangle]
+fn main() -> i32 {
+    println!("rlimit test");
+
+    let mut l = RLimit::default();
+    sys_getrlimit(RLIMIT_STACK, &mut l);
+    println!("RLIMIT_STACK: cur {}, max {:#x}", l.rlim_cur, l.rlim_max);
+    sys_getrlimit(RLIMIT_NOFILE, &mut l);
+    println!("RLIMIT_NOFILE: cur {}, max {}", l.rlim_cur, l.rlim_max);
+
+    println!("soft > hard -> {} (should be -1)", sys_setrlimit(RLIMIT_NOFILE, &limit(100, 10)));
+    println!("raise hard -> {} (should be -1)", sys_setrlimit(RLIMIT_NOFILE, &limit(10, 100_000)));
+
+    run_child(nofile);
+    run_child(address_space);
+    run_child(nproc);
+
+    // 超过软限制后每秒收到 SIGXCPU，到达硬限制时被 SIGKILL 结束
+    let status = run_child(cpu);
+    println!("RLIMIT_CPU 1/3: child signaled {} by {}, exit {}", wifsignaled(status), wtermsig(status), wexitstatus(status));
+
+    // 资源限制被子进程继承
+    sys_setrlimit(RLIMIT_NOFILE, &limit(16, 16));
+    let status = run_child(|| {
+        let mut l = RLimit::default();
+        sys_getrlimit(RLIMIT_NOFILE, &mut l);
+        l.rlim_cur as i32
+    });
+    println!("child inherits RLIMIT_NOFILE {}", wexitstatus(status));
+    println!("rlimit test done");
+    0
+}
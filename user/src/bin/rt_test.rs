@@ -0,0 +1,75 @@
+#![no_std]
+#![no_main]
+
+use ffos_app::syscall::*;
+
+#[macro_use]
+extern crate ffos_app;
+
+fn busy(n: usize) {
+    let mut i: usize = 0;
+    while i < n {
+        i = i + 1;
+        unsafe { core::ptr::read_volatile(&i) };
+    }
+}
+
+#[no_mangle]
+fn main() -> i32 {
+    println!("rt test");
+
+    // 1. FIFO 任务一直运行到结束，父进程在此期间得不到运行
+    let pid = sys_fork();
+    if pid == 0 {
+        let param = SchedParam { sched_priority: 10, ..SchedParam::default() };
+        println!("fifo child set scheduler {}", sys_sched_setscheduler(0, SCHED_FIFO, &param));
+        println!("fifo child policy {}", sys_sched_getscheduler(0));
+        busy(200_000_000);
+        println!("fifo child done");
+        return 0;
+    }
+    sys_wait(pid as usize);
+    println!("parent runs after fifo child");
+
+    // 2. 两个相同优先级的 RR 任务按时间片轮流运行
+    let mut pids: [usize; 2] = [0; 2];
+    for i in 0..2 {
+        let pid = sys_fork();
+        if pid == 0 {
+            let param = SchedParam { sched_priority: 5, ..SchedParam::default() };
+            sys_sched_setscheduler(0, SCHED_RR, &param);
+            for round in 0..5 {
+                busy(50_000_000);
+                println!("rr child {} round {}", i, round);
+            }
+            return 0;
+        }
+        pids[i] = pid as usize;
+    }
+    for pid in pids {
+        sys_wait(pid);
+    }
+
+    // 3. deadline 准入控制，带宽之和超过 95% 时失败
+    let param = SchedParam {
+        sched_priority: 0,
+        sched_runtime: 60_000_000,
+        sched_deadline: 100_000_000,
+        sched_period: 100_000_000,
+    };
+    println!("parent set deadline {}", sys_sched_setscheduler(0, SCHED_DEADLINE, &param));
+    let pid = sys_fork();
+    if pid == 0 {
+        println!("child policy after fork {}", sys_sched_getscheduler(0));
+        println!("child set deadline {} (should fail)", sys_sched_setscheduler(0, SCHED_DEADLINE, &param));
+        return 0;
+    }
+    sys_wait(pid as usize);
+
+    let mut p = SchedParam::default();
+    sys_sched_getparam(0, &mut p);
+    println!("parent deadline param {:?}", p);
+    sys_sched_setscheduler(0, SCHED_NORMAL, &SchedParam::default());
+    println!("rt test done");
+    0
+}
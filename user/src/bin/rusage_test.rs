@@ -0,0 +1,85 @@
+#![no_std]
+#![no_main]
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+use ffos_app::syscall::*;
+use ffos_app::signal::*;
+
+#[macro_use]
+extern crate ffos_app;
+
+const PAGES: usize = 4;
+static mut BUF: [u8; PAGES * 4096] = [1; PAGES * 4096];
+static VTALRM: AtomicUsize = AtomicUsize::new(0);
+
+fn vtalrm_handler() {
+    VTALRM.fetch_add(1, Ordering::Relaxed);
+    sys_sigreturn();
+}
+
+fn busy(n: usize) -> usize {
+    let mut sum = 0usize;
+    for i in 0..n {
+        sum = sum.wrapping_add(i * i);
+        core::hint::black_box(sum);
+    }
+    sum
+}
+
+fn print_usage(name: &str, u: &RUsage) {
+    println!("{}: utime {}us, stime {}us, maxrss {}KB, minflt {}, nvcsw {}, nivcsw {}, nsignals {}, inblock {}, oublock {}",
+        name, u.ru_utime.to_us(), u.ru_stime.to_us(), u.ru_maxrss, u.ru_minflt,
+        u.ru_nvcsw, u.ru_nivcsw, u.ru_nsignals, u.ru_inblock, u.ru_oublock);
+}
+
+#[no_mangle]
+fn main() -> i32 {
+    println!("rusage test");
+
+    let pid = sys_fork();
+    if pid == 0 {
+        // 写 fork 后共享的页，每页触发一次 copy on write
+        for i in 0..PAGES {
+            unsafe { BUF[i * 4096] = 2; }
+        }
+        // 主动让出 cpu，计入 nvcsw
+        for _ in 0..5 {
+            sys_nanosleep(10_000_000);
+        }
+        // 用户态忙等，计入 utime，时间片用完时计入 nivcsw
+        busy(20_000_000);
+        return 0;
+    }
+
+    let mut status = 0;
+    let mut ru = RUsage::default();
+    sys_wait4(pid, &mut status, 0, &mut ru);
+    print_usage("child", &ru);
+    println!("minflt should be >= {}, nvcsw should be >= 5", PAGES);
+
+    let mut children = RUsage::default();
+    sys_getrusage(RUSAGE_CHILDREN, &mut children);
+    print_usage("children", &children);
+
+    // ITIMER_VIRTUAL 只统计用户态时间，睡眠时不会到期
+    sys_sigaction(SIGVTALRM, vtalrm_handler as usize);
+    let tv = TimeVal { tv_sec: 0, tv_usec: 20_000 };
+    sys_setitimer(ITIMER_VIRTUAL, &ITimerVal { it_interval: TimeVal::default(), it_value: tv }, None);
+    sys_nanosleep(100_000_000);
+    println!("SIGVTALRM after sleep: {} (should be 0)", VTALRM.load(Ordering::Relaxed));
+    while VTALRM.load(Ordering::Relaxed) == 0 {
+        busy(100_000);
+    }
+    println!("SIGVTALRM after busy loop: {}", VTALRM.load(Ordering::Relaxed));
+
+    let mut own = RUsage::default();
+    sys_getrusage(RUSAGE_SELF, &mut own);
+    print_usage("self", &own);
+
+    let mut tms = Tms::default();
+    let clock = sys_times(&mut tms);
+    println!("times: {} clocks since boot, utime {}, stime {}, cutime {}, cstime {} ({} per second)",
+        clock, tms.tms_utime, tms.tms_stime, tms.tms_cutime, tms.tms_cstime, CLOCKS_PER_SEC);
+    println!("rusage test done");
+    0
+}
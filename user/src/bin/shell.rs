@@ -7,20 +7,157 @@ extern crate ffos_app;
 extern crate alloc;
 
 use alloc::string::String;
+use alloc::vec::Vec;
 use ffos_app::{
-    console::getchar, signal::{SignalFlags, SIGINT}, 
-    syscall::*
+    console::getchar, signal::{SignalFlags, SIGCONT, SIGINT, SIGTSTP},
+    syscall::*, envs, killpg, spawn, wexitstatus, wifsignaled, wifstopped, wtermsig
 };
 
 const LF: u8 = 0x0au8;
 const CR: u8 = 0x0du8;
 const DL: u8 = 0x7fu8;
 const BS: u8 = 0x08u8;
+const CTRL_C: u8 = 0x03u8;
+const CTRL_Z: u8 = 0x1au8;
+
+// 每个命令运行在自己的进程组中，pgid 即命令进程的 pid
+struct Job {
+    id: usize,
+    pgid: usize,
+    name: String,
+    stopped: bool,
+}
+
+struct Shell {
+    jobs: Vec<Job>,
+    next_id: usize,
+}
+
+impl Shell {
+    fn new() -> Self {
+        Self { jobs: Vec::new(), next_id: 1 }
+    }
+
+    fn execute(&mut self, line: &str) {
+        let (name, background) = match line.strip_suffix('&') {
+            Some(name) => (name.trim(), true),
+            None => (line, false),
+        };
+        let mut args = name.split_whitespace();
+        match args.next() {
+            Some("jobs") => self.list(),
+            Some("fg") => {
+                if let Some(i) = self.find(args.next()) {
+                    let job = self.jobs.remove(i);
+                    println!("{}", job.name);
+                    killpg(job.pgid, SIGCONT);
+                    self.foreground(job);
+                }
+            }
+            Some("bg") => {
+                if let Some(i) = self.find(args.next()) {
+                    let job = &mut self.jobs[i];
+                    job.stopped = false;
+                    println!("[{}] {} &", job.id, job.name);
+                    killpg(job.pgid, SIGCONT);
+                }
+            }
+            Some(_) => self.launch(name, background),
+            None => {}
+        }
+    }
+
+    fn launch(&mut self, name: &str, background: bool) {
+        let args: Vec<&str> = name.split_whitespace().collect();
+        // 不需要复制 shell 的地址空间，子进程创建时就在自己的进程组中
+        let pid = spawn(args[0], &args, &envs(), &[], Some(0));
+        if pid < 0 {
+            println!("{}: command not found", args[0]);
+            return;
+        }
+
+        let job = Job { id: self.next_id, pgid: pid as usize, name: String::from(name), stopped: false };
+        self.next_id += 1;
+        if background {
+            println!("[{}] {}", job.id, job.pgid);
+            self.jobs.push(job);
+        } else {
+            self.foreground(job);
+        }
+    }
+
+    // 前台运行，需要同时读取 ctrl-c 和 ctrl-z，所以不能阻塞在 wait 中
+    fn foreground(&mut self, mut job: Job) {
+        let mut status: i32 = 0;
+        loop {
+            match getchar() {
+                Some(CTRL_C) => { killpg(job.pgid, SIGINT); }
+                Some(CTRL_Z) => { killpg(job.pgid, SIGTSTP); }
+                _ => {}
+            }
+
+            if sys_waitpid(job.pgid as isize, &mut status, WNOHANG | WUNTRACED) == 0 {
+                sys_yield()
+            } else {
+                break;
+            }
+        }
+
+        if wifstopped(status) {
+            println!("");
+            println!("[{}] Stopped {}", job.id, job.name);
+            job.stopped = true;
+            self.jobs.push(job);
+        } else if wifsignaled(status) {
+            println!("Shell: Process {} killed by signal {}", job.pgid, wtermsig(status));
+        } else {
+            println!("Shell: Process {} exited with code {}", job.pgid, wexitstatus(status));
+        }
+    }
+
+    fn list(&self) {
+        for job in self.jobs.iter() {
+            let state = if job.stopped { "Stopped" } else { "Running" };
+            println!("[{}] {} {} {}", job.id, job.pgid, state, job.name);
+        }
+    }
+
+    // 没有指定 job id 时使用最后一个 job
+    fn find(&self, id: Option<&str>) -> Option<usize> {
+        let r = match id.map(|id| id.trim_start_matches('%').parse::<usize>()) {
+            Some(Ok(id)) => self.jobs.iter().position(|j| j.id == id),
+            Some(Err(_)) => None,
+            None => self.jobs.len().checked_sub(1),
+        };
+        if r.is_none() {
+            println!("no such job");
+        }
+        r
+    }
+
+    // shell 是 initproc，孤儿进程会交给它，后台任务也在这里回收
+    fn reap(&mut self) {
+        let mut status: i32 = 0;
+        loop {
+            let pid = sys_waitpid(-1, &mut status, WNOHANG);
+            if pid <= 0 {
+                break;
+            }
+            if let Some(i) = self.jobs.iter().position(|j| j.pgid == pid as usize) {
+                let job = self.jobs.remove(i);
+                println!("");
+                println!("[{}] Done {}", job.id, job.name);
+                print!(">> ");
+            }
+        }
+    }
+}
 
 #[no_mangle]
 pub fn main() -> i32 {
     let signal = SignalFlags::SIGINT;
     sys_sigprocmask(signal.bits() as usize);
+    let mut shell = Shell::new();
     let mut line: String = String::new();
     print!(">> ");
     loop {
@@ -28,47 +165,10 @@ pub fn main() -> i32 {
             match c {
                 LF | CR => {
                     println!("");
-                    if !line.is_empty() {
-                        // get line len
-                        line.push('\0');
-                        let fd = sys_open(&line.as_str());
-                        if fd < 0 {
-                            line.clear();
-                            println!("file not found");
-                            print!(">> ");
-                            continue;
-                        }
-                        let file_size = sys_filesize(fd as usize);
-                        let block_size = (file_size as usize / 4096) + 1;
-                        let buf_ptr = sys_mmap(4096 * block_size, 0x3) as usize as *mut u8;
-                        let buf = unsafe { core::slice::from_raw_parts_mut(buf_ptr, file_size as usize)};
-                        let r = sys_read(fd as usize, buf);
-                        if r >= 0 {
-                            let pid = sys_fork();
-                            if pid == 0 {
-                                sys_exec(&buf[0..file_size as usize]);
-                            } else {
-                                loop {
-                                    if let Some(c) = getchar() {
-                                        if c == 0x3 {
-                                            sys_sig(pid as usize, SIGINT);
-                                            continue;
-                                        }
-                                    }
-
-                                    if sys_wait(pid as usize) < 0 {
-                                        sys_yield()
-                                    } else {
-                                        break;
-                                    }
-                                }
-                                println!("Shell: Process {} exited with code", pid);
-                                sys_ummap(buf_ptr as usize);
-                            }
-                            line.clear();
-                        } else {
-                            line.clear();
-                        }
+                    let cmd = String::from(line.trim());
+                    line.clear();
+                    if !cmd.is_empty() {
+                        shell.execute(cmd.as_str());
                     }
                     print!(">> ");
                 }
@@ -80,12 +180,14 @@ pub fn main() -> i32 {
                         line.pop();
                     }
                 }
+                CTRL_C | CTRL_Z => {}
                 _ => {
                     print!("{}", c as char);
                     line.push(c as char);
                 }
             }
         } else {
+            shell.reap();
             sys_yield();
         }
     }
@@ -25,17 +25,12 @@ fn main() -> i32 {
         // sys_exec(0x8200_0000);
     } else if pid > 0 {
         println!("parent process");
-        loop {
-            if sys_wait(pid as usize) < 0 {
-                sys_nanosleep(1_000_000_000);
-                sys_kill(pid as usize, SIGUSR1);
-                sys_nanosleep(1_000_000_000);
-                sys_kill(pid as usize, SIGINT);                
-            } else {
-                println!("child process done");
-                break;
-            }
-        }
+        sys_nanosleep(1_000_000_000);
+        sys_kill(pid as usize, SIGUSR1);
+        sys_nanosleep(1_000_000_000);
+        sys_kill(pid as usize, SIGINT);
+        sys_wait(pid as usize);
+        println!("child process done");
     } else {
         println!("fork failed");
     }
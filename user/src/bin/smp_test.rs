@@ -0,0 +1,39 @@
+#![no_std]
+#![no_main]
+
+use ffos_app::syscall::*;
+
+#[macro_use]
+extern crate ffos_app;
+
+const CHILD_NUM: usize = 4;
+
+#[no_mangle]
+fn main() -> i32 {
+    println!("smp test, parent on cpu {}", sys_getcpu());
+    let mut pids: [usize; CHILD_NUM] = [0; CHILD_NUM];
+    for i in 0..CHILD_NUM {
+        let pid = sys_fork();
+        if pid == 0 {
+            // cpu 密集的子进程，会被分配到不同的 cpu 上
+            let mut count: usize = 0;
+            for _ in 0..50_000_000 {
+                count = count.wrapping_add(1);
+                core::hint::black_box(count);
+            }
+            println!("child {} run on cpu {}", sys_getpid(), sys_getcpu());
+            sys_exit(0);
+        } else if pid > 0 {
+            pids[i] = pid as usize;
+        } else {
+            println!("fork failed");
+            return -1;
+        }
+    }
+
+    for pid in pids {
+        sys_wait(pid);
+    }
+    println!("smp test done");
+    0
+}
@@ -0,0 +1,91 @@
+#![no_std]
+#![no_main]
+
+use ffos_app::syscall::*;
+use ffos_app::{args, execve, spawn, vfork_execve, wexitstatus, FileAction};
+
+#[macro_use]
+extern crate ffos_app;
+
+const RUNS: usize = 10;
+
+fn now() -> usize {
+    let mut ts = TimeSpec::default();
+    sys_clock_gettime(CLOCK_MONOTONIC, &mut ts);
+    ts.tv_sec * 1_000_000_000 + ts.tv_nsec
+}
+
+fn wait_exit(pid: isize) -> i32 {
+    let mut status = 0;
+    sys_waitpid(pid, &mut status, 0);
+    wexitstatus(status)
+}
+
+// spawn 的子进程，检查 file actions 和进程组
+fn child() -> i32 {
+    let opened = sys_filesize(5);
+    let duped = sys_filesize(6);
+    let closed = sys_filesize(3);
+    let pid = sys_getpid();
+    let pgid = sys_getpgid(0);
+    println!("child: fd 5 size {}, dup2 fd 6 size {}, closed fd 3 size {} (should be < 0)", opened, duped, closed);
+    println!("child: pid {}, pgid {} (should be equal)", pid, pgid);
+    if opened > 0 && duped == opened && closed < 0 && pid == pgid { 0 } else { 1 }
+}
+
+fn time_runs(name: &str, launch: fn() -> isize) {
+    let start = now();
+    for _ in 0..RUNS {
+        let pid = launch();
+        wait_exit(pid);
+    }
+    println!("{}: {} us per launch", name, (now() - start) / RUNS / 1000);
+}
+
+fn fork_exec() -> isize {
+    let pid = sys_fork();
+    if pid == 0 {
+        execve("spawn_test", &["spawn_test", "exit"], &[]);
+        sys_exit(127);
+    }
+    pid
+}
+
+#[no_mangle]
+fn main() -> i32 {
+    let argv = args();
+    if argv.len() == 2 && argv[1] == "child" {
+        return child();
+    }
+    if argv.len() == 2 && argv[1] == "exit" {
+        return 0;
+    }
+
+    println!("spawn test");
+    println!("spawn missing file -> {} (should be -1)", spawn("not_exist", &["not_exist"], &[], &[], None));
+
+    // fd 3 在子进程中被关闭，fd 5 打开文件，fd 6 是它的副本
+    let fd = sys_open("hello_world\0", 0);
+    let actions = [
+        FileAction::Close(fd as usize),
+        FileAction::Open(5, "hello_world", 0),
+        FileAction::Dup2(5, 6),
+    ];
+    let pid = spawn("spawn_test", &["spawn_test", "child"], &[], &actions, Some(0));
+    println!("spawn child {} exit {} (should be 0)", pid, wait_exit(pid));
+
+    let pid = spawn("spawn_test", &["spawn_test", "child"], &[], &[FileAction::Close(100)], None);
+    println!("spawn with bad file action -> {} (should be -1)", pid);
+
+    // vfork 的父进程在子进程 exec 之后才返回
+    let pid = vfork_execve("spawn_test", &["spawn_test", "exit"], &[]);
+    println!("vfork child {} exit {} (should be 0)", pid, wait_exit(pid));
+    let pid = vfork_execve("not_exist", &["not_exist"], &[]);
+    println!("vfork exec failed, child exit {} (should be 127)", wait_exit(pid));
+
+    time_runs("fork + exec", fork_exec);
+    time_runs("vfork + exec", || vfork_execve("spawn_test", &["spawn_test", "exit"], &[]));
+    time_runs("spawn", || spawn("spawn_test", &["spawn_test", "exit"], &[], &[], None));
+    println!("spawn test done");
+    0
+}
@@ -0,0 +1,77 @@
+#![no_std]
+#![no_main]
+
+use core::hint::black_box;
+use ffos_app::syscall::*;
+use ffos_app::{args, execve, report, run_child, usage};
+
+#[macro_use]
+extern crate ffos_app;
+
+const FRAME_SIZE: usize = 1024;
+
+fn limit(cur: usize) -> RLimit {
+    RLimit { rlim_cur: cur, rlim_max: RLIM_INFINITY }
+}
+
+// 每一层占用 FRAME_SIZE 以上的栈，返回 1 + 2 + ... + depth
+fn recurse(depth: usize) -> usize {
+    let mut frame = [0u8; FRAME_SIZE];
+    frame[0] = depth as u8;
+    let frame = black_box(frame);
+    if depth == 0 {
+        return frame[0] as usize;
+    }
+    depth + recurse(depth - 1) + frame[FRAME_SIZE - 1] as usize
+}
+
+// 在栈上放一个大数组
+#[inline(never)]
+fn big_buffer() -> usize {
+    let mut buf = [0u8; 2 * 1024 * 1024];
+    for i in (0..buf.len()).step_by(4096) {
+        buf[i] = 1;
+    }
+    black_box(&buf).iter().map(|&b| b as usize).sum()
+}
+
+// 无限递归，最后访问保护页
+fn overflow() -> i32 {
+    recurse(usize::MAX) as i32
+}
+
+// RLIMIT_STACK 在 exec 时决定预留的大小
+fn small_stack() -> i32 {
+    sys_setrlimit(RLIMIT_STACK, &limit(64 * 1024));
+    execve("stack_test", &["stack_test", "overflow"], &[]);
+    println!("exec failed");
+    -1
+}
+
+#[no_mangle]
+fn main() -> i32 {
+    let argv = args();
+    if argv.len() == 2 && argv[1] == "overflow" {
+        return overflow();
+    }
+
+    println!("stack test");
+    let mut l = RLimit::default();
+    sys_getrlimit(RLIMIT_STACK, &mut l);
+    println!("RLIMIT_STACK: cur {}K", l.rlim_cur / 1024);
+
+    // 栈按需向下增长
+    let before = usage();
+    let depth = 2000;
+    println!("recurse {}: sum {} (should be {})", depth, recurse(depth), depth * (depth + 1) / 2);
+    let after = usage();
+    println!("maxrss {}K -> {}K, minflt +{}", before.ru_maxrss, after.ru_maxrss, after.ru_minflt - before.ru_minflt);
+    println!("2M stack buffer: sum {} (should be 512)", big_buffer());
+
+    // 超过预留范围时访问保护页，收到 SIGSEGV
+    report("overflow 8M stack", run_child(overflow));
+    report("overflow 64K stack", run_child(small_stack));
+
+    println!("stack test done");
+    0
+}
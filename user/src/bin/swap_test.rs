@@ -0,0 +1,89 @@
+#![no_std]
+#![no_main]
+
+extern crate alloc;
+
+use alloc::vec::Vec;
+use ffos_app::syscall::*;
+use ffos_app::{sbrk, usage, wexitstatus};
+
+#[macro_use]
+extern crate ffos_app;
+
+const PAGE_SIZE: usize = 4096;
+const CHUNK_SIZE: usize = 4 * 1024 * 1024;
+// 堆和 mmap 加起来超过了用户可以使用的约 104M 物理内存（normal 区域减去给内核保留的页帧）
+const HEAP_SIZE: usize = 56 * 1024 * 1024;
+const CHUNKS: usize = 14;
+
+// 每一页的开头写入和地址相关的值，换出再换入后仍然应该相同
+fn tag(addr: usize, seed: usize) -> usize {
+    addr ^ seed
+}
+
+fn fill(regions: &[(usize, usize)], seed: usize) {
+    for &(start, size) in regions {
+        for addr in (start..start + size).step_by(PAGE_SIZE) {
+            unsafe { (addr as *mut usize).write_volatile(tag(addr, seed)); }
+        }
+    }
+}
+
+fn check(regions: &[(usize, usize)], seed: usize) -> usize {
+    let mut wrong = 0;
+    for &(start, size) in regions {
+        for addr in (start..start + size).step_by(PAGE_SIZE) {
+            if unsafe { (addr as *const usize).read_volatile() } != tag(addr, seed) {
+                wrong += 1;
+            }
+        }
+    }
+    wrong
+}
+
+#[no_mangle]
+fn main() -> i32 {
+    println!("swap test");
+
+    let mut regions: Vec<(usize, usize)> = Vec::new();
+    let heap = sbrk(HEAP_SIZE as isize);
+    if heap < 0 {
+        println!("sbrk failed");
+        return -1;
+    }
+    regions.push((heap as usize, HEAP_SIZE));
+    for _ in 0..CHUNKS {
+        let addr = sys_mmap(CHUNK_SIZE, 0x3);
+        if addr < 0 {
+            println!("mmap failed");
+            return -1;
+        }
+        regions.push((addr as usize, CHUNK_SIZE));
+    }
+    let total = HEAP_SIZE + CHUNKS * CHUNK_SIZE;
+
+    let before = usage();
+    fill(&regions, 0x5a5a);
+    let written = usage();
+    println!("wrote {}M, maxrss {}K, majflt +{}", total / 1024 / 1024, written.ru_maxrss, written.ru_majflt - before.ru_majflt);
+
+    let wrong = check(&regions, 0x5a5a);
+    let checked = usage();
+    println!("read back: {} pages wrong (should be 0), majflt +{}", wrong, checked.ru_majflt - written.ru_majflt);
+
+    // 子进程和父进程共享换出的页，子进程的修改对父进程不可见
+    let pid = sys_fork();
+    if pid == 0 {
+        let wrong = check(&regions, 0x5a5a);
+        fill(&regions[..1], 0xa5a5);
+        let wrong = wrong + check(&regions[..1], 0xa5a5);
+        sys_exit(wrong as i32);
+    }
+    let mut status = 0;
+    sys_waitpid(pid, &mut status, 0);
+    println!("child: {} pages wrong (should be 0)", wexitstatus(status));
+    println!("parent after fork: {} pages wrong (should be 0)", check(&regions, 0x5a5a));
+
+    println!("swap test done");
+    0
+}
@@ -0,0 +1,51 @@
+#![no_std]
+#![no_main]
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+use ffos_app::syscall::*;
+use ffos_app::{thread_spawn, tls};
+
+#[macro_use]
+extern crate ffos_app;
+
+static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+extern "C" fn worker(arg: usize) {
+    println!(
+        "thread {} pid {} tid {} tls {:#x}",
+        arg, sys_getpid(), sys_gettid(), tls()
+    );
+    for _ in 0..1000 {
+        COUNTER.fetch_add(1, Ordering::Relaxed);
+        if COUNTER.load(Ordering::Relaxed) % 100 == 0 {
+            sys_yield();
+        }
+    }
+}
+
+#[no_mangle]
+fn main() -> i32 {
+    println!("thread test, pid {} tid {}", sys_getpid(), sys_gettid());
+    let mut threads = [None, None, None, None];
+    for (i, t) in threads.iter_mut().enumerate() {
+        // 每个线程使用不同的 tls 值，检查切换时是否正确保存
+        *t = thread_spawn(worker, i, 0x1000 * (i + 1));
+        if t.is_none() {
+            println!("thread {} spawn failed", i);
+            return -1;
+        }
+    }
+
+    for t in threads {
+        t.unwrap().join();
+    }
+
+    let count = COUNTER.load(Ordering::Relaxed);
+    println!("counter {} (expect 4000)", count);
+    if count == 4000 {
+        println!("thread test passed");
+        0
+    } else {
+        -1
+    }
+}
@@ -0,0 +1,31 @@
+#![no_std]
+#![no_main]
+
+use ffos_app::syscall::*;
+use ffos_app::wexitstatus;
+
+#[macro_use]
+extern crate ffos_app;
+
+const CHILD_NUM: usize = 5;
+
+#[no_mangle]
+fn main() -> i32 {
+    println!("timer test");
+    // 先创建的子进程睡得更久，按唤醒时间的顺序退出
+    for i in 0..CHILD_NUM {
+        let pid = sys_fork();
+        if pid == 0 {
+            sys_nanosleep((CHILD_NUM - i) * 200_000_000);
+            return i as i32;
+        }
+    }
+
+    let mut status: i32 = 0;
+    for _ in 0..CHILD_NUM {
+        let pid = sys_waitpid(-1, &mut status, 0);
+        println!("child {} woke up, index {}", pid, wexitstatus(status));
+    }
+    println!("timer test done, index should be in reverse order");
+    0
+}
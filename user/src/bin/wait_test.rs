@@ -0,0 +1,60 @@
+#![no_std]
+#![no_main]
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+use ffos_app::syscall::*;
+use ffos_app::signal::*;
+use ffos_app::{wexitstatus, wifexited, wifsignaled, wtermsig};
+
+#[macro_use]
+extern crate ffos_app;
+
+static SIGCHLD_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+fn sigchld_handler() {
+    SIGCHLD_COUNT.fetch_add(1, Ordering::Relaxed);
+    sys_sigreturn();
+}
+
+#[no_mangle]
+fn main() -> i32 {
+    println!("wait test");
+    sys_sigaction(SIGCHLD, sigchld_handler as usize);
+
+    // 1. 阻塞等待，拿到子进程的退出码
+    let pid = sys_fork();
+    if pid == 0 {
+        sys_nanosleep(500_000_000);
+        return 7;
+    }
+    let mut status: i32 = 0;
+    let r = sys_waitpid(pid, &mut status, 0);
+    println!("waitpid {} -> {}, exited {} code {}", pid, r, wifexited(status), wexitstatus(status));
+
+    // 2. WNOHANG，子进程还在运行时返回 0
+    let pid = sys_fork();
+    if pid == 0 {
+        sys_nanosleep(500_000_000);
+        return 0;
+    }
+    println!("WNOHANG -> {} (should be 0)", sys_waitpid(pid, &mut status, WNOHANG));
+    println!("wait any -> {}", sys_waitpid(-1, &mut status, 0));
+
+    // 3. 被信号结束的子进程
+    let pid = sys_fork();
+    if pid == 0 {
+        loop {
+            sys_nanosleep(100_000_000);
+        }
+    }
+    sys_nanosleep(200_000_000);
+    sys_kill(pid as usize, SIGINT);
+    sys_waitpid(pid, &mut status, 0);
+    println!("killed {} by signal {}", wifsignaled(status), wtermsig(status));
+
+    // 4. 没有子进程时返回 -1
+    println!("no child -> {}", sys_waitpid(-1, &mut status, 0));
+    println!("sigchld received {} times (should be 3)", SIGCHLD_COUNT.load(Ordering::Relaxed));
+    println!("wait test done");
+    0
+}
@@ -0,0 +1,67 @@
+// 用户堆，内存不足时先通过 brk 扩大，超过内核的堆上限后再通过 mmap 扩大
+use core::alloc::{GlobalAlloc, Layout};
+use core::ptr::{null_mut, NonNull};
+use buddy_system_allocator::Heap;
+use spin::Mutex;
+
+use crate::syscall::{sys_brk, sys_mmap};
+
+const PAGE_SIZE: usize = 4096;
+// 每次至少扩大 128K，减少系统调用
+const MIN_GROW: usize = 32 * PAGE_SIZE;
+// 内核的 mmap 一次最多分配 4M
+const MAX_MMAP: usize = 1024 * PAGE_SIZE;
+
+pub struct GrowableHeap(Mutex<Heap>);
+
+impl GrowableHeap {
+    pub const fn new() -> Self {
+        Self(Mutex::new(Heap::new()))
+    }
+
+    // 扩大至少 size 字节，成功时返回 true
+    pub fn grow(&self, size: usize) -> bool {
+        Self::grow_locked(&mut self.0.lock(), size)
+    }
+
+    fn grow_locked(heap: &mut Heap, size: usize) -> bool {
+        let size = (size.max(MIN_GROW) + PAGE_SIZE - 1) / PAGE_SIZE * PAGE_SIZE;
+        let brk = sys_brk(0) as usize;
+        if sys_brk(brk + size) as usize == brk + size {
+            unsafe { heap.add_to_heap(brk, brk + size) };
+            return true;
+        }
+        if size <= MAX_MMAP {
+            let start = sys_mmap(size, 0x3);
+            if start > 0 {
+                unsafe { heap.add_to_heap(start as usize, start as usize + size) };
+                return true;
+            }
+        }
+        false
+    }
+
+    // 已经从内核得到的堆大小
+    pub fn total(&self) -> usize {
+        self.0.lock().stats_total_bytes()
+    }
+}
+
+unsafe impl GlobalAlloc for GrowableHeap {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let mut heap = self.0.lock();
+        if let Ok(ptr) = heap.alloc(layout) {
+            return ptr.as_ptr();
+        }
+        // buddy 需要按大小对齐的块，扩大两倍才能保证新加入的区域中有一块满足要求
+        let need = layout.size().max(layout.align()).next_power_of_two() * 2;
+        if !Self::grow_locked(&mut heap, need) {
+            return null_mut();
+        }
+        heap.alloc(layout).map_or(null_mut(), |ptr| ptr.as_ptr())
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        self.0.lock().dealloc(NonNull::new_unchecked(ptr), layout)
+    }
+}
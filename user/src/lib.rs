@@ -2,31 +2,41 @@
 #![feature(linkage)]
 #![feature(panic_info_message)]
 
+extern crate alloc;
+
 #[macro_use]
 pub mod console;
+pub mod heap;
 mod lang_items;
 pub mod syscall;
 pub mod signal;
 
-use buddy_system_allocator::LockedHeap;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use heap::GrowableHeap;
 
 #[global_allocator]
-/// heap allocator instance
-static HEAP_ALLOCATOR: LockedHeap = LockedHeap::empty();
+/// heap allocator instance, 开始时为空，第一次分配时通过 brk 扩大
+static HEAP_ALLOCATOR: GrowableHeap = GrowableHeap::new();
 
-pub fn init_heap(size: usize) {
-    let start = sys_mmap(size, 0x3);
+// 预先扩大堆，返回是否成功
+pub fn init_heap(size: usize) -> bool {
+    HEAP_ALLOCATOR.grow(size)
+}
 
-    unsafe {
-        HEAP_ALLOCATOR.lock().init(start as usize, size);
-    }
+// 已经从内核得到的堆大小
+pub fn heap_size() -> usize {
+    HEAP_ALLOCATOR.total()
 }
 
+// 内核在 a0/x0 中传入初始用户栈的地址，栈上依次是 argc、argv、envp 和 auxv
+static INIT_SP: AtomicUsize = AtomicUsize::new(0);
+
 #[no_mangle]
 #[link_section = ".text.entry"]
-pub extern "C" fn _start() -> ! {
-    // 512KB, 继续增加一个动态内存分配功能
-    init_heap(4096 * 128);
+pub extern "C" fn _start(sp: usize) -> ! {
+    INIT_SP.store(sp, Ordering::Relaxed);
     exit(main());
     panic!("unreachable after sys_exit!");
 }
@@ -46,8 +56,131 @@ pub fn read(fd: usize, buf: &mut [u8]) -> isize {
 pub fn write(fd: usize, buf: &[u8]) -> isize {
     sys_write(fd, buf)
 }
+
+// 和 libc 一样返回原来的 brk，失败时返回 -1
+pub fn sbrk(increment: isize) -> isize {
+    let old = sys_brk(0);
+    let new = (old + increment) as usize;
+    if sys_brk(new) as usize != new {
+        return -1;
+    }
+    old
+}
+// 和 libc 一样，进程退出时结束所有线程
 pub fn exit(exit_code: i32) -> isize {
-    sys_exit(exit_code)
+    sys_exit_group(exit_code)
+}
+
+// 以 \0 结尾的字符串，参数和环境变量在进程运行期间不会被释放
+unsafe fn c_str(p: *const u8) -> &'static str {
+    let mut len = 0;
+    while *p.add(len) != 0 {
+        len += 1;
+    }
+    core::str::from_utf8_unchecked(core::slice::from_raw_parts(p, len))
+}
+
+// 从 p 开始读取以空指针结尾的字符串数组，返回字符串和空指针的下一个位置
+unsafe fn str_array(mut p: *const usize) -> (Vec<&'static str>, *const usize) {
+    let mut strs = Vec::new();
+    while *p != 0 {
+        strs.push(c_str(*p as *const u8));
+        p = p.add(1);
+    }
+    (strs, p.add(1))
+}
+
+// 命令行参数，第一个是程序名
+pub fn args() -> Vec<&'static str> {
+    let sp = INIT_SP.load(Ordering::Relaxed) as *const usize;
+    unsafe { str_array(sp.add(1)).0 }
+}
+
+// 环境变量，格式为 NAME=VALUE
+pub fn envs() -> Vec<&'static str> {
+    let sp = INIT_SP.load(Ordering::Relaxed) as *const usize;
+    unsafe {
+        let (_, envp) = str_array(sp.add(1));
+        str_array(envp).0
+    }
+}
+
+// 和 glibc 相同，找不到时返回 0
+pub fn getauxval(t: usize) -> usize {
+    let sp = INIT_SP.load(Ordering::Relaxed) as *const usize;
+    unsafe {
+        let (_, envp) = str_array(sp.add(1));
+        let (_, mut auxv) = str_array(envp);
+        while *auxv != AT_NULL {
+            if *auxv == t {
+                return *auxv.add(1);
+            }
+            auxv = auxv.add(2);
+        }
+    }
+    0
+}
+
+// 在字符串后面加上 \0，并生成以空指针结尾的指针数组
+fn c_strs(strs: &[&str]) -> (Vec<String>, Vec<*const u8>) {
+    let owned: Vec<String> = strs.iter().map(|s| {
+        let mut s = String::from(*s);
+        s.push('\0');
+        s
+    }).collect();
+    let mut ptrs: Vec<*const u8> = owned.iter().map(|s| s.as_ptr()).collect();
+    ptrs.push(core::ptr::null());
+    (owned, ptrs)
+}
+
+// 执行文件系统中的 path，成功时不会返回
+pub fn execve(path: &str, args: &[&str], envs: &[&str]) -> isize {
+    let (path, _) = c_strs(&[path]);
+    let (_args, argv) = c_strs(args);
+    let (_envs, envp) = c_strs(envs);
+    sys_execve(path[0].as_ptr(), argv.as_ptr(), envp.as_ptr())
+}
+
+// 和 fork + execve 相同，但是不复制地址空间，子进程 exec 失败时以 127 退出
+pub fn vfork_execve(path: &str, args: &[&str], envs: &[&str]) -> isize {
+    let (path, _) = c_strs(&[path]);
+    let (_args, argv) = c_strs(args);
+    let (_envs, envp) = c_strs(envs);
+    sys_vfork_execve(path[0].as_ptr(), argv.as_ptr(), envp.as_ptr())
+}
+
+// spawn 在子进程中依次执行的文件操作
+pub enum FileAction<'a> {
+    // fd, path, flags
+    Open(usize, &'a str, usize),
+    Close(usize),
+    // fd, newfd
+    Dup2(usize, usize),
+}
+
+// 直接从 path 创建子进程，返回子进程的 pid
+// pgroup 不为 None 时子进程加入这个进程组，0 表示以子进程为首进程的新进程组
+pub fn spawn(path: &str, args: &[&str], envs: &[&str], actions: &[FileAction], pgroup: Option<usize>) -> isize {
+    let (path, _) = c_strs(&[path]);
+    let (_args, argv) = c_strs(args);
+    let (_envs, envp) = c_strs(envs);
+    let paths: Vec<&str> = actions.iter().map(|a| match a {
+        FileAction::Open(_, path, _) => *path,
+        _ => "",
+    }).collect();
+    let (paths, _) = c_strs(&paths);
+    let actions: Vec<SpawnFileAction> = actions.iter().zip(paths.iter()).map(|(a, path)| match *a {
+        FileAction::Open(fd, _, flags) => SpawnFileAction { op: SPAWN_OPEN, fd, arg: flags, path: path.as_ptr() as usize },
+        FileAction::Close(fd) => SpawnFileAction { op: SPAWN_CLOSE, fd, ..Default::default() },
+        FileAction::Dup2(fd, newfd) => SpawnFileAction { op: SPAWN_DUP2, fd, arg: newfd, ..Default::default() },
+    }).collect();
+    let attr = SpawnAttr {
+        flags: if pgroup.is_some() { SPAWN_SETPGROUP } else { 0 },
+        pgroup: pgroup.unwrap_or(0),
+        actions: actions.as_ptr() as usize,
+        actions_len: actions.len(),
+    };
+    sys_spawn(path[0].as_ptr(), argv.as_ptr(), envp.as_ptr(), &attr)
 }
 
 pub fn r#yield() {
@@ -63,6 +196,133 @@ pub fn wait(pid: usize) -> isize {
     sys_wait(pid)
 }
 
+// 解析 waitpid 返回的 status，和 linux 的 WIFEXITED 等宏相同
+pub fn wifexited(status: i32) -> bool {
+    status & 0x7f == 0
+}
+
+pub fn wexitstatus(status: i32) -> i32 {
+    (status >> 8) & 0xff
+}
+
+pub fn wifsignaled(status: i32) -> bool {
+    status & 0x7f != 0 && status & 0x7f != 0x7f
+}
+
+pub fn wifstopped(status: i32) -> bool {
+    status & 0xff == 0x7f
+}
+
+pub fn wstopsig(status: i32) -> i32 {
+    (status >> 8) & 0xff
+}
+
+pub fn wifcontinued(status: i32) -> bool {
+    status == 0xffff
+}
+
+// 在子进程中运行 f，返回子进程的退出状态
+pub fn run_child(f: fn() -> i32) -> i32 {
+    let pid = sys_fork();
+    if pid == 0 {
+        sys_exit(f());
+    }
+    let mut status = 0;
+    sys_waitpid(pid, &mut status, 0);
+    status
+}
+
+// 子进程被 SIGSEGV 结束时的输出，用于检查非法访问
+pub fn report(name: &str, status: i32) {
+    println!("{}: signaled {} by {} (should be {})", name, wifsignaled(status), wtermsig(status), signal::SIGSEGV);
+}
+
+// 当前进程的资源使用情况
+pub fn usage() -> RUsage {
+    let mut u = RUsage::default();
+    sys_getrusage(RUSAGE_SELF, &mut u);
+    u
+}
+
+// normal 区域中空闲的页帧数
+pub fn normal_free() -> usize {
+    let mut info = ZoneInfo::default();
+    sys_meminfo(ZONE_NORMAL, &mut info);
+    info.free
+}
+
+// 给进程组中的所有进程发送信号
+pub fn killpg(pgid: usize, signal: usize) -> isize {
+    sys_kill((-(pgid as isize)) as usize, signal)
+}
+
+pub fn wtermsig(status: i32) -> i32 {
+    status & 0x7f
+}
+
 pub fn create_pipe(fd: &mut [usize]) -> isize {
     sys_create_pipe(fd)
-}
\ No newline at end of file
+}
+
+// 返回修改后的 nice 值
+pub fn nice(inc: isize) -> isize {
+    let nice = 20 - sys_getpriority(PRIO_PROCESS, 0) + inc;
+    if sys_setpriority(PRIO_PROCESS, 0, nice) < 0 {
+        return -1;
+    }
+    20 - sys_getpriority(PRIO_PROCESS, 0)
+}
+
+// 线程栈大小，64KB
+const THREAD_STACK_SIZE: usize = 4096 * 16;
+
+pub struct Thread {
+    // 线程退出时被内核清零，放在堆上保证地址不变
+    tid: alloc::boxed::Box<usize>,
+    stack: usize,
+}
+
+impl Thread {
+    pub fn tid(&self) -> usize {
+        unsafe { core::ptr::read_volatile(&*self.tid) }
+    }
+
+    // 等待线程结束，并释放线程栈
+    pub fn join(self) {
+        while self.tid() != 0 {
+            sys_yield();
+        }
+        sys_ummap(self.stack);
+    }
+}
+
+// 创建一个和当前线程共享地址空间、文件和信号处理函数的线程，tls 写入新线程的 tp/TPIDR_EL0
+pub fn thread_spawn(entry: extern "C" fn(usize), arg: usize, tls: usize) -> Option<Thread> {
+    let stack = sys_mmap(THREAD_STACK_SIZE, 0x3);
+    if stack < 0 {
+        return None;
+    }
+    let stack = stack as usize;
+
+    let mut tid = alloc::boxed::Box::new(0usize);
+    let tid_ptr = &mut *tid as *mut usize;
+    let flags = CLONE_VM | CLONE_FS | CLONE_FILES | CLONE_SIGHAND | CLONE_THREAD
+        | CLONE_SETTLS | CLONE_PARENT_SETTID | CLONE_CHILD_CLEARTID;
+    let r = sys_clone(flags, stack + THREAD_STACK_SIZE, tid_ptr, tid_ptr, tls, entry, arg);
+    if r < 0 {
+        sys_ummap(stack);
+        return None;
+    }
+
+    Some(Thread { tid, stack })
+}
+
+// 读取当前线程的线程局部存储寄存器
+pub fn tls() -> usize {
+    let tls: usize;
+    #[cfg(feature = "riscv64")]
+    unsafe { core::arch::asm!("mv {}, tp", out(reg) tls); }
+    #[cfg(feature = "aarch64")]
+    unsafe { core::arch::asm!("mrs {}, TPIDR_EL0", out(reg) tls); }
+    tls
+}
@@ -13,6 +13,12 @@ const SYSCALL_SIZE: usize = 5;
 const SYSCALL_MMAP: usize = 9;
 const SYSCALL_UMMAP: usize = 10;
 const SYSCALL_MMAP_WITH_ADDR: usize = 11;
+const SYSCALL_MSYNC: usize = 26;
+const SYSCALL_MMAP_FILE: usize = 27;
+const SYSCALL_BRK: usize = 214;
+const SYSCALL_MPROTECT: usize = 329;
+const SYSCALL_PRCTL: usize = 157;
+const SYSCALL_MEMINFO: usize = 99;
 const SYSCALL_SIG: usize = 12;
 const SYSCALL_SIGACTION: usize = 13;
 const SYSCALL_SIGPROCMASK: usize = 14;
@@ -20,9 +26,14 @@ const SYSCALL_SIGRETURN: usize = 15;
 const SYSCALL_PIPE: usize = 22;
 const SYSCALL_YIELD: usize = 24;
 const SYSCALL_NANOSLEEP: usize = 35;
+const SYSCALL_GETITIMER: usize = 36;
+const SYSCALL_ALARM: usize = 37;
+const SYSCALL_SETITIMER: usize = 38;
 const SYSCALL_GETPID: usize = 39;
+const SYSCALL_CLONE: usize = 56;
 const SYSCALL_FORK: usize = 57;
-const SYSCALL_EXEC: usize = 59;
+const SYSCALL_VFORK: usize = 58;
+const SYSCALL_EXECVE: usize = 59;
 const SYSCALL_EXIT: usize = 60;
 const SYSCALL_WAIT: usize = 61;
 const SYSCALL_KILL: usize = 62;
@@ -35,6 +46,251 @@ const SYSCALL_SRV_CONNECT: usize = 91;
 const SYSCALL_SRV_REQUEST: usize = 92;
 const SYSCALL_SRV_RECV: usize = 93;
 const SYSCALL_SRV_REPLY: usize = 94;
+const SYSCALL_SPAWN: usize = 95;
+const SYSCALL_GETRLIMIT: usize = 97;
+const SYSCALL_GETRUSAGE: usize = 98;
+const SYSCALL_TIMES: usize = 100;
+const SYSCALL_SETPGID: usize = 109;
+const SYSCALL_GETPPID: usize = 110;
+const SYSCALL_SETSID: usize = 112;
+const SYSCALL_GETPGID: usize = 121;
+const SYSCALL_GETSID: usize = 124;
+const SYSCALL_GETPRIORITY: usize = 140;
+const SYSCALL_SETPRIORITY: usize = 141;
+const SYSCALL_SCHED_GETPARAM: usize = 143;
+const SYSCALL_SCHED_SETSCHEDULER: usize = 144;
+const SYSCALL_SCHED_GETSCHEDULER: usize = 145;
+const SYSCALL_SETRLIMIT: usize = 160;
+const SYSCALL_GETTID: usize = 186;
+const SYSCALL_TIMER_CREATE: usize = 222;
+const SYSCALL_TIMER_SETTIME: usize = 223;
+const SYSCALL_TIMER_GETTIME: usize = 224;
+const SYSCALL_TIMER_GETOVERRUN: usize = 225;
+const SYSCALL_TIMER_DELETE: usize = 226;
+const SYSCALL_CLOCK_GETTIME: usize = 228;
+const SYSCALL_CLOCK_GETRES: usize = 229;
+const SYSCALL_CLOCK_NANOSLEEP: usize = 230;
+const SYSCALL_EXIT_GROUP: usize = 231;
+const SYSCALL_PRLIMIT64: usize = 302;
+const SYSCALL_GETCPU: usize = 309;
+
+pub const PRIO_PROCESS: usize = 0;
+
+// open flags, same as linux
+pub const O_CLOEXEC: usize = 0o2000000;
+
+// sys_mmap_file 的 prot 和 flags
+pub const PROT_READ: usize = 0x1;
+pub const PROT_WRITE: usize = 0x2;
+pub const PROT_EXEC: usize = 0x4;
+pub const MAP_SHARED: usize = 0x01;
+pub const MAP_PRIVATE: usize = 0x02;
+pub const PR_SET_MDWE: usize = 65;
+pub const PR_GET_MDWE: usize = 66;
+pub const PR_MDWE_REFUSE_EXEC_GAIN: usize = 1;
+
+// auxv types, same as linux
+pub const AT_NULL: usize = 0;
+pub const AT_PHDR: usize = 3;
+pub const AT_PHENT: usize = 4;
+pub const AT_PHNUM: usize = 5;
+pub const AT_PAGESZ: usize = 6;
+pub const AT_BASE: usize = 7;
+pub const AT_ENTRY: usize = 9;
+pub const AT_RANDOM: usize = 25;
+
+// wait options, same as linux
+pub const WNOHANG: usize = 1;
+pub const WUNTRACED: usize = 2;
+pub const WCONTINUED: usize = 8;
+
+// clone flags, same as linux
+pub const CLONE_VM: usize = 0x100;
+pub const CLONE_FS: usize = 0x200;
+pub const CLONE_FILES: usize = 0x400;
+pub const CLONE_SIGHAND: usize = 0x800;
+pub const CLONE_THREAD: usize = 0x10000;
+pub const CLONE_SETTLS: usize = 0x80000;
+pub const CLONE_PARENT_SETTID: usize = 0x100000;
+pub const CLONE_CHILD_CLEARTID: usize = 0x200000;
+
+// spawn 的属性和文件操作，SPAWN_SETPGROUP 和 glibc 的 POSIX_SPAWN_SETPGROUP 相同
+pub const SPAWN_SETPGROUP: usize = 0x2;
+pub const SPAWN_OPEN: usize = 0;
+pub const SPAWN_CLOSE: usize = 1;
+pub const SPAWN_DUP2: usize = 2;
+
+#[repr(C)]
+#[derive(Clone, Copy, Default, Debug)]
+pub struct SpawnAttr {
+    pub flags: usize,
+    // 0 表示以子进程为首进程的新进程组
+    pub pgroup: usize,
+    // SpawnFileAction 数组的地址和长度
+    pub actions: usize,
+    pub actions_len: usize,
+}
+
+// open 时 arg 为 flags，path 为以 \0 结尾的字符串地址，dup2 时 arg 为新的 fd
+#[repr(C)]
+#[derive(Clone, Copy, Default, Debug)]
+pub struct SpawnFileAction {
+    pub op: usize,
+    pub fd: usize,
+    pub arg: usize,
+    pub path: usize,
+}
+
+pub const SCHED_NORMAL: usize = 0;
+pub const SCHED_FIFO: usize = 1;
+pub const SCHED_RR: usize = 2;
+pub const SCHED_DEADLINE: usize = 6;
+
+// deadline 任务的参数也放在这里，时间单位为 ns
+#[repr(C)]
+#[derive(Clone, Copy, Default, Debug)]
+pub struct SchedParam {
+    pub sched_priority: usize,
+    pub sched_runtime: usize,
+    pub sched_deadline: usize,
+    pub sched_period: usize,
+}
+
+// clock id, same as linux
+pub const CLOCK_REALTIME: usize = 0;
+pub const CLOCK_MONOTONIC: usize = 1;
+pub const CLOCK_PROCESS_CPUTIME_ID: usize = 2;
+pub const CLOCK_THREAD_CPUTIME_ID: usize = 3;
+
+pub const TIMER_ABSTIME: usize = 1;
+
+pub const ITIMER_REAL: usize = 0;
+pub const ITIMER_VIRTUAL: usize = 1;
+pub const ITIMER_PROF: usize = 2;
+
+pub const SIGEV_SIGNAL: i32 = 0;
+pub const SIGEV_NONE: i32 = 1;
+
+#[repr(C)]
+#[derive(Clone, Copy, Default, Debug)]
+pub struct TimeSpec {
+    pub tv_sec: usize,
+    pub tv_nsec: usize,
+}
+
+impl TimeSpec {
+    pub fn from_ns(ns: usize) -> Self {
+        Self { tv_sec: ns / 1_000_000_000, tv_nsec: ns % 1_000_000_000 }
+    }
+
+    pub fn to_ns(&self) -> usize {
+        self.tv_sec * 1_000_000_000 + self.tv_nsec
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Default, Debug)]
+pub struct TimeVal {
+    pub tv_sec: usize,
+    pub tv_usec: usize,
+}
+
+impl TimeVal {
+    pub fn to_us(&self) -> usize {
+        self.tv_sec * 1_000_000 + self.tv_usec
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Default, Debug)]
+pub struct ITimerVal {
+    pub it_interval: TimeVal,
+    pub it_value: TimeVal,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Default, Debug)]
+pub struct ITimerSpec {
+    pub it_interval: TimeSpec,
+    pub it_value: TimeSpec,
+}
+
+// 只有 linux sigevent 的前 16 个字节
+#[repr(C)]
+#[derive(Clone, Copy, Default, Debug)]
+pub struct SigEvent {
+    pub sigev_value: usize,
+    pub sigev_signo: i32,
+    pub sigev_notify: i32,
+}
+
+pub const RUSAGE_SELF: isize = 0;
+pub const RUSAGE_CHILDREN: isize = -1;
+pub const RUSAGE_THREAD: isize = 1;
+
+// times 的单位，即 linux 的 USER_HZ
+pub const CLOCKS_PER_SEC: usize = 100;
+
+#[repr(C)]
+#[derive(Clone, Copy, Default, Debug)]
+pub struct RUsage {
+    pub ru_utime: TimeVal,
+    pub ru_stime: TimeVal,
+    pub ru_maxrss: usize,
+    pub ru_ixrss: usize,
+    pub ru_idrss: usize,
+    pub ru_isrss: usize,
+    pub ru_minflt: usize,
+    pub ru_majflt: usize,
+    pub ru_nswap: usize,
+    pub ru_inblock: usize,
+    pub ru_oublock: usize,
+    pub ru_msgsnd: usize,
+    pub ru_msgrcv: usize,
+    pub ru_nsignals: usize,
+    pub ru_nvcsw: usize,
+    pub ru_nivcsw: usize,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Default, Debug)]
+pub struct Tms {
+    pub tms_utime: usize,
+    pub tms_stime: usize,
+    pub tms_cutime: usize,
+    pub tms_cstime: usize,
+}
+
+// resource, same as linux
+pub const RLIMIT_CPU: usize = 0;
+pub const RLIMIT_STACK: usize = 3;
+pub const RLIMIT_RSS: usize = 5;
+pub const RLIMIT_NPROC: usize = 6;
+pub const RLIMIT_NOFILE: usize = 7;
+pub const RLIMIT_AS: usize = 9;
+pub const RLIM_INFINITY: usize = usize::MAX;
+
+#[repr(C)]
+#[derive(Clone, Copy, Default, Debug)]
+pub struct RLimit {
+    pub rlim_cur: usize,
+    pub rlim_max: usize,
+}
+
+// 页帧分配器的区域
+pub const ZONE_DMA: usize = 0;
+pub const ZONE_NORMAL: usize = 1;
+pub const MAX_ORDER: usize = 10;
+
+// 一个区域的页帧数量，free_blocks 是每一阶空闲块的数量
+#[repr(C)]
+#[derive(Clone, Copy, Default, Debug)]
+pub struct ZoneInfo {
+    pub total: usize,
+    pub free: usize,
+    pub used: usize,
+    pub free_blocks: [usize; MAX_ORDER + 1],
+}
 
 fn syscall(id: usize, args: [usize; 4]) -> isize {
     let mut ret: isize;
@@ -65,6 +321,40 @@ fn syscall(id: usize, args: [usize; 4]) -> isize {
     ret
 }
 
+// 超过 4 个参数的系统调用
+fn syscall6(id: usize, args: [usize; 6]) -> isize {
+    let mut ret: isize;
+    #[cfg(feature = "riscv64")]
+    unsafe {
+        asm!(
+            "ecall",
+            inlateout("x10") args[0] => ret,
+            in("x11") args[1],
+            in("x12") args[2],
+            in("x13") args[3],
+            in("x14") args[4],
+            in("x15") args[5],
+            in("x17") id
+        );
+    }
+
+    #[cfg(feature = "aarch64")]
+    unsafe {
+        asm!(
+            "svc #0",
+            inlateout("x0") args[0] => ret,
+            in("x1") args[1],
+            in("x2") args[2],
+            in("x3") args[3],
+            in("x4") args[4],
+            in("x5") args[5],
+            in("x8") id
+        );
+    }
+
+    ret
+}
+
 pub fn sys_read(fd: usize, buffer: &mut [u8]) -> isize {
     syscall(SYSCALL_READ, [fd, buffer.as_ptr() as usize, buffer.len(), 0])
 }
@@ -73,8 +363,9 @@ pub fn sys_write(fd: usize, buffer: &[u8]) -> isize {
     syscall(SYSCALL_WRITE, [fd, buffer.as_ptr() as usize, buffer.len(), 0])
 }
 
-pub fn sys_open(name: &str) -> isize {
-    syscall(SYSCALL_OPEN, [name.as_ptr() as usize, name.len() as usize, 0, 0])
+// name 需要以 \0 结尾，flags 只支持 O_CLOEXEC
+pub fn sys_open(name: &str, flags: usize) -> isize {
+    syscall(SYSCALL_OPEN, [name.as_ptr() as usize, flags, 0, 0])
 }
 
 pub fn sys_lseek(fd: usize, seek: usize) -> isize {
@@ -89,6 +380,66 @@ pub fn sys_exit(exit_code: i32) -> isize {
     syscall(SYSCALL_EXIT, [exit_code as usize, 0, 0, 0])
 }
 
+pub fn sys_exit_group(exit_code: i32) -> isize {
+    syscall(SYSCALL_EXIT_GROUP, [exit_code as usize, 0, 0, 0])
+}
+
+// 子线程从 clone 返回后就运行在新的栈上，不能再返回到调用者的栈帧
+// 所以直接在汇编中调用 entry(arg)，返回后调用 exit 结束线程
+pub fn sys_clone(
+    flags: usize, stack: usize, parent_tid: *mut usize, child_tid: *mut usize, tls: usize,
+    entry: extern "C" fn(usize), arg: usize
+) -> isize {
+    let mut ret: isize;
+    #[cfg(feature = "riscv64")]
+    unsafe {
+        asm!(
+            "ecall",
+            "bnez a0, 1f",
+            "mv a0, a6",
+            "jalr a5",
+            // exit(0)
+            "li a0, 0",
+            "li a7, 60",
+            "ecall",
+            "1:",
+            inlateout("x10") flags => ret,
+            in("x11") stack,
+            in("x12") parent_tid,
+            in("x13") child_tid,
+            in("x14") tls,
+            in("x15") entry,
+            in("x16") arg,
+            in("x17") SYSCALL_CLONE
+        );
+    }
+
+    #[cfg(feature = "aarch64")]
+    unsafe {
+        asm!(
+            "svc #0",
+            "cbnz x0, 1f",
+            "mov x0, x6",
+            "blr x5",
+            // exit(0)
+            "mov x0, #0",
+            "mov x8, #60",
+            "svc #0",
+            "1:",
+            inlateout("x0") flags => ret,
+            in("x1") stack,
+            in("x2") parent_tid,
+            in("x3") child_tid,
+            in("x4") tls,
+            in("x5") entry,
+            in("x6") arg,
+            in("x8") SYSCALL_CLONE
+        );
+    }
+
+    ret
+}
+
 pub fn sys_yield() {
     syscall(SYSCALL_YIELD, [0, 0, 0, 0]);
 }
@@ -101,14 +452,89 @@ pub fn sys_fork() -> isize {
     syscall(SYSCALL_FORK, [0, 0, 0, 0])
 }
 
-pub fn sys_exec(elf: &[u8]) -> isize {
-    syscall(SYSCALL_EXEC, [elf.as_ptr() as usize, elf.len(), 0, 0])
+// path 和所有参数都以 \0 结尾，argv 和 envp 以空指针结尾，成功时不会返回
+pub fn sys_execve(path: *const u8, argv: *const *const u8, envp: *const *const u8) -> isize {
+    syscall(SYSCALL_EXECVE, [path as usize, argv as usize, envp as usize, 0])
+}
+
+// attr 为空时使用默认属性，返回子进程的 pid
+pub fn sys_spawn(path: *const u8, argv: *const *const u8, envp: *const *const u8, attr: *const SpawnAttr) -> isize {
+    syscall(SYSCALL_SPAWN, [path as usize, argv as usize, envp as usize, attr as usize])
 }
 
+// vfork 的子进程和父进程共享栈，在子进程中不能从函数返回，也不能调用会写栈的函数
+// 所以和 sys_clone 一样在汇编中直接 execve，失败时以 127 退出，父进程在子进程 exec 或者退出后返回
+pub fn sys_vfork_execve(path: *const u8, argv: *const *const u8, envp: *const *const u8) -> isize {
+    let mut ret: isize;
+    #[cfg(feature = "riscv64")]
+    unsafe {
+        asm!(
+            "ecall",
+            "bnez a0, 1f",
+            "mv a0, a3",
+            "mv a1, a4",
+            "mv a2, a5",
+            "li a7, 59",
+            "ecall",
+            // exit(127)
+            "li a0, 127",
+            "li a7, 60",
+            "ecall",
+            "1:",
+            lateout("x10") ret,
+            in("x13") path,
+            in("x14") argv,
+            in("x15") envp,
+            inlateout("x17") SYSCALL_VFORK => _,
+            lateout("x11") _,
+            lateout("x12") _,
+        );
+    }
+
+    #[cfg(feature = "aarch64")]
+    unsafe {
+        asm!(
+            "svc #0",
+            "cbnz x0, 1f",
+            "mov x0, x3",
+            "mov x1, x4",
+            "mov x2, x5",
+            "mov x8, #59",
+            "svc #0",
+            // exit(127)
+            "mov x0, #127",
+            "mov x8, #60",
+            "svc #0",
+            "1:",
+            lateout("x0") ret,
+            in("x3") path,
+            in("x4") argv,
+            in("x5") envp,
+            inlateout("x8") SYSCALL_VFORK => _,
+            lateout("x1") _,
+            lateout("x2") _,
+        );
+    }
+
+    ret
+}
+
+// 阻塞等待子进程退出，不关心退出状态
 pub fn sys_wait(pid: usize) -> isize {
     syscall(SYSCALL_WAIT, [pid, 0, 0, 0])
 }
 
+// pid 为 -1 时等待任意子进程，为 0 时等待同一进程组的子进程，小于 -1 时等待进程组 -pid 中的子进程
+// 返回退出的子进程 pid
+pub fn sys_waitpid(pid: isize, status: &mut i32, options: usize) -> isize {
+    syscall(SYSCALL_WAIT, [pid as usize, status as *mut i32 as usize, options, 0])
+}
+
+// 即 wait4，回收子进程时在 rusage 中返回子进程的资源统计
+pub fn sys_wait4(pid: isize, status: &mut i32, options: usize, rusage: &mut RUsage) -> isize {
+    syscall(SYSCALL_WAIT, [pid as usize, status as *mut i32 as usize, options, rusage as *mut _ as usize])
+}
+
 pub fn sys_create_pipe(fd: &mut [usize]) -> isize {
     syscall(SYSCALL_PIPE, [fd.as_mut_ptr() as usize, 0, 0, 0])
 }
@@ -133,6 +559,42 @@ pub fn sys_getpid() -> isize {
     syscall(SYSCALL_GETPID, [0, 0, 0, 0])
 }
 
+pub fn sys_getppid() -> isize {
+    syscall(SYSCALL_GETPPID, [0, 0, 0, 0])
+}
+
+// pid 和 pgid 为 0 时表示当前进程
+pub fn sys_setpgid(pid: usize, pgid: usize) -> isize {
+    syscall(SYSCALL_SETPGID, [pid, pgid, 0, 0])
+}
+
+pub fn sys_getpgid(pid: usize) -> isize {
+    syscall(SYSCALL_GETPGID, [pid, 0, 0, 0])
+}
+
+pub fn sys_setsid() -> isize {
+    syscall(SYSCALL_SETSID, [0, 0, 0, 0])
+}
+
+pub fn sys_getsid(pid: usize) -> isize {
+    syscall(SYSCALL_GETSID, [pid, 0, 0, 0])
+}
+
+pub fn sys_gettid() -> isize {
+    syscall(SYSCALL_GETTID, [0, 0, 0, 0])
+}
+
+pub fn sys_getcpu() -> isize {
+    let mut cpu: usize = 0;
+    let ret = syscall(SYSCALL_GETCPU, [&mut cpu as *mut usize as usize, 0, 0, 0]);
+    if ret < 0 {
+        ret
+    } else {
+        cpu as isize
+    }
+}
+
+// pid 为 0 时发给当前进程组，强转为负数时发给进程组，参考 killpg
 pub fn sys_kill(pid: usize, signal: usize) -> isize {
     syscall(SYSCALL_KILL, [pid, signal, 0, 0])
 }
@@ -141,6 +603,36 @@ pub fn sys_mmap(size: usize, permission: usize) -> isize {
     syscall(SYSCALL_MMAP, [size, permission, 0, 0])
 }
 
+// 映射 fd 中从 offset 开始的 len 字节，offset 需要页对齐，页在第一次访问时读取
+// MAP_SHARED 的修改在 msync、munmap 和退出时写回文件，MAP_PRIVATE 的修改写时复制
+pub fn sys_mmap_file(fd: usize, offset: usize, len: usize, prot: usize, flags: usize) -> isize {
+    syscall6(SYSCALL_MMAP_FILE, [fd, offset, len, prot, flags, 0])
+}
+
+pub fn sys_msync(addr: usize, len: usize) -> isize {
+    syscall(SYSCALL_MSYNC, [addr, len, 0, 0])
+}
+
+// addr 需要页对齐，默认不允许同时可写和可执行
+pub fn sys_mprotect(addr: usize, len: usize, prot: usize) -> isize {
+    syscall(SYSCALL_MPROTECT, [addr, len, prot, 0])
+}
+
+// 只支持 PR_SET_MDWE 和 PR_GET_MDWE，PR_SET_MDWE 的参数为 0 时允许同时可写和可执行的页
+pub fn sys_prctl(option: usize, arg: usize) -> isize {
+    syscall(SYSCALL_PRCTL, [option, arg, 0, 0])
+}
+
+// zone 不存在时返回 -1
+pub fn sys_meminfo(zone: usize, info: &mut ZoneInfo) -> isize {
+    syscall(SYSCALL_MEMINFO, [zone, info as *mut ZoneInfo as usize, 0, 0])
+}
+
+// 返回新的 brk，addr 为 0 或者失败时返回当前的 brk
+pub fn sys_brk(addr: usize) -> isize {
+    syscall(SYSCALL_BRK, [addr, 0, 0, 0])
+}
+
 pub fn sys_ummap(addr: usize) -> isize {
     syscall(SYSCALL_UMMAP, [addr, 0, 0, 0])
 }
@@ -179,4 +671,99 @@ pub fn sys_recv_server(name: &str, req: *mut u8, req_len: *mut usize, timeout_ms
 
 pub fn sys_reply_server(rcvid: usize, resp: &[u8]) -> isize {
     syscall(SYSCALL_SRV_REPLY, [rcvid, resp.as_ptr() as usize, resp.len(), 0])
-}
\ No newline at end of file
+}
+
+pub fn sys_getpriority(which: usize, who: usize) -> isize {
+    syscall(SYSCALL_GETPRIORITY, [which, who, 0, 0])
+}
+
+pub fn sys_setpriority(which: usize, who: usize, prio: isize) -> isize {
+    syscall(SYSCALL_SETPRIORITY, [which, who, prio as usize, 0])
+}
+
+pub fn sys_sched_setscheduler(pid: usize, policy: usize, param: &SchedParam) -> isize {
+    syscall(SYSCALL_SCHED_SETSCHEDULER, [pid, policy, param as *const _ as usize, 0])
+}
+
+pub fn sys_sched_getscheduler(pid: usize) -> isize {
+    syscall(SYSCALL_SCHED_GETSCHEDULER, [pid, 0, 0, 0])
+}
+
+pub fn sys_sched_getparam(pid: usize, param: &mut SchedParam) -> isize {
+    syscall(SYSCALL_SCHED_GETPARAM, [pid, param as *mut _ as usize, 0, 0])
+}
+
+pub fn sys_clock_gettime(clock: usize, tp: &mut TimeSpec) -> isize {
+    syscall(SYSCALL_CLOCK_GETTIME, [clock, tp as *mut _ as usize, 0, 0])
+}
+
+pub fn sys_clock_getres(clock: usize, res: &mut TimeSpec) -> isize {
+    syscall(SYSCALL_CLOCK_GETRES, [clock, res as *mut _ as usize, 0, 0])
+}
+
+// 被信号打断时返回 -2，相对时间的睡眠在 rem 中返回剩余时间
+pub fn sys_clock_nanosleep(clock: usize, flags: usize, req: &TimeSpec, rem: Option<&mut TimeSpec>) -> isize {
+    let rem = rem.map_or(0, |r| r as *mut _ as usize);
+    syscall(SYSCALL_CLOCK_NANOSLEEP, [clock, flags, req as *const _ as usize, rem])
+}
+
+pub fn sys_alarm(seconds: usize) -> isize {
+    syscall(SYSCALL_ALARM, [seconds, 0, 0, 0])
+}
+
+pub fn sys_setitimer(which: usize, new: &ITimerVal, old: Option<&mut ITimerVal>) -> isize {
+    let old = old.map_or(0, |o| o as *mut _ as usize);
+    syscall(SYSCALL_SETITIMER, [which, new as *const _ as usize, old, 0])
+}
+
+pub fn sys_getitimer(which: usize, cur: &mut ITimerVal) -> isize {
+    syscall(SYSCALL_GETITIMER, [which, cur as *mut _ as usize, 0, 0])
+}
+
+// sevp 为 None 时到期发送 SIGALRM，成功时返回 0 并写入 timer id
+pub fn sys_timer_create(clock: usize, sevp: Option<&SigEvent>, timerid: &mut usize) -> isize {
+    let sevp = sevp.map_or(0, |e| e as *const _ as usize);
+    syscall(SYSCALL_TIMER_CREATE, [clock, sevp, timerid as *mut _ as usize, 0])
+}
+
+pub fn sys_timer_settime(id: usize, flags: usize, new: &ITimerSpec, old: Option<&mut ITimerSpec>) -> isize {
+    let old = old.map_or(0, |o| o as *mut _ as usize);
+    syscall(SYSCALL_TIMER_SETTIME, [id, flags, new as *const _ as usize, old])
+}
+
+pub fn sys_timer_gettime(id: usize, cur: &mut ITimerSpec) -> isize {
+    syscall(SYSCALL_TIMER_GETTIME, [id, cur as *mut _ as usize, 0, 0])
+}
+
+pub fn sys_timer_getoverrun(id: usize) -> isize {
+    syscall(SYSCALL_TIMER_GETOVERRUN, [id, 0, 0, 0])
+}
+
+pub fn sys_timer_delete(id: usize) -> isize {
+    syscall(SYSCALL_TIMER_DELETE, [id, 0, 0, 0])
+}
+
+// 返回启动以来的 clock 数
+pub fn sys_times(buf: &mut Tms) -> isize {
+    syscall(SYSCALL_TIMES, [buf as *mut _ as usize, 0, 0, 0])
+}
+
+pub fn sys_getrusage(who: isize, usage: &mut RUsage) -> isize {
+    syscall(SYSCALL_GETRUSAGE, [who as usize, usage as *mut _ as usize, 0, 0])
+}
+
+pub fn sys_getrlimit(resource: usize, limit: &mut RLimit) -> isize {
+    syscall(SYSCALL_GETRLIMIT, [resource, limit as *mut _ as usize, 0, 0])
+}
+
+// 不能提高硬限制，软限制不能超过硬限制
+pub fn sys_setrlimit(resource: usize, limit: &RLimit) -> isize {
+    syscall(SYSCALL_SETRLIMIT, [resource, limit as *const _ as usize, 0, 0])
+}
+
+// pid 为 0 表示当前进程
+pub fn sys_prlimit(pid: usize, resource: usize, new: Option<&RLimit>, old: Option<&mut RLimit>) -> isize {
+    let new = new.map_or(0, |n| n as *const _ as usize);
+    let old = old.map_or(0, |o| o as *mut _ as usize);
+    syscall(SYSCALL_PRLIMIT64, [pid, resource, new, old])
+}